@@ -0,0 +1,3 @@
+drop index if exists uniq_accounts_account;
+alter table accounts drop column if exists disabled;
+alter table accounts drop column if exists token_version;
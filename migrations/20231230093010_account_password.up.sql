@@ -0,0 +1,5 @@
+-- 账号: 密码改为hash存储(老的明文密码在下次登陆成功时自动升级), 支持停用
+alter table accounts
+    add column disabled      boolean not null default false, -- 是否停用
+    add column token_version integer not null default 0;     -- 重置密码/停用时+1, 之前签发的token随之失效
+create unique index uniq_accounts_account on accounts (account);
@@ -0,0 +1,8 @@
+-- 已注销(登出/刷新)的token, 过期后可以清掉
+create table revoked_tokens
+(
+    jti        text PRIMARY KEY,
+    account_id integer     not null default 0,
+    expires_at timestamptz not null
+);
+create index idx_revoked_tokens_expires_at on revoked_tokens (expires_at);
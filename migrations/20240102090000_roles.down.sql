@@ -0,0 +1,12 @@
+-- 只删没有账号的管理部, 账号数据不删
+delete
+from departments
+where name = '管理部'
+  and not exists (select 1 from accounts a where a.department_id = departments.id);
+
+alter table departments
+    drop column role_ids;
+alter table accounts
+    drop column role_ids;
+
+drop table roles;
@@ -0,0 +1,39 @@
+-- 角色: 权限用字符串表示, '*' 表示所有权限
+create table roles
+(
+    id          serial PRIMARY KEY,
+    name        text   not null default '',  -- 角色名称
+    permissions text[] not null default '{}' -- 权限列表
+);
+create unique index uniq_roles_name on roles (name);
+
+insert into roles (name, permissions)
+values ('管理员', '{*}');
+insert into roles (name, permissions)
+values ('业务', '{order:read,order:write,order:delete,excel:import,customer:read,customer:write,goods:read,goods:write,material:read,material:write,progress:mark,stats:read,upload}');
+insert into roles (name, permissions)
+values ('仓库', '{order:read,goods:read,material:read,material:write,progress:mark}');
+insert into roles (name, permissions)
+values ('车间', '{order:read,goods:read,progress:mark}');
+insert into roles (name, permissions)
+values ('只读', '{order:read,customer:read,goods:read,material:read,stats:read}');
+
+-- 账号的权限 = 账号自己的角色 + 所在部门的角色
+alter table accounts
+    add column role_ids integer[] not null default '{}'; -- 角色
+alter table departments
+    add column role_ids integer[] not null default '{}'; -- 角色
+
+update departments
+set role_ids = '{2}'
+where name = '业务部';
+update departments
+set role_ids = '{3}'
+where name = '仓库部';
+update departments
+set role_ids = '{4}'
+where name in ('生产部', '品检部', '碰焊部', '包装部', '装箱部');
+
+-- 管理员账号不在这里建, 启动时按配置(ADMIN_ACCOUNT/ADMIN_PASSWORD)创建到管理部下
+insert into departments (name, steps, role_ids)
+values ('管理部', '{}', '{1}');
@@ -0,0 +1,2 @@
+drop table workflow_step_options;
+drop table workflow_steps;
@@ -0,0 +1,94 @@
+-- 生产流程定义: 之前只写在README的js里, 后端写死了 step + 1 / index=2(已完成) / index=1(异常)
+create table workflow_steps
+(
+    id            serial PRIMARY KEY,
+    step          integer not null,           -- 流程序号, 按从小到大的顺序流转
+    name          text    not null default '', -- 流程名称
+    department_id integer not null default 0  -- 负责部门
+);
+create unique index uniq_workflow_steps_step on workflow_steps (step);
+
+-- 每个流程可选的状态
+create table workflow_step_options
+(
+    id           serial PRIMARY KEY,
+    step         integer not null,
+    index        integer not null,
+    name         text    not null default '',
+    color        text    not null default '',
+    is_done      boolean not null default false, -- 选了这个就表示该流程已完成, 进入下一个流程
+    is_exception boolean not null default false  -- 异常(备注)
+);
+create unique index uniq_workflow_step_options_step_index on workflow_step_options (step, index);
+
+insert into workflow_steps (step, name, department_id)
+select 1, '下订单', id
+from departments
+where name = '业务部';
+insert into workflow_steps (step, name, department_id)
+select 2, '仓库', id
+from departments
+where name = '仓库部';
+insert into workflow_steps (step, name, department_id)
+select 3, '生产', id
+from departments
+where name = '生产部';
+insert into workflow_steps (step, name, department_id)
+select 4, '品检', id
+from departments
+where name = '品检部';
+insert into workflow_steps (step, name, department_id)
+select 5, '碰焊', id
+from departments
+where name = '碰焊部';
+insert into workflow_steps (step, name, department_id)
+select 6, '包装', id
+from departments
+where name = '包装部';
+insert into workflow_steps (step, name, department_id)
+select 7, '出货', id
+from departments
+where name = '装箱部';
+insert into workflow_steps (step, name, department_id)
+select 8, '拍照', id
+from departments
+where name = '业务部';
+
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (1, 3, '颜色打样', '#FFFE00', false, false),
+       (1, 4, '成品不锈钢订货', '#92D04F', false, false),
+       (1, 5, '打材料单', '#00B0F0', false, false),
+       (1, 1, '异常(备注)', '#C9D2DC', false, true),
+       (1, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (2, 3, '已发车间', '#7030A1', false, false),
+       (2, 4, '已发品检', '#02B151', false, false),
+       (2, 5, '已发滴油', '#FFC100', false, false),
+       (2, 6, '兰溪JLZ', '#FF0000', false, false),
+       (2, 7, '兰溪FW', '#BF0101', false, false),
+       (2, 8, '兰溪LSS', '#D9E4BC', false, false),
+       (2, 9, '兰溪CN', '#8DB3E2', false, false),
+       (2, 1, '异常(备注)', '#C9D2DC', false, true),
+       (2, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (3, 3, '外发', '#CCC1DB', false, false),
+       (3, 4, '生产', '#528DD5', false, false),
+       (3, 1, '异常(备注)', '#C9D2DC', false, true),
+       (3, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (4, 1, '异常(备注)', '#C9D2DC', false, true),
+       (4, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (5, 1, '异常(备注)', '#C9D2DC', false, true),
+       (5, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (6, 3, '兰溪包装', '#FAC08F', false, false),
+       (6, 4, '义乌包装', '#E8B7B8', false, false),
+       (6, 1, '异常(备注)', '#C9D2DC', false, true),
+       (6, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (7, 1, '异常(备注)', '#C9D2DC', false, true),
+       (7, 2, '已完成', '#C9D2DC', true, false);
+insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+values (8, 1, '异常(备注)', '#C9D2DC', false, true),
+       (8, 2, '已完成', '#C9D2DC', true, false);
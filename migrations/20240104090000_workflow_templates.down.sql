@@ -0,0 +1,5 @@
+alter table progress
+    drop column skipped;
+alter table orders
+    drop column workflow_template_id;
+drop table workflow_templates;
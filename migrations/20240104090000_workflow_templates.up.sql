@@ -0,0 +1,29 @@
+-- 流程模板: 不同的制作方式/客户/订单走不同的流程路线
+create table workflow_templates
+(
+    id              serial PRIMARY KEY,
+    name            text      not null default '',    -- 模板名称
+    steps           integer[] not null default '{}',  -- 流程路线, 按数组顺序流转
+    optional_steps  integer[] not null default '{}',  -- 可选流程: 到这一步时, 后面一个流程的部门也可以直接操作
+    skippable_steps integer[] not null default '{}',  -- 可跳过的流程: 可以直接标记为跳过
+    build_by        integer   not null default 0,     -- 按制作方式匹配, 0: 不限
+    customer_no     text      not null default '',    -- 按客户匹配, 空: 不限
+    is_default      boolean   not null default false  -- 都匹配不上时用的模板
+);
+
+insert into workflow_templates (name, steps, skippable_steps, is_default)
+values ('默认', '{1,2,3,4,5,6,7,8}', '{8}', true);
+insert into workflow_templates (name, steps, skippable_steps, build_by)
+values ('手工', '{1,2,3,4,6,7,8}', '{8}', 1);
+insert into workflow_templates (name, steps, skippable_steps, build_by)
+values ('不锈钢', '{1,2,4,5,6,7,8}', '{8}', 2);
+insert into workflow_templates (name, steps, optional_steps, skippable_steps)
+values ('外发', '{1,2,4,6,7,8}', '{4}', '{8}');
+
+-- 订单可以单独指定模板, 0: 自动匹配
+alter table orders
+    add column workflow_template_id integer not null default 0;
+
+-- 被跳过的流程
+alter table progress
+    add column skipped boolean not null default false;
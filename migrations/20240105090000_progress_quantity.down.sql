@@ -0,0 +1,4 @@
+alter table progress
+    drop column defective;
+alter table progress
+    drop column quantity;
@@ -0,0 +1,11 @@
+-- 流程按数量分批流转
+alter table progress
+    add column quantity integer not null default 0;  -- 本次经过这个流程的数量
+alter table progress
+    add column defective integer not null default 0; -- 本次的次品数量
+
+-- 老数据是整个产品经过这个流程, 补上产品的数量; 之后0就是这次没有经过的(比如都是次品)
+update progress p
+set quantity = oi.count
+from order_items oi
+where p.order_item_id = oi.id;
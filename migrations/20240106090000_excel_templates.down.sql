@@ -0,0 +1,3 @@
+drop index if exists uniq_customer_excel_template_customer_no;
+drop table excel_template_columns;
+drop table excel_templates;
@@ -0,0 +1,92 @@
+-- excel导入模版: 之前是 parse_order_template_1..4 写死的列号, customer_excel_template.template_id 对应这里的id
+create table excel_templates
+(
+    id                   serial PRIMARY KEY,
+    name                 text    not null default '',
+    header_row           integer not null default 6,     -- 表头所在的行(按表头名称找列时用)
+    start_row            integer not null default 7,     -- 数据开始的行
+    package_image_column integer not null default 0,     -- 包装卡图片所在的列, 0: 没有
+    goods_image_column   integer not null default 0,     -- 商品图片所在的列, 0: 没有
+    notes_image_column   integer not null default 0,     -- 备注图片所在的列, 0: 没有
+    carry_down           text[]  not null default '{}'   -- 合并单元格: 这些字段为空时沿用上一行的
+);
+
+-- 模版的列 -> OrderItemExcel的字段
+create table excel_template_columns
+(
+    id                serial PRIMARY KEY,
+    template_id       integer not null,
+    col               integer not null default 0,       -- 第几列(从1开始), 0: 按表头名称找
+    header            text    not null default '',      -- 表头名称
+    field             text    not null,                 -- 字段, 如: goods_no, color, count
+    remove_whitespace boolean not null default false    -- 去掉所有空白(编号类的), 否则只去掉两头的
+);
+create unique index uniq_excel_template_columns_template_id_field on excel_template_columns (template_id, field);
+
+insert into excel_templates (id, name, package_image_column, goods_image_column, notes_image_column, carry_down)
+values (1, '通用', 2, 4, 12,
+        '{index,package_card,package_card_des,goods_no,sku_no,image_des,name,plating,color,color_2,size,barcode,purchase_price,unit_price,total_price}'),
+       (2, '无商品编号(L1005)', 2, 4, 12,
+        '{index,package_card,package_card_des,goods_no,sku_no,image_des,name,plating,color,color_2,size,barcode,purchase_price,unit_price,total_price}'),
+       (3, '条码(L1012)', 0, 2, 12,
+        '{index,package_card,package_card_des,goods_no,sku_no,image_des,name,plating,color,color_2,size,barcode,purchase_price,unit_price,total_price}'),
+       (4, 'SKU编号(L1004)', 2, 7, 15,
+        '{index,package_card,package_card_des,goods_no,sku_no,image_des,name,plating,color,color_2,size,barcode,purchase_price,unit_price,total_price}');
+select setval('excel_templates_id_seq', (select max(id) from excel_templates));
+
+insert into excel_template_columns (template_id, col, field, remove_whitespace)
+values (1, 1, 'index', false),
+       (1, 2, 'package_card_des', false),
+       (1, 3, 'goods_no', true),
+       (1, 4, 'image_des', false),
+       (1, 5, 'name', false),
+       (1, 6, 'plating', false),
+       (1, 7, 'color', true),
+       (1, 8, 'count', false),
+       (1, 9, 'unit', false),
+       (1, 10, 'unit_price', false),
+       (1, 11, 'total_price', false),
+       (1, 12, 'notes', false),
+
+       (2, 1, 'index', false),
+       (2, 2, 'package_card_des', false),
+       (2, 3, 'sku_no', true),
+       (2, 4, 'image_des', false),
+       (2, 5, 'name', false),
+       (2, 6, 'plating', false),
+       (2, 7, 'color', false),
+       (2, 8, 'count', false),
+       (2, 9, 'unit', false),
+       (2, 10, 'unit_price', false),
+       (2, 11, 'total_price', false),
+       (2, 12, 'notes', false),
+
+       (3, 1, 'index', false),
+       (3, 3, 'goods_no', true),
+       (3, 4, 'name', false),
+       (3, 5, 'plating', false),
+       (3, 6, 'color', true),
+       (3, 7, 'color_2', false),
+       (3, 8, 'barcode', false),
+       (3, 9, 'purchase_price', false),
+       (3, 10, 'count', false),
+       (3, 11, 'total_price', false),
+       (3, 12, 'notes', false),
+
+       (4, 1, 'index', false),
+       (4, 2, 'package_card_des', false),
+       (4, 3, 'sku_no', true),
+       (4, 4, 'goods_no', true),
+       (4, 5, 'color_2', true),
+       (4, 6, 'size', false),
+       (4, 7, 'image_des', false),
+       (4, 8, 'name', false),
+       (4, 9, 'plating', false),
+       (4, 10, 'color', true),
+       (4, 11, 'count', false),
+       (4, 12, 'unit', false),
+       (4, 13, 'unit_price', false),
+       (4, 14, 'total_price', false),
+       (4, 15, 'notes', false);
+
+create unique index uniq_customer_excel_template_customer_no on customer_excel_template (customer_no);
@@ -0,0 +1 @@
+drop table excel_header_synonyms;
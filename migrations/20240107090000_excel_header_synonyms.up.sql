@@ -0,0 +1,43 @@
+-- 按表头自动识别列: 表头名称(去掉空白, 不区分大小写) -> OrderItemExcel的字段
+create table excel_header_synonyms
+(
+    id     serial PRIMARY KEY,
+    field  text not null,
+    header text not null
+);
+create unique index uniq_excel_header_synonyms_header on excel_header_synonyms (header);
+
+insert into excel_header_synonyms (field, header)
+values ('index', '序号'),
+       ('index', '项次'),
+       ('index', 'no'),
+       ('package_card_des', '包装卡'),
+       ('package_card_des', '包装'),
+       ('goods_no', '商品编号'),
+       ('goods_no', '货号'),
+       ('goods_no', '款号'),
+       ('goods_no', '产品编号'),
+       ('sku_no', 'sku编号'),
+       ('sku_no', 'sku'),
+       ('image_des', '图片'),
+       ('image_des', '商品图片'),
+       ('image_des', '产品图片'),
+       ('name', '名称'),
+       ('name', '品名'),
+       ('name', '商品名称'),
+       ('name', '产品名称'),
+       ('plating', '电镀'),
+       ('color', '颜色'),
+       ('color', '色号'),
+       ('color_2', '颜色2'),
+       ('color_2', '英文颜色'),
+       ('size', '尺寸'),
+       ('size', '规格'),
+       ('barcode', '条码'),
+       ('purchase_price', '进货价'),
+       ('count', '数量'),
+       ('unit', '单位'),
+       ('unit_price', '单价'),
+       ('total_price', '金额'),
+       ('total_price', '总价'),
+       ('notes', '备注');
@@ -0,0 +1,11 @@
+-- 订单生成过的单据(订单确认书/装箱单/送货单), 文件存在 STORAGE_FILE_PATH/order/<单号>/ 下
+create table order_documents
+(
+    id         serial PRIMARY KEY,
+    order_id   integer     not null,
+    kind       text        not null,            -- confirmation: 订单确认书, packing: 装箱单, delivery: 送货单
+    file       text        not null default '', -- 文件地址
+    account_id integer     not null default 0,  -- 生成人
+    dt         timestamptz not null default now()
+);
+create index idx_order_documents_order_id on order_documents (order_id);
@@ -0,0 +1,3 @@
+drop table if exists order_item_materials;
+drop table if exists sku_materials;
+drop table if exists materials;
@@ -0,0 +1,41 @@
+-- 材料
+create table materials
+(
+    id    serial PRIMARY KEY,
+    name  text not null,            -- 材料名称
+    color text not null default '', -- 材料颜色
+    unit  text not null default '', -- 单位, 如: 米, 个, 克
+    notes text not null default ''  -- 备注
+);
+create unique index uniq_materials_name_and_color on materials (name, color);
+
+-- sku的材料清单(BOM): 做一个sku要用多少材料, 导入订单时按这个生成订单sku的材料单
+create table sku_materials
+(
+    id          serial PRIMARY KEY,
+    sku_id      integer        not null,
+    material_id integer        not null,
+    single      numeric(12, 3) not null default 0, -- 单件用量
+    notes       text           not null default ''
+);
+create unique index uniq_sku_materials_sku_id_and_material_id on sku_materials (sku_id, material_id);
+
+-- 订单sku的材料单 * N
+create table order_item_materials
+(
+    id            serial PRIMARY KEY,
+    order_id      integer        not null,            -- 订单ID
+    order_item_id integer        not null,            -- 订单商品ID
+    material_id   integer        not null default 0,  -- 材料ID, 0: 手工添加的(不在材料表里)
+    name          text           not null,            -- 材料名称
+    color         text           not null default '', -- 材料颜色
+    unit          text           not null default '', -- 单位
+    single        numeric(12, 3) not null default 0,  -- 单件用量
+    count         integer        not null default 0,  -- 数量(订单sku的数量)
+    total         numeric(12, 3) not null default 0,  -- 总用量
+    stock         numeric(12, 3) not null default 0,  -- 库存
+    debt          numeric(12, 3) not null default 0,  -- 欠数
+    notes         text           not null default ''  -- 备注
+);
+create index idx_order_item_materials_order_id on order_item_materials (order_id);
+create unique index uniq_order_item_materials_order_item_id_and_name_and_color on order_item_materials (order_item_id, name, color);
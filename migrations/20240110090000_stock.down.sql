@@ -0,0 +1,6 @@
+alter table order_item_materials
+    add column stock numeric(12, 3) not null default 0,
+    add column debt  numeric(12, 3) not null default 0;
+
+drop table if exists stock_movements;
+drop table if exists warehouses;
@@ -0,0 +1,32 @@
+-- 仓库
+create table warehouses
+(
+    id   serial PRIMARY KEY,
+    name text not null -- 仓库名称
+);
+create unique index uniq_warehouses_name on warehouses (name);
+
+insert into warehouses (name)
+values ('兰溪'),
+       ('义乌');
+
+-- 库存流水: 库存 = 流水的合计
+create table stock_movements
+(
+    id           serial PRIMARY KEY,
+    material_id  integer        not null,
+    warehouse_id integer        not null,
+    kind         text           not null,           -- in: 入库, out: 出库, adjust: 盘点调整
+    quantity     numeric(12, 3) not null,           -- 变动数量, 入库为正, 出库为负, 调整可正可负
+    order_id     integer        not null default 0, -- 出库到哪个订单, 0: 不是订单用的
+    notes        text           not null default '',
+    account_id   integer        not null default 0, -- 操作人
+    dt           timestamptz    not null default now()
+);
+create index idx_stock_movements_material_id_and_warehouse_id on stock_movements (material_id, warehouse_id);
+create index idx_stock_movements_order_id on stock_movements (order_id);
+
+-- 材料单的库存/欠数改成按库存流水算出来
+alter table order_item_materials
+    drop column stock,
+    drop column debt;
@@ -0,0 +1,5 @@
+drop table if exists shipment_items;
+drop table if exists shipments;
+
+alter table workflow_steps
+    drop column if exists is_shipping;
@@ -0,0 +1,33 @@
+-- 出货流程: 只能通过发货单完成, 不能直接在流程上标记完成
+alter table workflow_steps
+    add column is_shipping boolean not null default false;
+update workflow_steps
+set is_shipping = true
+where name = '出货';
+
+-- 发货单, 一个订单可以分多次发货
+create table shipments
+(
+    id            serial PRIMARY KEY,
+    order_id      integer     not null,
+    shipment_date date        not null,            -- 发货日期
+    carrier       text        not null default '', -- 物流公司
+    tracking_no   text        not null default '', -- 物流单号
+    boxes         integer     not null default 0,  -- 箱数
+    notes         text        not null default '',
+    account_id    integer     not null default 0,  -- 操作人
+    dt            timestamptz not null default now()
+);
+create index idx_shipments_order_id on shipments (order_id);
+
+-- 发货单里每个订单sku发了多少
+create table shipment_items
+(
+    id            serial PRIMARY KEY,
+    shipment_id   integer not null,
+    order_item_id integer not null,
+    quantity      integer not null,
+    progress_id   integer not null default 0 -- 对应的出货流程记录, 0: 订单的流程里没有出货
+);
+create index idx_shipment_items_shipment_id on shipment_items (shipment_id);
+create index idx_shipment_items_order_item_id on shipment_items (order_item_id);
@@ -0,0 +1,9 @@
+update roles
+set permissions = array_remove(array_remove(permissions, 'purchase:read'), 'purchase:write');
+
+drop table if exists purchase_receipts;
+drop table if exists purchase_orders;
+drop table if exists suppliers;
+
+alter table workflow_step_options
+    drop column if exists purchase_kind;
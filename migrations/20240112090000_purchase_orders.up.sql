@@ -0,0 +1,60 @@
+-- 需要采购单的流程选项: stainless: 成品不锈钢订货, outsource: 外发
+alter table workflow_step_options
+    add column purchase_kind text not null default '';
+update workflow_step_options
+set purchase_kind = 'stainless'
+where name = '成品不锈钢订货';
+update workflow_step_options
+set purchase_kind = 'outsource'
+where name = '外发';
+
+-- 供应商
+create table suppliers
+(
+    id    serial PRIMARY KEY,
+    code  text not null,            -- 供应商编号
+    name  text not null default '', -- 名称
+    notes text not null default ''
+);
+create unique index uniq_suppliers_code on suppliers (code);
+
+-- 采购单: 不锈钢成品订货/外发加工, 对应订单里的一个sku
+create table purchase_orders
+(
+    id            serial PRIMARY KEY,
+    order_id      integer     not null,
+    order_item_id integer     not null,
+    supplier_id   integer     not null,
+    kind          text        not null,            -- stainless: 不锈钢订货, outsource: 外发
+    item          text        not null default '', -- 采购/外发的内容
+    quantity      integer     not null,
+    received      integer     not null default 0,  -- 已收货数量(收货记录的合计)
+    expected_date date        not null,            -- 预计到货日期
+    progress_id   integer     not null default 0,  -- 标记流程时关联的流程记录
+    notes         text        not null default '',
+    account_id    integer     not null default 0,  -- 操作人
+    dt            timestamptz not null default now()
+);
+create index idx_purchase_orders_order_id on purchase_orders (order_id);
+create index idx_purchase_orders_order_item_id on purchase_orders (order_item_id);
+create index idx_purchase_orders_supplier_id on purchase_orders (supplier_id);
+
+-- 收货记录, 一个采购单可以分多次收货
+create table purchase_receipts
+(
+    id                serial PRIMARY KEY,
+    purchase_order_id integer     not null,
+    quantity          integer     not null,
+    receipt_date      date        not null,            -- 收货日期
+    notes             text        not null default '',
+    account_id        integer     not null default 0,  -- 操作人
+    dt                timestamptz not null default now()
+);
+create index idx_purchase_receipts_purchase_order_id on purchase_receipts (purchase_order_id);
+
+update roles
+set permissions = permissions || '{purchase:read,purchase:write}'
+where name in ('业务', '仓库');
+update roles
+set permissions = permissions || '{purchase:read}'
+where name = '只读';
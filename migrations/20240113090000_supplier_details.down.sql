@@ -0,0 +1,8 @@
+alter table stock_movements
+    drop column if exists supplier_id;
+
+alter table suppliers
+    drop column if exists contact,
+    drop column if exists phone,
+    drop column if exists address,
+    drop column if exists provides;
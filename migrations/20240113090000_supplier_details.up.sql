@@ -0,0 +1,10 @@
+-- 供应商的联系方式, 提供的材料/加工
+alter table suppliers
+    add column contact  text   not null default '',  -- 联系人
+    add column phone    text   not null default '',
+    add column address  text   not null default '',
+    add column provides text[] not null default '{}'; -- 提供的材料/加工, 如: 不锈钢, 电镀, 滴油
+
+-- 材料入库时记是从哪个供应商采购的
+alter table stock_movements
+    add column supplier_id integer not null default 0; -- 0: 不是采购入库
@@ -0,0 +1,6 @@
+update roles
+set permissions = array_remove(array_remove(permissions, 'invoice:read'), 'invoice:write');
+
+drop table if exists payments;
+drop table if exists invoice_items;
+drop table if exists invoices;
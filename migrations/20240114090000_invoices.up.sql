@@ -0,0 +1,58 @@
+-- 发票(对账单), 由一个或多个订单/发货单生成, 同一张发票只能是同一个客户的
+create table invoices
+(
+    id           serial PRIMARY KEY,
+    invoice_no   text           not null,            -- 发票编号
+    customer_no  text           not null,
+    invoice_date date           not null,            -- 开票日期
+    due_date     date           not null,            -- 到期日(账龄按这个算)
+    subtotal     numeric(14, 2) not null default 0,  -- 明细合计
+    discount     numeric(14, 2) not null default 0,  -- 整单折扣金额
+    tax_rate     numeric(5, 2)  not null default 0,  -- 税率(%)
+    tax          numeric(14, 2) not null default 0,  -- 税额
+    total        numeric(14, 2) not null default 0,  -- 应收 = 明细合计 - 折扣 + 税额
+    paid         numeric(14, 2) not null default 0,  -- 已收(收款记录的合计)
+    notes        text           not null default '',
+    account_id   integer        not null default 0,  -- 操作人
+    dt           timestamptz    not null default now()
+);
+create unique index uniq_invoices_invoice_no on invoices (invoice_no);
+create index idx_invoices_customer_no on invoices (customer_no);
+
+-- 发票明细, 一行对应订单里的一个sku(从发货单生成时是这次发的数量)
+create table invoice_items
+(
+    id            serial PRIMARY KEY,
+    invoice_id    integer        not null,
+    order_id      integer        not null,
+    order_item_id integer        not null,
+    shipment_id   integer        not null default 0, -- 0: 按订单开的
+    description   text           not null default '',
+    quantity      integer        not null,
+    unit_price    numeric(12, 2) not null,
+    discount      numeric(12, 2) not null default 0, -- 这一行的折扣金额
+    amount        numeric(14, 2) not null            -- 数量 * 单价 - 折扣
+);
+create index idx_invoice_items_invoice_id on invoice_items (invoice_id);
+create index idx_invoice_items_order_item_id on invoice_items (order_item_id);
+
+-- 收款记录
+create table payments
+(
+    id           serial PRIMARY KEY,
+    invoice_id   integer        not null,
+    amount       numeric(14, 2) not null,
+    payment_date date           not null,
+    method       text           not null default '', -- 收款方式: 转账, 现金等
+    notes        text           not null default '',
+    account_id   integer        not null default 0,  -- 操作人
+    dt           timestamptz    not null default now()
+);
+create index idx_payments_invoice_id on payments (invoice_id);
+
+update roles
+set permissions = permissions || '{invoice:read,invoice:write}'
+where name = '业务';
+update roles
+set permissions = permissions || '{invoice:read}'
+where name = '只读';
@@ -0,0 +1,16 @@
+alter table invoices
+    drop column if exists currency;
+alter table orders
+    drop column if exists currency;
+alter table customers
+    drop column if exists currency;
+drop function if exists base_currency();
+
+drop table if exists exchange_rates;
+drop table if exists currencies;
+
+alter table invoice_items
+    alter column unit_price type numeric(12, 2);
+alter table order_items
+    alter column unit_price type integer using round(unit_price)::integer,
+    alter column total_price type integer using round(total_price)::integer;
@@ -0,0 +1,48 @@
+-- 价格改成小数, 单价保留4位(有的客户按0.035美元报价), 金额保留2位
+alter table order_items
+    alter column unit_price type numeric(12, 4),
+    alter column total_price type numeric(14, 2);
+alter table invoice_items
+    alter column unit_price type numeric(12, 4);
+
+-- 币种, 基础货币(本币)只能有一个
+create table currencies
+(
+    code    text PRIMARY KEY,               -- 如: CNY, USD
+    name    text    not null default '',
+    is_base boolean not null default false -- 基础货币, 汇总金额都换算成它
+);
+create unique index uniq_currencies_is_base on currencies (is_base) where is_base;
+
+insert into currencies (code, name, is_base)
+values ('CNY', '人民币', true),
+       ('USD', '美元', false),
+       ('EUR', '欧元', false);
+
+-- 汇率: 1单位外币 = rate 基础货币, 按生效日期取当时的汇率
+create table exchange_rates
+(
+    id             serial PRIMARY KEY,
+    currency       text           not null,
+    rate           numeric(14, 6) not null,
+    effective_date date           not null,
+    account_id     integer        not null default 0, -- 操作人
+    dt             timestamptz    not null default now()
+);
+create unique index uniq_exchange_rates_currency_and_effective_date on exchange_rates (currency, effective_date);
+
+-- 当前的基础货币, 用做币种字段的默认值(改了基础货币后默认值跟着变)
+create function base_currency() returns text as
+$$
+select code
+from currencies
+where is_base
+$$ language sql stable;
+
+-- 客户的报价币种, 新订单默认用客户的币种
+alter table customers
+    add column currency text not null default base_currency();
+alter table orders
+    add column currency text not null default base_currency();
+alter table invoices
+    add column currency text not null default base_currency();
@@ -0,0 +1,6 @@
+alter table workflow_steps
+    drop column if exists labor_rate;
+alter table purchase_orders
+    drop column if exists unit_price;
+alter table materials
+    drop column if exists unit_cost;
@@ -0,0 +1,10 @@
+-- 算订单毛利用的成本, 都是基础货币
+-- 材料单价: 出库到订单的材料按这个算材料成本
+alter table materials
+    add column unit_cost numeric(12, 4) not null default 0;
+-- 采购单价: 不锈钢订货/外发加工的成本 = 采购数量 * 单价
+alter table purchase_orders
+    add column unit_price numeric(12, 4) not null default 0;
+-- 流程的计件工价(每件), 0: 不算人工成本
+alter table workflow_steps
+    add column labor_rate numeric(12, 4) not null default 0;
@@ -2,4 +2,5 @@ pub mod datetime;
 pub mod db;
 pub mod hashmap;
 pub mod log;
+pub mod password;
 pub mod string;
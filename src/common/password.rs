@@ -0,0 +1,69 @@
+use crate::{ERPError, ERPResult};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+pub const MIN_PASSWORD_LEN: usize = 6;
+
+pub fn hash_password(password: &str) -> ERPResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hashed = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|_| ERPError::Failed("密码加密失败".to_string()))?
+        .to_string();
+
+    Ok(hashed)
+}
+
+/// 数据库里的密码是否已经是hash过的(老数据是明文)
+pub fn is_hashed_password(stored: &str) -> bool {
+    PasswordHash::new(stored).is_ok()
+}
+
+/// 校验密码, 兼容还没升级的明文密码
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => !stored.is_empty() && stored == password,
+    }
+}
+
+pub fn check_password_strength(password: &str) -> ERPResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(ERPError::ParamError(format!(
+            "密码至少需要{}位",
+            MIN_PASSWORD_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::password::{
+        check_password_strength, hash_password, is_hashed_password, verify_password,
+    };
+
+    #[test]
+    fn test_hash_and_verify() -> anyhow::Result<()> {
+        let hashed = hash_password("888888")?;
+        assert!(is_hashed_password(&hashed));
+        assert!(verify_password("888888", &hashed));
+        assert!(!verify_password("888889", &hashed));
+        // 同一个密码每次的盐不一样
+        assert_ne!(hashed, hash_password("888888")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plaintext_password() {
+        assert!(!is_hashed_password("888888"));
+        assert!(verify_password("888888", "888888"));
+        assert!(!verify_password("", ""));
+        assert!(check_password_strength("12345").is_err());
+        assert!(check_password_strength("123456").is_ok());
+    }
+}
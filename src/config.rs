@@ -1,5 +1,35 @@
-#[derive(Debug, clap::Parser)]
+/// 仓库里带的中文子集字体(相对项目目录), 见 assets/fonts/README.md
+pub const DEFAULT_PDF_FONT_PATH: &str = "assets/fonts/NotoSansSC-Subset.ttf";
+
+#[derive(Debug, Clone)]
 pub struct Config {
-    #[arg(short, long)]
-    database_url: String,
+    pub jwt_secret: String,
+    pub jwt_expires_in: i64,            // token有效期(分钟)
+    pub pdf_font_path: String,          // 生成PDF用的中文字体(ttf), 默认用仓库里的子集字体
+    pub admin_account: String,          // 启动时没有这个账号就创建成管理员
+    pub admin_password: Option<String>, // 管理员初始密码, 不配置就不创建
+}
+
+impl Config {
+    pub fn init() -> Config {
+        let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+        let jwt_expires_in = std::env::var("JWT_EXPIRES_IN")
+            .unwrap_or("10080".to_string())
+            .parse::<i64>()
+            .expect("JWT_EXPIRES_IN should be minutes");
+        let pdf_font_path =
+            std::env::var("PDF_FONT_PATH").unwrap_or(DEFAULT_PDF_FONT_PATH.to_string());
+        let admin_account = std::env::var("ADMIN_ACCOUNT").unwrap_or("ADMIN".to_string());
+        let admin_password = std::env::var("ADMIN_PASSWORD")
+            .ok()
+            .filter(|password| !password.is_empty());
+
+        Config {
+            jwt_secret,
+            jwt_expires_in,
+            pdf_font_path,
+            admin_account,
+            admin_password,
+        }
+    }
 }
@@ -1,8 +1,7 @@
 use std::collections::HashMap;
 
 pub const DEFAULT_PAGE_SIZE: i32 = 50;
-pub const DONE_INDEX: i32 = 2;
-pub const EXCEPTION_OR_NOTES_INDEX: i32 = 1;
+pub const TOKEN_COOKIE_NAME: &str = "token";
 
 pub const STORAGE_FILE_PATH: &str = "/home/debian/data/file/";
 // pub const STORAGE_FILE_PATH: &str = "/Users/ligangzhou/data/file/";
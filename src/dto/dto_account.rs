@@ -1,4 +1,6 @@
-use crate::model::account::{AccountModel, DepartmentModel};
+use crate::middleware::permission::{Permission, ALL_PERMISSION};
+use crate::model::account::{AccountModel, DepartmentModel, RoleModel};
+use crate::ERPError;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AccountDto {
@@ -9,10 +11,25 @@ pub struct AccountDto {
     pub department_id: i32,
     pub department: String,
     pub steps: Vec<i32>,
+    pub disabled: bool,
+    pub role_ids: Vec<i32>, // 账号自己的角色
+    pub roles: Vec<String>, // 生效的角色(含部门的)
+    pub permissions: Vec<String>,
 }
 
 impl AccountDto {
-    pub fn from(account: AccountModel, department: DepartmentModel) -> AccountDto {
+    pub fn from(
+        account: AccountModel,
+        department: DepartmentModel,
+        roles: Vec<RoleModel>,
+    ) -> AccountDto {
+        let mut permissions = roles
+            .iter()
+            .flat_map(|role| role.permissions.clone())
+            .collect::<Vec<String>>();
+        permissions.sort();
+        permissions.dedup();
+
         Self {
             id: account.id,
             name: account.name,
@@ -20,6 +37,34 @@ impl AccountDto {
             department_id: department.id,
             department: department.name,
             steps: department.steps,
+            disabled: account.disabled,
+            role_ids: account.role_ids,
+            roles: roles.into_iter().map(|role| role.name).collect(),
+            permissions,
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions
+            .iter()
+            .any(|p| p == ALL_PERMISSION || p == permission.key())
+    }
+
+    pub fn check_permission(&self, permission: Permission) -> Result<(), ERPError> {
+        if !self.has_permission(permission) {
+            return Err(ERPError::NoPermission(format!(
+                "需要「{}」权限",
+                permission.name()
+            )));
         }
+        Ok(())
     }
 }
+
+#[derive(Debug, Serialize)]
+pub struct LoginDto {
+    #[serde(flatten)]
+    pub account: AccountDto,
+    pub token: String,
+    pub expires_at: i64, // unix timestamp(秒)
+}
@@ -8,6 +8,7 @@ pub struct CustomerDto {
     pub address: String,
     pub phone: String,
     pub notes: String,
+    pub currency: String,
 }
 
 impl CustomerDto {
@@ -19,6 +20,7 @@ impl CustomerDto {
             address: customer.address,
             phone: customer.phone,
             notes: customer.notes,
+            currency: customer.currency,
         }
     }
 }
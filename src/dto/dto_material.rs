@@ -0,0 +1,106 @@
+use crate::model::material::OrderItemMaterialModel;
+use crate::model::stock::{get_material_allocations, MaterialAllocation};
+use crate::ERPResult;
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+
+/// 材料单的一行, 带上按库存流水算出来的备料情况
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderItemMaterialDto {
+    #[serde(flatten)]
+    pub material: OrderItemMaterialModel,
+    pub issued: Decimal,   // 已出库
+    pub reserved: Decimal, // 库存预留
+    pub debt: Decimal,     // 欠数
+}
+
+impl OrderItemMaterialDto {
+    pub fn from(
+        material: OrderItemMaterialModel,
+        allocation: MaterialAllocation,
+    ) -> OrderItemMaterialDto {
+        Self {
+            material,
+            issued: allocation.issued,
+            reserved: allocation.reserved,
+            debt: allocation.debt,
+        }
+    }
+
+    /// 按库存流水给材料单算上备料情况
+    pub async fn from_materials(
+        db: &Pool<Postgres>,
+        materials: Vec<OrderItemMaterialModel>,
+    ) -> ERPResult<Vec<OrderItemMaterialDto>> {
+        let material_ids = materials
+            .iter()
+            .map(|material| material.material_id)
+            .filter(|material_id| *material_id > 0)
+            .unique()
+            .collect::<Vec<i32>>();
+        let mut allocations = get_material_allocations(db, &material_ids).await?;
+
+        Ok(materials
+            .into_iter()
+            .map(|material| {
+                // 手工添加的材料不算库存, 全部是欠数
+                let allocation = allocations
+                    .remove(&material.id)
+                    .unwrap_or(MaterialAllocation {
+                        debt: material.total,
+                        ..Default::default()
+                    });
+                OrderItemMaterialDto::from(material, allocation)
+            })
+            .collect())
+    }
+}
+
+/// 订单按材料汇总的需求和欠数
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderMaterialShortageDto {
+    pub material_id: i32,
+    pub name: String,
+    pub color: String,
+    pub unit: String,
+    pub total: Decimal,
+    pub issued: Decimal,
+    pub reserved: Decimal,
+    pub debt: Decimal,
+}
+
+impl OrderMaterialShortageDto {
+    /// 同一个材料(手工添加的按名称+颜色)的材料单合在一起
+    pub fn from_materials(materials: &[OrderItemMaterialDto]) -> Vec<OrderMaterialShortageDto> {
+        let mut shortages: Vec<OrderMaterialShortageDto> = vec![];
+        for dto in materials.iter() {
+            let material = &dto.material;
+            let existing = shortages.iter_mut().find(|shortage| {
+                shortage.material_id == material.material_id
+                    && shortage.name == material.name
+                    && shortage.color == material.color
+            });
+            match existing {
+                Some(shortage) => {
+                    shortage.total += material.total;
+                    shortage.issued += dto.issued;
+                    shortage.reserved += dto.reserved;
+                    shortage.debt += dto.debt;
+                }
+                None => shortages.push(OrderMaterialShortageDto {
+                    material_id: material.material_id,
+                    name: material.name.clone(),
+                    color: material.color.clone(),
+                    unit: material.unit.clone(),
+                    total: material.total,
+                    issued: dto.issued,
+                    reserved: dto.reserved,
+                    debt: dto.debt,
+                }),
+            }
+        }
+
+        shortages
+    }
+}
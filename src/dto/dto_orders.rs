@@ -2,8 +2,13 @@ use crate::dto::dto_goods::GoodsImagesAndPackage;
 use crate::dto::dto_progress::OneProgress;
 use crate::model::customer::CustomerModel;
 use crate::model::order::OrderModel;
+use crate::model::progress::OrderQuantityStats;
+use crate::model::shipment::{OrderItemShipping, Shipment};
+use crate::model::workflow::StepQuantity;
+use crate::{ERPError, ERPResult};
 use chrono::NaiveDate;
-use sqlx::FromRow;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, Pool, Postgres};
 use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Serialize, FromRow)]
@@ -18,6 +23,8 @@ pub struct OrderDto {
     pub is_special: bool,
     pub special_customer: String,
     pub build_by: i32,
+    pub workflow_template_id: i32,
+    pub currency: String,
 }
 
 impl OrderDto {
@@ -33,6 +40,8 @@ impl OrderDto {
             is_special: order.is_special,
             special_customer: order.special_customer,
             build_by: order.build_by,
+            workflow_template_id: order.workflow_template_id,
+            currency: order.currency,
         }
     }
 
@@ -48,6 +57,8 @@ impl OrderDto {
             is_special: order.is_special,
             special_customer: order.special_customer,
             build_by: order.build_by,
+            workflow_template_id: order.workflow_template_id,
+            currency: order.currency,
         }
     }
 }
@@ -80,6 +91,41 @@ impl StepIndexCountUF {
     }
 }
 
+/// 订单详情: 带上发货情况
+#[derive(Debug, Serialize)]
+pub struct OrderDetailDto {
+    #[serde(flatten)]
+    pub order: OrderDto,
+    pub total_quantity: i32,
+    pub shipped_quantity: i32,              // 已发货
+    pub outstanding_quantity: i32,          // 未发货
+    pub total_amount: Decimal,              // 订单金额(订单币种)
+    pub total_amount_base: Option<Decimal>, // 按下单日的汇率换算成基础货币, 没有汇率时为空
+    pub items: Vec<OrderItemShipping>,
+    pub shipments: Vec<Shipment>,
+}
+
+impl OrderDetailDto {
+    pub fn from(
+        order: OrderDto,
+        total_amount: Decimal,
+        total_amount_base: Option<Decimal>,
+        items: Vec<OrderItemShipping>,
+        shipments: Vec<Shipment>,
+    ) -> OrderDetailDto {
+        Self {
+            order,
+            total_amount,
+            total_amount_base,
+            total_quantity: items.iter().map(|item| item.count).sum(),
+            shipped_quantity: items.iter().map(|item| item.shipped).sum(),
+            outstanding_quantity: items.iter().map(|item| item.outstanding).sum(),
+            items,
+            shipments,
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct OrderWithStepsDto {
     pub id: i32,
@@ -92,10 +138,16 @@ pub struct OrderWithStepsDto {
     pub is_special: bool,
     pub build_by: i32,
     pub special_customer: String,
+    pub workflow_template_id: i32,
 
     pub done_count: i32,
     pub exception_count: i32,
     pub total_count: i32,
+    pub total_quantity: i32,
+    pub done_quantity: i32,
+    pub defective_quantity: i32,
+    pub shipped_quantity: i32,     // 已发货
+    pub outstanding_quantity: i32, // 未发货
     pub steps: Vec<StepIndexCountUF>,
 }
 
@@ -103,7 +155,7 @@ impl OrderWithStepsDto {
     pub fn from_order_dto_and_steps(
         order: OrderDto,
         steps: StepIndexCount,
-        done_count: i32,
+        quantity_stats: OrderQuantityStats,
         exception_count: i32,
         total_count: i32,
     ) -> OrderWithStepsDto {
@@ -118,9 +170,19 @@ impl OrderWithStepsDto {
             is_special: order.is_special,
             build_by: order.build_by,
             special_customer: order.special_customer,
-            done_count,
+            workflow_template_id: order.workflow_template_id,
+            done_count: quantity_stats.done_count,
             exception_count,
             total_count,
+            total_quantity: quantity_stats.total_quantity,
+            done_quantity: quantity_stats.done_quantity,
+            defective_quantity: quantity_stats.defective_quantity,
+            shipped_quantity: quantity_stats.shipped_quantity,
+            // 次品不会发货
+            outstanding_quantity: (quantity_stats.total_quantity
+                - quantity_stats.defective_quantity
+                - quantity_stats.shipped_quantity)
+                .max(0),
             steps: StepIndexCountUF::from_step_index_count(steps),
         }
     }
@@ -137,12 +199,39 @@ pub struct OrderGoodsItemDto {
     pub color: String,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes_images: Vec<String>,
     pub notes: String,
 }
 
+impl OrderGoodsItemDto {
+    pub async fn get_by_order_goods_ids(
+        db: &Pool<Postgres>,
+        order_goods_ids: &[i32],
+    ) -> ERPResult<Vec<OrderGoodsItemDto>> {
+        let items = sqlx::query_as!(
+            OrderGoodsItemDto,
+            r#"
+            select
+                oi.id, oi.order_id, oi.sku_id, s.color, s.sku_no, oi.count, oi.unit,
+                oi.unit_price, oi.total_price, oi.notes, og.goods_id, oi.order_goods_id,
+                oi.notes_images
+            from order_items oi, skus s, order_goods og
+            where oi.sku_id = s.id and oi.order_goods_id = og.id
+                and oi.order_goods_id = any($1)
+            order by id;
+            "#,
+            order_goods_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(items)
+    }
+}
+
 #[derive(Debug, Serialize, Clone, FromRow)]
 pub struct OrderPlainItemWithoutImagesPackageDto {
     pub id: i32,
@@ -160,8 +249,8 @@ pub struct OrderPlainItemWithoutImagesPackageDto {
     pub color: String,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes_images: Vec<String>,
     pub notes: String,
 }
@@ -183,8 +272,8 @@ pub struct OrderPlainItemDto {
     pub color: String,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes_images: Vec<String>,
     pub notes: String,
 }
@@ -234,8 +323,8 @@ pub struct OrderPlainItemWithCurrentStepDto {
     pub color: String,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes: String,
 
     pub is_next_action: bool,
@@ -289,13 +378,15 @@ pub struct OrderGoodsItemWithStepsDto {
     pub color: String,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes_images: Vec<String>,
     pub notes: String,
 
     pub is_next_action: bool,
     pub current_step: i32,
+    pub remaining_steps: Vec<i32>, // 还没走完的流程(含当前流程)
+    pub step_quantities: Vec<StepQuantity>,
     pub steps: Vec<OneProgress>,
 }
 
@@ -305,6 +396,8 @@ impl OrderGoodsItemWithStepsDto {
         steps: Vec<OneProgress>,
         is_next_action: bool,
         current_step: i32,
+        remaining_steps: Vec<i32>,
+        step_quantities: Vec<StepQuantity>,
     ) -> OrderGoodsItemWithStepsDto {
         Self {
             id: ogid.id,
@@ -322,6 +415,8 @@ impl OrderGoodsItemWithStepsDto {
             notes: ogid.notes,
             is_next_action,
             current_step,
+            remaining_steps,
+            step_quantities,
             steps,
         }
     }
@@ -341,6 +436,37 @@ pub struct OrderGoodsDto {
     pub package_card_des: String,
 }
 
+impl OrderGoodsDto {
+    /// limit为None时取全部
+    pub async fn get_by_order_id(
+        db: &Pool<Postgres>,
+        order_id: i32,
+        offset: i64,
+        limit: Option<i64>,
+    ) -> ERPResult<Vec<OrderGoodsDto>> {
+        let order_goods = sqlx::query_as!(
+            OrderGoodsDto,
+            r#"
+            select
+                og.id as id, og.order_id as order_id, og.goods_id as goods_id, g.goods_no as goods_no,
+                g.name as name, og.images as images, og.image_des as image_des,
+                og.package_card as package_card, og.package_card_des as package_card_des
+            from order_goods og, goods g
+            where og.goods_id = g.id and og.order_id = $1
+            order by og.id offset $2 limit $3
+            "#,
+            order_id,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(order_goods)
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct OrderGoodsWithStepsWithItemStepDto {
     pub id: i32,
@@ -396,11 +522,11 @@ struct OrderItemDto {
     order_id: i32, // -- 订单ID
     sku_id: i32,   // integer not null, -- 商品ID
     // order_goods_id: i32,   // integer not null,
-    package_card: String,     // text,    -- 包装卡片    （存在大问题）
-    package_card_des: String, //  -- 包装卡片说明 （存在大问题）
-    count: i32,               //   integer not null,  - - 数量
-    unit: String,             //  text,- - 单位
-    unit_price: Option<i32>,  //  integer, - - 单价
-    total_price: Option<i32>, //   integer,  - - 总价 / 金额
-    notes: String,            //    text - - 备注,
+    package_card: String,         // text,    -- 包装卡片    （存在大问题）
+    package_card_des: String,     //  -- 包装卡片说明 （存在大问题）
+    count: i32,                   //   integer not null,  - - 数量
+    unit: String,                 //  text,- - 单位
+    unit_price: Option<Decimal>,  //  numeric, - - 单价
+    total_price: Option<Decimal>, //   numeric,  - - 总价 / 金额
+    notes: String,                //    text - - 备注,
 }
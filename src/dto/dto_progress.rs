@@ -13,4 +13,7 @@ pub struct OneProgress {
     pub done: bool,
     pub notes: String,
     pub dt: DateTime<Utc>,
+    pub skipped: bool,
+    pub quantity: i32,
+    pub defective: i32,
 }
@@ -1,12 +1,5 @@
 use crate::dto::dto_goods::{GoodsDto, SKUModelDto};
 
-#[derive(Serialize)]
-pub struct ReturnOrderStat {
-    pub sku: SKUModelDto,
-    pub count: i32,
-    pub sum: i32,
-}
-
 #[derive(Serialize)]
 pub struct ReturnOrderItemStat {
     pub sku: SKUModelDto,
@@ -0,0 +1,33 @@
+use crate::model::workflow::{WorkflowStepModel, WorkflowStepOptionModel};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WorkflowStepDto {
+    pub id: i32,
+    pub step: i32,
+    pub name: String,
+    pub department_id: i32,
+    pub department: String,
+    pub is_shipping: bool,
+    pub labor_rate: Decimal,
+    pub options: Vec<WorkflowStepOptionModel>,
+}
+
+impl WorkflowStepDto {
+    pub fn from(
+        step: WorkflowStepModel,
+        department: String,
+        options: Vec<WorkflowStepOptionModel>,
+    ) -> WorkflowStepDto {
+        Self {
+            id: step.id,
+            step: step.step,
+            name: step.name,
+            department_id: step.department_id,
+            department,
+            is_shipping: step.is_shipping,
+            labor_rate: step.labor_rate,
+            options,
+        }
+    }
+}
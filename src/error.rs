@@ -1,3 +1,4 @@
+use crate::model::excel::ExcelIssueReport;
 use axum::extract::rejection::{JsonRejection, QueryRejection};
 use axum::{
     http::StatusCode,
@@ -23,6 +24,9 @@ pub enum ERPError {
     #[error("账号不存在")]
     AccountNotFound,
 
+    #[error("账号已停用")]
+    AccountDisabled,
+
     #[error("无权限: {}", .0)]
     NoPermission(String),
 
@@ -44,6 +48,9 @@ pub enum ERPError {
     #[error("Excel数据有误: {:?}", .0)]
     ExcelError(String),
 
+    #[error("Excel数据有误: 共{}处问题", .0.issues.len())]
+    ExcelIssues(ExcelIssueReport),
+
     #[error("json参数错误: {:?}", .0)]
     JsonExtractorRejection(#[from] JsonRejection),
 
@@ -70,18 +77,19 @@ impl IntoResponse for ERPError {
         let msg = self.to_string();
 
         let code = match self {
-            ERPError::NotAuthorized => 401,
+            ERPError::NotAuthorized | ERPError::AccountDisabled => 401,
             _ => 1,
         };
 
-        (
-            StatusCode::OK,
-            serde_json::json!({
-                "code": code, // failed code is always 1
-                "msg": msg
-            })
-            .to_string(),
-        )
-            .into_response()
+        let mut body = serde_json::json!({
+            "code": code, // failed code is always 1
+            "msg": msg
+        });
+        // excel的问题列表一起返回, 方便一次改完
+        if let ERPError::ExcelIssues(report) = &self {
+            body["data"] = serde_json::json!(report);
+        }
+
+        (StatusCode::OK, body.to_string()).into_response()
     }
 }
@@ -0,0 +1,221 @@
+use crate::model::order::{
+    ExcelOrderDiff, ExcelOrderGoodsWithItems, ExcelOrderItemDiff, ExcelOrderItemValues,
+};
+use crate::{ERPError, ERPResult};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// 订单里已有的产品(按 goods_no + 电镀 + 颜色 来对应excel里的行)
+#[derive(Debug, Clone)]
+pub struct ExistingOrderItem {
+    pub order_item_id: i32,
+    pub goods_no: String,
+    pub plating: String,
+    pub color: String,
+    pub values: ExcelOrderItemValues,
+}
+
+pub async fn get_existing_order_items(
+    db: &mut PgConnection,
+    order_id: i32,
+) -> ERPResult<Vec<ExistingOrderItem>> {
+    let items = sqlx::query!(
+        r#"
+        select oi.id, g.goods_no, s.plating, s.color, oi.count, oi.unit, oi.unit_price,
+            oi.total_price, oi.notes
+        from order_items oi, order_goods og, goods g, skus s
+        where oi.order_goods_id = og.id and og.goods_id = g.id and oi.sku_id = s.id
+            and oi.order_id = $1
+        order by oi.id;
+        "#,
+        order_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|r| ExistingOrderItem {
+        order_item_id: r.id,
+        goods_no: r.goods_no,
+        plating: r.plating,
+        color: r.color,
+        values: ExcelOrderItemValues {
+            count: r.count,
+            unit: r.unit.unwrap_or_default(),
+            unit_price: r.unit_price.unwrap_or_default(),
+            total_price: r.total_price.unwrap_or_default(),
+            notes: r.notes,
+        },
+    })
+    .collect();
+
+    Ok(items)
+}
+
+/// 对比excel和订单里已有的数据, 得出新增/修改/删除的商品和产品
+pub fn diff_order_items(
+    existing: &[ExistingOrderItem],
+    excel: &[ExcelOrderGoodsWithItems],
+) -> ExcelOrderDiff {
+    let mut diff = ExcelOrderDiff::default();
+
+    let mut existing_goods_nos: Vec<&str> = vec![];
+    existing.iter().for_each(|item| {
+        if !existing_goods_nos.contains(&item.goods_no.as_str()) {
+            existing_goods_nos.push(&item.goods_no);
+        }
+    });
+    let excel_goods_nos = excel
+        .iter()
+        .map(|goods| goods.goods.goods_no.as_str())
+        .collect::<Vec<&str>>();
+
+    diff.new_goods = excel_goods_nos
+        .iter()
+        .filter(|goods_no| !existing_goods_nos.contains(goods_no))
+        .map(|goods_no| goods_no.to_string())
+        .collect();
+    diff.removed_goods = existing_goods_nos
+        .iter()
+        .filter(|goods_no| !excel_goods_nos.contains(goods_no))
+        .map(|goods_no| goods_no.to_string())
+        .collect();
+
+    let mut key_to_existing = existing
+        .iter()
+        .map(|item| {
+            (
+                (
+                    item.goods_no.as_str(),
+                    item.plating.as_str(),
+                    item.color.as_str(),
+                ),
+                item,
+            )
+        })
+        .collect::<HashMap<(&str, &str, &str), &ExistingOrderItem>>();
+
+    for goods in excel.iter() {
+        for item in goods.items.iter() {
+            let key = (
+                goods.goods.goods_no.as_str(),
+                item.plating.as_str(),
+                item.color.as_str(),
+            );
+            let values = ExcelOrderItemValues::from(item);
+            match key_to_existing.remove(&key) {
+                None => diff.new_items.push(ExcelOrderItemDiff {
+                    order_item_id: 0,
+                    goods_no: key.0.to_string(),
+                    plating: key.1.to_string(),
+                    color: key.2.to_string(),
+                    old: None,
+                    new: Some(values),
+                }),
+                Some(existing_item) if existing_item.values != values => {
+                    diff.changed_items.push(ExcelOrderItemDiff {
+                        order_item_id: existing_item.order_item_id,
+                        goods_no: key.0.to_string(),
+                        plating: key.1.to_string(),
+                        color: key.2.to_string(),
+                        old: Some(existing_item.values.clone()),
+                        new: Some(values),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // excel里没有对应行的, 就是要删除的
+    diff.removed_items = existing
+        .iter()
+        .filter(|item| {
+            key_to_existing.contains_key(&(
+                item.goods_no.as_str(),
+                item.plating.as_str(),
+                item.color.as_str(),
+            ))
+        })
+        .map(|item| ExcelOrderItemDiff {
+            order_item_id: item.order_item_id,
+            goods_no: item.goods_no.clone(),
+            plating: item.plating.clone(),
+            color: item.color.clone(),
+            old: Some(item.values.clone()),
+            new: None,
+        })
+        .collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::excel_order_diff::{diff_order_items, ExistingOrderItem};
+    use crate::model::order::{ExcelOrderGoodsWithItems, ExcelOrderItemValues, OrderItemExcel};
+    use rust_decimal::Decimal;
+
+    fn existing(order_item_id: i32, goods_no: &str, color: &str, count: i32) -> ExistingOrderItem {
+        ExistingOrderItem {
+            order_item_id,
+            goods_no: goods_no.to_string(),
+            plating: "".to_string(),
+            color: color.to_string(),
+            values: ExcelOrderItemValues {
+                count,
+                unit: "".to_string(),
+                unit_price: Decimal::ZERO,
+                total_price: Decimal::ZERO,
+                notes: "".to_string(),
+            },
+        }
+    }
+
+    fn excel_item(goods_no: &str, color: &str, count: i32) -> OrderItemExcel {
+        OrderItemExcel {
+            goods_no: goods_no.to_string(),
+            color: color.to_string(),
+            count,
+            ..Default::default()
+        }
+    }
+
+    fn excel_goods(items: Vec<OrderItemExcel>) -> ExcelOrderGoodsWithItems {
+        ExcelOrderGoodsWithItems {
+            goods: OrderItemExcel::pick_up_excel_goods(&items),
+            items,
+        }
+    }
+
+    #[test]
+    fn test_diff_order_items() {
+        let existing_items = vec![
+            existing(1, "A", "红", 10),
+            existing(2, "A", "蓝", 10),
+            existing(3, "B", "红", 5),
+        ];
+        let excel = vec![
+            excel_goods(vec![excel_item("A", "红", 10), excel_item("A", "蓝", 12)]),
+            excel_goods(vec![excel_item("C", "黑", 3)]),
+        ];
+
+        let diff = diff_order_items(&existing_items, &excel);
+        assert_eq!(diff.new_goods, vec!["C".to_string()]);
+        assert_eq!(diff.removed_goods, vec!["B".to_string()]);
+        assert_eq!(diff.new_items.len(), 1);
+        assert_eq!(diff.new_items[0].color, "黑");
+        assert_eq!(diff.changed_items.len(), 1);
+        assert_eq!(diff.changed_items[0].order_item_id, 2);
+        assert_eq!(diff.changed_items[0].new.as_ref().unwrap().count, 12);
+        assert_eq!(diff.removed_items.len(), 1);
+        assert_eq!(diff.removed_items[0].order_item_id, 3);
+
+        // 同样的数据再导一次, 没有差异
+        let same = vec![excel_goods(vec![
+            excel_item("A", "红", 10),
+            excel_item("A", "蓝", 10),
+        ])];
+        assert!(diff_order_items(&existing_items[..2], &same).is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{ERPError, ERPResult};
+use calamine::{open_workbook, Data, Range, Reader, Xls};
+use umya_spreadsheet::{reader, Spreadsheet, Worksheet};
+
+/// 支持导入的文件格式(按后缀判断)
+pub const ORDER_FILE_EXTENSIONS: [&str; 4] = ["xlsx", "xls", "csv", "tsv"];
+
+pub fn order_file_extension(path: &str) -> String {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// 读取订单文件, xls/csv/tsv 都转成 umya 的 Spreadsheet, 后面的解析都一样;
+/// 第二个返回值是转换过程中的提醒(比如xls里的图片对应不到行)
+pub fn read_order_book(path: &str) -> ERPResult<(Spreadsheet, Vec<String>)> {
+    match order_file_extension(path).as_str() {
+        "xlsx" => {
+            let book = reader::xlsx::read(std::path::Path::new(path))
+                .map_err(|_| ERPError::Failed("读xlsx文件失败".to_string()))?;
+            Ok((book, vec![]))
+        }
+        "xls" => read_xls(path),
+        "csv" => Ok((read_delimited(path, b',')?, vec![])),
+        "tsv" => Ok((read_delimited(path, b'\t')?, vec![])),
+        ext => Err(ERPError::Failed(format!(
+            "不支持的文件格式: {}, 只支持{}",
+            ext,
+            ORDER_FILE_EXTENSIONS.join("/")
+        ))),
+    }
+}
+
+fn read_xls(path: &str) -> ERPResult<(Spreadsheet, Vec<String>)> {
+    let mut workbook: Xls<_> =
+        open_workbook(path).map_err(|_| ERPError::Failed("读xls文件失败".to_string()))?;
+
+    let mut book = umya_spreadsheet::new_file_empty_worksheet();
+    for name in workbook.sheet_names() {
+        let range = workbook
+            .worksheet_range(&name)
+            .map_err(|_| ERPError::Failed(format!("读xls的sheet({})失败", name)))?;
+        let sheet = book
+            .new_sheet(&name)
+            .map_err(|_| ERPError::Failed(format!("读xls的sheet({})失败", name)))?;
+        copy_range_to_sheet(&range, sheet);
+    }
+    if book.get_sheet_collection().is_empty() {
+        return Err(ERPError::Failed("xls文件里没有sheet".to_string()));
+    }
+
+    // xls里的图片没有位置信息, 对应不到具体的行
+    let mut warnings = vec![];
+    let picture_count = workbook.pictures().map(|p| p.len()).unwrap_or(0);
+    if picture_count > 0 {
+        warnings.push(format!(
+            "xls文件里有{}张图片, 无法对应到具体的行, 没有导入; 需要图片请另存为xlsx再导入",
+            picture_count
+        ));
+    }
+
+    Ok((book, warnings))
+}
+
+/// calamine的Range是从第一个有数据的单元格开始的, 这里按原来的行列写回去
+fn copy_range_to_sheet(range: &Range<Data>, sheet: &mut Worksheet) {
+    let Some((start_row, start_col)) = range.start() else {
+        return;
+    };
+    for (row, col, data) in range.cells() {
+        let coordinate = (start_col + col as u32 + 1, start_row + row as u32 + 1);
+        match data {
+            Data::Empty => {}
+            Data::Int(number) => {
+                sheet
+                    .get_cell_mut(coordinate)
+                    .set_value_number(*number as f64);
+            }
+            Data::Float(number) => {
+                sheet.get_cell_mut(coordinate).set_value_number(*number);
+            }
+            Data::DateTime(datetime) => {
+                let value = match datetime.as_datetime() {
+                    Some(datetime) => datetime.format("%Y-%m-%d").to_string(),
+                    None => datetime.to_string(),
+                };
+                sheet.get_cell_mut(coordinate).set_value(value);
+            }
+            _ => {
+                sheet.get_cell_mut(coordinate).set_value(data.to_string());
+            }
+        }
+    }
+}
+
+/// 客户系统导出的csv经常是GBK编码的, 不是UTF-8时按GB18030解码
+fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::GB18030.decode(bytes).0.into_owned(),
+    }
+}
+
+fn read_delimited(path: &str, delimiter: u8) -> ERPResult<Spreadsheet> {
+    let bytes = std::fs::read(path).map_err(|_| ERPError::Failed("读csv文件失败".to_string()))?;
+    parse_delimited(&decode_text(&bytes), delimiter)
+}
+
+fn parse_delimited(text: &str, delimiter: u8) -> ERPResult<Spreadsheet> {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book.get_sheet_mut(&0).unwrap();
+
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    for (row, record) in csv_reader.records().enumerate() {
+        let record =
+            record.map_err(|_| ERPError::Failed(format!("csv文件第{}行格式不对", row + 1)))?;
+        for (col, value) in record.iter().enumerate() {
+            if value.trim().is_empty() {
+                continue;
+            }
+            sheet
+                .get_cell_mut((col as u32 + 1, row as u32 + 1))
+                .set_value(value);
+        }
+    }
+
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::excel_order_file::{copy_range_to_sheet, decode_text, parse_delimited};
+    use calamine::{Data, Range};
+
+    #[test]
+    fn test_parse_delimited() -> anyhow::Result<()> {
+        let text = "客户:C01,,\n序号,货号,数量\n1,\"A01, 大\",10\n";
+        let book = parse_delimited(text, b',')?;
+        let sheet = book.get_sheet(&0).unwrap();
+        assert_eq!(sheet.get_value((1, 1)), "客户:C01");
+        assert_eq!(sheet.get_value((2, 3)), "A01, 大");
+        assert_eq!(sheet.get_value((3, 3)), "10");
+        assert!(sheet.get_cell((2, 1)).is_none());
+
+        let book = parse_delimited("序号\t货号\n1\tB01\n", b'\t')?;
+        assert_eq!(book.get_sheet(&0).unwrap().get_value((2, 2)), "B01");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let (gbk, _, _) = encoding_rs::GB18030.encode("货号,数量");
+        assert_eq!(decode_text(&gbk), "货号,数量");
+        assert_eq!(decode_text("\u{feff}货号".as_bytes()), "货号");
+    }
+
+    #[test]
+    fn test_copy_range_to_sheet() {
+        let mut range = Range::new((2, 1), (3, 2));
+        range.set_value((2, 1), Data::String("货号".to_string()));
+        range.set_value((3, 1), Data::String("A01".to_string()));
+        range.set_value((3, 2), Data::Float(10.0));
+
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        copy_range_to_sheet(&range, sheet);
+        assert_eq!(sheet.get_value((2, 3)), "货号");
+        assert_eq!(sheet.get_value((2, 4)), "A01");
+        assert_eq!(sheet.get_value((3, 4)), "10");
+    }
+}
@@ -21,6 +21,7 @@ pub fn parse_order_info(sheet: &Worksheet) -> ERPResult<OrderInfo> {
                 continue;
             }
 
+            // 只认"客户"; "供应商"是客户那边给我们编的号, 不是客户编号, 也和我们的供应商(suppliers)无关
             if cell_value.contains("客户") {
                 let mut customer_no = cell_value.strip_prefix("客户:").unwrap_or("");
                 if customer_no.is_empty() {
@@ -29,14 +30,6 @@ pub fn parse_order_info(sheet: &Worksheet) -> ERPResult<OrderInfo> {
                 order_info.customer_no = remove_whitespace_str(&customer_no.to_uppercase());
             }
 
-            if cell_value.contains("供应商") {
-                let mut customer_no = cell_value.strip_prefix("供应商:").unwrap_or("");
-                if customer_no.is_empty() {
-                    customer_no = cell_value.strip_prefix("供应商：").unwrap_or("");
-                }
-                order_info.customer_no = remove_whitespace_str(&customer_no.to_uppercase());
-            }
-
             if cell_value.contains("单号") {
                 let mut order_no = cell_value.strip_prefix("单号:").unwrap_or("");
                 if order_no.is_empty() {
@@ -100,4 +93,18 @@ mod tests {
         tracing::info!("order_info: {:#?}", order_info);
         Ok(())
     }
+
+    #[test]
+    fn test_supplier_no_is_not_customer_no() -> anyhow::Result<()> {
+        let mut book = new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        sheet.get_cell_mut("A1").set_value("客户: l1001");
+        sheet.get_cell_mut("B1").set_value("供应商: S88");
+        assert_eq!(parse_order_info(sheet)?.customer_no, "L1001");
+
+        // 没有"客户"时客户编号是空的, 不拿供应商顶上
+        sheet.get_cell_mut("A1").set_value("单号: 123");
+        assert_eq!(parse_order_info(sheet)?.customer_no, "");
+        Ok(())
+    }
 }
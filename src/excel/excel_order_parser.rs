@@ -1,17 +1,21 @@
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::excel::excel_order_diff::{diff_order_items, get_existing_order_items};
+use crate::excel::excel_order_file::read_order_book;
 use crate::excel::excel_order_info::parse_order_info;
-use crate::excel::parse_order_template_1::parse_order_excel_t1;
-use crate::excel::parse_order_template_2::parse_order_excel_t2;
-use crate::excel::parse_order_template_3::parse_order_excel_t3;
-use crate::excel::parse_order_template_4::parse_order_excel_t4;
+use crate::excel::parse_order_template::{detect_template, parse_order_excel, template_mapping};
 use crate::excel::process_order_excel_goods::{
-    convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items,
-    process_order_excel_with_goods_no_and_sku_color,
+    apply_order_items_diff, convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items,
+    merge_excel_order_goods, process_order_excel_with_goods_no_and_sku_color,
 };
-use crate::model::excel::CustomerExcelTemplateModel;
-use crate::model::order::{ExcelOrderV2, OrderInfo, OrderModel};
+use crate::model::excel::{
+    CustomerExcelTemplateModel, ExcelHeaderSynonymModel, ExcelIssue, ExcelIssueReport,
+    ExcelMapping, ExcelSheetResult, ExcelTemplate,
+};
+use crate::model::material::OrderItemMaterialModel;
+use crate::model::order::{ExcelOrderDiff, ExcelOrderPreview, ExcelOrderV2, OrderInfo, OrderModel};
 use crate::{ERPError, ERPResult};
 use sqlx::{Pool, Postgres};
-use umya_spreadsheet::reader;
+use umya_spreadsheet::{writer, Spreadsheet, Worksheet};
 
 #[derive(Debug)]
 pub struct ExcelOrderParser<'a> {
@@ -25,31 +29,221 @@ impl<'a> ExcelOrderParser<'a> {
         Self { path, db, build_by }
     }
 
-    pub async fn parse(&self) -> ERPResult<ExcelOrderV2> {
+    /// 只解析excel(要查客户用哪个模版), 不写数据库; 支持xlsx/xls/csv/tsv;
+    /// 每个有订单表头的sheet是一个订单, 没有表头(或单号和上一个sheet相同)的sheet接在上一个订单后面,
+    /// 续页上识别不出表头也读不到产品时跳过;
+    /// 有问题时返回所有sheet的问题, 以及一份标红了问题单元格的excel
+    async fn read(&self) -> ERPResult<Vec<ExcelOrderV2>> {
+        let (mut book, warnings) = read_order_book(self.path)?;
+        let synonyms = ExcelHeaderSynonymModel::get_all(&self.db).await?;
+
+        let mut issues = vec![];
+        let mut orders: Vec<ExcelOrderV2> = vec![];
+        for sheet in book.get_sheet_collection().iter() {
+            // 空的sheet(比如默认的Sheet2, Sheet3)跳过
+            if sheet.get_highest_column_and_row() == (0, 0) {
+                continue;
+            }
+            self.read_sheet(sheet, &synonyms, &mut orders, &mut issues)
+                .await?;
+        }
+        if orders.is_empty() {
+            return Err(ERPError::Failed("excel里没有读到订单".to_string()));
+        }
+        // xls转换时的提醒, 放在第一个sheet上
+        orders[0].sheets[0].mapping.warnings.extend(warnings);
+
+        if !issues.is_empty() {
+            let annotated_file = annotate_excel_issues(&mut book, self.path, &issues);
+            return Err(ERPError::ExcelIssues(ExcelIssueReport {
+                issues,
+                annotated_file,
+            }));
+        }
+
+        Ok(orders)
+    }
+
+    async fn read_sheet(
+        &self,
+        sheet: &Worksheet,
+        synonyms: &[ExcelHeaderSynonymModel],
+        orders: &mut Vec<ExcelOrderV2>,
+        issues: &mut Vec<ExcelIssue>,
+    ) -> ERPResult<()> {
+        let sheet_name = sheet.get_name().to_string();
+
         // parse order_info
-        let path = std::path::Path::new(self.path);
-        let book = reader::xlsx::read(path)
-            .map_err(|_| ERPError::Failed("读xlsx文件失败,不支持xls格式".to_string()))?;
-        let sheet = book.get_sheet(&0).unwrap();
-        let order_info = parse_order_info(sheet)?;
-
-        tracing::info!("order_info: {:?}", order_info);
-        if order_info.customer_no.is_empty() {
-            return Err(ERPError::Failed(
-                "客户编号未找到，请检查一下excel表格".to_string(),
-            ));
+        let mut order_info = parse_order_info(sheet)?;
+        tracing::info!("sheet({}) order_info: {:?}", sheet_name, order_info);
+
+        let continuation = match orders.last() {
+            Some(last) => {
+                order_info.order_no.is_empty() || order_info.order_no == last.info.order_no
+            }
+            None => false,
+        };
+        if continuation {
+            order_info = orders.last().unwrap().info.clone();
+        } else {
+            if order_info.order_no.is_empty() {
+                return Err(ERPError::Failed(format!(
+                    "sheet({})的订单编号未找到，请检查一下excel表格",
+                    sheet_name
+                )));
+            }
+            if orders
+                .iter()
+                .any(|order| order.info.order_no == order_info.order_no)
+            {
+                issues.push(ExcelIssue::new(
+                    &sheet_name,
+                    0,
+                    0,
+                    "order_no",
+                    &order_info.order_no,
+                    "单号和前面的sheet重复了",
+                ));
+            }
+            if order_info.customer_no.is_empty() {
+                issues.push(ExcelIssue::new(
+                    &sheet_name,
+                    0,
+                    0,
+                    "customer_no",
+                    "",
+                    "没有找到\"客户:\"单元格, 请在表头上面写上客户编号(供应商不是客户编号)",
+                ));
+            } else {
+                self.check_customer(&sheet_name, &order_info.customer_no, issues)
+                    .await?;
+            }
         }
-        if order_info.order_no.is_empty() {
-            return Err(ERPError::Failed(
-                "订单编号未找到，请检查一下excel表格".to_string(),
+
+        // 续页识别不出表头时, 沿用上一个sheet的模版
+        let previous_template = orders
+            .last()
+            .filter(|_| continuation)
+            .and_then(|order| order.sheets.last())
+            .map(|sheet| sheet.mapping.template.clone());
+        let mapping = self
+            .sheet_mapping(sheet, &order_info.customer_no, synonyms, previous_template)
+            .await?;
+        tracing::info!("sheet({}) excel mapping: {:?}", sheet_name, mapping);
+        let mut sheet_issues = vec![];
+        let order_items = parse_order_excel(
+            sheet,
+            &order_info.order_no,
+            &mapping.template,
+            &mut sheet_issues,
+        )?;
+
+        // 续页上既没有识别出表头, 也没有读到有数量的产品(比如备注/汇总sheet), 不合并, 在结果里标出来
+        if continuation
+            && !mapping.detected
+            && !order_items.values().flatten().any(|item| item.count > 0)
+        {
+            tracing::info!("sheet({}) skipped: no header or items", sheet_name);
+            let mut mapping = mapping;
+            mapping
+                .warnings
+                .push("没有识别出表头, 也没有读到产品, 这个sheet没有导入".to_string());
+            orders.last_mut().unwrap().sheets.push(ExcelSheetResult {
+                sheet: sheet_name,
+                order_no: order_info.order_no.clone(),
+                continuation,
+                item_count: 0,
+                exists: false,
+                skipped: true,
+                mapping,
+            });
+            return Ok(());
+        }
+        issues.extend(sheet_issues);
+
+        tracing::info!("order_items: {:?}", order_items);
+        let no_goods_no = !mapping.template.has_field("goods_no");
+        let order_goods_item =
+            convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items(
+                order_items,
+                no_goods_no,
+                &sheet_name,
+                issues,
+            );
+
+        let sheet_result = ExcelSheetResult {
+            sheet: sheet_name,
+            order_no: order_info.order_no.clone(),
+            continuation,
+            item_count: order_goods_item.iter().map(|goods| goods.items.len()).sum(),
+            exists: false,
+            skipped: false,
+            mapping,
+        };
+        if continuation {
+            let order = orders.last_mut().unwrap();
+            merge_excel_order_goods(&mut order.items, order_goods_item);
+            order.sheets.push(sheet_result);
+        } else {
+            orders.push(ExcelOrderV2 {
+                info: order_info,
+                items: order_goods_item,
+                exists: false,
+                sheets: vec![sheet_result],
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn check_customer(
+        &self,
+        sheet_name: &str,
+        customer_no: &str,
+        issues: &mut Vec<ExcelIssue>,
+    ) -> ERPResult<()> {
+        if sqlx::query!(
+            "select id from customers where customer_no = $1",
+            customer_no
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_none()
+        {
+            issues.push(ExcelIssue::new(
+                sheet_name,
+                0,
+                0,
+                "customer_no",
+                customer_no,
+                "客户不存在, 请先添加客户",
             ));
         }
 
+        Ok(())
+    }
+
+    /// 先按表头自动识别列, 识别不出来再用上一个sheet的模版(续页), 最后用客户配置的模版
+    async fn sheet_mapping(
+        &self,
+        sheet: &Worksheet,
+        customer_no: &str,
+        synonyms: &[ExcelHeaderSynonymModel],
+        previous_template: Option<ExcelTemplate>,
+    ) -> ERPResult<ExcelMapping> {
+        if let Some(mapping) = detect_template(sheet, synonyms) {
+            return Ok(mapping);
+        }
+        if let Some(template) = previous_template {
+            return template_mapping(sheet, template);
+        }
+
         // find which template is for this customer.
         let customer_excel_template_model = sqlx::query_as!(
             CustomerExcelTemplateModel,
             "select * from customer_excel_template where customer_no=$1",
-            &order_info.customer_no
+            customer_no
         )
         .fetch_optional(&self.db)
         .await
@@ -57,80 +251,130 @@ impl<'a> ExcelOrderParser<'a> {
 
         if customer_excel_template_model.is_none() {
             return Err(ERPError::Failed(format!(
-                "请先配置{}需要使用什么模版",
-                &order_info.customer_no
+                "sheet({})没有识别出表头, 请先配置{}需要使用什么模版",
+                sheet.get_name(),
+                customer_no
             )));
         }
 
         let template_id = customer_excel_template_model.unwrap().template_id;
-        let order_items = match template_id {
-            1 => parse_order_excel_t1(sheet, &order_info.order_no)?,
-            2 => parse_order_excel_t2(sheet, &order_info.order_no)?,
-            3 => parse_order_excel_t3(sheet, &order_info.order_no)?,
-            4 => parse_order_excel_t4(sheet, &order_info.order_no)?,
-            _ => parse_order_excel_t1(sheet, &order_info.order_no)?,
-        };
+        let template = ExcelTemplate::get(&self.db, template_id).await?;
+        template_mapping(sheet, template)
+    }
 
-        println!("order_items: {:?}", order_items);
-        let no_goods_no = matches!(template_id, 2);
-        let order_goods_item =
-            convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items(
-                order_items,
-                no_goods_no,
-            )?;
+    /// 预览: 解析excel, 每个订单和已存在的订单做对比, 不写数据库
+    pub async fn preview(&self) -> ERPResult<Vec<ExcelOrderPreview>> {
+        let orders = self.read().await?;
+
+        let mut conn = self.db.acquire().await.map_err(ERPError::DBError)?;
+        let mut previews = vec![];
+        for mut order in orders.into_iter() {
+            let existing_order =
+                OrderModel::get_order_with_order_no(&mut conn, &order.info.order_no).await?;
+            let existing_items = match &existing_order {
+                None => vec![],
+                Some(existing_order) => {
+                    get_existing_order_items(&mut conn, existing_order.id).await?
+                }
+            };
+            let diff = diff_order_items(&existing_items, &order.items);
+
+            order.set_exists(existing_order.is_some());
+            previews.push(ExcelOrderPreview { order, diff });
+        }
 
-        // 判断order_no是否已经存在
-        let mut order_exists = false;
-        let order = OrderModel::get_order_with_order_no(&self.db, &order_info.order_no).await?;
+        Ok(previews)
+    }
+
+    /// 导入订单, 整个文件(所有sheet)在一个事务里, 出错不会留下导了一半的订单;
+    /// 订单已存在时, overwrite为true才会按excel覆盖(新增/修改/删除产品)
+    pub async fn parse(&self, overwrite: bool) -> ERPResult<Vec<ExcelOrderV2>> {
+        let mut orders = self.read().await?;
+
+        let mut tx = self.db.begin().await.map_err(ERPError::DBError)?;
+        for order in orders.iter_mut() {
+            let order_info = &order.info;
 
-        let order_id = {
-            match order {
+            // 判断order_no是否已经存在
+            let existing_order =
+                OrderModel::get_order_with_order_no(&mut tx, &order_info.order_no).await?;
+            let order_exists = existing_order.is_some();
+            let (order_id, diff) = match existing_order {
                 None => {
                     tracing::info!("order#{} not exists, we will save", &order_info.order_no);
-                    OrderInfo::insert_to_orders(&self.db, &order_info, self.build_by).await?
+                    let order_id =
+                        OrderInfo::insert_to_orders(&mut tx, order_info, self.build_by).await?;
+                    (order_id, ExcelOrderDiff::default())
                 }
                 Some(existing_order) => {
+                    if !overwrite {
+                        return Err(ERPError::AlreadyExists(format!(
+                            "订单#{}已经导入",
+                            &order_info.order_no
+                        )));
+                    }
                     tracing::info!("订单#{}已存在,尝试更新数据", &order_info.order_no);
-                    order_exists = true;
+                    let existing_items =
+                        get_existing_order_items(&mut tx, existing_order.id).await?;
                     OrderInfo::update_to_orders(
-                        &self.db,
-                        &order_info,
+                        &mut tx,
+                        order_info,
                         self.build_by,
                         existing_order.id,
                     )
                     .await?;
-                    existing_order.id
+                    (
+                        existing_order.id,
+                        diff_order_items(&existing_items, &order.items),
+                    )
                 }
-            }
-        };
+            };
 
-        match template_id {
-            1 => {
-                process_order_excel_with_goods_no_and_sku_color(
-                    &self.db,
-                    &order_goods_item,
-                    &order_info,
-                    order_id,
-                )
-                .await?
+            process_order_excel_with_goods_no_and_sku_color(
+                &mut tx,
+                &order.items,
+                order_info,
+                order_id,
+            )
+            .await?;
+            if !diff.is_empty() {
+                tracing::info!("订单#{}覆盖导入: {:?}", &order_info.order_no, diff);
+                apply_order_items_diff(&mut tx, order_id, &diff).await?;
             }
-            _ => {
-                process_order_excel_with_goods_no_and_sku_color(
-                    &self.db,
-                    &order_goods_item,
-                    &order_info,
-                    order_id,
-                )
-                .await?
-            }
-        };
+            // 按sku的材料清单生成材料单
+            OrderItemMaterialModel::generate_for_order(&mut tx, order_id).await?;
 
-        let excel_order = ExcelOrderV2 {
-            info: order_info,
-            items: order_goods_item,
-            exists: order_exists,
+            order.set_exists(order_exists);
+        }
+
+        tx.commit().await.map_err(ERPError::DBError)?;
+
+        Ok(orders)
+    }
+}
+
+/// 把有问题的单元格标红, 另存一份, 返回文件的地址
+fn annotate_excel_issues(book: &mut Spreadsheet, path: &str, issues: &[ExcelIssue]) -> String {
+    for issue in issues.iter().filter(|issue| issue.row > 0) {
+        let Some(sheet) = book.get_sheet_by_name_mut(&issue.sheet) else {
+            continue;
         };
+        // 没有具体列的, 把整行的第一格标红
+        let col = issue.col.max(1);
+        sheet
+            .get_style_mut((col, issue.row))
+            .set_background_color("FFFF0000");
+    }
 
-        Ok(excel_order)
+    // xls/csv 也统一存成xlsx
+    let annotated_path = format!(
+        "{}-issues.xlsx",
+        std::path::Path::new(path).with_extension("").display()
+    );
+    if let Err(err) = writer::xlsx::write(book, &annotated_path) {
+        tracing::error!("write {} failed: {:?}", annotated_path, err);
+        return "".to_string();
     }
+
+    annotated_path.replacen(STORAGE_FILE_PATH, STORAGE_URL_PREFIX, 1)
 }
@@ -0,0 +1,159 @@
+use crate::excel::export_order_excel::save_export_book;
+use crate::model::margin::{CustomerMargin, OrderMargin};
+use crate::ERPResult;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use umya_spreadsheet::{Spreadsheet, Worksheet};
+
+const ORDER_MARGIN_HEADERS: [&str; 13] = [
+    "订单号",
+    "客户",
+    "下单日期",
+    "制作方式",
+    "币种",
+    "销售额",
+    "销售额(本币)",
+    "材料成本",
+    "采购成本",
+    "人工成本",
+    "总成本",
+    "毛利",
+    "毛利率(%)",
+];
+
+const CUSTOMER_MARGIN_HEADERS: [&str; 9] = [
+    "客户",
+    "订单数",
+    "销售额(本币)",
+    "材料成本",
+    "采购成本",
+    "人工成本",
+    "总成本",
+    "毛利",
+    "毛利率(%)",
+];
+
+fn build_by_name(build_by: i32) -> &'static str {
+    match build_by {
+        1 => "手工",
+        2 => "不锈钢",
+        _ => "不明",
+    }
+}
+
+fn write_headers(sheet: &mut Worksheet, headers: &[&str]) {
+    for (j, header) in headers.iter().enumerate() {
+        sheet.get_cell_mut((j as u32 + 1, 1)).set_value(*header);
+    }
+}
+
+fn write_numbers(sheet: &mut Worksheet, row: u32, start_col: u32, numbers: &[Decimal]) {
+    for (j, number) in numbers.iter().enumerate() {
+        sheet
+            .get_cell_mut((start_col + j as u32, row))
+            .set_value_number(number.to_f64().unwrap_or(0.0));
+    }
+}
+
+/// 两个sheet: 订单毛利, 客户毛利
+pub fn render_margin_excel(orders: &[OrderMargin], customers: &[CustomerMargin]) -> Spreadsheet {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book.get_sheet_mut(&0).unwrap();
+    sheet.set_name("订单毛利");
+    write_headers(sheet, &ORDER_MARGIN_HEADERS);
+    for (i, order) in orders.iter().enumerate() {
+        let row = i as u32 + 2;
+        sheet.get_cell_mut((1, row)).set_value(&order.order_no);
+        sheet.get_cell_mut((2, row)).set_value(&order.customer_no);
+        sheet
+            .get_cell_mut((3, row))
+            .set_value(order.order_date.format("%Y-%m-%d").to_string());
+        sheet
+            .get_cell_mut((4, row))
+            .set_value(build_by_name(order.build_by));
+        sheet.get_cell_mut((5, row)).set_value(&order.currency);
+        write_numbers(
+            sheet,
+            row,
+            6,
+            &[
+                order.sales,
+                order.sales_base,
+                order.material_cost,
+                order.purchase_cost,
+                order.labor_cost,
+                order.total_cost,
+                order.margin,
+                order.margin_rate,
+            ],
+        );
+    }
+
+    let sheet = book.new_sheet("客户毛利").unwrap();
+    write_headers(sheet, &CUSTOMER_MARGIN_HEADERS);
+    for (i, customer) in customers.iter().enumerate() {
+        let row = i as u32 + 2;
+        sheet
+            .get_cell_mut((1, row))
+            .set_value(&customer.customer_no);
+        sheet
+            .get_cell_mut((2, row))
+            .set_value_number(customer.order_count as f64);
+        write_numbers(
+            sheet,
+            row,
+            3,
+            &[
+                customer.sales_base,
+                customer.material_cost,
+                customer.purchase_cost,
+                customer.labor_cost,
+                customer.total_cost,
+                customer.margin,
+                customer.margin_rate,
+            ],
+        );
+    }
+
+    book
+}
+
+/// 导出毛利报表xlsx, 返回文件的地址
+pub fn export_margin_excel(orders: &[OrderMargin]) -> ERPResult<String> {
+    let customers = CustomerMargin::from_orders(orders);
+    let book = render_margin_excel(orders, &customers);
+    save_export_book(&book, "毛利报表")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::export_margin::render_margin_excel;
+    use crate::model::margin::{CustomerMargin, OrderMargin};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_render_margin_excel() {
+        let orders = vec![OrderMargin {
+            order_id: 1,
+            order_no: "20240101".to_string(),
+            customer_no: "L1001".to_string(),
+            order_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            build_by: 2,
+            currency: "USD".to_string(),
+            sales: Decimal::new(1250, 2),
+            sales_base: Decimal::new(8875, 2),
+            ..Default::default()
+        }];
+        let customers = CustomerMargin::from_orders(&orders);
+        let book = render_margin_excel(&orders, &customers);
+
+        let sheet = book.get_sheet_by_name("订单毛利").unwrap();
+        assert_eq!(sheet.get_value((1, 2)), "20240101");
+        assert_eq!(sheet.get_value((4, 2)), "不锈钢");
+        assert_eq!(sheet.get_value((6, 2)), "12.5");
+        let sheet = book.get_sheet_by_name("客户毛利").unwrap();
+        assert_eq!(sheet.get_value((1, 2)), "L1001");
+        assert_eq!(sheet.get_value((3, 2)), "88.75");
+    }
+}
@@ -0,0 +1,509 @@
+use crate::common::datetime::format_date;
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::dto::dto_orders::{OrderGoodsDto, OrderGoodsItemDto};
+use crate::excel::parse_order_template::field_header;
+use crate::model::excel::{
+    CustomerExcelTemplateModel, ExcelTemplate, ExcelTemplateColumnModel, ExcelTemplateModel,
+};
+use crate::model::order::OrderModel;
+use crate::{ERPError, ERPResult};
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use umya_spreadsheet::helper::coordinate::coordinate_from_index;
+use umya_spreadsheet::structs::drawing::spreadsheet::MarkerType;
+use umya_spreadsheet::{writer, Image, Spreadsheet, Worksheet};
+
+/// 商品级别的字段, 商品有多行产品时合并单元格(模版里沿用上一行的才合并)
+const GOODS_FIELDS: [&str; 5] = ["index", "package_card_des", "goods_no", "image_des", "name"];
+
+/// 有图片的行的行高
+const IMAGE_ROW_HEIGHT: f64 = 60.0;
+
+#[derive(Debug, Clone)]
+pub struct ExportOrderItem {
+    pub item: OrderGoodsItemDto,
+    pub plating: String,
+    pub color_2: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOrderGoods {
+    pub goods: OrderGoodsDto,
+    pub items: Vec<ExportOrderItem>,
+}
+
+/// 客户没有配置模版时用的格式(和"通用"模版一样)
+fn default_export_template() -> ExcelTemplate {
+    let fields = [
+        "index",
+        "package_card_des",
+        "goods_no",
+        "image_des",
+        "name",
+        "plating",
+        "color",
+        "count",
+        "unit",
+        "unit_price",
+        "total_price",
+        "notes",
+    ];
+    ExcelTemplate {
+        template: ExcelTemplateModel {
+            id: 0,
+            name: "默认".to_string(),
+            header_row: 6,
+            start_row: 7,
+            package_image_column: 2,
+            goods_image_column: 4,
+            notes_image_column: 12,
+            carry_down: GOODS_FIELDS.iter().map(|field| field.to_string()).collect(),
+        },
+        columns: fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| ExcelTemplateColumnModel {
+                id: 0,
+                template_id: 0,
+                col: i as i32 + 1,
+                header: "".to_string(),
+                field: field.to_string(),
+                remove_whitespace: false,
+            })
+            .collect(),
+    }
+}
+
+/// 订单的商品和产品(带电镀/颜色2), 按商品分组
+pub async fn load_export_order_goods(
+    db: &Pool<Postgres>,
+    order_id: i32,
+) -> ERPResult<Vec<ExportOrderGoods>> {
+    let order_goods = OrderGoodsDto::get_by_order_id(db, order_id, 0, None).await?;
+    let order_goods_ids = order_goods
+        .iter()
+        .map(|goods| goods.id)
+        .collect::<Vec<i32>>();
+    let order_items = OrderGoodsItemDto::get_by_order_goods_ids(db, &order_goods_ids).await?;
+
+    // 电镀/颜色2 不在 OrderGoodsItemDto 里, 单独查
+    let sku_ids = order_items
+        .iter()
+        .map(|item| item.sku_id)
+        .collect::<Vec<i32>>();
+    let sku_id_to_plating_color2 = sqlx::query!(
+        "select id, plating, color2 from skus where id = any($1)",
+        &sku_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|sku| (sku.id, (sku.plating, sku.color2)))
+    .collect::<HashMap<i32, (String, String)>>();
+
+    let mut order_goods_id_to_items: HashMap<i32, Vec<ExportOrderItem>> = HashMap::new();
+    for item in order_items.into_iter() {
+        let (plating, color_2) = sku_id_to_plating_color2
+            .get(&item.sku_id)
+            .cloned()
+            .unwrap_or_default();
+        order_goods_id_to_items
+            .entry(item.order_goods_id)
+            .or_default()
+            .push(ExportOrderItem {
+                item,
+                plating,
+                color_2,
+            });
+    }
+    let goods = order_goods
+        .into_iter()
+        .map(|goods| ExportOrderGoods {
+            items: order_goods_id_to_items
+                .remove(&goods.id)
+                .unwrap_or_default(),
+            goods,
+        })
+        .collect::<Vec<ExportOrderGoods>>();
+
+    Ok(goods)
+}
+
+/// 按客户配置的模版导出订单, 返回文件的地址
+pub async fn export_order_excel(db: &Pool<Postgres>, order_id: i32) -> ERPResult<String> {
+    let order = sqlx::query_as!(OrderModel, "select * from orders where id = $1", order_id)
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("订单#{}", order_id)))?;
+
+    let customer_excel_template = sqlx::query_as!(
+        CustomerExcelTemplateModel,
+        "select * from customer_excel_template where customer_no = $1",
+        &order.customer_no
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let template = match customer_excel_template {
+        Some(customer_excel_template) => {
+            ExcelTemplate::get(db, customer_excel_template.template_id).await?
+        }
+        None => default_export_template(),
+    };
+
+    let goods = load_export_order_goods(db, order_id).await?;
+
+    let book = render_order_excel(&order, &template, &goods, &local_image_path);
+
+    save_export_book(&book, &order.order_no)
+}
+
+/// 导出的文件存在 export 目录下, 文件名带上时间, 返回文件的地址
+pub fn save_export_book(book: &Spreadsheet, name: &str) -> ERPResult<String> {
+    let dir_path = format!("{}export", STORAGE_FILE_PATH);
+    std::fs::create_dir_all(&dir_path)
+        .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", dir_path)))?;
+    let file_path = format!(
+        "{}/{}-{}.xlsx",
+        dir_path,
+        name,
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    writer::xlsx::write(book, &file_path)
+        .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", file_path)))?;
+
+    Ok(file_path.replacen(STORAGE_FILE_PATH, STORAGE_URL_PREFIX, 1))
+}
+
+/// 订单信息写在表头上面, 和导入时 parse_order_info 读的格式一样
+fn order_info_lines(order: &OrderModel) -> Vec<String> {
+    let mut lines = vec![
+        format!("客户:{}", order.customer_no),
+        format!("单号:{}", order.order_no),
+        format!("订货日期:{}", format_date(order.order_date)),
+    ];
+    if let Some(delivery_date) = order.delivery_date {
+        lines.push(format!("交货日期:{}", format_date(delivery_date)));
+    }
+    let mut flags = vec![];
+    if order.is_urgent {
+        flags.push("加急");
+    }
+    if order.is_return_order {
+        flags.push("返单");
+    }
+    if order.is_special {
+        flags.push("特别客人");
+    }
+    if !flags.is_empty() {
+        lines.push(flags.join(" "));
+    }
+    lines
+}
+
+/// 模版里按表头名称找的列(col=0), 导出时依次放在固定列的后面
+fn export_columns(template: &ExcelTemplate) -> Vec<(u32, &ExcelTemplateColumnModel)> {
+    let mut next_col = template
+        .columns
+        .iter()
+        .map(|column| column.col)
+        .chain([
+            template.template.package_image_column,
+            template.template.goods_image_column,
+            template.template.notes_image_column,
+        ])
+        .max()
+        .unwrap_or(0)
+        .max(0) as u32;
+    template
+        .columns
+        .iter()
+        .map(|column| match column.col {
+            col if col > 0 => (col as u32, column),
+            _ => {
+                next_col += 1;
+                (next_col, column)
+            }
+        })
+        .collect()
+}
+
+fn field_value(
+    field: &str,
+    index: usize,
+    goods: &ExportOrderGoods,
+    item: &ExportOrderItem,
+) -> String {
+    match field {
+        "index" => (index + 1).to_string(),
+        "package_card_des" => goods.goods.package_card_des.clone(),
+        "goods_no" => goods.goods.goods_no.clone(),
+        "image_des" => goods.goods.image_des.clone(),
+        "name" => goods.goods.name.clone(),
+        "sku_no" => item.item.sku_no.clone().unwrap_or_default(),
+        "plating" => item.plating.clone(),
+        "color" => item.item.color.clone(),
+        "color_2" => item.color_2.clone(),
+        "count" => item.item.count.to_string(),
+        "unit" => item.item.unit.clone().unwrap_or_default(),
+        "unit_price" => item
+            .item
+            .unit_price
+            .map(|price| price.normalize().to_string())
+            .unwrap_or_default(),
+        "total_price" => item
+            .item
+            .total_price
+            .map(|price| price.normalize().to_string())
+            .unwrap_or_default(),
+        "notes" => item.item.notes.clone(),
+        // size/barcode/purchase_price 订单里没有存
+        _ => "".to_string(),
+    }
+}
+
+/// 图片地址转成本地文件, 文件不存在时返回None
+pub fn local_image_path(url: &str) -> Option<String> {
+    let path = url.replacen(STORAGE_URL_PREFIX, STORAGE_FILE_PATH, 1);
+    std::path::Path::new(&path).is_file().then_some(path)
+}
+
+pub fn add_images(sheet: &mut Worksheet, col: i32, row: u32, paths: &[String]) {
+    if col <= 0 || paths.is_empty() {
+        return;
+    }
+    for path in paths.iter() {
+        let mut marker = MarkerType::default();
+        marker.set_coordinate(coordinate_from_index(&(col as u32), &row));
+        let mut image = Image::default();
+        image.new_image(path, marker);
+        sheet.add_image(image);
+    }
+    sheet
+        .get_row_dimension_mut(&row)
+        .set_height(IMAGE_ROW_HEIGHT);
+}
+
+/// 按模版画出订单; image_path 把图片地址转成本地文件, 文件不存在时返回None(不放图片)
+pub fn render_order_excel(
+    order: &OrderModel,
+    template: &ExcelTemplate,
+    goods: &[ExportOrderGoods],
+    image_path: &dyn Fn(&str) -> Option<String>,
+) -> Spreadsheet {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book.get_sheet_mut(&0).unwrap();
+    sheet.set_name(order.order_no.clone());
+
+    // 表头上面至少留一行写订单信息
+    let info_lines = order_info_lines(order);
+    let offset = 2_u32.saturating_sub(template.template.header_row.max(1) as u32);
+    let header_row = template.template.header_row.max(1) as u32 + offset;
+    let start_row = (template.template.start_row.max(1) as u32 + offset).max(header_row + 1);
+    // 表头上面的行不够时, 都放在第一行
+    let one_per_row = (header_row - 1) as usize >= info_lines.len();
+    for (i, line) in info_lines.iter().enumerate() {
+        let (col, row) = match one_per_row {
+            true => (1, i as u32 + 1),
+            false => (i as u32 * 2 + 1, 1),
+        };
+        sheet.get_cell_mut((col, row)).set_value(line);
+    }
+
+    let columns = export_columns(template);
+    for (col, column) in columns.iter() {
+        let header = match column.header.is_empty() {
+            true => field_header(&column.field),
+            false => column.header.as_str(),
+        };
+        sheet.get_cell_mut((*col, header_row)).set_value(header);
+    }
+
+    let mut row = start_row;
+    for (index, order_goods) in goods.iter().enumerate() {
+        let first_row = row;
+        for (i, item) in order_goods.items.iter().enumerate() {
+            for (col, column) in columns.iter() {
+                let field = column.field.as_str();
+                let merged = GOODS_FIELDS.contains(&field)
+                    && template.template.carry_down.iter().any(|f| f == field);
+                if merged && i > 0 {
+                    continue;
+                }
+                let value = field_value(field, index, order_goods, item);
+                if value.is_empty() {
+                    continue;
+                }
+                let cell = sheet.get_cell_mut((*col, row));
+                match field {
+                    "count" | "unit_price" | "total_price" | "index" => {
+                        cell.set_value_number(value.parse::<f64>().unwrap_or(0.0));
+                    }
+                    _ => {
+                        cell.set_value(value);
+                    }
+                }
+            }
+
+            let notes_images = item
+                .item
+                .notes_images
+                .iter()
+                .filter_map(|url| image_path(url))
+                .collect::<Vec<String>>();
+            add_images(
+                sheet,
+                template.template.notes_image_column,
+                row,
+                &notes_images,
+            );
+            row += 1;
+        }
+        if order_goods.items.is_empty() {
+            continue;
+        }
+
+        let goods_images = order_goods
+            .goods
+            .images
+            .iter()
+            .filter_map(|url| image_path(url))
+            .collect::<Vec<String>>();
+        add_images(
+            sheet,
+            template.template.goods_image_column,
+            first_row,
+            &goods_images,
+        );
+        let package_images = image_path(&order_goods.goods.package_card)
+            .into_iter()
+            .collect::<Vec<String>>();
+        add_images(
+            sheet,
+            template.template.package_image_column,
+            first_row,
+            &package_images,
+        );
+
+        let last_row = row - 1;
+        if last_row > first_row {
+            for (col, column) in columns.iter() {
+                let field = column.field.as_str();
+                if GOODS_FIELDS.contains(&field)
+                    && template.template.carry_down.iter().any(|f| f == field)
+                {
+                    sheet.add_merge_cells(format!(
+                        "{}:{}",
+                        coordinate_from_index(col, &first_row),
+                        coordinate_from_index(col, &last_row)
+                    ));
+                }
+            }
+        }
+    }
+
+    book
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dto::dto_orders::{OrderGoodsDto, OrderGoodsItemDto};
+    use crate::excel::excel_order_info::parse_order_info;
+    use crate::excel::export_order_excel::{
+        default_export_template, render_order_excel, ExportOrderGoods, ExportOrderItem,
+    };
+    use crate::excel::parse_order_template::parse_order_excel;
+    use crate::model::order::OrderModel;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    fn goods(id: i32, goods_no: &str, colors: &[&str]) -> ExportOrderGoods {
+        ExportOrderGoods {
+            goods: OrderGoodsDto {
+                id,
+                order_id: 1,
+                goods_id: id,
+                goods_no: goods_no.to_string(),
+                name: format!("{}名称", goods_no),
+                images: vec![],
+                image_des: "".to_string(),
+                package_card: "".to_string(),
+                package_card_des: "".to_string(),
+            },
+            items: colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| ExportOrderItem {
+                    item: OrderGoodsItemDto {
+                        id: i as i32 + 1,
+                        order_id: 1,
+                        order_goods_id: id,
+                        goods_id: id,
+                        sku_id: i as i32 + 1,
+                        sku_no: None,
+                        color: color.to_string(),
+                        count: 10,
+                        unit: Some("个".to_string()),
+                        unit_price: Some(Decimal::from(2)),
+                        total_price: Some(Decimal::from(20)),
+                        notes_images: vec![],
+                        notes: "".to_string(),
+                    },
+                    plating: "金".to_string(),
+                    color_2: "".to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_order_excel() -> anyhow::Result<()> {
+        let order = OrderModel {
+            id: 1,
+            customer_no: "L1001".to_string(),
+            order_no: "X001".to_string(),
+            order_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            delivery_date: NaiveDate::from_ymd_opt(2024, 2, 3),
+            is_urgent: true,
+            is_return_order: false,
+            is_special: false,
+            special_customer: "".to_string(),
+            build_by: 0,
+            workflow_template_id: 0,
+            currency: "CNY".to_string(),
+        };
+        let template = default_export_template();
+        let book = render_order_excel(
+            &order,
+            &template,
+            &[goods(1, "A01", &["红", "蓝"]), goods(2, "B01", &["黑"])],
+            &|_| None,
+        );
+        let sheet = book.get_sheet(&0).unwrap();
+        assert_eq!(sheet.get_value((3, 6)), "商品编号");
+        assert_eq!(sheet.get_value((3, 7)), "A01");
+        assert_eq!(sheet.get_value((3, 8)), "");
+        assert_eq!(sheet.get_value((7, 8)), "蓝");
+        assert_eq!(sheet.get_value((3, 9)), "B01");
+        assert_eq!(sheet.get_value((1, 9)), "2");
+
+        // 导出的文件可以按同样的模版再导入
+        let info = parse_order_info(sheet)?;
+        assert_eq!(info.customer_no, "L1001");
+        assert_eq!(info.order_no, "X001");
+        assert_eq!(info.delivery_date, order.delivery_date);
+        assert!(info.is_urgent);
+
+        let mut issues = vec![];
+        let index_to_items = parse_order_excel(sheet, "X001", &template, &mut issues)?;
+        assert!(issues.is_empty());
+        assert_eq!(index_to_items[&1].len(), 2);
+        assert_eq!(index_to_items[&1][1].goods_no, "A01");
+        assert_eq!(index_to_items[&1][1].plating, "金");
+        assert_eq!(index_to_items[&2][0].count, 10);
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use crate::common::datetime::format_datetime;
+use crate::dto::dto_progress::OneProgress;
+use crate::excel::export_order_excel::{
+    add_images, load_export_order_goods, local_image_path, save_export_book,
+};
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::model::workflow::{actionable_steps_of, current_step_of, ProgressRecord, Workflow};
+use crate::{ERPError, ERPResult};
+use chrono::Local;
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeSet;
+use umya_spreadsheet::Spreadsheet;
+
+const TRAVELER_HEADERS: [&str; 12] = [
+    "序号",
+    "商品编号",
+    "图片",
+    "名称",
+    "电镀",
+    "颜色",
+    "数量",
+    "当前流程",
+    "状态",
+    "操作人",
+    "操作时间",
+    "异常备注",
+];
+const TRAVELER_IMAGE_COLUMN: u32 = 3;
+const TRAVELER_STATUS_COLUMN: u32 = 9;
+
+/// 生产单上的一行(一个产品)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TravelerRow {
+    pub index: usize, // 商品序号
+    pub goods_no: String,
+    pub name: String,
+    pub image: String, // 商品的第一张图片
+    pub plating: String,
+    pub color: String,
+    pub count: i32,
+    pub current_step: String, // 空: 所有流程都已完成
+    pub option: String,       // 最后一次标记的选项
+    pub option_color: String, // 选项的颜色, 如 #FF0000
+    pub marked_by: String,
+    pub marked_at: String,
+    pub exception_notes: Vec<String>,
+}
+
+/// 生产单: 订单信息, 各流程的产品数, 以及每个产品的进度
+#[derive(Debug, Clone)]
+pub struct Traveler {
+    pub order: OrderModel,
+    pub department: String, // 空: 所有部门
+    pub summary: Vec<String>,
+    pub rows: Vec<TravelerRow>,
+}
+
+/// 产品的最后一次标记, 以及所有异常备注
+fn traveler_progress(workflow: &Workflow, progresses: &[OneProgress], row: &mut TravelerRow) {
+    if let Some(last) = progresses.last() {
+        if let Some(option) = workflow.get_option(last.step, last.index) {
+            row.option = format!("{}-{}", workflow.step_name(last.step), option.name);
+            row.option_color = option.color.clone();
+        }
+        row.marked_by = last.account_name.clone();
+        row.marked_at = format_datetime(last.dt.with_timezone(&Local).naive_local());
+    }
+    row.exception_notes = progresses
+        .iter()
+        .filter(|progress| {
+            workflow
+                .get_option(progress.step, progress.index)
+                .map(|option| option.is_exception)
+                .unwrap_or(false)
+        })
+        .map(|progress| match progress.notes.is_empty() {
+            true => workflow.step_name(progress.step).to_string(),
+            false => format!("{}: {}", workflow.step_name(progress.step), progress.notes),
+        })
+        .collect();
+}
+
+/// department_id 不为0时, 只保留这个部门现在有数量可以操作的产品
+pub async fn get_order_traveler(
+    db: &Pool<Postgres>,
+    order_id: i32,
+    department_id: i32,
+) -> ERPResult<Traveler> {
+    let order = sqlx::query_as!(OrderModel, "select * from orders where id = $1", order_id)
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("订单#{}", order_id)))?;
+
+    let department = match department_id {
+        0 => "".to_string(),
+        _ => {
+            sqlx::query!("select name from departments where id = $1", department_id)
+                .fetch_optional(db)
+                .await
+                .map_err(ERPError::DBError)?
+                .ok_or(ERPError::NotFound(format!("部门#{}", department_id)))?
+                .name
+        }
+    };
+
+    let workflow = Workflow::get(db).await?;
+    let route = workflow.route_for_order(&order);
+    let department_steps = workflow
+        .steps
+        .iter()
+        .filter(|step| step.department_id == department_id)
+        .map(|step| step.step)
+        .collect::<Vec<i32>>();
+
+    let goods = load_export_order_goods(db, order_id).await?;
+    let order_item_ids = goods
+        .iter()
+        .flat_map(|goods| goods.items.iter().map(|item| item.item.id))
+        .collect::<Vec<i32>>();
+    let order_item_id_to_progresses =
+        ProgressModel::get_order_items_progresses(db, &order_item_ids).await?;
+
+    // 各流程/选项的产品数
+    let summary = ProgressModel::get_progress_status(db, &[order_id])
+        .await?
+        .remove(&order_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|((step, _), _)| department_id == 0 || department_steps.contains(step))
+        .map(|((step, index), count)| {
+            let option = workflow
+                .get_option(step, index)
+                .map(|option| option.name.as_str())
+                .unwrap_or("未开始");
+            (
+                step,
+                index,
+                format!("{}-{}: {}", workflow.step_name(step), option, count),
+            )
+        })
+        .collect::<BTreeSet<(i32, i32, String)>>()
+        .into_iter()
+        .map(|(_, _, line)| line)
+        .collect::<Vec<String>>();
+
+    let empty: Vec<OneProgress> = vec![];
+    let mut rows = vec![];
+    for (index, order_goods) in goods.iter().enumerate() {
+        for item in order_goods.items.iter() {
+            let progresses = order_item_id_to_progresses
+                .get(&item.item.id)
+                .unwrap_or(&empty);
+            let records = progresses
+                .iter()
+                .map(ProgressRecord::from)
+                .collect::<Vec<_>>();
+            let step_quantities = route.step_quantities(&workflow, item.item.count, &records);
+
+            if department_id != 0
+                && !actionable_steps_of(&step_quantities)
+                    .iter()
+                    .any(|step| department_steps.contains(step))
+            {
+                continue;
+            }
+
+            let mut row = TravelerRow {
+                index: index + 1,
+                goods_no: order_goods.goods.goods_no.clone(),
+                name: order_goods.goods.name.clone(),
+                image: order_goods
+                    .goods
+                    .images
+                    .first()
+                    .cloned()
+                    .unwrap_or_default(),
+                plating: item.plating.clone(),
+                color: item.item.color.clone(),
+                count: item.item.count,
+                current_step: current_step_of(&step_quantities)
+                    .map(|step| workflow.step_name(step).to_string())
+                    .unwrap_or_default(),
+                ..Default::default()
+            };
+            traveler_progress(&workflow, progresses, &mut row);
+            rows.push(row);
+        }
+    }
+
+    Ok(Traveler {
+        order,
+        department,
+        summary,
+        rows,
+    })
+}
+
+fn traveler_title(traveler: &Traveler) -> String {
+    let mut title = format!(
+        "生产单 单号:{} 客户:{}",
+        traveler.order.order_no, traveler.order.customer_no
+    );
+    if let Some(delivery_date) = traveler.order.delivery_date {
+        title.push_str(&format!(" 交货日期:{}", delivery_date.format("%Y-%m-%d")));
+    }
+    if traveler.order.is_urgent {
+        title.push_str(" 加急");
+    }
+    if !traveler.department.is_empty() {
+        title.push_str(&format!(" 部门:{}", traveler.department));
+    }
+    title
+}
+
+fn traveler_row_values(row: &TravelerRow) -> [String; 12] {
+    [
+        row.index.to_string(),
+        row.goods_no.clone(),
+        "".to_string(),
+        row.name.clone(),
+        row.plating.clone(),
+        row.color.clone(),
+        row.count.to_string(),
+        match row.current_step.is_empty() {
+            true => "已完成".to_string(),
+            false => row.current_step.clone(),
+        },
+        row.option.clone(),
+        row.marked_by.clone(),
+        row.marked_at.clone(),
+        row.exception_notes.join("; "),
+    ]
+}
+
+/// 生产单xlsx: 第1行订单信息, 第2行各流程的产品数, 第3行表头, 后面每行一个产品
+pub fn render_traveler_excel(
+    traveler: &Traveler,
+    image_path: &dyn Fn(&str) -> Option<String>,
+) -> Spreadsheet {
+    let mut book = umya_spreadsheet::new_file();
+    let sheet = book.get_sheet_mut(&0).unwrap();
+    sheet.set_name(traveler.order.order_no.clone());
+
+    sheet
+        .get_cell_mut((1, 1))
+        .set_value(traveler_title(traveler));
+    sheet
+        .get_cell_mut((1, 2))
+        .set_value(traveler.summary.join("; "));
+    for (j, header) in TRAVELER_HEADERS.iter().enumerate() {
+        sheet.get_cell_mut((j as u32 + 1, 3)).set_value(*header);
+    }
+
+    for (i, row) in traveler.rows.iter().enumerate() {
+        let excel_row = i as u32 + 4;
+        for (j, value) in traveler_row_values(row).into_iter().enumerate() {
+            if !value.is_empty() {
+                sheet
+                    .get_cell_mut((j as u32 + 1, excel_row))
+                    .set_value(value);
+            }
+        }
+        if let Some(color) = row.option_color.strip_prefix('#') {
+            sheet
+                .get_style_mut((TRAVELER_STATUS_COLUMN, excel_row))
+                .set_background_color(format!("FF{}", color.to_uppercase()));
+        }
+        let images = image_path(&row.image).into_iter().collect::<Vec<String>>();
+        add_images(sheet, TRAVELER_IMAGE_COLUMN as i32, excel_row, &images);
+    }
+
+    book
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 可以直接打印的生产单
+pub fn render_traveler_html(traveler: &Traveler) -> String {
+    let mut html = String::new();
+    html.push_str(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<style>
+    body { font-family: sans-serif; font-size: 12px; }
+    table { border-collapse: collapse; width: 100%; }
+    th, td { border: 1px solid #333; padding: 4px; }
+    img { height: 60px; }
+    @media print { @page { size: landscape; } }
+</style>
+</head>
+<body>
+"#,
+    );
+    html.push_str(&format!(
+        "<h3>{}</h3>\n<p>{}</p>\n<table>\n<tr>",
+        escape_html(&traveler_title(traveler)),
+        escape_html(&traveler.summary.join("; "))
+    ));
+    for header in TRAVELER_HEADERS.iter() {
+        html.push_str(&format!("<th>{}</th>", header));
+    }
+    html.push_str("</tr>\n");
+
+    for row in traveler.rows.iter() {
+        html.push_str("<tr>");
+        for (j, value) in traveler_row_values(row).iter().enumerate() {
+            let j = j as u32 + 1;
+            if j == TRAVELER_IMAGE_COLUMN && !row.image.is_empty() {
+                html.push_str(&format!(
+                    "<td><img src=\"{}\"></td>",
+                    escape_html(&row.image)
+                ));
+            } else if j == TRAVELER_STATUS_COLUMN && !row.option_color.is_empty() {
+                html.push_str(&format!(
+                    "<td style=\"background-color: {}\">{}</td>",
+                    escape_html(&row.option_color),
+                    escape_html(value)
+                ));
+            } else {
+                html.push_str(&format!("<td>{}</td>", escape_html(value)));
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+
+    html
+}
+
+/// 导出生产单xlsx, 返回文件的地址
+pub fn export_traveler_excel(traveler: &Traveler) -> ERPResult<String> {
+    let book = render_traveler_excel(traveler, &local_image_path);
+    let name = match traveler.department.is_empty() {
+        true => format!("{}-生产单", traveler.order.order_no),
+        false => format!("{}-生产单-{}", traveler.order.order_no, traveler.department),
+    };
+    save_export_book(&book, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dto::dto_progress::OneProgress;
+    use crate::excel::export_order_traveler::{
+        render_traveler_excel, render_traveler_html, traveler_progress, Traveler, TravelerRow,
+    };
+    use crate::model::order::OrderModel;
+    use crate::model::workflow::{Workflow, WorkflowStepModel, WorkflowStepOptionModel};
+    use chrono::{NaiveDate, Utc};
+    use rust_decimal::Decimal;
+
+    fn option(step: i32, index: i32, name: &str, is_exception: bool) -> WorkflowStepOptionModel {
+        WorkflowStepOptionModel {
+            id: 0,
+            step,
+            index,
+            name: name.to_string(),
+            color: "#FF0000".to_string(),
+            is_done: false,
+            is_exception,
+            purchase_kind: "".to_string(),
+        }
+    }
+
+    fn progress(step: i32, index: i32, notes: &str) -> OneProgress {
+        OneProgress {
+            id: 0,
+            order_item_id: 1,
+            step,
+            index,
+            account_id: 1,
+            account_name: "张三".to_string(),
+            department: "生产部".to_string(),
+            done: false,
+            notes: notes.to_string(),
+            dt: Utc::now(),
+            skipped: false,
+            quantity: 0,
+            defective: 0,
+        }
+    }
+
+    #[test]
+    fn test_traveler() {
+        let workflow = Workflow {
+            steps: vec![WorkflowStepModel {
+                id: 3,
+                step: 3,
+                name: "生产".to_string(),
+                department_id: 3,
+                is_shipping: false,
+                labor_rate: Decimal::ZERO,
+            }],
+            options: vec![
+                option(3, 1, "异常(备注)", true),
+                option(3, 3, "已发车间", false),
+            ],
+            templates: vec![],
+        };
+        let mut row = TravelerRow {
+            index: 1,
+            goods_no: "A01".to_string(),
+            current_step: "生产".to_string(),
+            count: 10,
+            ..Default::default()
+        };
+        traveler_progress(
+            &workflow,
+            &[progress(3, 1, "缺料"), progress(3, 3, "")],
+            &mut row,
+        );
+        assert_eq!(row.option, "生产-已发车间");
+        assert_eq!(row.option_color, "#FF0000");
+        assert_eq!(row.marked_by, "张三");
+        assert_eq!(row.exception_notes, vec!["生产: 缺料".to_string()]);
+
+        let traveler = Traveler {
+            order: OrderModel {
+                id: 1,
+                customer_no: "L1001".to_string(),
+                order_no: "X001".to_string(),
+                order_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+                delivery_date: None,
+                is_urgent: false,
+                is_return_order: false,
+                is_special: false,
+                special_customer: "".to_string(),
+                build_by: 0,
+                workflow_template_id: 0,
+                currency: "CNY".to_string(),
+            },
+            department: "生产部".to_string(),
+            summary: vec!["生产-已发车间: 1".to_string()],
+            rows: vec![row],
+        };
+        let book = render_traveler_excel(&traveler, &|_| None);
+        let sheet = book.get_sheet(&0).unwrap();
+        assert!(sheet.get_value((1, 1)).contains("部门:生产部"));
+        assert_eq!(sheet.get_value((2, 4)), "A01");
+        assert_eq!(sheet.get_value((9, 4)), "生产-已发车间");
+        assert_eq!(sheet.get_value((12, 4)), "生产: 缺料");
+
+        let html = render_traveler_html(&traveler);
+        assert!(html.contains("<td style=\"background-color: #FF0000\">生产-已发车间</td>"));
+    }
+}
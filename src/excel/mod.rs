@@ -2,12 +2,14 @@ use crate::common::datetime::parse_date;
 use crate::model::order::{OrderInfo, OrderItemExcel};
 use umya_spreadsheet::Worksheet;
 
+mod excel_order_diff;
+pub mod excel_order_file;
 mod excel_order_info;
 pub mod excel_order_parser;
-mod parse_order_template_1;
-mod parse_order_template_2;
-mod parse_order_template_3;
-mod parse_order_template_4;
+pub mod export_margin;
+pub mod export_order_excel;
+pub mod export_order_traveler;
+pub mod parse_order_template;
 mod process_order_excel_goods;
 
 pub trait OrderExcelHandler {
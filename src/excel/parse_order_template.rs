@@ -0,0 +1,695 @@
+use crate::common::string::remove_whitespace_str;
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::model::excel::{
+    ExcelHeaderSynonymModel, ExcelIssue, ExcelMapping, ExcelTemplate, ExcelTemplateColumnModel,
+    ExcelTemplateModel,
+};
+use crate::model::order::OrderItemExcel;
+use crate::{ERPError, ERPResult};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::str::FromStr;
+use umya_spreadsheet::*;
+
+/// 模版的列可以对应的字段
+pub const EXCEL_TEMPLATE_FIELDS: [&str; 17] = [
+    "index",
+    "package_card_des",
+    "goods_no",
+    "sku_no",
+    "image_des",
+    "name",
+    "plating",
+    "color",
+    "color_2",
+    "size",
+    "barcode",
+    "purchase_price",
+    "count",
+    "unit",
+    "unit_price",
+    "total_price",
+    "notes",
+];
+
+/// 合并单元格时默认沿用上一行的字段(数量/单位/备注/图片每行都不一样)
+pub const DEFAULT_CARRY_DOWN: [&str; 15] = [
+    "index",
+    "package_card",
+    "package_card_des",
+    "goods_no",
+    "sku_no",
+    "image_des",
+    "name",
+    "plating",
+    "color",
+    "color_2",
+    "size",
+    "barcode",
+    "purchase_price",
+    "unit_price",
+    "total_price",
+];
+
+/// 导出excel时, 模版的列没有配置表头名称时用的表头
+pub fn field_header(field: &str) -> &'static str {
+    match field {
+        "index" => "序号",
+        "package_card_des" => "包装卡",
+        "goods_no" => "商品编号",
+        "sku_no" => "sku编号",
+        "image_des" => "图片",
+        "name" => "名称",
+        "plating" => "电镀",
+        "color" => "颜色",
+        "color_2" => "颜色2",
+        "size" => "尺寸",
+        "barcode" => "条码",
+        "purchase_price" => "进货价",
+        "count" => "数量",
+        "unit" => "单位",
+        "unit_price" => "单价",
+        "total_price" => "金额",
+        "notes" => "备注",
+        _ => "",
+    }
+}
+
+/// 在前面多少行里找表头
+const HEADER_SEARCH_ROWS: u32 = 20;
+
+/// 可以沿用上一行的字段: 上面的字段, 再加上图片
+pub fn is_carry_down_field(field: &str) -> bool {
+    EXCEL_TEMPLATE_FIELDS.contains(&field)
+        || matches!(field, "images" | "package_card" | "notes_images")
+}
+
+/// 逗号只能是千分位: 第一组1到3位, 后面每组都是3位, 且只能在整数部分;
+/// 1,25(欧洲的小数)/1,2,3 这种不能直接去掉逗号, 不然数字就变了
+fn remove_thousands_separator(value: &str) -> Result<String, String> {
+    if !value.contains(',') {
+        return Ok(value.to_string());
+    }
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let groups = integer
+        .trim_start_matches(['-', '+'])
+        .split(',')
+        .collect::<Vec<&str>>();
+    if fraction.contains(',')
+        || !(1..=3).contains(&groups[0].len())
+        || groups[1..].iter().any(|group| group.len() != 3)
+    {
+        return Err("逗号只能是千分位(如1,234.5), 小数点请用.".to_string());
+    }
+    Ok(value.replace(',', ""))
+}
+
+fn parse_number(value: &str) -> Result<i32, String> {
+    let value = remove_thousands_separator(value.trim())?;
+    let value = value.as_str();
+    if let Ok(number) = value.parse::<i32>() {
+        return Ok(number);
+    }
+    match value.parse::<f64>() {
+        Ok(number) if number.fract() == 0.0 => Ok(number as i32),
+        Ok(_) => Err("不是整数".to_string()),
+        Err(_) => Err("不是数字".to_string()),
+    }
+}
+
+/// 金额: 去掉千分位和货币符号(¥ ￥ $ US$ €), 支持科学计数法, 保留4位小数
+fn parse_decimal(value: &str) -> Result<Decimal, String> {
+    let mut value = value.trim().replace(' ', "");
+    for symbol in ["US$", "$", "¥", "￥", "€"] {
+        if let Some(stripped) = value.strip_prefix(symbol) {
+            value = stripped.to_string();
+            break;
+        }
+    }
+    let value = remove_thousands_separator(&value)?;
+    Decimal::from_str(&value)
+        .or_else(|_| Decimal::from_scientific(&value))
+        .map(|number| number.round_dp(4))
+        .map_err(|_| "不是数字".to_string())
+}
+
+/// 数字类的字段转换失败时返回错误说明
+fn set_field(
+    item: &mut OrderItemExcel,
+    field: &str,
+    value: &str,
+    remove_whitespace: bool,
+) -> Result<(), String> {
+    let text = match remove_whitespace {
+        true => remove_whitespace_str(value),
+        false => value.trim().to_string(),
+    };
+
+    match field {
+        "index" => item.index = parse_number(value)?,
+        "package_card_des" => item.package_card_des = Some(text),
+        "goods_no" => item.goods_no = text,
+        "sku_no" => item.sku_no = Some(text),
+        "image_des" => item.image_des = Some(text),
+        "name" => item.name = text,
+        "plating" => item.plating = text,
+        "color" => item.color = text,
+        "color_2" => item.color_2 = Some(text),
+        "size" => item.size = Some(text),
+        "barcode" => item.barcode = Some(text),
+        "purchase_price" => item.purchase_price = Some(parse_decimal(value)?),
+        "count" => item.count = parse_number(value)?,
+        "unit" => item.unit = Some(text),
+        "unit_price" => item.unit_price = Some(parse_decimal(value)?),
+        "total_price" => item.total_price = Some(parse_decimal(value)?),
+        "notes" => item.notes = Some(text),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn clear_field(item: &mut OrderItemExcel, field: &str) {
+    match field {
+        "index" => item.index = 0,
+        "package_card" => item.package_card = None,
+        "package_card_des" => item.package_card_des = None,
+        "goods_no" => item.goods_no = "".to_string(),
+        "sku_no" => item.sku_no = None,
+        "images" => item.images = vec![],
+        "image_des" => item.image_des = None,
+        "name" => item.name = "".to_string(),
+        "plating" => item.plating = "".to_string(),
+        "color" => item.color = "".to_string(),
+        "color_2" => item.color_2 = None,
+        "size" => item.size = None,
+        "barcode" => item.barcode = None,
+        "purchase_price" => item.purchase_price = None,
+        "count" => item.count = 0,
+        "unit" => item.unit = None,
+        "unit_price" => item.unit_price = None,
+        "total_price" => item.total_price = None,
+        "notes_images" => item.notes_images = vec![],
+        "notes" => item.notes = None,
+        _ => {}
+    }
+}
+
+/// 按表头名称找列, col有值时直接用col
+fn resolve_columns<'a>(
+    sheet: &Worksheet,
+    template: &'a ExcelTemplate,
+) -> ERPResult<Vec<(u32, &'a ExcelTemplateColumnModel)>> {
+    let (cols, _rows) = sheet.get_highest_column_and_row();
+    let header_row = template.template.header_row as u32;
+
+    template
+        .columns
+        .iter()
+        .map(|column| {
+            if column.col > 0 {
+                return Ok((column.col as u32, column));
+            }
+            (1..cols + 1)
+                .find(|j| {
+                    sheet
+                        .get_cell((*j, header_row))
+                        .map(|cell| {
+                            normalize_header(&cell.get_raw_value().to_string())
+                                == normalize_header(&column.header)
+                        })
+                        .unwrap_or(false)
+                })
+                .map(|j| (j, column))
+                .ok_or(ERPError::ExcelError(format!(
+                    "第{}行没有找到表头: {}",
+                    header_row, column.header
+                )))
+        })
+        .collect()
+}
+
+/// 表头比较时去掉所有空白(包括换行), 不区分大小写
+pub fn normalize_header(header: &str) -> String {
+    remove_whitespace_str(header).to_lowercase()
+}
+
+fn cell_text(sheet: &Worksheet, col: u32, row: u32) -> String {
+    sheet
+        .get_cell((col, row))
+        .map(|cell| cell.get_raw_value().to_string().trim().to_string())
+        .unwrap_or_default()
+}
+
+/// 表头行里没有对应上字段的列
+fn unmapped_header_warnings(
+    sheet: &Worksheet,
+    header_row: u32,
+    mapped_cols: &[u32],
+) -> Vec<String> {
+    let (cols, _rows) = sheet.get_highest_column_and_row();
+    (1..cols + 1)
+        .filter(|j| !mapped_cols.contains(j))
+        .filter_map(|j| {
+            let header = cell_text(sheet, j, header_row);
+            match header.is_empty() {
+                true => None,
+                false => Some(format!("第{}列\"{}\"没有对应的字段, 已忽略", j, header)),
+            }
+        })
+        .collect()
+}
+
+/// 按表头自动识别列: 在前面几行里找到同时有 数量 和 商品编号/sku编号 的那一行当表头
+pub fn detect_template(
+    sheet: &Worksheet,
+    synonyms: &[ExcelHeaderSynonymModel],
+) -> Option<ExcelMapping> {
+    let (cols, rows) = sheet.get_highest_column_and_row();
+    let header_to_field = synonyms
+        .iter()
+        .map(|synonym| (normalize_header(&synonym.header), synonym.field.as_str()))
+        .collect::<HashMap<String, &str>>();
+
+    for i in 1..rows.min(HEADER_SEARCH_ROWS) + 1 {
+        let mut columns: Vec<ExcelTemplateColumnModel> = vec![];
+        for j in 1..cols + 1 {
+            let header = cell_text(sheet, j, i);
+            let Some(field) = header_to_field.get(&normalize_header(&header)) else {
+                continue;
+            };
+            // 同一个字段有多列时, 用第一列
+            if columns.iter().any(|column| column.field == *field) {
+                continue;
+            }
+            columns.push(ExcelTemplateColumnModel {
+                id: 0,
+                template_id: 0,
+                col: j as i32,
+                header,
+                field: field.to_string(),
+                remove_whitespace: matches!(*field, "goods_no" | "sku_no"),
+            });
+        }
+
+        let has = |field: &str| columns.iter().any(|column| column.field == field);
+        if !has("count") || !(has("goods_no") || has("sku_no")) {
+            continue;
+        }
+
+        // 图片和对应的文字在同一列
+        let col_of = |field: &str| {
+            columns
+                .iter()
+                .find(|column| column.field == field)
+                .map(|column| column.col)
+                .unwrap_or(0)
+        };
+        let template = ExcelTemplate {
+            template: ExcelTemplateModel {
+                id: 0,
+                name: "按表头识别".to_string(),
+                header_row: i as i32,
+                start_row: i as i32 + 1,
+                package_image_column: col_of("package_card_des"),
+                goods_image_column: col_of("image_des"),
+                notes_image_column: col_of("notes"),
+                carry_down: DEFAULT_CARRY_DOWN.iter().map(|f| f.to_string()).collect(),
+            },
+            columns,
+        };
+        let mapped_cols = template
+            .columns
+            .iter()
+            .map(|column| column.col as u32)
+            .collect::<Vec<u32>>();
+
+        return Some(ExcelMapping {
+            detected: true,
+            warnings: unmapped_header_warnings(sheet, i, &mapped_cols),
+            template,
+        });
+    }
+
+    None
+}
+
+/// 用客户配置的模版
+pub fn template_mapping(sheet: &Worksheet, template: ExcelTemplate) -> ERPResult<ExcelMapping> {
+    let mapped_cols = resolve_columns(sheet, &template)?
+        .iter()
+        .map(|(j, _)| *j)
+        .collect::<Vec<u32>>();
+    let warnings =
+        unmapped_header_warnings(sheet, template.template.header_row as u32, &mapped_cols);
+
+    Ok(ExcelMapping {
+        detected: false,
+        template,
+        warnings,
+    })
+}
+
+/// 合计行之后就不是产品数据了
+fn is_total_row(value: &str) -> bool {
+    value.contains("合计") || value.contains("总计")
+}
+
+/// 解析产品数据, 有问题的单元格不会中断解析, 都记到issues里
+pub fn parse_order_excel(
+    sheet: &Worksheet,
+    order_no: &str,
+    template: &ExcelTemplate,
+    issues: &mut Vec<ExcelIssue>,
+) -> ERPResult<HashMap<i32, Vec<OrderItemExcel>>> {
+    let (_cols, rows) = sheet.get_highest_column_and_row();
+    let sheet_name = sheet.get_name().to_string();
+    let columns = resolve_columns(sheet, template)?;
+    let carry_down = &template.template.carry_down;
+    let has_unit = template.has_field("unit");
+    let has_index = template.has_field("index");
+    let col_of = |field: &str| {
+        columns
+            .iter()
+            .find(|(_, column)| column.field == field)
+            .map(|(j, _)| *j)
+            .unwrap_or(0)
+    };
+
+    // 先获得了 HashMap<index, vec<Row>>
+    let mut index_to_items = HashMap::new();
+    let mut pre: Option<OrderItemExcel> = None;
+
+    for i in template.template.start_row.max(1) as u32..rows + 1 {
+        let cells = columns
+            .iter()
+            .map(|(j, column)| (*j, *column, cell_text(sheet, *j, i)))
+            .collect::<Vec<(u32, &ExcelTemplateColumnModel, String)>>();
+
+        // 数据结束: 合计行, 或者 编号/颜色/数量 都是空的
+        if cells.iter().any(|(_, _, value)| is_total_row(value)) {
+            break;
+        }
+        if cells.iter().all(|(_, column, value)| {
+            value.is_empty()
+                || !matches!(
+                    column.field.as_str(),
+                    "goods_no" | "sku_no" | "color" | "count"
+                )
+        }) {
+            break;
+        }
+
+        let mut cur = OrderItemExcel::default();
+        if let Some(previous) = pre.as_ref() {
+            cur = previous.clone();
+            EXCEL_TEMPLATE_FIELDS
+                .iter()
+                .chain(["images", "package_card", "notes_images"].iter())
+                .filter(|field| !carry_down.iter().any(|carry| carry == *field))
+                .for_each(|field| clear_field(&mut cur, field));
+        }
+        cur.row = i;
+
+        let image_column = |column: i32| match column {
+            0 => vec![],
+            _ => sheet.get_images((column as u32, i)),
+        };
+        let package_image = image_column(template.template.package_image_column)
+            .first()
+            .map(|image| (*image).clone());
+        let goods_images = image_column(template.template.goods_image_column);
+        let notes_images = image_column(template.template.notes_image_column);
+
+        let mut invalid_fields = vec![];
+        for (j, column, cell_value) in cells.iter() {
+            if cell_value.is_empty() {
+                continue;
+            }
+            if let Err(message) = set_field(
+                &mut cur,
+                &column.field,
+                cell_value,
+                column.remove_whitespace,
+            ) {
+                issues.push(ExcelIssue::new(
+                    &sheet_name,
+                    i,
+                    *j,
+                    &column.field,
+                    cell_value,
+                    &message,
+                ));
+                invalid_fields.push(column.field.as_str());
+            }
+        }
+
+        tracing::info!("cur: {:?}", cur);
+
+        let mut row_issue = |field: &str, value: String, message: &str| {
+            issues.push(ExcelIssue::new(
+                &sheet_name,
+                i,
+                col_of(field),
+                field,
+                &value,
+                message,
+            ))
+        };
+        if cur.count <= 0 && !invalid_fields.contains(&"count") {
+            row_issue("count", cur.count.to_string(), "数量为空");
+        }
+        if has_unit && cur.unit.is_none() {
+            row_issue("unit", "".to_string(), "单位为空");
+        }
+        if has_index && cur.index == 0 && !invalid_fields.contains(&"index") {
+            row_issue("index", "".to_string(), "没有读到序号");
+        }
+        if let (Some(unit_price), Some(total_price)) = (cur.unit_price, cur.total_price) {
+            // 金额按2位小数比较, 避免单价有3-4位小数时的舍入误差
+            if unit_price > Decimal::ZERO
+                && total_price > Decimal::ZERO
+                && (unit_price * Decimal::from(cur.count)).round_dp(2) != total_price.round_dp(2)
+            {
+                row_issue(
+                    "total_price",
+                    total_price.to_string(),
+                    &format!("单价{}×数量{}不等于金额", unit_price, cur.count),
+                );
+            }
+        }
+
+        let mut sku_identifier = cur.goods_no.clone();
+        if sku_identifier.is_empty() {
+            sku_identifier = cur.sku_no.as_deref().unwrap_or("").to_string();
+        }
+
+        let sku_identifier = sku_identifier.replace('/', "-");
+        let goods_identifier = cur.goods_no.replace('/', "-");
+
+        if !goods_images.is_empty() {
+            let mut image_urls = vec![];
+            for (index, real_goods_image) in goods_images.into_iter().enumerate() {
+                let sku_image_name = format!("{}-{}-{}.png", sku_identifier, index, order_no);
+                let goods_image_path = format!("{}/sku/{}", STORAGE_FILE_PATH, sku_image_name);
+                real_goods_image.download_image(&goods_image_path);
+                image_urls.push(format!("{}/sku/{}", STORAGE_URL_PREFIX, sku_image_name));
+            }
+            cur.images = image_urls;
+        }
+
+        if let Some(read_package_image) = package_image {
+            let package_image_name = format!("{}-{}.png", goods_identifier, order_no);
+            let package_image_path =
+                format!("{}/package/{}", STORAGE_FILE_PATH, package_image_name);
+            read_package_image.download_image(&package_image_path);
+            cur.package_card = Some(format!(
+                "{}/package/{}",
+                STORAGE_URL_PREFIX, package_image_name
+            ));
+        }
+
+        if !notes_images.is_empty() {
+            let mut notes_image_urls = vec![];
+            for (index, real_notes_image) in notes_images.into_iter().enumerate() {
+                let notes_image_name = format!("{}-{}-{}.png", goods_identifier, index, order_no);
+                let notes_image_path = format!("{}/notes/{}", STORAGE_FILE_PATH, notes_image_name);
+                real_notes_image.download_image(&notes_image_path);
+                notes_image_urls.push(format!("{}/notes/{}", STORAGE_URL_PREFIX, notes_image_name));
+            }
+            cur.notes_images = notes_image_urls;
+        }
+
+        index_to_items
+            .entry(cur.index)
+            .or_insert(vec![])
+            .push(cur.clone());
+        pre = Some(cur);
+    }
+
+    Ok(index_to_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::parse_order_template::{detect_template, parse_decimal, parse_order_excel};
+    use crate::model::excel::{
+        ExcelHeaderSynonymModel, ExcelTemplate, ExcelTemplateColumnModel, ExcelTemplateModel,
+    };
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    fn column(col: i32, header: &str, field: &str) -> ExcelTemplateColumnModel {
+        ExcelTemplateColumnModel {
+            id: 0,
+            template_id: 1,
+            col,
+            header: header.to_string(),
+            field: field.to_string(),
+            remove_whitespace: field == "goods_no",
+        }
+    }
+
+    #[test]
+    fn test_parse_order_excel() -> anyhow::Result<()> {
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        for (j, header) in ["序号", "货号", "颜色", "数量", "单位"].iter().enumerate() {
+            sheet.get_cell_mut((j as u32 + 1, 6)).set_value(*header);
+        }
+        let rows = [
+            ["1", "A 01", "红", "10", "个"],
+            ["", "", "蓝", "5", "个"],
+            ["2", "B01", "黑", "3", "个"],
+            ["合计", "", "", "", ""],
+        ];
+        for (i, row) in rows.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                if !value.is_empty() {
+                    sheet
+                        .get_cell_mut((j as u32 + 1, i as u32 + 7))
+                        .set_value(*value);
+                }
+            }
+        }
+
+        let template = ExcelTemplate {
+            template: ExcelTemplateModel {
+                id: 1,
+                name: "".to_string(),
+                header_row: 6,
+                start_row: 7,
+                package_image_column: 0,
+                goods_image_column: 0,
+                notes_image_column: 0,
+                carry_down: vec!["index".to_string(), "goods_no".to_string()],
+            },
+            columns: vec![
+                column(1, "", "index"),
+                column(0, "货号", "goods_no"),
+                column(3, "", "color"),
+                column(0, "数量", "count"),
+                column(0, "单位", "unit"),
+            ],
+        };
+
+        let sheet = book.get_sheet(&0).unwrap();
+        let mut issues = vec![];
+        let index_to_items = parse_order_excel(sheet, "xyz", &template, &mut issues)?;
+        assert!(issues.is_empty());
+        assert_eq!(index_to_items.len(), 2);
+        let first = &index_to_items[&1];
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].goods_no, "A01");
+        assert_eq!(first[1].color, "蓝");
+        assert_eq!(first[1].count, 5);
+        assert_eq!(index_to_items[&2][0].goods_no, "B01");
+
+        let mut missing_header = template.clone();
+        missing_header.columns.push(column(0, "单价", "unit_price"));
+        assert!(parse_order_excel(sheet, "xyz", &missing_header, &mut vec![]).is_err());
+
+        // 数量不是数字, 记录问题但继续往下解析
+        let mut book = book.clone();
+        book.get_sheet_mut(&0)
+            .unwrap()
+            .get_cell_mut((4, 7))
+            .set_value("十");
+        let mut issues = vec![];
+        let index_to_items =
+            parse_order_excel(book.get_sheet(&0).unwrap(), "xyz", &template, &mut issues)?;
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].row, issues[0].col), (7, 4));
+        assert_eq!(issues[0].field, "count");
+        assert_eq!(index_to_items[&2][0].goods_no, "B01");
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_template() {
+        let synonyms = [
+            ("index", "序号"),
+            ("goods_no", "商品编号"),
+            ("goods_no", "货号"),
+            ("count", "数量"),
+            ("unit", "单位"),
+            ("notes", "备注"),
+        ]
+        .iter()
+        .map(|(field, header)| ExcelHeaderSynonymModel {
+            id: 0,
+            field: field.to_string(),
+            header: header.to_string(),
+        })
+        .collect::<Vec<ExcelHeaderSynonymModel>>();
+
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        sheet.get_cell_mut((1, 1)).set_value("客户:L1001");
+        for (j, header) in ["序号", "货 号", "颜色(中文)", "数量", "单位", "备注"]
+            .iter()
+            .enumerate()
+        {
+            sheet.get_cell_mut((j as u32 + 1, 5)).set_value(*header);
+        }
+
+        let sheet = book.get_sheet(&0).unwrap();
+        let mapping = detect_template(sheet, &synonyms).unwrap();
+        assert!(mapping.detected);
+        assert_eq!(mapping.template.template.header_row, 5);
+        assert_eq!(mapping.template.template.start_row, 6);
+        assert_eq!(mapping.template.template.notes_image_column, 6);
+        let goods_no = mapping
+            .template
+            .columns
+            .iter()
+            .find(|column| column.field == "goods_no")
+            .unwrap();
+        assert_eq!(goods_no.col, 2);
+        assert_eq!(mapping.warnings.len(), 1);
+        assert!(mapping.warnings[0].contains("颜色(中文)"));
+
+        // 没有数量列就识别不出来
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        sheet.get_cell_mut((1, 5)).set_value("货号");
+        assert!(detect_template(book.get_sheet(&0).unwrap(), &synonyms).is_none());
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        let decimal = |value: &str| Decimal::from_str(value).unwrap();
+        assert_eq!(parse_decimal("12.5"), Ok(decimal("12.5")));
+        assert_eq!(parse_decimal(" 1,234.56 "), Ok(decimal("1234.56")));
+        assert_eq!(parse_decimal("¥0.35"), Ok(decimal("0.35")));
+        assert_eq!(parse_decimal("US$ 2.125"), Ok(decimal("2.125")));
+        assert_eq!(parse_decimal("1.23456"), Ok(decimal("1.2346")));
+        assert_eq!(parse_decimal("1.5e2"), Ok(decimal("150")));
+        assert!(parse_decimal("abc").is_err());
+        assert_eq!(parse_decimal("1,234.5"), Ok(decimal("1234.5")));
+        assert_eq!(parse_decimal("€1,234,567"), Ok(decimal("1234567")));
+        // 欧洲的小数逗号/不是千分位的逗号, 报出来不要改掉数字
+        assert!(parse_decimal("1,25").is_err());
+        assert!(parse_decimal("1,2,3").is_err());
+        assert!(parse_decimal("1,234.5,6").is_err());
+    }
+}
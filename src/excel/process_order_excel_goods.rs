@@ -1,67 +1,86 @@
 use crate::common::string::is_empty_string_vec;
 use crate::error::ERPResult;
+use crate::model::excel::ExcelIssue;
 use crate::model::goods::SKUModel;
 use crate::model::order::{
-    ExcelOrderGoods, ExcelOrderGoodsWithItems, OrderGoodsModel, OrderInfo, OrderItemExcel,
-    OrderItemModel,
+    ExcelOrderDiff, ExcelOrderGoods, ExcelOrderGoodsWithItems, OrderGoodsModel, OrderInfo,
+    OrderItemExcel, OrderItemModel,
 };
 use crate::ERPError;
 use itertools::Itertools;
-use sqlx::{Pool, Postgres};
+use sqlx::PgConnection;
 use std::collections::HashMap;
 
+/// 按序号把产品分组成商品, 有问题的序号记到issues里(不会中断)
 pub fn convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items(
     index_to_order_item_excel: HashMap<i32, Vec<OrderItemExcel>>,
     no_goods_no: bool, // template_id: i32
-) -> ERPResult<Vec<ExcelOrderGoodsWithItems>> {
+    sheet_name: &str,
+    issues: &mut Vec<ExcelIssue>,
+) -> Vec<ExcelOrderGoodsWithItems> {
     let empty_order_item_excel_vec: Vec<OrderItemExcel> = vec![];
     let mut res = vec![];
     for index in index_to_order_item_excel.keys().sorted() {
         let items = index_to_order_item_excel
             .get(index)
             .unwrap_or(&empty_order_item_excel_vec);
+        let first_row = items.first().map(|item| item.row).unwrap_or(0);
 
         if !no_goods_no {
             // 检查数据是否有问题(goods_no至少有一个值）
-            let mut goods_nos = items
+            let goods_nos = items
                 .iter()
                 .map(|item| item.goods_no.as_str())
                 .collect::<Vec<&str>>();
             tracing::info!("goods_nos: {goods_nos:?}");
 
             if is_empty_string_vec(&goods_nos) {
-                return Err(ERPError::ExcelError(format!(
-                    "Excel内序号#{index},没有读到商品编号"
-                )));
+                issues.push(ExcelIssue::new(
+                    sheet_name,
+                    first_row,
+                    0,
+                    "goods_no",
+                    "",
+                    &format!("序号#{index}没有读到商品编号"),
+                ));
+                continue;
             }
 
-            goods_nos.dedup();
-
-            if goods_nos.len() > 1 {
-                return Err(ERPError::ExcelError(format!(
-                    "请检查Excel内序号#{index}，有重复数据, 或者序号重复"
-                )));
+            let unique_goods_nos = goods_nos
+                .iter()
+                .filter(|goods_no| !goods_no.is_empty())
+                .unique()
+                .collect::<Vec<&&str>>();
+            if unique_goods_nos.len() > 1 {
+                issues.push(ExcelIssue::new(
+                    sheet_name,
+                    first_row,
+                    0,
+                    "index",
+                    &index.to_string(),
+                    &format!(
+                        "序号#{index}重复了, 对应了多个商品编号: {}",
+                        unique_goods_nos.iter().join(",")
+                    ),
+                ));
+                continue;
             }
-        } else {
-            // todo
-            // 没有goods_no
+        } else if items
+            .iter()
+            .all(|item| item.goods_no.is_empty() && item.sku_no.as_deref().unwrap_or("").is_empty())
+        {
+            // 没有goods_no, 从sku编号里取
+            issues.push(ExcelIssue::new(
+                sheet_name,
+                first_row,
+                0,
+                "sku_no",
+                "",
+                &format!("序号#{index}没有读到sku编号"),
+            ));
+            continue;
         }
 
-        // todo: 检查数据是否有问题
-        // let mut goods_nos = items
-        //     .iter()
-        //     .map(|item| item.goods_no.as_str())
-        //     .collect::<Vec<&str>>();
-        //
-        // println!("goods_no: {:?}", goods_nos);
-        // goods_nos.dedup();
-        // println!("goods_no: {:?}", goods_nos);
-        // if goods_nos.len() > 1 {
-        //     return Err(ERPError::ExcelError(format!(
-        //         "Excel内序号#{index}可能重复,或者有多余总计的行"
-        //     )));
-        // }
-
         let goods = OrderItemExcel::pick_up_excel_goods(items);
         tracing::info!("pick_up_excel_goods: {:?}", goods);
         let excel_order_goods_with_items = ExcelOrderGoodsWithItems {
@@ -71,11 +90,27 @@ pub fn convert_index_vec_order_item_excel_to_vec_excel_order_goods_with_items(
         res.push(excel_order_goods_with_items);
     }
 
-    Ok(res)
+    res
+}
+
+/// 续页sheet里的商品合并到订单里: 商品编号相同的合并产品, 否则作为新的商品
+pub fn merge_excel_order_goods(
+    order_goods: &mut Vec<ExcelOrderGoodsWithItems>,
+    sheet_goods: Vec<ExcelOrderGoodsWithItems>,
+) {
+    for goods in sheet_goods.into_iter() {
+        match order_goods
+            .iter_mut()
+            .find(|existing| existing.goods.goods_no == goods.goods.goods_no)
+        {
+            Some(existing) => existing.items.extend(goods.items),
+            None => order_goods.push(goods),
+        }
+    }
 }
 
 pub async fn process_order_excel_with_goods_no_and_sku_color(
-    db: &Pool<Postgres>,
+    db: &mut PgConnection,
     order_goods_excel: &Vec<ExcelOrderGoodsWithItems>,
     order_info: &OrderInfo,
     order_id: i32,
@@ -90,7 +125,7 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
         "select id, goods_no from goods where goods_no = any($1)",
         &goods_nos
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await
     .map_err(ERPError::DBError)?
     .into_iter()
@@ -148,7 +183,7 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
         "select * from skus where goods_id = any($1)",
         &goods_ids
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await
     .map_err(ERPError::DBError)?;
 
@@ -216,7 +251,7 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
         order_id,
         &goods_ids
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await
     .map_err(ERPError::DBError)?;
 
@@ -280,7 +315,7 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
         order_id,
         &order_goods_ids,
     )
-    .fetch_all(db)
+    .fetch_all(&mut *db)
     .await
     .map_err(ERPError::DBError)?
     .into_iter()
@@ -311,8 +346,9 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
                     sku_id: *this_sku_id,
                     count: order_item.count,
                     unit: Some(order_item.unit.as_deref().unwrap_or("").to_string()),
-                    unit_price: Some(order_item.unit_price.unwrap_or(0)),
-                    total_price: Some(order_item.total_price.unwrap_or(0)),
+                    // excel里没有单价时存NULL, 开票时才能发现
+                    unit_price: order_item.unit_price,
+                    total_price: Some(order_item.total_price.unwrap_or_default()),
                     notes_images: order_item.notes_images.clone(),
                     notes: order_item.notes.as_deref().unwrap_or("").to_string(),
                 })
@@ -326,3 +362,112 @@ pub async fn process_order_excel_with_goods_no_and_sku_color(
 
     Ok(())
 }
+
+/// 覆盖导入时, 按excel修改已有的产品, 删掉excel里已经没有的产品和商品
+pub async fn apply_order_items_diff(
+    db: &mut PgConnection,
+    order_id: i32,
+    diff: &ExcelOrderDiff,
+) -> ERPResult<()> {
+    for item in diff.changed_items.iter() {
+        let Some(values) = &item.new else {
+            continue;
+        };
+        sqlx::query!(
+            r#"
+            update order_items set count=$1, unit=$2, unit_price=$3, total_price=$4, notes=$5
+            where id = $6
+            "#,
+            values.count,
+            values.unit,
+            Some(values.unit_price).filter(|unit_price| !unit_price.is_zero()),
+            values.total_price,
+            values.notes,
+            item.order_item_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+    }
+
+    if diff.removed_items.is_empty() {
+        return Ok(());
+    }
+
+    let removed_order_item_ids = diff
+        .removed_items
+        .iter()
+        .map(|item| item.order_item_id)
+        .collect::<Vec<i32>>();
+
+    // 已经开始生产的产品不能直接删掉
+    let progress_count = sqlx::query!(
+        "select count(1) from progress where order_item_id = any($1)",
+        &removed_order_item_ids
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(ERPError::DBError)?
+    .count
+    .unwrap_or(0);
+    if progress_count > 0 {
+        return Err(ERPError::Failed(
+            "excel里删掉的产品已经有生产流程记录, 不能覆盖导入".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        "delete from order_items where id = any($1)",
+        &removed_order_item_ids
+    )
+    .execute(&mut *db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    sqlx::query!(
+        r#"
+        delete from order_goods og
+        where og.order_id = $1
+            and not exists (select 1 from order_items oi where oi.order_goods_id = og.id)
+        "#,
+        order_id
+    )
+    .execute(&mut *db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::process_order_excel_goods::merge_excel_order_goods;
+    use crate::model::order::{ExcelOrderGoodsWithItems, OrderItemExcel};
+
+    fn goods(goods_no: &str, colors: &[&str]) -> ExcelOrderGoodsWithItems {
+        let items = colors
+            .iter()
+            .map(|color| OrderItemExcel {
+                goods_no: goods_no.to_string(),
+                color: color.to_string(),
+                ..Default::default()
+            })
+            .collect::<Vec<OrderItemExcel>>();
+        ExcelOrderGoodsWithItems {
+            goods: OrderItemExcel::pick_up_excel_goods(&items),
+            items,
+        }
+    }
+
+    #[test]
+    fn test_merge_excel_order_goods() {
+        let mut order_goods = vec![goods("A", &["红"]), goods("B", &["红"])];
+        merge_excel_order_goods(
+            &mut order_goods,
+            vec![goods("B", &["蓝", "黑"]), goods("C", &["红"])],
+        );
+        assert_eq!(order_goods.len(), 3);
+        assert_eq!(order_goods[1].items.len(), 3);
+        assert_eq!(order_goods[2].goods.goods_no, "C");
+    }
+}
@@ -1,14 +1,23 @@
 pub mod routes_account;
+pub mod routes_currency;
 pub mod routes_customer;
 pub mod routes_excel;
+pub mod routes_excel_template;
 pub mod routes_goods;
+pub mod routes_invoice;
 pub mod routes_login;
 pub mod routes_material;
 pub mod routes_order;
 pub mod routes_progress;
+pub mod routes_purchase;
+pub mod routes_role;
+pub mod routes_shipment;
 pub mod routes_static;
 pub mod routes_stats;
+pub mod routes_stock;
+pub mod routes_supplier;
 pub mod routes_upload;
+pub mod routes_workflow;
 
 pub trait ListParamToSQLTrait {
     fn to_pagination_sql(&self) -> String;
@@ -1,16 +1,29 @@
+use crate::common::password::{check_password_strength, hash_password, verify_password};
 use crate::dto::dto_account::AccountDto;
+use crate::handler::routes_login::login_response;
 use crate::middleware::auth::auth;
-use crate::response::api_response::APIDataResponse;
-use crate::{AppState, ERPResult};
+use crate::middleware::permission::{AccountManage, Require};
+use crate::model::account::{AccountModel, DepartmentModel, RoleModel};
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
 use axum::extract::State;
 use axum::middleware;
-use axum::routing::get;
-use axum::{Extension, Router};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/api/account/info", get(account_info))
+        .route("/api/accounts", get(get_accounts).post(create_account))
+        .route("/api/departments", get(get_departments))
+        .route("/api/account/disable", post(disable_account))
+        .route("/api/account/password/reset", post(reset_password))
+        .route("/api/account/password/change", post(change_password))
         .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
@@ -21,3 +34,231 @@ pub async fn account_info(
 ) -> ERPResult<APIDataResponse<AccountDto>> {
     Ok(APIDataResponse::new(account))
 }
+
+async fn get_accounts(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<AccountDto>> {
+    let accounts = sqlx::query_as!(AccountModel, "select * from accounts order by id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    let id_to_department = sqlx::query_as!(DepartmentModel, "select * from departments")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|department| (department.id, department))
+        .collect::<HashMap<i32, DepartmentModel>>();
+
+    let id_to_role = sqlx::query_as!(RoleModel, "select * from roles")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|role| (role.id, role))
+        .collect::<HashMap<i32, RoleModel>>();
+
+    let account_dtos = accounts
+        .into_iter()
+        .map(|account| {
+            let department = id_to_department
+                .get(&account.department_id)
+                .cloned()
+                .unwrap_or(DepartmentModel {
+                    id: account.department_id,
+                    name: "".to_string(),
+                    steps: vec![],
+                    role_ids: vec![],
+                });
+            let roles = account
+                .role_ids
+                .iter()
+                .chain(department.role_ids.iter())
+                .filter_map(|role_id| id_to_role.get(role_id).cloned())
+                .collect::<Vec<RoleModel>>();
+            AccountDto::from(account, department, roles)
+        })
+        .collect::<Vec<AccountDto>>();
+
+    let count = account_dtos.len() as i32;
+    Ok(APIListResponse::new(account_dtos, count))
+}
+
+async fn get_departments(
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<DepartmentModel>> {
+    let departments = sqlx::query_as!(DepartmentModel, "select * from departments order by id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    let count = departments.len() as i32;
+    Ok(APIListResponse::new(departments, count))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CreateAccountParam {
+    name: String,
+    account: String,
+    password: String,
+    department_id: i32,
+    #[serde(default)]
+    role_ids: Vec<i32>,
+}
+
+async fn create_account(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateAccountParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let account_name = payload.account.trim();
+    if account_name.is_empty() {
+        return Err(ERPError::ParamNeeded("account".to_string()));
+    }
+    check_password_strength(&payload.password)?;
+
+    if sqlx::query!("select id from accounts where account=$1", account_name)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("账号{}", account_name)));
+    }
+
+    sqlx::query!(
+        "select id from departments where id=$1",
+        payload.department_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .ok_or(ERPError::NotFound("部门不存在".to_string()))?;
+
+    RoleModel::check_role_ids(&state.db, &payload.role_ids).await?;
+
+    let hashed_password = hash_password(&payload.password)?;
+    sqlx::query!(
+        r#"
+        insert into accounts (name, account, password, department_id, role_ids)
+        values ($1, $2, $3, $4, $5)
+        "#,
+        payload.name.trim(),
+        account_name,
+        hashed_password,
+        payload.department_id,
+        &payload.role_ids
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DisableAccountParam {
+    id: i32,
+    disabled: bool,
+}
+
+async fn disable_account(
+    Require(account, _): Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DisableAccountParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if account.id == payload.id && payload.disabled {
+        return Err(ERPError::Failed("不能停用自己的账号".to_string()));
+    }
+
+    let rows_affected = sqlx::query!(
+        "update accounts set disabled=$1, token_version=token_version+1 where id=$2",
+        payload.disabled,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::AccountNotFound);
+    }
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetPasswordParam {
+    id: i32,
+    password: String,
+}
+
+async fn reset_password(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<ResetPasswordParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    check_password_strength(&payload.password)?;
+
+    let hashed_password = hash_password(&payload.password)?;
+    // token_version+1: 该账号之前签发的token全部失效
+    let rows_affected = sqlx::query!(
+        "update accounts set password=$1, token_version=token_version+1 where id=$2",
+        hashed_password,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::AccountNotFound);
+    }
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswordParam {
+    old_password: String,
+    new_password: String,
+}
+
+async fn change_password(
+    Extension(account): Extension<AccountDto>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<ChangePasswordParam>, ERPError>,
+) -> ERPResult<Response> {
+    let account_model = sqlx::query_as!(
+        AccountModel,
+        "select * from accounts where id=$1",
+        account.id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .ok_or(ERPError::AccountNotFound)?;
+
+    if !verify_password(&payload.old_password, &account_model.password) {
+        return Err(ERPError::LoginFailForPasswordIsWrong);
+    }
+    check_password_strength(&payload.new_password)?;
+
+    let hashed_password = hash_password(&payload.new_password)?;
+    // token_version+1: 之前签发的token(包括别的设备上的)全部失效, 当前登录换发一个新token
+    let token_version = sqlx::query!(
+        "update accounts set password=$1, token_version=token_version+1 where id=$2 returning token_version",
+        hashed_password,
+        account.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .token_version;
+
+    login_response(&state, account, token_version)
+}
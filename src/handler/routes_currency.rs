@@ -0,0 +1,112 @@
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{InvoiceRead, InvoiceWrite, Require};
+use crate::model::currency::{CurrencyModel, ExchangeRateModel};
+use crate::response::api_response::{APIDataResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/currencies", get(get_currencies))
+        .route(
+            "/api/exchange/rates",
+            get(get_exchange_rates).post(create_exchange_rate),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+async fn get_currencies(
+    _: Require<InvoiceRead>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<CurrencyModel>> {
+    let currencies = CurrencyModel::get_list(&state.db).await?;
+
+    let count = currencies.len() as i32;
+    Ok(APIListResponse::new(currencies, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListExchangeRatesParam {
+    currency: Option<String>,
+    page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    page_size: Option<i32>,
+}
+
+async fn get_exchange_rates(
+    _: Require<InvoiceRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListExchangeRatesParam>, ERPError>,
+) -> ERPResult<APIListResponse<ExchangeRateModel>> {
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (rates, count) = ExchangeRateModel::get_list(
+        &state.db,
+        &param
+            .currency
+            .as_deref()
+            .unwrap_or("")
+            .trim()
+            .to_uppercase(),
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
+
+    Ok(APIListResponse::new(rates, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateExchangeRateParam {
+    currency: String,
+    rate: Decimal,                     // 1单位外币 = rate 基础货币
+    effective_date: Option<NaiveDate>, // 不传: 今天
+}
+
+/// 录入汇率, 同一币种同一天再录一次就是修改; 返回汇率id
+async fn create_exchange_rate(
+    Require(account, _): Require<InvoiceWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateExchangeRateParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    let currency =
+        CurrencyModel::get_by_code(&state.db, &payload.currency.trim().to_uppercase()).await?;
+    if currency.is_base {
+        return Err(ERPError::ParamError(format!(
+            "{}是基础货币, 不用录汇率",
+            currency.code
+        )));
+    }
+    if payload.rate <= Decimal::ZERO {
+        return Err(ERPError::ParamError("汇率必须大于0".to_string()));
+    }
+
+    let id = sqlx::query!(
+        r#"
+        insert into exchange_rates (currency, rate, effective_date, account_id)
+        values ($1, $2, $3, $4)
+        on conflict (currency, effective_date) do update set rate = excluded.rate, account_id = excluded.account_id, dt = now()
+        returning id
+        "#,
+        currency.code,
+        payload.rate,
+        payload.effective_date.unwrap_or(Utc::now().date_naive()),
+        account.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .id;
+
+    Ok(APIDataResponse::new(id))
+}
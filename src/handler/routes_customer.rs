@@ -1,11 +1,14 @@
 use crate::constants::DEFAULT_PAGE_SIZE;
 use crate::dto::dto_customer::CustomerDto;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{CustomerRead, CustomerWrite, Require};
+use crate::model::currency::CurrencyModel;
 use crate::model::customer::CustomerModel;
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::{get, post};
-use axum::{Json, Router};
+use axum::{middleware, Json, Router};
 use axum_extra::extract::WithRejection;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -15,6 +18,7 @@ pub fn routes(state: Arc<AppState>) -> Router {
         .route("/api/customers", get(get_customers).post(create_customer))
         .route("/api/customer/detail", get(detail_customer))
         .route("/api/customer/update", post(update_customer))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
 
@@ -28,6 +32,7 @@ struct ListCustomerParam {
 }
 
 async fn get_customers(
+    _: Require<CustomerRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<ListCustomerParam>, ERPError>,
 ) -> ERPResult<APIListResponse<CustomerDto>> {
@@ -61,21 +66,24 @@ struct CreateCustomerParam {
     pub address: Option<String>,
     pub phone: Option<String>,
     pub notes: Option<String>,
+    pub currency: Option<String>, // 不传: 基础货币
 }
 
 impl CreateCustomerParam {
     fn to_sql(&self) -> String {
         format!(
-            "insert into customers (customer_no, name, address, phone, notes) values ('{}', '{}', '{}', '{}', '{}')",
-            self.customer_no, self.name.as_ref().unwrap_or(&"".to_string()), self.address.as_ref().unwrap_or(&"".to_string()), self.phone.as_ref().unwrap_or(&"".to_string()), self.notes.as_ref().unwrap_or(&"".to_string())
+            "insert into customers (customer_no, name, address, phone, notes, currency) values ('{}', '{}', '{}', '{}', '{}', '{}')",
+            self.customer_no, self.name.as_ref().unwrap_or(&"".to_string()), self.address.as_ref().unwrap_or(&"".to_string()), self.phone.as_ref().unwrap_or(&"".to_string()), self.notes.as_ref().unwrap_or(&"".to_string()), self.currency.as_ref().unwrap_or(&"".to_string())
         )
     }
 }
 
 async fn create_customer(
+    _: Require<CustomerWrite>,
     State(state): State<Arc<AppState>>,
-    WithRejection(Json(payload), _): WithRejection<Json<CreateCustomerParam>, ERPError>,
+    WithRejection(Json(mut payload), _): WithRejection<Json<CreateCustomerParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
+    payload.currency = Some(CurrencyModel::checked(&state.db, payload.currency.as_deref()).await?);
     let customer = sqlx::query_as!(
         CustomerModel,
         "select * from customers where customer_no = $1",
@@ -105,6 +113,7 @@ struct DetailParam {
 }
 
 async fn detail_customer(
+    _: Require<CustomerRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<DetailParam>, ERPError>,
 ) -> ERPResult<APIDataResponse<CustomerModel>> {
@@ -148,6 +157,7 @@ struct UpdateCustomerParam {
     pub address: Option<String>,
     pub phone: Option<String>,
     pub notes: Option<String>,
+    pub currency: Option<String>,
 }
 
 impl UpdateCustomerParam {
@@ -167,6 +177,9 @@ impl UpdateCustomerParam {
         if let Some(notes) = &self.notes {
             set_clauses.push(format!("notes='{}'", notes))
         }
+        if let Some(currency) = &self.currency {
+            set_clauses.push(format!("currency='{}'", currency))
+        }
 
         format!(
             "update customers set {} where id = {};",
@@ -177,9 +190,15 @@ impl UpdateCustomerParam {
 }
 
 async fn update_customer(
+    _: Require<CustomerWrite>,
     State(state): State<Arc<AppState>>,
-    WithRejection(Json(payload), _): WithRejection<Json<UpdateCustomerParam>, ERPError>,
+    WithRejection(Json(mut payload), _): WithRejection<Json<UpdateCustomerParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
+    // 只影响之后的新订单, 已有订单还是原来的币种
+    if payload.currency.is_some() {
+        payload.currency =
+            Some(CurrencyModel::checked(&state.db, payload.currency.as_deref()).await?);
+    }
     let customer = sqlx::query_as!(
         CustomerModel,
         "select * from customers where id = $1",
@@ -1,19 +1,26 @@
 use crate::constants::STORAGE_FILE_PATH;
+use crate::excel::excel_order_file::{order_file_extension, ORDER_FILE_EXTENSIONS};
 use crate::excel::excel_order_parser::ExcelOrderParser;
-use crate::response::api_response::APIEmptyResponse;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{ExcelImport, Require};
+use crate::model::excel::ExcelSheetResult;
+use crate::model::order::ExcelOrderPreview;
+use crate::response::api_response::APIDataResponse;
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Multipart, State};
 use axum::response::{Html, IntoResponse};
 use axum::routing::{get, post};
-use axum::Router;
+use axum::{middleware, Router};
 use chrono::{Datelike, Timelike, Utc};
 use std::fs;
 use std::sync::Arc;
 
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
-        .route("/page/upload", get(page_upload_file))
         .route("/api/upload/excel", post(import_excel))
+        .route("/api/upload/excel/preview", post(preview_excel))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .route("/page/upload", get(page_upload_file))
         .with_state(state)
 }
 
@@ -26,7 +33,7 @@ async fn page_upload_file() -> impl IntoResponse {
 
 <form action="/api/upload/excel" method="post" enctype="multipart/form-data">
     Select image to upload:
-    <input type="file" name="file" id="fileToUpload">
+    <input type="file" name="file" id="fileToUpload" accept=".xlsx,.xls,.csv,.tsv">
     <input type="submit" value="Upload Image" name="submit">
 </form>
 
@@ -36,12 +43,16 @@ async fn page_upload_file() -> impl IntoResponse {
     )
 }
 
-async fn import_excel(
-    State(state): State<Arc<AppState>>,
-    mut multipart: Multipart,
-) -> ERPResult<APIEmptyResponse> {
+struct ExcelUpload {
+    file_path: String,
+    build_by: i32,
+    overwrite: bool,
+}
+
+async fn save_excel_upload(mut multipart: Multipart) -> ERPResult<ExcelUpload> {
     // let mut id = 0;
     let mut build_by = 0;
+    let mut overwrite = false;
     let mut file_path: String = "".to_string();
 
     // 1
@@ -50,6 +61,18 @@ async fn import_excel(
     while let Some(field) = multipart.next_field().await.unwrap() {
         let name = field.name().unwrap().to_string();
         if name == "file" {
+            // 按上传的文件名保留后缀(xlsx/xls/csv/tsv), 没有文件名的当成xlsx
+            let extension = match field.file_name() {
+                Some(file_name) => order_file_extension(file_name),
+                None => "xlsx".to_string(),
+            };
+            if !ORDER_FILE_EXTENSIONS.contains(&extension.as_str()) {
+                return Err(ERPError::Failed(format!(
+                    "不支持的文件格式: {}, 只支持{}",
+                    extension,
+                    ORDER_FILE_EXTENSIONS.join("/")
+                )));
+            }
             let data = field.bytes().await.unwrap();
             let now = Utc::now();
             let dir_path = format!(
@@ -60,13 +83,14 @@ async fn import_excel(
                 now.day()
             );
             let file_name = format!(
-                "{}{:02}{:02}{:02}{:02}{:02}.xlsx",
+                "{}{:02}{:02}{:02}{:02}{:02}.{}",
                 now.year(),
                 now.month(),
                 now.day(),
                 now.hour(),
                 now.minute(),
-                now.second()
+                now.second(),
+                extension
             );
             fs::create_dir_all(&dir_path)
                 .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", dir_path)))?;
@@ -88,6 +112,10 @@ async fn import_excel(
             build_by = data
                 .parse::<i32>()
                 .map_err(|_| ERPError::ConvertFailed("type".to_string()))?;
+        } else if name == "overwrite" {
+            let data = String::from_utf8(field.bytes().await.unwrap().to_vec()).unwrap();
+            tracing::info!("value of `{}` is: {}", name, data);
+            overwrite = matches!(data.trim(), "1" | "true");
         }
     }
 
@@ -96,13 +124,42 @@ async fn import_excel(
         return Err(ERPError::Failed("save excel file failed".to_string()));
     }
 
-    // 解析excel文件
-    let parser = ExcelOrderParser::new(&file_path, state.db.clone(), build_by);
-    let order_info = parser.parse().await?;
-    // tracing::info!("order_info: {:#?}", order_info);
+    Ok(ExcelUpload {
+        file_path,
+        build_by,
+        overwrite,
+    })
+}
 
-    if order_info.exists {
-        return Err(ERPError::AlreadyExists("订单已经导入".to_string()));
-    }
-    Ok(APIEmptyResponse::new())
+/// 预览: 不写数据库, 返回每个订单(一个文件里可以有多个sheet/订单)和已有订单的差异, 确认后再导入(overwrite=1)
+async fn preview_excel(
+    _: Require<ExcelImport>,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> ERPResult<APIDataResponse<Vec<ExcelOrderPreview>>> {
+    let upload = save_excel_upload(multipart).await?;
+
+    let parser = ExcelOrderParser::new(&upload.file_path, state.db.clone(), upload.build_by);
+    let preview = parser.preview().await?;
+
+    Ok(APIDataResponse::new(preview))
+}
+
+async fn import_excel(
+    _: Require<ExcelImport>,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> ERPResult<APIDataResponse<Vec<ExcelSheetResult>>> {
+    let upload = save_excel_upload(multipart).await?;
+
+    // 解析excel文件, 订单已存在且没有确认覆盖时, 不会写入任何数据
+    let parser = ExcelOrderParser::new(&upload.file_path, state.db.clone(), upload.build_by);
+    let excel_orders = parser.parse(upload.overwrite).await?;
+
+    // 按sheet返回: 对应的订单, 用的哪种列对应关系, 以及没有对应上的列
+    let sheets = excel_orders
+        .into_iter()
+        .flat_map(|order| order.sheets)
+        .collect::<Vec<ExcelSheetResult>>();
+    Ok(APIDataResponse::new(sheets))
 }
@@ -0,0 +1,421 @@
+use crate::excel::parse_order_template::{
+    is_carry_down_field, normalize_header, DEFAULT_CARRY_DOWN, EXCEL_TEMPLATE_FIELDS,
+};
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{ExcelImport, ExcelTemplateManage, Require};
+use crate::model::excel::{CustomerExcelTemplateModel, ExcelHeaderSynonymModel, ExcelTemplate};
+use crate::response::api_response::{APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::State;
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_extra::extract::WithRejection;
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/api/excel/templates",
+            get(get_excel_templates).post(create_excel_template),
+        )
+        .route("/api/excel/template/update", post(update_excel_template))
+        .route("/api/excel/template/delete", post(delete_excel_template))
+        .route(
+            "/api/excel/header/synonyms",
+            get(get_header_synonyms).post(create_header_synonym),
+        )
+        .route(
+            "/api/excel/header/synonym/delete",
+            post(delete_header_synonym),
+        )
+        .route(
+            "/api/customer/excel/templates",
+            get(get_customer_excel_templates).post(set_customer_excel_template),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+async fn get_excel_templates(
+    _: Require<ExcelImport>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<ExcelTemplate>> {
+    let templates = ExcelTemplate::get_all(&state.db).await?;
+
+    let count = templates.len() as i32;
+    Ok(APIListResponse::new(templates, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExcelTemplateColumnParam {
+    #[serde(default)]
+    col: i32,
+    #[serde(default)]
+    header: String,
+    field: String,
+    #[serde(default)]
+    remove_whitespace: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateExcelTemplateParam {
+    name: String,
+    header_row: i32,
+    start_row: i32,
+    #[serde(default)]
+    package_image_column: i32,
+    #[serde(default)]
+    goods_image_column: i32,
+    #[serde(default)]
+    notes_image_column: i32,
+    #[serde(default = "default_carry_down")]
+    carry_down: Vec<String>,
+    columns: Vec<ExcelTemplateColumnParam>,
+}
+
+fn default_carry_down() -> Vec<String> {
+    DEFAULT_CARRY_DOWN.iter().map(|f| f.to_string()).collect()
+}
+
+impl CreateExcelTemplateParam {
+    fn check(&self) -> ERPResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(ERPError::ParamNeeded("name".to_string()));
+        }
+        if self.start_row < 1 || self.start_row <= self.header_row {
+            return Err(ERPError::ParamError(
+                "数据开始的行必须在表头下面".to_string(),
+            ));
+        }
+        if self.package_image_column < 0
+            || self.goods_image_column < 0
+            || self.notes_image_column < 0
+        {
+            return Err(ERPError::ParamError("图片所在的列不正确".to_string()));
+        }
+
+        let mut fields = vec![];
+        for column in self.columns.iter() {
+            if !EXCEL_TEMPLATE_FIELDS.contains(&column.field.as_str()) {
+                return Err(ERPError::ParamError(format!("字段{}不存在", column.field)));
+            }
+            if fields.contains(&column.field.as_str()) {
+                return Err(ERPError::ParamError(format!("字段{}重复了", column.field)));
+            }
+            fields.push(column.field.as_str());
+
+            if column.col < 0 || (column.col == 0 && column.header.trim().is_empty()) {
+                return Err(ERPError::ParamError(format!(
+                    "字段{}需要设置列或表头名称",
+                    column.field
+                )));
+            }
+        }
+        // 数量为空的行就当作数据结束了
+        if !fields.contains(&"count") {
+            return Err(ERPError::ParamError("模版里必须有数量(count)".to_string()));
+        }
+        if let Some(field) = self
+            .carry_down
+            .iter()
+            .find(|field| !is_carry_down_field(field))
+        {
+            return Err(ERPError::ParamError(format!("字段{}不存在", field)));
+        }
+
+        Ok(())
+    }
+}
+
+async fn insert_columns(
+    db: &mut PgConnection,
+    template_id: i32,
+    columns: &[ExcelTemplateColumnParam],
+) -> ERPResult<()> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "insert into excel_template_columns (template_id, col, header, field, remove_whitespace) ",
+    );
+    query_builder.push_values(columns, |mut b, column| {
+        b.push_bind(template_id)
+            .push_bind(column.col)
+            .push_bind(column.header.trim())
+            .push_bind(&column.field)
+            .push_bind(column.remove_whitespace);
+    });
+    query_builder
+        .build()
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    Ok(())
+}
+
+async fn create_excel_template(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateExcelTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    payload.check()?;
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let template_id = sqlx::query!(
+        r#"
+        insert into excel_templates (name, header_row, start_row, package_image_column, goods_image_column, notes_image_column, carry_down)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        returning id
+        "#,
+        payload.name.trim(),
+        payload.header_row,
+        payload.start_row,
+        payload.package_image_column,
+        payload.goods_image_column,
+        payload.notes_image_column,
+        &payload.carry_down
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?
+    .id;
+    insert_columns(&mut tx, template_id, &payload.columns).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateExcelTemplateParam {
+    id: i32,
+    #[serde(flatten)]
+    template: CreateExcelTemplateParam,
+}
+
+async fn update_excel_template(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateExcelTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let template = &payload.template;
+    template.check()?;
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        update excel_templates set name = $1, header_row = $2, start_row = $3, package_image_column = $4,
+            goods_image_column = $5, notes_image_column = $6, carry_down = $7
+        where id = $8
+        "#,
+        template.name.trim(),
+        template.header_row,
+        template.start_row,
+        template.package_image_column,
+        template.goods_image_column,
+        template.notes_image_column,
+        &template.carry_down,
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("excel模版不存在".to_string()));
+    }
+
+    // 列整个替换掉
+    sqlx::query!(
+        "delete from excel_template_columns where template_id = $1",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    insert_columns(&mut tx, payload.id, &template.columns).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteExcelTemplateParam {
+    id: i32,
+}
+
+async fn delete_excel_template(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteExcelTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let customer_nos = sqlx::query!(
+        "select customer_no from customer_excel_template where template_id = $1",
+        payload.id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|r| r.customer_no)
+    .collect::<Vec<String>>();
+    if !customer_nos.is_empty() {
+        return Err(ERPError::Failed(format!(
+            "客户{}还在使用该模版",
+            customer_nos.join(",")
+        )));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let rows_affected = sqlx::query!("delete from excel_templates where id = $1", payload.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?
+        .rows_affected();
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("excel模版不存在".to_string()));
+    }
+    sqlx::query!(
+        "delete from excel_template_columns where template_id = $1",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn get_header_synonyms(
+    _: Require<ExcelImport>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<ExcelHeaderSynonymModel>> {
+    let synonyms = ExcelHeaderSynonymModel::get_all(&state.db).await?;
+
+    let count = synonyms.len() as i32;
+    Ok(APIListResponse::new(synonyms, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateHeaderSynonymParam {
+    field: String,
+    header: String,
+}
+
+async fn create_header_synonym(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateHeaderSynonymParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if !EXCEL_TEMPLATE_FIELDS.contains(&payload.field.as_str()) {
+        return Err(ERPError::ParamError(format!("字段{}不存在", payload.field)));
+    }
+    let header = normalize_header(&payload.header);
+    if header.is_empty() {
+        return Err(ERPError::ParamNeeded("header".to_string()));
+    }
+
+    if let Some(existing) = sqlx::query!(
+        "select field from excel_header_synonyms where header = $1",
+        header
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    {
+        return Err(ERPError::AlreadyExists(format!(
+            "表头{}(对应{})",
+            header, existing.field
+        )));
+    }
+
+    sqlx::query!(
+        "insert into excel_header_synonyms (field, header) values ($1, $2)",
+        payload.field,
+        header
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteHeaderSynonymParam {
+    id: i32,
+}
+
+async fn delete_header_synonym(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteHeaderSynonymParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let rows_affected = sqlx::query!(
+        "delete from excel_header_synonyms where id = $1",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("表头不存在".to_string()));
+    }
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn get_customer_excel_templates(
+    _: Require<ExcelImport>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<CustomerExcelTemplateModel>> {
+    let customer_excel_templates = sqlx::query_as!(
+        CustomerExcelTemplateModel,
+        "select * from customer_excel_template order by customer_no"
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    let count = customer_excel_templates.len() as i32;
+    Ok(APIListResponse::new(customer_excel_templates, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetCustomerExcelTemplateParam {
+    customer_no: String,
+    template_id: i32,
+}
+
+/// 设置客户导入excel时用哪个模版
+async fn set_customer_excel_template(
+    _: Require<ExcelTemplateManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<SetCustomerExcelTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let customer_no = payload.customer_no.trim();
+    if customer_no.is_empty() {
+        return Err(ERPError::ParamNeeded("customer_no".to_string()));
+    }
+    ExcelTemplate::get(&state.db, payload.template_id).await?;
+
+    sqlx::query!(
+        r#"
+        insert into customer_excel_template (customer_no, template_id)
+        values ($1, $2)
+        on conflict (customer_no) do update set template_id = excluded.template_id
+        "#,
+        customer_no,
+        payload.template_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
@@ -1,6 +1,8 @@
 use crate::constants::DEFAULT_PAGE_SIZE;
 use crate::dto::dto_goods::{GoodsDto, SKUModelDto, SKUModelWithoutImageAndPackageDto};
 use crate::handler::ListParamToSQLTrait;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{GoodsRead, GoodsWrite, Require};
 use crate::model::goods::{GoodsModel, SKUModel};
 use crate::model::order::OrderGoodsModel;
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
@@ -8,7 +10,7 @@ use crate::service::goods_service::GoodsService;
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::{get, post};
-use axum::{Json, Router};
+use axum::{middleware, Json, Router};
 use axum_extra::extract::WithRejection;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -21,6 +23,7 @@ pub fn routes(state: Arc<AppState>) -> Router {
         .route("/api/skus", get(get_skus).post(create_sku)) //.post(create_skus))
         .route("/api/sku/detail", get(get_sku_detail)) //.post(create_skus))
         .route("/api/sku/update", post(update_sku))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
 
@@ -126,6 +129,7 @@ impl ListParamToSQLTrait for ListGoodsParam {
 
 // todo
 async fn get_goods(
+    _: Require<GoodsRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<ListGoodsParam>, ERPError>,
 ) -> ERPResult<APIListResponse<GoodsDto>> {
@@ -257,6 +261,7 @@ impl ListParamToSQLTrait for ListSKUsParam {
 }
 
 async fn get_skus(
+    _: Require<GoodsRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<ListSKUsParam>, ERPError>,
 ) -> ERPResult<APIListResponse<SKUModelDto>> {
@@ -301,6 +306,7 @@ impl CreateSKUParam {
 }
 
 async fn create_sku(
+    _: Require<GoodsWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(param), _): WithRejection<Json<CreateSKUParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -331,6 +337,7 @@ struct SkuDetailParam {
 }
 
 async fn get_sku_detail(
+    _: Require<GoodsRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<SkuDetailParam>, ERPError>,
 ) -> ERPResult<APIDataResponse<SKUModelDto>> {
@@ -426,6 +433,7 @@ impl UpdateSKUParam {
 }
 
 async fn update_sku(
+    _: Require<GoodsWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<UpdateSKUParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
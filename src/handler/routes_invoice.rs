@@ -0,0 +1,299 @@
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{InvoiceRead, InvoiceWrite, Require};
+use crate::model::invoice::{CustomerReceivable, Invoice, InvoiceLine, InvoiceModel, PaymentModel};
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_PAYMENT_DAYS: i64 = 30; // 默认账期(天)
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/invoices", get(get_invoices).post(create_invoice))
+        .route("/api/invoice/detail", get(detail_invoice))
+        .route("/api/invoice/delete", post(delete_invoice))
+        .route("/api/payments", post(create_payment))
+        .route("/api/payment/delete", post(delete_payment))
+        .route("/api/receivables", get(get_receivables))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+struct ListInvoicesParam {
+    customer_no: Option<String>,
+    #[serde(default)]
+    unpaid: bool, // 只看还没收完的
+    page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    page_size: Option<i32>,
+}
+
+async fn get_invoices(
+    _: Require<InvoiceRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListInvoicesParam>, ERPError>,
+) -> ERPResult<APIListResponse<InvoiceModel>> {
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (invoices, count) = InvoiceModel::get_list(
+        &state.db,
+        param.customer_no.as_deref().unwrap_or(""),
+        param.unpaid,
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
+
+    Ok(APIListResponse::new(invoices, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemDiscountParam {
+    order_item_id: i32,
+    discount: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateInvoiceParam {
+    #[serde(default)]
+    order_ids: Vec<i32>, // 按订单开票: 开每个sku还没开过的数量
+    #[serde(default)]
+    shipment_ids: Vec<i32>, // 按发货单开票: 开发出去的数量
+    invoice_no: Option<String>,      // 不传: INV+开票日期+序号
+    invoice_date: Option<NaiveDate>, // 不传: 今天
+    due_date: Option<NaiveDate>,     // 不传: 开票日期后30天
+    #[serde(default)]
+    discount: Decimal, // 整单折扣金额
+    #[serde(default)]
+    tax_rate: Decimal, // 税率(%)
+    #[serde(default)]
+    item_discounts: Vec<ItemDiscountParam>, // 单个sku的折扣金额
+    #[serde(default)]
+    notes: String,
+}
+
+/// 由订单或发货单生成发票, 返回发票id
+async fn create_invoice(
+    Require(account, _): Require<InvoiceWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateInvoiceParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    if payload.order_ids.is_empty() == payload.shipment_ids.is_empty() {
+        return Err(ERPError::ParamError(
+            "order_ids和shipment_ids要传且只能传一个".to_string(),
+        ));
+    }
+    let invoice_date = payload.invoice_date.unwrap_or(Utc::now().date_naive());
+    let due_date = payload
+        .due_date
+        .unwrap_or(invoice_date + Duration::days(DEFAULT_PAYMENT_DAYS));
+    if due_date < invoice_date {
+        return Err(ERPError::ParamError("到期日不能早于开票日期".to_string()));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    // 发票号按当天的发票数生成, 开票排队处理避免重号; 开票的数量由InvoiceLine里锁住的sku保证不重复
+    sqlx::query!("select pg_advisory_xact_lock(hashtext('invoice'))")
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    let mut lines = match payload.order_ids.is_empty() {
+        false => InvoiceLine::from_orders(&mut tx, &payload.order_ids).await?,
+        true => InvoiceLine::from_shipments(&mut tx, &payload.shipment_ids).await?,
+    };
+    for item_discount in payload.item_discounts.iter() {
+        let line = lines
+            .iter_mut()
+            .find(|line| line.order_item_id == item_discount.order_item_id)
+            .ok_or(ERPError::NotFound(format!(
+                "发票里没有产品#{}",
+                item_discount.order_item_id
+            )))?;
+        if item_discount.discount < Decimal::ZERO {
+            return Err(ERPError::ParamError("折扣不能小于0".to_string()));
+        }
+        line.discount = item_discount.discount;
+    }
+
+    let invoice_no = match payload.invoice_no.as_deref().map(str::trim) {
+        Some(invoice_no) if !invoice_no.is_empty() => invoice_no.to_string(),
+        _ => {
+            let count = sqlx::query!(
+                "select count(1) from invoices where invoice_date = $1",
+                invoice_date
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(ERPError::DBError)?
+            .count
+            .unwrap_or(0);
+            format!("INV{}-{:03}", invoice_date.format("%Y%m%d"), count + 1)
+        }
+    };
+    if sqlx::query!("select id from invoices where invoice_no = $1", invoice_no)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("发票#{}", invoice_no)));
+    }
+
+    let invoice = InvoiceModel {
+        id: 0,
+        invoice_no,
+        customer_no: lines
+            .first()
+            .map(|line| line.customer_no.clone())
+            .unwrap_or_default(),
+        invoice_date,
+        due_date,
+        subtotal: Decimal::ZERO,
+        discount: payload.discount,
+        tax_rate: payload.tax_rate,
+        tax: Decimal::ZERO,
+        total: Decimal::ZERO,
+        paid: Decimal::ZERO,
+        notes: payload.notes.clone(),
+        account_id: account.id,
+        dt: Utc::now(),
+        currency: lines
+            .first()
+            .map(|line| line.currency.clone())
+            .unwrap_or_default(),
+    };
+    let invoice_id = InvoiceModel::create(&mut tx, &invoice, &lines).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIDataResponse::new(invoice_id))
+}
+
+#[derive(Debug, Deserialize)]
+struct InvoiceIdParam {
+    id: i32,
+}
+
+async fn detail_invoice(
+    _: Require<InvoiceRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<InvoiceIdParam>, ERPError>,
+) -> ERPResult<APIDataResponse<Invoice>> {
+    let invoice = Invoice::get(&state.db, param.id).await?;
+
+    Ok(APIDataResponse::new(invoice))
+}
+
+/// 已经有收款的发票不能删, 要先删收款记录
+async fn delete_invoice(
+    _: Require<InvoiceWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<InvoiceIdParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let invoice = Invoice::get(&state.db, payload.id).await?;
+    if !invoice.payments.is_empty() {
+        return Err(ERPError::Failed(
+            "该发票已经有收款记录, 不能删除".to_string(),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    sqlx::query!(
+        "delete from invoice_items where invoice_id = $1",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    sqlx::query!("delete from invoices where id = $1", payload.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatePaymentParam {
+    invoice_id: i32,
+    amount: Decimal,
+    payment_date: Option<NaiveDate>, // 不传: 今天
+    #[serde(default)]
+    method: String,
+    #[serde(default)]
+    notes: String,
+}
+
+/// 记一笔收款(可以分多次收), 返回发票累计已收
+async fn create_payment(
+    Require(account, _): Require<InvoiceWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreatePaymentParam>, ERPError>,
+) -> ERPResult<APIDataResponse<Decimal>> {
+    let payment = PaymentModel {
+        id: 0,
+        invoice_id: payload.invoice_id,
+        amount: payload.amount,
+        payment_date: payload.payment_date.unwrap_or(Utc::now().date_naive()),
+        method: payload.method.trim().to_string(),
+        notes: payload.notes.clone(),
+        account_id: account.id,
+        dt: Utc::now(),
+    };
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let paid = InvoiceModel::record_payment(&mut tx, &payment).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIDataResponse::new(paid))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeletePaymentParam {
+    id: i32,
+}
+
+async fn delete_payment(
+    _: Require<InvoiceWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeletePaymentParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    InvoiceModel::delete_payment(&mut tx, payload.id).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct ReceivablesParam {
+    customer_no: Option<String>,
+    as_of: Option<NaiveDate>, // 账龄算到哪一天(只算这天及之前的收款), 不传: 今天
+}
+
+/// 每个客户的应收账款和账龄
+async fn get_receivables(
+    _: Require<InvoiceRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ReceivablesParam>, ERPError>,
+) -> ERPResult<APIListResponse<CustomerReceivable>> {
+    let receivables = CustomerReceivable::get_list(
+        &state.db,
+        param.customer_no.as_deref().unwrap_or(""),
+        param.as_of.unwrap_or(Utc::now().date_naive()),
+    )
+    .await?;
+
+    let count = receivables.len() as i32;
+    Ok(APIListResponse::new(receivables, count))
+}
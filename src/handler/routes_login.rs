@@ -1,23 +1,53 @@
-use crate::dto::dto_account::AccountDto;
-use crate::model::account::{AccountModel, DepartmentModel};
+use crate::common::password::{hash_password, is_hashed_password, verify_password};
+use crate::dto::dto_account::{AccountDto, LoginDto};
+use crate::middleware::auth::auth;
+use crate::middleware::jwt_auth::{
+    create_token, decode_token, extract_token, revoke_token, token_cookie, TokenClaims,
+};
+use crate::model::account::{AccountModel, DepartmentModel, RoleModel};
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse};
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::State;
-use axum::http::header;
-use axum::response::IntoResponse;
-use axum::{routing::post, Json, Router};
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum::http::{header, HeaderMap};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::{routing::post, Extension, Json, Router};
+use axum_extra::extract::cookie::CookieJar;
 use axum_extra::extract::WithRejection;
 use serde::Deserialize;
 use std::sync::Arc;
 
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
+        .route("/api/token/refresh", post(api_refresh_token))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .route("/api/login", post(api_login))
         .route("/api/logout", post(api_logout))
         .with_state(state)
 }
 
+/// 生成新token, 返回带 Set-Cookie 的响应 (浏览器用cookie, 其他客户端用返回的token)
+pub fn login_response(
+    state: &AppState,
+    account_dto: AccountDto,
+    token_version: i32,
+) -> ERPResult<Response> {
+    let (token, claims) = create_token(account_dto.id, token_version, &state.env)?;
+    let cookie = token_cookie(&token, time::Duration::minutes(state.env.jwt_expires_in));
+
+    let login_dto = LoginDto {
+        account: account_dto,
+        token,
+        expires_at: claims.exp,
+    };
+    let mut response = APIDataResponse::new(login_dto).into_response();
+    response
+        .headers_mut()
+        .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+
+    Ok(response)
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LoginPayload {
     pub account: String,
@@ -39,13 +69,27 @@ async fn api_login(
     .map_err(ERPError::DBError)?
     .ok_or(ERPError::NotFound("账号不存在".to_string()))?;
 
-    // let account_unwrap = account.unwrap();
-    // todo: hash password.
-    if account.password != payload.password {
+    if account.disabled {
+        return Err(ERPError::AccountDisabled);
+    }
+
+    if !verify_password(&payload.password, &account.password) {
         return Err(ERPError::LoginFailForPasswordIsWrong);
     }
 
-    let account_id = account.id;
+    // 老的明文密码, 登陆成功后升级成hash
+    if !is_hashed_password(&account.password) {
+        let hashed_password = hash_password(&payload.password)?;
+        sqlx::query!(
+            "update accounts set password=$1 where id=$2",
+            hashed_password,
+            account.id
+        )
+        .execute(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+    }
+
     let department = sqlx::query_as!(
         DepartmentModel,
         "select * from departments where id=$1",
@@ -55,34 +99,38 @@ async fn api_login(
     .await
     .map_err(ERPError::DBError)?;
 
-    let account_dto = AccountDto::from(account, department);
-
-    let cookie = Cookie::build("account_id", account_id.to_string())
-        .path("/")
-        .max_age(time::Duration::days(365 * 10))
-        .same_site(SameSite::None)
-        .domain("")
-        .http_only(true)
-        .secure(true)
-        .finish();
+    let roles = RoleModel::get_roles_of_account(&state.db, &account, &department).await?;
+    let token_version = account.token_version;
+    let account_dto = AccountDto::from(account, department, roles);
+    login_response(&state, account_dto, token_version)
+}
 
-    let mut response = APIDataResponse::new(account_dto).into_response();
-    response
-        .headers_mut()
-        .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+/// 换一个新token, 旧的作废
+async fn api_refresh_token(
+    Extension(account): Extension<AccountDto>,
+    Extension(claims): Extension<TokenClaims>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<impl IntoResponse> {
+    // auth里已经校验过claims.ver和账号当前的token_version一致
+    let response = login_response(&state, account, claims.ver)?;
+    revoke_token(&state.db, &claims).await?;
 
     Ok(response)
 }
 
-async fn api_logout() -> ERPResult<impl IntoResponse> {
-    let cookie = Cookie::build("account_id", "")
-        .path("/")
-        .max_age(time::Duration::hours(-1))
-        .domain("")
-        .same_site(SameSite::None)
-        .secure(true)
-        .http_only(true)
-        .finish();
+async fn api_logout(
+    cookie_jar: CookieJar,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<impl IntoResponse> {
+    // token已经无效(过期/被篡改)时不用注销, 直接清cookie
+    if let Some(token) = extract_token(&cookie_jar, &headers) {
+        if let Ok(claims) = decode_token(&token, &state.env) {
+            revoke_token(&state.db, &claims).await?;
+        }
+    }
+
+    let cookie = token_cookie("", time::Duration::hours(-1));
 
     let mut response = APIEmptyResponse::new().into_response();
     response
@@ -1,17 +1,30 @@
 use crate::constants::DEFAULT_PAGE_SIZE;
-use crate::handler::ListParamToSQLTrait;
-use crate::model::order::{OrderItemMaterialModel, OrderItemModel};
+use crate::dto::dto_material::OrderItemMaterialDto;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{MaterialRead, MaterialWrite, Require};
+use crate::model::material::{
+    material_total, MaterialModel, OrderItemMaterialModel, SkuMaterial, SkuMaterialLine,
+};
+use crate::model::order::OrderItemModel;
 use crate::response::api_response::{APIEmptyResponse, APIListResponse};
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::{get, post};
-use axum::{Json, Router};
+use axum::{middleware, Json, Router};
 use axum_extra::extract::WithRejection;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
+        .route("/api/materials", get(get_materials).post(create_material))
+        .route("/api/material/update", post(update_material))
+        .route("/api/material/delete", post(delete_material))
+        .route(
+            "/api/sku/materials",
+            get(get_sku_materials).post(set_sku_materials),
+        )
         .route(
             "/api/order/item/materials",
             get(get_order_item_materials).post(add_order_item_materials),
@@ -20,99 +33,351 @@ pub fn routes(state: Arc<AppState>) -> Router {
             "/api/order/item/material/update",
             post(update_order_item_material),
         )
+        .route(
+            "/api/order/item/material/delete",
+            post(delete_order_item_material),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct ListOrderItemMaterialsParam {
-    pub order_id: Option<i32>,
-    pub order_item_id: i32,
-    pub name: Option<String>,
-    pub color: Option<String>,
-    pub page: Option<i32>,
+#[derive(Debug, Deserialize)]
+struct ListMaterialsParam {
+    name: Option<String>,
+    page: Option<i32>,
     #[serde(rename(deserialize = "pageSize"))]
-    pub page_size: Option<i32>,
+    page_size: Option<i32>,
 }
 
-impl ListParamToSQLTrait for ListOrderItemMaterialsParam {
-    fn to_pagination_sql(&self) -> String {
-        let mut sql = "select * from order_item_materials".to_string();
-        let mut where_clauses = vec![];
-        if let Some(order_id) = self.order_id {
-            where_clauses.push(format!("order_id={}", order_id));
-        }
-        where_clauses.push(format!("order_item_id={}", self.order_item_id));
-        if self.name.is_some() && !self.name.as_ref().unwrap().is_empty() {
-            where_clauses.push(format!("name='{}'", self.name.as_ref().unwrap()));
-        }
-        if self.color.is_some() && !self.color.as_ref().unwrap().is_empty() {
-            where_clauses.push(format!("color='{}'", self.color.as_ref().unwrap()));
-        }
-        sql.push_str(" where ");
-        sql.push_str(&where_clauses.join(" and "));
+async fn get_materials(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListMaterialsParam>, ERPError>,
+) -> ERPResult<APIListResponse<MaterialModel>> {
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (materials, count) = MaterialModel::get_list(
+        &state.db,
+        param.name.as_deref().unwrap_or("").trim(),
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
 
-        let page = self.page.unwrap_or(1);
-        let page_size = self.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
-        sql.push_str(&format!(
-            " order by id desc limit {page} offset {page_size};"
-        ));
+    Ok(APIListResponse::new(materials, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct MaterialParam {
+    #[serde(default)]
+    id: i32,
+    name: String,
+    #[serde(default)]
+    color: String,
+    #[serde(default)]
+    unit: String,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    unit_cost: Decimal,
+}
 
-        tracing::info!("{sql}");
-        sql
+async fn check_material_duplicate(
+    state: &AppState,
+    id: i32,
+    name: &str,
+    color: &str,
+) -> ERPResult<()> {
+    if name.is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    let existing = sqlx::query!(
+        "select id from materials where name = $1 and color = $2 and id != $3",
+        name,
+        color,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+    if existing.is_some() {
+        return Err(ERPError::AlreadyExists(format!("材料{}-{}", name, color)));
     }
 
-    fn to_count_sql(&self) -> String {
-        let mut sql = "select count(1) from order_item_materials".to_string();
-        let mut where_clauses = vec![];
-        if let Some(order_id) = self.order_id {
-            where_clauses.push(format!("order_id={}", order_id));
-        }
-        where_clauses.push(format!("order_item_id={}", self.order_item_id));
-        if self.name.is_some() && !self.name.as_ref().unwrap().is_empty() {
-            where_clauses.push(format!("name='{}'", self.name.as_ref().unwrap()));
-        }
-        if self.color.is_some() && !self.color.as_ref().unwrap().is_empty() {
-            where_clauses.push(format!("color='{}'", self.color.as_ref().unwrap()));
-        }
-        sql.push_str(" where ");
-        sql.push_str(&where_clauses.join(" and "));
-        sql.push(';');
+    Ok(())
+}
 
-        tracing::info!("{sql}");
-        sql
+fn check_unit_cost(unit_cost: Decimal) -> ERPResult<()> {
+    if unit_cost < Decimal::ZERO {
+        return Err(ERPError::ParamError("材料单价不能小于0".to_string()));
     }
+
+    Ok(())
 }
 
-async fn get_order_item_materials(
+async fn create_material(
+    _: Require<MaterialWrite>,
     State(state): State<Arc<AppState>>,
-    WithRejection(Query(param), _): WithRejection<Query<ListOrderItemMaterialsParam>, ERPError>,
-) -> ERPResult<APIListResponse<OrderItemMaterialModel>> {
-    let materials = sqlx::query_as::<_, OrderItemMaterialModel>(&param.to_pagination_sql())
-        .fetch_all(&state.db)
+    WithRejection(Json(payload), _): WithRejection<Json<MaterialParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let name = payload.name.trim();
+    let color = payload.color.trim();
+    check_material_duplicate(&state, 0, name, color).await?;
+    check_unit_cost(payload.unit_cost)?;
+
+    sqlx::query!(
+        "insert into materials (name, color, unit, notes, unit_cost) values ($1, $2, $3, $4, $5)",
+        name,
+        color,
+        payload.unit.trim(),
+        payload.notes,
+        payload.unit_cost
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+/// 修改材料只影响之后生成的材料单, 已经生成的不变
+async fn update_material(
+    _: Require<MaterialWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<MaterialParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    MaterialModel::get_by_id(&state.db, payload.id).await?;
+    let name = payload.name.trim();
+    let color = payload.color.trim();
+    check_material_duplicate(&state, payload.id, name, color).await?;
+    check_unit_cost(payload.unit_cost)?;
+
+    sqlx::query!(
+        "update materials set name = $1, color = $2, unit = $3, notes = $4, unit_cost = $5 where id = $6",
+        name,
+        color,
+        payload.unit.trim(),
+        payload.notes,
+        payload.unit_cost,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteParam {
+    id: i32,
+}
+
+async fn delete_material(
+    _: Require<MaterialWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let used = sqlx::query!(
+        "select count(1) from sku_materials where material_id = $1",
+        payload.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .count
+    .unwrap_or(0);
+    if used > 0 {
+        return Err(ERPError::Collision(format!(
+            "材料#{}还在{}个sku的材料清单里",
+            payload.id, used
+        )));
+    }
+
+    // 订单的材料单和库存流水里用到的材料不能删, 不然库存和材料单里就对不上了
+    let references = sqlx::query!(
+        r#"
+        select
+            (select count(1) from order_item_materials where material_id = $1) as "order_items!",
+            (select count(1) from stock_movements where material_id = $1) as "movements!"
+        "#,
+        payload.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+    if references.order_items > 0 {
+        return Err(ERPError::Collision(format!(
+            "材料#{}还在{}个订单产品的材料单里",
+            payload.id, references.order_items
+        )));
+    }
+    if references.movements > 0 {
+        return Err(ERPError::Collision(format!(
+            "材料#{}已经有{}条库存流水, 不能删除",
+            payload.id, references.movements
+        )));
+    }
+
+    sqlx::query!("delete from materials where id = $1", payload.id)
+        .execute(&state.db)
         .await
         .map_err(ERPError::DBError)?;
 
-    let total: (i64,) = sqlx::query_as(&param.to_count_sql())
-        .fetch_one(&state.db)
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct SkuMaterialsParam {
+    sku_id: i32,
+}
+
+async fn get_sku_materials(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<SkuMaterialsParam>, ERPError>,
+) -> ERPResult<APIListResponse<SkuMaterial>> {
+    let materials = SkuMaterial::get_by_sku_id(&state.db, param.sku_id).await?;
+
+    let count = materials.len() as i32;
+    Ok(APIListResponse::new(materials, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetSkuMaterialsParam {
+    sku_id: i32,
+    materials: Vec<SkuMaterialLine>,
+}
+
+/// 设置sku的材料清单(整个替换), 之后导入的订单会按这个自动生成材料单
+async fn set_sku_materials(
+    _: Require<MaterialWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<SetSkuMaterialsParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    sqlx::query!("select id from skus where id = $1", payload.sku_id)
+        .fetch_optional(&state.db)
         .await
-        .map_err(ERPError::DBError)?;
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("sku#{}", payload.sku_id)))?;
+
+    let mut material_ids = vec![];
+    for line in payload.materials.iter() {
+        if line.single <= Decimal::ZERO {
+            return Err(ERPError::ParamError(format!(
+                "材料#{}的单件用量必须大于0",
+                line.material_id
+            )));
+        }
+        if material_ids.contains(&line.material_id) {
+            return Err(ERPError::ParamError(format!(
+                "材料#{}重复了",
+                line.material_id
+            )));
+        }
+        material_ids.push(line.material_id);
+    }
+    let count = sqlx::query!(
+        "select count(1) from materials where id = any($1)",
+        &material_ids
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .count
+    .unwrap_or(0);
+    if count as usize != material_ids.len() {
+        return Err(ERPError::NotFound("材料".to_string()));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    SkuMaterial::set_for_sku(&mut tx, payload.sku_id, &payload.materials).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ListOrderItemMaterialsParam {
+    pub order_id: Option<i32>,
+    pub order_item_id: Option<i32>,
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    pub page_size: Option<i32>,
+}
+
+async fn get_order_item_materials(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListOrderItemMaterialsParam>, ERPError>,
+) -> ERPResult<APIListResponse<OrderItemMaterialDto>> {
+    let order_id = param.order_id.unwrap_or(0);
+    let order_item_id = param.order_item_id.unwrap_or(0);
+    if order_id == 0 && order_item_id == 0 {
+        return Err(ERPError::ParamNeeded("order_id或order_item_id".to_string()));
+    }
+    let name = param.name.as_deref().unwrap_or("").trim();
+    let color = param.color.as_deref().unwrap_or("").trim();
+
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+
+    let materials = sqlx::query_as!(
+        OrderItemMaterialModel,
+        r#"
+        select * from order_item_materials
+        where ($1 = 0 or order_id = $1) and ($2 = 0 or order_item_id = $2)
+            and ($3 = '' or name = $3) and ($4 = '' or color = $4)
+        order by order_item_id, id offset $5 limit $6
+        "#,
+        order_id,
+        order_item_id,
+        name,
+        color,
+        offset as i64,
+        page_size as i64
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
 
-    Ok(APIListResponse::new(materials, total.0 as i32))
+    let total = sqlx::query!(
+        r#"
+        select count(1) from order_item_materials
+        where ($1 = 0 or order_id = $1) and ($2 = 0 or order_item_id = $2)
+            and ($3 = '' or name = $3) and ($4 = '' or color = $4)
+        "#,
+        order_id,
+        order_item_id,
+        name,
+        color
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .count
+    .unwrap_or(0);
+
+    let materials = OrderItemMaterialDto::from_materials(&state.db, materials).await?;
+
+    Ok(APIListResponse::new(materials, total as i32))
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct CreateOrderItemMaterialParam {
-    pub order_id: i32,
-    pub order_item_id: i32,
+    #[serde(default)]
+    pub material_id: i32, // 选了材料表里的材料时, 名称/颜色/单位用材料表里的
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub color: String,
-    // material_id   integer, -- 材料ID  (暂时先不用)
-    pub single: Option<i32>,   //  integer, -- 单数      ？小数
-    pub count: i32,            //  integer, -- 数量      ？小数
-    pub total: Option<i32>,    //  integer, -- 总数(米)  ? 小数
-    pub stock: Option<i32>,    //  integer, -- 库存 ?
-    pub debt: Option<i32>,     //  integer, -- 欠数
-    pub notes: Option<String>, //  text     -- 备注
+    pub unit: Option<String>,
+    pub single: Decimal, // 单件用量
+    pub notes: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -121,34 +386,15 @@ struct CreateOrderItemMaterialsParam {
     materials: Vec<CreateOrderItemMaterialParam>,
 }
 
-impl CreateOrderItemMaterialsParam {
-    fn to_sql(&self) -> String {
-        let values = self
-            .materials
-            .iter()
-            .map(|material| {
-                format!(
-                    "({}, {}, '{}', '{}', {:?}, {}, {:?}, {:?}, {:?}, '{:?}')",
-                    material.order_id,
-                    material.order_item_id,
-                    material.name,
-                    material.color,
-                    material.single,
-                    material.count,
-                    material.total,
-                    material.stock,
-                    material.debt,
-                    material.notes
-                )
-            })
-            .collect::<Vec<String>>()
-            .join(",");
-
-        format!("insert into order_item_materials (order_id, order_item_id, name, color, single, count, total, stock, debt, notes) values {};", values)
+fn check_single(single: Decimal) -> ERPResult<()> {
+    if single <= Decimal::ZERO {
+        return Err(ERPError::ParamError("单件用量必须大于0".to_string()));
     }
+    Ok(())
 }
 
 async fn add_order_item_materials(
+    _: Require<MaterialWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<CreateOrderItemMaterialsParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -157,38 +403,78 @@ async fn add_order_item_materials(
         return Err(ERPError::ParamNeeded("materials".to_string()));
     }
 
+    let order_item = sqlx::query_as!(
+        OrderItemModel,
+        "select * from order_items where id = $1",
+        payload.order_item_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .ok_or(ERPError::NotFound(format!(
+        "OrderItem#{}",
+        payload.order_item_id
+    )))?;
+
+    let mut materials = vec![];
+    for param in payload.materials.iter() {
+        check_single(param.single)?;
+        let (name, color, unit) = match param.material_id {
+            0 => (
+                param.name.trim().to_string(),
+                param.color.trim().to_string(),
+                param.unit.as_deref().unwrap_or("").trim().to_string(),
+            ),
+            material_id => {
+                let material = MaterialModel::get_by_id(&state.db, material_id).await?;
+                (material.name, material.color, material.unit)
+            }
+        };
+        if name.is_empty() {
+            return Err(ERPError::ParamNeeded("name".to_string()));
+        }
+
+        materials.push(OrderItemMaterialModel {
+            id: 0,
+            order_id: order_item.order_id,
+            order_item_id: order_item.id,
+            material_id: param.material_id,
+            name,
+            color,
+            unit,
+            single: param.single,
+            count: order_item.count,
+            total: material_total(param.single, order_item.count),
+            notes: param.notes.clone().unwrap_or_default(),
+        });
+    }
+
     // checking material
-    let existings = sqlx::query_as::<_, OrderItemMaterialModel>(&format!(
-        "select * from order_item_materials where order_item_id={};",
+    let existings = sqlx::query!(
+        "select name, color from order_item_materials where order_item_id = $1",
         payload.order_item_id
-    ))
+    )
     .fetch_all(&state.db)
     .await
-    .map_err(ERPError::DBError)?;
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|material| (material.name, material.color))
+    .collect::<Vec<(String, String)>>();
 
-    // if already have some material, then check against it.
-    if !existings.is_empty() {
-        let existing_name_color_tuples: Vec<(String, String)> = existings
-            .iter()
-            .map(|material| (material.name.clone(), material.color.clone()))
-            .collect();
-
-        let duplicates = payload
-            .materials
-            .iter()
-            .filter(|&material| {
-                existing_name_color_tuples
-                    .contains(&(material.name.clone(), material.color.clone()))
-            })
-            .map(|material| format!("({}-{})", material.name, material.color))
-            .collect::<Vec<String>>();
-
-        if !duplicates.is_empty() {
-            return Err(ERPError::AlreadyExists(duplicates.join(",")));
+    let mut name_color_tuples = existings;
+    let mut duplicates = vec![];
+    for material in materials.iter() {
+        let name_color = (material.name.clone(), material.color.clone());
+        if name_color_tuples.contains(&name_color) {
+            duplicates.push(format!("({}-{})", material.name, material.color));
         }
+        name_color_tuples.push(name_color);
+    }
+    if !duplicates.is_empty() {
+        return Err(ERPError::AlreadyExists(duplicates.join(",")));
     }
 
-    state.execute_sql(&payload.to_sql()).await?;
+    OrderItemMaterialModel::insert_many(&state.db, &materials).await?;
 
     Ok(APIEmptyResponse::new())
 }
@@ -196,60 +482,73 @@ async fn add_order_item_materials(
 #[derive(Debug, Deserialize)]
 struct UpdateOrderItemMaterialParam {
     id: i32,
-    order_id: i32,
-    sku_id: i32,
-    package_card: Option<String>,
-    package_card_des: Option<String>,
-    count: i32,
+    name: Option<String>,
+    color: Option<String>,
     unit: Option<String>,
-    unit_price: Option<i32>,
-    total_price: Option<i32>,
+    single: Option<Decimal>,
     notes: Option<String>,
 }
 
-impl UpdateOrderItemMaterialParam {
-    fn to_sql(&self) -> String {
-        let mut sql = format!(
-            "update order_items set order_id={},sku_id={},count={}",
-            self.order_id, self.sku_id, self.count
-        );
-        if let Some(package_card) = &self.package_card {
-            sql.push_str(&format!(",package_card='{}'", package_card));
-        }
-        if let Some(package_card_des) = &self.package_card_des {
-            sql.push_str(&format!(",package_card_des='{}'", package_card_des));
-        }
-        if let Some(unit) = &self.unit {
-            sql.push_str(&format!(",unit='{}'", unit));
-        }
-        if let Some(unit_price) = &self.unit_price {
-            sql.push_str(&format!(",unit_price={}", unit_price));
-        }
-        if let Some(total_price) = &self.total_price {
-            sql.push_str(&format!(",total_price='{}'", total_price));
-        }
-        if let Some(notes) = &self.notes {
-            sql.push_str(&format!(",notes={}", notes));
-        }
-
-        sql
-    }
-}
-
+/// 改了单件用量时重算总用量(欠数按库存流水算)
 async fn update_order_item_material(
+    _: Require<MaterialWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<UpdateOrderItemMaterialParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    let _ = sqlx::query_as!(
-        OrderItemModel,
-        "select * from order_items where id=$1",
-        payload.id
+    let mut material = OrderItemMaterialModel::get_by_id(&state.db, payload.id).await?;
+
+    if let Some(name) = payload.name.as_deref().map(|name| name.trim()) {
+        if name.is_empty() {
+            return Err(ERPError::ParamNeeded("name".to_string()));
+        }
+        material.name = name.to_string();
+    }
+    if let Some(color) = &payload.color {
+        material.color = color.trim().to_string();
+    }
+    if let Some(unit) = &payload.unit {
+        material.unit = unit.trim().to_string();
+    }
+    if let Some(single) = payload.single {
+        check_single(single)?;
+        material.single = single;
+    }
+    if let Some(notes) = &payload.notes {
+        material.notes = notes.to_string();
+    }
+    material.total = material_total(material.single, material.count);
+
+    let duplicate = sqlx::query!(
+        "select id from order_item_materials where order_item_id = $1 and name = $2 and color = $3 and id != $4",
+        material.order_item_id,
+        material.name,
+        material.color,
+        material.id
     )
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .map_err(|err| ERPError::NotFound(format!("OrderItem#{} {err}", payload.id)))?;
+    .map_err(ERPError::DBError)?;
+    if duplicate.is_some() {
+        return Err(ERPError::AlreadyExists(format!(
+            "({}-{})",
+            material.name, material.color
+        )));
+    }
+
+    material.update(&state.db).await?;
+
+    Ok(APIEmptyResponse::new())
+}
 
-    state.execute_sql(&payload.to_sql()).await?;
+async fn delete_order_item_material(
+    _: Require<MaterialWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    sqlx::query!("delete from order_item_materials where id = $1", payload.id)
+        .execute(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
 
     Ok(APIEmptyResponse::new())
 }
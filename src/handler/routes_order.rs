@@ -1,26 +1,40 @@
 use crate::common::db::sorter_order_to_db_sorter_order;
 use crate::constants::DEFAULT_PAGE_SIZE;
-use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_goods::GoodsImagesAndPackage;
 use crate::dto::dto_orders::{
-    OrderDto, OrderGoodsDto, OrderGoodsItemDto, OrderGoodsItemWithStepsDto,
+    OrderDetailDto, OrderDto, OrderGoodsDto, OrderGoodsItemDto, OrderGoodsItemWithStepsDto,
     OrderGoodsWithStepsWithItemStepDto, OrderPlainItemDto, OrderPlainItemWithCurrentStepDto,
     OrderPlainItemWithoutImagesPackageDto, OrderWithStepsDto,
 };
 use crate::dto::dto_progress::OneProgress;
+use crate::excel::export_order_excel::export_order_excel;
+use crate::excel::export_order_traveler::{
+    export_traveler_excel, get_order_traveler, render_traveler_html,
+};
 use crate::handler::ListParamToSQLTrait;
 use crate::middleware::auth::auth;
-use crate::model::order::OrderModel;
+use crate::middleware::permission::{OrderDelete, OrderRead, OrderWrite, Require};
+use crate::model::currency::{CurrencyModel, ExchangeRates};
+use crate::model::material::OrderItemMaterialModel;
+use crate::model::order::{OrderDocumentModel, OrderModel};
 use crate::model::progress::ProgressModel;
+use crate::model::shipment::Shipment;
+use crate::model::workflow::{
+    actionable_steps_of, current_step_of, remaining_steps_of, ProgressRecord, Workflow,
+};
+use crate::pdf::order_pdf::{generate_order_pdf, OrderPdfKind};
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
 use crate::service::goods_service::GoodsService;
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::{Html, IntoResponse};
 use axum::routing::{get, post};
-use axum::{middleware, Extension, Json, Router};
+use axum::{middleware, Json, Router};
 use axum_extra::extract::WithRejection;
 use chrono::NaiveDate;
 use itertools::Itertools;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::collections::HashMap;
@@ -32,6 +46,11 @@ pub fn routes(state: Arc<AppState>) -> Router {
         .route("/api/order/delete", post(delete_order))
         .route("/api/orders/by/dates", get(get_orders_dates))
         .route("/api/order/detail", get(order_detail))
+        .route("/api/order/export", get(export_order))
+        .route("/api/order/traveler", get(export_traveler))
+        .route("/api/order/traveler/html", get(traveler_html))
+        .route("/api/order/pdf", get(order_pdf))
+        .route("/api/order/documents", get(get_order_documents))
         .route("/api/order/update", post(update_order))
         .route("/api/order/items", get(get_order_items))
         .route("/api/order/plain/items", get(get_plain_order_items))
@@ -43,12 +62,29 @@ pub fn routes(state: Arc<AppState>) -> Router {
         .with_state(state)
 }
 
+/// 已经下了采购单的订单/产品不能删, 要先处理采购单
+async fn check_no_purchase_orders(state: &AppState, sql: &str, id: i32) -> ERPResult<()> {
+    let (count,): (i64,) = sqlx::query_as(sql)
+        .bind(id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+    if count > 0 {
+        return Err(ERPError::Failed(
+            "已经有采购单了, 请先删除采购单".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct DeleteOrderParam {
     id: i32,
 }
 
 async fn delete_order(
+    _: Require<OrderDelete>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<DeleteOrderParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -72,6 +108,21 @@ async fn delete_order(
             "该订单已经有流程数据，删除不合法".to_string(),
         ));
     }
+    check_no_purchase_orders(
+        &state,
+        "select count(1) from purchase_orders where order_id = $1",
+        payload.id,
+    )
+    .await?;
+
+    // delete order_item_materials
+    sqlx::query!(
+        "delete from order_item_materials where order_id = $1",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| ERPError::Failed("删除数据失败".to_string()))?;
 
     // delete order_items
     sqlx::query!("delete from order_items where order_id = $1", payload.id)
@@ -117,6 +168,7 @@ struct OrdersWithDate {
 type OrdersByDate = Vec<OrdersWithDate>;
 
 async fn get_orders_dates(
+    _: Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<OrderDatesParam>, ERPError>,
 ) -> ERPResult<APIListResponse<OrdersWithDate>> {
@@ -200,9 +252,25 @@ struct DeleteOrderItemParam {
 }
 
 async fn delete_order_item(
+    _: Require<OrderDelete>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<DeleteOrderItemParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
+    check_no_purchase_orders(
+        &state,
+        "select count(1) from purchase_orders where order_item_id = $1",
+        payload.id,
+    )
+    .await?;
+
+    sqlx::query!(
+        "delete from order_item_materials where order_item_id = $1",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| ERPError::Failed("删除数据失败".to_string()))?;
+
     sqlx::query!("delete from order_items where id = $1", payload.id)
         .execute(&state.db)
         .await
@@ -217,16 +285,32 @@ struct DeleteOrderGoods {
 }
 
 async fn delete_order_goods(
+    _: Require<OrderDelete>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<DeleteOrderGoods>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
+    check_no_purchase_orders(
+        &state,
+        "select count(1) from purchase_orders where order_item_id in (select id from order_items where order_goods_id = $1)",
+        payload.id,
+    )
+    .await?;
+
     // 先删 order_goods
     sqlx::query!("delete from order_goods where id = $1", payload.id)
         .execute(&state.db)
         .await
         .map_err(|_| ERPError::Failed("删除数据失败".to_string()))?;
 
-    // 再删 order_items
+    // 再删 order_items(和它们的材料单)
+    sqlx::query!(
+        "delete from order_item_materials where order_item_id in (select id from order_items where order_goods_id = $1)",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| ERPError::Failed("删除数据失败".to_string()))?;
+
     sqlx::query!(
         "delete from order_items where order_goods_id=$1",
         payload.id
@@ -246,9 +330,11 @@ struct CreateOrderParam {
     delivery_date: Option<NaiveDate>,
     is_urgent: bool,
     is_return_order: bool,
+    currency: Option<String>, // 不传: 客户的币种
 }
 
 async fn create_order(
+    _: Require<OrderWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<CreateOrderParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -268,11 +354,14 @@ async fn create_order(
         )));
     }
 
+    let currency =
+        CurrencyModel::for_order(&state.db, &payload.customer_no, payload.currency.as_deref())
+            .await?;
     sqlx::query!(
         r#"
-        insert into orders (customer_no, order_no, order_date, delivery_date, is_urgent, is_return_order)
-        values ($1, $2, $3, $4, $5, $6)
-        "#, payload.customer_no, payload.order_no, payload.order_date, payload.delivery_date, payload.is_urgent, payload.is_return_order
+        insert into orders (customer_no, order_no, order_date, delivery_date, is_urgent, is_return_order, currency)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        "#, payload.customer_no, payload.order_no, payload.order_date, payload.delivery_date, payload.is_urgent, payload.is_return_order, currency
     ).execute(&state.db).await?;
 
     Ok(APIEmptyResponse::new())
@@ -285,9 +374,10 @@ struct DetailParam {
 }
 
 async fn order_detail(
+    _: Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<DetailParam>, ERPError>,
-) -> ERPResult<APIDataResponse<OrderDto>> {
+) -> ERPResult<APIDataResponse<OrderDetailDto>> {
     let id = param.id.unwrap_or(0);
     let order_no = param.order_no.as_deref().unwrap_or("");
 
@@ -302,7 +392,40 @@ async fn order_detail(
             .map_err(ERPError::DBError)?,
     };
 
-    Ok(APIDataResponse::new(order_dto))
+    // 发货情况
+    let order = sqlx::query_as!(
+        OrderModel,
+        "select * from orders where id = $1",
+        order_dto.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let mut conn = state.db.acquire().await.map_err(ERPError::DBError)?;
+    let items = Shipment::get_order_shipping(&mut conn, &order).await?;
+    let shipments = Shipment::get_by_order_id(&state.db, order.id).await?;
+
+    // 金额
+    let total_amount = sqlx::query!(
+        r#"select coalesce(sum(total_price), 0) as "total!" from order_items where order_id = $1"#,
+        order.id
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .total;
+    let total_amount_base = ExchangeRates::on(&state.db, order.order_date)
+        .await?
+        .to_base(total_amount, &order.currency)
+        .ok();
+
+    Ok(APIDataResponse::new(OrderDetailDto::from(
+        order_dto,
+        total_amount,
+        total_amount_base,
+        items,
+        shipments,
+    )))
 }
 
 #[derive(Debug, Deserialize)]
@@ -448,6 +571,7 @@ impl ListParamToSQLTrait for ListParam {
 }
 
 async fn get_orders(
+    _: Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<ListParam>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderWithStepsDto>> {
@@ -474,7 +598,8 @@ async fn get_orders(
     let order_id_exception_stats =
         ProgressModel::get_order_exception_count(&state.db, &order_ids).await?;
     let order_id_total_stats = ProgressModel::get_order_total_count(&state.db, &order_ids).await?;
-    let order_id_done_stats = ProgressModel::get_order_done_count(&state.db, &order_ids).await?;
+    let order_id_quantity_stats =
+        ProgressModel::get_order_quantity_stats(&state.db, &order_ids).await?;
 
     let empty_order_item_step = HashMap::new();
     let zero = 0;
@@ -485,13 +610,16 @@ async fn get_orders(
                 .get(&order_dto.id)
                 .unwrap_or(&empty_order_item_step);
 
-            let done_count = *order_id_done_stats.get(&order_dto.id).unwrap_or(&zero);
+            let quantity_stats = order_id_quantity_stats
+                .get(&order_dto.id)
+                .cloned()
+                .unwrap_or_default();
             let exception_count = *order_id_exception_stats.get(&order_dto.id).unwrap_or(&zero);
             let total_count = *order_id_total_stats.get(&order_dto.id).unwrap_or(&zero);
             OrderWithStepsDto::from_order_dto_and_steps(
                 order_dto,
                 steps.clone(),
-                done_count,
+                quantity_stats,
                 exception_count,
                 total_count,
             )
@@ -506,6 +634,111 @@ async fn get_orders(
     Ok(APIListResponse::new(order_with_step_dtos, count.0 as i32))
 }
 
+#[derive(Debug, Deserialize)]
+struct ExportOrderParam {
+    id: i32,
+}
+
+/// 按客户配置的excel模版导出订单, 返回xlsx文件的地址
+async fn export_order(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ExportOrderParam>, ERPError>,
+) -> ERPResult<APIDataResponse<String>> {
+    let url = export_order_excel(&state.db, param.id).await?;
+
+    Ok(APIDataResponse::new(url))
+}
+
+#[derive(Debug, Deserialize)]
+struct TravelerParam {
+    id: i32,
+    department_id: Option<i32>, // 只要某个部门现在要做的产品
+}
+
+/// 生产单xlsx, 返回文件的地址
+async fn export_traveler(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<TravelerParam>, ERPError>,
+) -> ERPResult<APIDataResponse<String>> {
+    let traveler =
+        get_order_traveler(&state.db, param.id, param.department_id.unwrap_or(0)).await?;
+    let url = export_traveler_excel(&traveler)?;
+
+    Ok(APIDataResponse::new(url))
+}
+
+/// 可以直接打印的生产单
+async fn traveler_html(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<TravelerParam>, ERPError>,
+) -> ERPResult<Html<String>> {
+    let traveler =
+        get_order_traveler(&state.db, param.id, param.department_id.unwrap_or(0)).await?;
+
+    Ok(Html(render_traveler_html(&traveler)))
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderPdfParam {
+    id: i32,
+    kind: String,          // confirmation: 订单确认书, packing: 装箱单, delivery: 送货单
+    archive: Option<bool>, // 是否同时存档到订单下
+}
+
+/// 直接返回订单的单据PDF
+async fn order_pdf(
+    Require(account, _): Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<OrderPdfParam>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    let kind = OrderPdfKind::from_str(&param.kind)?;
+    let (order, bytes) = generate_order_pdf(
+        &state.db,
+        &state.env.pdf_font_path,
+        param.id,
+        kind,
+        param.archive.unwrap_or(false),
+        account.id,
+    )
+    .await?;
+
+    // 文件名只用ascii, 避免header编码问题
+    let file_name = format!("{}-{}.pdf", kind.as_str(), order.order_no)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "-_.".contains(*c))
+        .collect::<String>();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}\"", file_name),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderDocumentsParam {
+    id: i32,
+}
+
+/// 订单存档过的单据
+async fn get_order_documents(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<OrderDocumentsParam>, ERPError>,
+) -> ERPResult<APIListResponse<OrderDocumentModel>> {
+    let documents = OrderDocumentModel::get_by_order_id(&state.db, param.id).await?;
+    let count = documents.len() as i32;
+
+    Ok(APIListResponse::new(documents, count))
+}
+
 #[derive(Debug, Deserialize)]
 struct OrderItemsQuery {
     order_id: Option<i32>,
@@ -517,7 +750,7 @@ struct OrderItemsQuery {
 }
 
 async fn get_order_items(
-    Extension(account): Extension<AccountDto>,
+    Require(account, _): Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<OrderItemsQuery>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderGoodsWithStepsWithItemStepDto>> {
@@ -531,40 +764,34 @@ async fn get_order_items(
         // ));
     }
 
-    let order_id = match param_order_id {
-        0 => {
-            sqlx::query!("select id from orders where order_no=$1", order_no)
-                .fetch_one(&state.db)
-                .await
-                .map_err(ERPError::DBError)?
-                .id
-        }
-        _ => param_order_id,
+    let order = match param_order_id {
+        0 => sqlx::query_as!(
+            OrderModel,
+            "select * from orders where order_no=$1",
+            order_no
+        )
+        .fetch_one(&state.db)
+        .await
+        .map_err(ERPError::DBError)?,
+        _ => sqlx::query_as!(
+            OrderModel,
+            "select * from orders where id=$1",
+            param_order_id
+        )
+        .fetch_one(&state.db)
+        .await
+        .map_err(ERPError::DBError)?,
     };
+    let order_id = order.id;
 
     let page = param.page.unwrap_or(1);
     let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
     let offset = (page - 1) * page_size;
 
     // 获取order_good
-    let order_goods = sqlx::query_as!(
-        OrderGoodsDto,
-        r#"
-        select
-            og.id as id, og.order_id as order_id, og.goods_id as goods_id, g.goods_no as goods_no,
-            g.name as name, og.images as images, og.image_des as image_des,
-            og.package_card as package_card, og.package_card_des as package_card_des
-        from order_goods og, goods g
-        where og.goods_id = g.id and og.order_id = $1
-        order by og.id offset $2 limit $3
-        "#,
-        order_id,
-        offset as i64,
-        page_size as i64
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(ERPError::DBError)?;
+    let order_goods =
+        OrderGoodsDto::get_by_order_id(&state.db, order_id, offset as i64, Some(page_size as i64))
+            .await?;
 
     if order_goods.is_empty() {
         return Ok(APIListResponse::new(vec![], 0));
@@ -575,23 +802,8 @@ async fn get_order_items(
     tracing::info!("order_goods_ids: {:?}", order_goods_ids);
 
     // 用order_goods_ids去获取order_items
-    let order_items_dto = sqlx::query_as!(
-        OrderGoodsItemDto,
-        r#"
-        select
-            oi.id, oi.order_id, oi.sku_id, s.color, s.sku_no, oi.count, oi.unit,
-            oi.unit_price, oi.total_price, oi.notes, og.goods_id, oi.order_goods_id,
-            oi.notes_images
-        from order_items oi, skus s, order_goods og
-        where oi.sku_id = s.id and oi.order_goods_id = og.id
-            and oi.order_goods_id = any($1)
-        order by id;
-        "#,
-        &order_goods_ids
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(ERPError::DBError)?;
+    let order_items_dto =
+        OrderGoodsItemDto::get_by_order_goods_ids(&state.db, &order_goods_ids).await?;
 
     if order_items_dto.is_empty() {
         return Ok(APIListResponse::new(
@@ -618,31 +830,11 @@ async fn get_order_items(
     tracing::info!("order_item_ids: {:?}", order_item_ids);
 
     // 获取所有的order_item的流程数据
-    let progresses = sqlx::query_as!(
-        OneProgress,
-        r#"
-        select
-            p.*, a.name as account_name, d.name as department
-        from progress p, accounts a, departments d
-        where p.account_id = a.id and a.department_id = d.id
-            and p.order_item_id = any($1)
-        order by p.id;
-        "#,
-        &order_item_ids
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(ERPError::DBError)?;
-    tracing::info!("progresses: {:?}", progresses);
-
-    let mut order_item_id_to_progress_vec = HashMap::new();
-    progresses.into_iter().for_each(|one_progress| {
-        let progress_vec = order_item_id_to_progress_vec
-            .entry(one_progress.order_item_id)
-            .or_insert(vec![]);
-        progress_vec.push(one_progress);
-    });
+    let order_item_id_to_progress_vec =
+        ProgressModel::get_order_items_progresses(&state.db, &order_item_ids).await?;
 
+    let workflow = Workflow::get(&state.db).await?;
+    let route = workflow.route_for_order(&order);
     let empty: Vec<OneProgress> = vec![];
     let order_items_with_steps_dtos = order_items_dto
         .into_iter()
@@ -651,22 +843,21 @@ async fn get_order_items(
                 .get(&item.id)
                 .unwrap_or(&empty);
 
-            let step_for_checking_next_action = {
-                match steps.len() {
-                    0 => 1,
-                    _ => match steps[steps.len() - 1].done {
-                        true => steps[steps.len() - 1].step + 1,
-                        false => steps[steps.len() - 1].step,
-                    },
-                }
-            };
-            let is_next_action = account.steps.contains(&step_for_checking_next_action);
+            let records = steps.iter().map(ProgressRecord::from).collect::<Vec<_>>();
+            let step_quantities = route.step_quantities(&workflow, item.count, &records);
+            // 0: 所有流程都已完成
+            let step_for_checking_next_action = current_step_of(&step_quantities).unwrap_or(0);
+            let is_next_action = actionable_steps_of(&step_quantities)
+                .iter()
+                .any(|step| account.steps.contains(step));
 
             OrderGoodsItemWithStepsDto::from(
                 item,
                 steps.clone(),
                 is_next_action,
                 step_for_checking_next_action,
+                remaining_steps_of(&step_quantities),
+                step_quantities,
             )
         })
         .collect::<Vec<OrderGoodsItemWithStepsDto>>();
@@ -704,7 +895,7 @@ async fn get_order_items(
                     // };
                     // (item.id, step)
                     let step_index = match &item.steps.len() {
-                        0 => (1, 0),
+                        0 => (route.first_step(), 0),
                         _ => (
                             item.steps[item.steps.len() - 1].step,
                             item.steps[item.steps.len() - 1].index,
@@ -726,19 +917,15 @@ async fn get_order_items(
             let mut current_step = 0;
 
             // 如果这个款式下的进度一样，才能做一起做
-            let step_indexs = order_item_to_step_index_count
+            let mut current_steps = items
                 .iter()
-                .map(|sc| sc.0.to_owned())
-                .collect::<Vec<(i32, i32)>>();
-            if step_indexs.len() == 1 {
-                if step_indexs[0].1 == 2 {
-                    current_step = step_indexs[0].0 + 1;
-                } else {
-                    current_step = step_indexs[0].0;
-                }
-                if account.steps.contains(&current_step) {
-                    is_next_action = true;
-                }
+                .map(|item| item.current_step)
+                .collect::<Vec<i32>>();
+            current_steps.sort();
+            current_steps.dedup();
+            if current_steps.len() == 1 {
+                current_step = current_steps[0];
+                is_next_action = items.iter().all(|item| item.is_next_action);
             }
             // println!("steps: {:?}, {}", steps, is_next_action);
             OrderGoodsWithStepsWithItemStepDto::from_order_with_goods_and_steps_and_items(
@@ -766,7 +953,7 @@ async fn get_order_items(
 }
 
 async fn get_plain_order_items(
-    Extension(account): Extension<AccountDto>,
+    Require(account, _): Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<OrderItemsQuery>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderPlainItemWithCurrentStepDto>> {
@@ -840,10 +1027,10 @@ async fn get_plain_order_items(
         ProgressModel,
         r#"
         select distinct on (order_item_id)
-        id, order_item_id, step, account_id, done, notes, dt, index
+        id, order_item_id, step, account_id, done, notes, dt, index, skipped, quantity, defective
         from progress
         where order_item_id = any($1)
-        order by order_item_id, step desc, id desc;
+        order by order_item_id, id desc;
         "#,
         &order_item_ids
     )
@@ -922,9 +1109,11 @@ struct UpdateOrderParam {
     is_special: bool,
     special_customer: String,
     build_by: i32,
+    currency: Option<String>, // 不传: 不变, 换了客户时用新客户的币种
 }
 
 async fn update_order(
+    _: Require<OrderWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<UpdateOrderParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -968,12 +1157,39 @@ async fn update_order(
         return Err(ERPError::NotFound("该订单不存在".to_string()));
     }
 
+    let order = order.unwrap();
+    let currency = match payload.currency.as_deref() {
+        Some(currency) if !currency.trim().is_empty() => {
+            CurrencyModel::checked(&state.db, Some(currency)).await?
+        }
+        _ if payload.customer_no != order.customer_no => {
+            CurrencyModel::for_order(&state.db, &payload.customer_no, None).await?
+        }
+        _ => order.currency.clone(),
+    };
+    // 发票的金额是按订单的币种开的, 开过票就不能再改币种了
+    if currency != order.currency
+        && sqlx::query!(
+            "select id from invoice_items where order_id = $1 limit 1",
+            order.id
+        )
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some()
+    {
+        return Err(ERPError::Failed(format!(
+            "订单#{}已经开过发票, 不能把币种从{}改成{}",
+            order.order_no, order.currency, currency
+        )));
+    }
+
     sqlx::query!(
         r#"
         update orders set
             order_no=$1, customer_no=$2, order_date=$3, delivery_date=$4, is_return_order=$5,
-            is_urgent=$6, is_special=$7, special_customer=$8, build_by=$9
-        where id=$10
+            is_urgent=$6, is_special=$7, special_customer=$8, build_by=$9, currency=$10
+        where id=$11
         "#,
         payload.order_no,
         payload.customer_no,
@@ -984,6 +1200,7 @@ async fn update_order(
         payload.is_special,
         payload.special_customer,
         payload.build_by,
+        currency,
         payload.id
     )
     .execute(&state.db)
@@ -1000,8 +1217,8 @@ struct UpdateOrderItemParam {
     sku_id: Option<i32>,
     count: i32,
     unit: Option<String>,
-    unit_price: Option<i32>,
-    total_price: Option<i32>,
+    unit_price: Option<Decimal>,
+    total_price: Option<Decimal>,
 }
 
 impl UpdateOrderItemParam {
@@ -1063,16 +1280,23 @@ impl UpdateOrderItemParam {
 }
 
 async fn update_order_item(
+    _: Require<OrderWrite>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<UpdateOrderItemParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    if let Some(_id) = payload.id {
+    let order_id = if let Some(id) = payload.id {
         // 修改数据
         tracing::info!(
             "=> handler update_order_item: update sql: {:?}",
             payload.to_update_sql()
         );
         state.execute_sql(&payload.to_update_sql()).await?;
+        sqlx::query!("select order_id from order_items where id = $1", id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound(format!("OrderItem#{}", id)))?
+            .order_id
     } else {
         // 新增
         let order_id = payload.order_id.expect("订单ID");
@@ -1098,7 +1322,13 @@ async fn update_order_item(
         );
 
         state.execute_sql(&payload.to_insert_sql()).await?;
-    }
+        order_id
+    };
+
+    // 数量变了, 材料单的总用量跟着重算; 新增的sku按材料清单生成材料单
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    OrderItemMaterialModel::generate_for_order(&mut tx, order_id).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
 
     Ok(APIEmptyResponse::new())
 }
@@ -1168,6 +1398,7 @@ mod tests {
             delivery_date: None,
             is_urgent: false,
             is_return_order: false,
+            currency: None,
         };
 
         client
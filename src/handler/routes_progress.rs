@@ -1,20 +1,24 @@
-use crate::constants::DONE_INDEX;
-use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_orders::{
     OrderGoodsDto, OrderGoodsItemDto, OrderGoodsItemWithStepsDto,
     OrderGoodsWithStepsWithItemStepDto,
 };
 use crate::dto::dto_progress::OneProgress;
 use crate::middleware::auth::auth;
+use crate::middleware::permission::{OrderRead, ProgressMark, Require};
 use crate::model::goods::{GoodsModel, SKUModel};
 use crate::model::order::OrderModel;
 use crate::model::progress::ProgressModel;
+use crate::model::purchase::{PurchaseKind, PurchaseOrderModel};
+use crate::model::workflow::{
+    actionable_steps_of, current_step_of, remaining_steps_of, ProgressRecord, StepQuantity,
+    Workflow,
+};
 use crate::response::api_response::{APIEmptyResponse, APIListResponse};
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::{get, post};
+use axum::Router;
 use axum::{middleware, Json};
-use axum::{Extension, Router};
 use axum_extra::extract::WithRejection;
 use chrono::Utc;
 use itertools::Itertools;
@@ -40,13 +44,13 @@ struct OrderItemProgressParam {
 }
 
 async fn get_order_items_progress(
-    Extension(account): Extension<AccountDto>,
+    Require(account, _): Require<OrderRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(param), _): WithRejection<Query<OrderItemProgressParam>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderGoodsWithStepsWithItemStepDto>> {
     let goods_no_param = param.goods_no.to_ascii_uppercase();
     let order_no_param = param.order_no.to_ascii_uppercase();
-    let order_id = sqlx::query_as!(
+    let order = sqlx::query_as!(
         OrderModel,
         "select * from orders where order_no = $1",
         order_no_param
@@ -54,8 +58,8 @@ async fn get_order_items_progress(
     .fetch_optional(&state.db)
     .await
     .map_err(ERPError::DBError)?
-    .ok_or(ERPError::ParamError("订单号未找到".to_string()))?
-    .id;
+    .ok_or(ERPError::ParamError("订单号未找到".to_string()))?;
+    let order_id = order.id;
 
     // let goods = sqlx::query_as!(
     //     GoodsModel,
@@ -76,26 +80,17 @@ async fn get_order_items_progress(
     // let mut goods_ids = vec![];
     let mut goods_ids = goodss.iter().map(|item| item.id).collect::<Vec<_>>();
 
-    let item_ids = match goodss.is_empty() {
-        false => sqlx::query!("select id from skus where goods_id = any($1)", &goods_ids)
-            .fetch_all(&state.db)
-            .await
-            .map_err(ERPError::DBError)?
-            .into_iter()
-            .map(|r| r.id)
-            .collect::<Vec<i32>>(),
-        true => {
-            let skus = sqlx::query_as::<_, SKUModel>(&format!(
-                "select * from skus where sku_no like '%{}%'",
-                &goods_no_param
-            ))
-            .fetch_all(&state.db)
-            .await?;
+    // 商品编号找不到时, 按sku编号找
+    if goodss.is_empty() {
+        let skus = sqlx::query_as::<_, SKUModel>(&format!(
+            "select * from skus where sku_no like '%{}%'",
+            &goods_no_param
+        ))
+        .fetch_all(&state.db)
+        .await?;
 
-            goods_ids = skus.iter().map(|item| item.goods_id).collect::<Vec<_>>();
-            skus.into_iter().map(|item| item.id).collect::<Vec<_>>()
-        }
-    };
+        goods_ids = skus.iter().map(|item| item.goods_id).collect::<Vec<_>>();
+    }
 
     // {
     // ...
@@ -233,6 +228,8 @@ async fn get_order_items_progress(
         progress_vec.push(one_progress);
     });
 
+    let workflow = Workflow::get(&state.db).await?;
+    let route = workflow.route_for_order(&order);
     let empty: Vec<OneProgress> = vec![];
     let order_items_with_steps_dtos = order_items_dto
         .into_iter()
@@ -241,22 +238,21 @@ async fn get_order_items_progress(
                 .get(&item.id)
                 .unwrap_or(&empty);
 
-            let step_for_checking_next_action = {
-                match steps.len() {
-                    0 => 1,
-                    _ => match steps[steps.len() - 1].done {
-                        true => steps[steps.len() - 1].step + 1,
-                        false => steps[steps.len() - 1].step,
-                    },
-                }
-            };
-            let is_next_action = account.steps.contains(&step_for_checking_next_action);
+            let records = steps.iter().map(ProgressRecord::from).collect::<Vec<_>>();
+            let step_quantities = route.step_quantities(&workflow, item.count, &records);
+            // 0: 所有流程都已完成
+            let step_for_checking_next_action = current_step_of(&step_quantities).unwrap_or(0);
+            let is_next_action = actionable_steps_of(&step_quantities)
+                .iter()
+                .any(|step| account.steps.contains(step));
 
             OrderGoodsItemWithStepsDto::from(
                 item,
                 steps.clone(),
                 is_next_action,
                 step_for_checking_next_action,
+                remaining_steps_of(&step_quantities),
+                step_quantities,
             )
         })
         .collect::<Vec<OrderGoodsItemWithStepsDto>>();
@@ -284,7 +280,7 @@ async fn get_order_items_progress(
                 .iter()
                 .map(|item| {
                     let step_index = match &item.steps.len() {
-                        0 => (1, 0),
+                        0 => (route.first_step(), 0),
                         _ => (
                             item.steps[item.steps.len() - 1].step,
                             item.steps[item.steps.len() - 1].index,
@@ -306,19 +302,15 @@ async fn get_order_items_progress(
             let mut current_step = 0;
 
             // 如果这个款式下的进度一样，才能做一起做
-            let step_indexs = order_item_to_step_index_count
+            let mut current_steps = items
                 .iter()
-                .map(|sc| sc.0.to_owned())
-                .collect::<Vec<(i32, i32)>>();
-            if step_indexs.len() == 1 {
-                if step_indexs[0].1 == 2 {
-                    current_step = step_indexs[0].0 + 1;
-                } else {
-                    current_step = step_indexs[0].0;
-                }
-                if account.steps.contains(&current_step) {
-                    is_next_action = true;
-                }
+                .map(|item| item.current_step)
+                .collect::<Vec<i32>>();
+            current_steps.sort();
+            current_steps.dedup();
+            if current_steps.len() == 1 {
+                current_step = current_steps[0];
+                is_next_action = items.iter().all(|item| item.is_next_action);
             }
             // println!("steps: {:?}, {}", steps, is_next_action);
             OrderGoodsWithStepsWithItemStepDto::from_order_with_goods_and_steps_and_items(
@@ -345,67 +337,13 @@ async fn get_order_items_progress(
     Ok(APIListResponse::new(order_goods_dtos, count))
 }
 
-async fn get_order_items_progress_bk(
-    // Extension(account): Extension<AccountDto>,
-    State(state): State<Arc<AppState>>,
-    WithRejection(Query(param), _): WithRejection<Query<OrderItemProgressParam>, ERPError>,
-) -> ERPResult<APIEmptyResponse> {
-    // 检查订单号是否存在
-    let order_id = sqlx::query_as!(
-        OrderModel,
-        "select * from orders where order_no = $1",
-        param.order_no
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(ERPError::DBError)?
-    .ok_or(ERPError::ParamError("订单号未找到".to_string()))?
-    .id;
-
-    // 先检查这个goods_no是: 商品编号/sku编号
-    let goods = sqlx::query_as!(
-        GoodsModel,
-        "select * from goods where goods_no=$1",
-        param.goods_no
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(ERPError::DBError)?;
-
-    let item_ids = match goods {
-        Some(real_goods) => sqlx::query!("select id from skus where goods_id = $1", real_goods.id)
-            .fetch_all(&state.db)
-            .await
-            .map_err(ERPError::DBError)?
-            .into_iter()
-            .map(|r| r.id)
-            .collect::<Vec<i32>>(),
-        None => {
-            vec![
-                sqlx::query_as!(
-                    SKUModel,
-                    "select * from skus where sku_no = $1",
-                    param.goods_no
-                )
-                .fetch_optional(&state.db)
-                .await
-                .map_err(ERPError::DBError)?
-                .ok_or(ERPError::ParamError("商品编号未找到".to_string()))?
-                .id,
-            ]
-        }
-    };
-
-    Ok(APIEmptyResponse::new())
-}
-
 #[derive(Deserialize)]
 struct RevokeProgressParam {
     id: i32,
 }
 
 async fn revoke_progress(
-    Extension(account): Extension<AccountDto>,
+    Require(account, _): Require<ProgressMark>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<RevokeProgressParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -423,11 +361,35 @@ async fn revoke_progress(
         return Err(ERPError::NoPermission("无操作权限".to_string()));
     }
 
+    // 发货单生成的出货记录, 要删除发货单才能撤销
+    if let Some(shipment) = sqlx::query!(
+        "select shipment_id from shipment_items where progress_id = $1",
+        payload.id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    {
+        return Err(ERPError::Failed(format!(
+            "该记录属于发货单#{}, 请删除发货单",
+            shipment.shipment_id
+        )));
+    }
+
     sqlx::query!("delete from progress where id = $1", payload.id)
         .execute(&state.db)
         .await
         .map_err(|_| ERPError::Failed("删除数据失败".to_string()))?;
 
+    // 采购单保留, 只是不再关联这条流程记录
+    sqlx::query!(
+        "update purchase_orders set progress_id = 0 where progress_id = $1",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
     Ok(APIEmptyResponse::new())
 }
 
@@ -437,10 +399,18 @@ struct MarkProgressParam {
     order_item_id: Option<i32>,
     index: i32,
     notes: String,
+    #[serde(default)]
+    skip: bool, // 跳过当前流程(模板里可跳过的流程才行), 不用传index
+    #[serde(default)]
+    step: Option<i32>, // 要操作的流程, 不传: 自己部门第一个有数量可操作的流程
+    #[serde(default)]
+    quantity: Option<i32>, // 本次经过的数量, 不传: 当前流程上所有的数量, 只能按order_item_id传
+    #[serde(default)]
+    defective: Option<i32>, // 次品数量
 }
 
 async fn mark_progress(
-    Extension(account): Extension<AccountDto>,
+    Require(account, _): Require<ProgressMark>,
     State(state): State<Arc<AppState>>,
     WithRejection(Json(payload), _): WithRejection<Json<MarkProgressParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
@@ -453,11 +423,21 @@ async fn mark_progress(
         ));
     }
 
-    if payload.index == 0 {
+    if payload.index == 0 && !payload.skip {
         return Err(ERPError::ParamError("请选择正确的流程".to_string()));
     }
+    let quantity = payload.quantity.unwrap_or(0);
+    let defective = payload.defective.unwrap_or(0);
+    if quantity < 0 || defective < 0 {
+        return Err(ERPError::ParamError("数量不能小于0".to_string()));
+    }
+    if order_item_id == 0 && (quantity > 0 || defective > 0) {
+        return Err(ERPError::ParamError(
+            "按数量标记时请传order_item_id".to_string(),
+        ));
+    }
 
-    if order_goods_id > 0 {
+    let order_item_ids = if order_goods_id > 0 {
         let order_goods = sqlx::query_as::<_, (i32,)>(&format!(
             "select id from order_goods where id = {order_goods_id}"
         ))
@@ -484,82 +464,8 @@ async fn mark_progress(
                 "该商品下无添加任何颜色/款式".to_string(),
             ));
         }
-
-        let progresses = sqlx::query_as!(
-            ProgressModel,
-            r#"
-            select distinct on (order_item_id)
-            id, order_item_id, step, account_id, done, notes, dt, index
-            from progress
-            where order_item_id = any($1)
-            order by order_item_id, step desc, id desc;
-            "#,
-            &order_item_ids,
-        )
-        .fetch_all(&state.db)
-        .await
-        .map_err(ERPError::DBError)?;
-        // tracing::info!("progresses: {:?}", progresses);
-
-        let mut order_item_progress = progresses
-            .into_iter()
-            .map(|progress| {
-                if progress.done {
-                    (progress.order_item_id, progress.step + 1)
-                } else {
-                    (progress.order_item_id, progress.step)
-                }
-            })
-            .collect::<HashMap<i32, i32>>();
-
-        tracing::info!("order_item_progress: {:?}", order_item_progress);
-        order_item_ids.iter().for_each(|order_item_id| {
-            order_item_progress
-                .entry(order_item_id.to_owned())
-                .or_insert(1);
-        });
-        tracing::info!("after order_item_progress: {:?}", order_item_progress);
-
-        // 检查所有的产品，是否在同一个步骤上
-        let mut values = order_item_progress
-            .into_iter()
-            .map(|oip| oip.1)
-            .collect::<Vec<i32>>();
-
-        tracing::info!("order_item_progress values: {:?}", values);
-        values.dedup();
-
-        tracing::info!("after dedup order_item_progress values: {:?}", values);
-        if values.len() > 1 {
-            return Err(ERPError::Failed(
-                "该产品的所有颜色，不在同一个流程下，请单独处理".to_string(),
-            ));
-        }
-
-        let step = values[0];
-        if !account.steps.contains(&step) {
-            return Err(ERPError::NoPermission(
-                "当前的状态并不是你可以修改的".to_string(),
-            ));
-        }
-
-        let now = Utc::now();
-        let to_insert_progress_models = order_item_ids
-            .iter()
-            .map(|oii| ProgressModel {
-                id: 0,
-                order_item_id: *oii,
-                step,
-                index: payload.index,
-                account_id: account.id,
-                done: payload.index == DONE_INDEX,
-                notes: payload.notes.clone(),
-                dt: now,
-            })
-            .collect::<Vec<ProgressModel>>();
-        ProgressModel::insert_multiple(&state.db, &to_insert_progress_models).await?;
+        order_item_ids
     } else {
-        // 获得上一个 节点 在什么步骤
         let order_item = sqlx::query_as::<_, (i32,)>(&format!(
             "select id from order_items where id = {order_item_id}"
         ))
@@ -569,50 +475,189 @@ async fn mark_progress(
         if order_item.is_none() {
             return Err(ERPError::NotFound("订单商品不存在".to_string()));
         }
+        vec![order_item_id]
+    };
 
-        let progress = sqlx::query_as!(
-            ProgressModel,
-            "select * from progress where order_item_id=$1 order by step desc, id desc limit 1",
-            order_item_id
-        )
-        .fetch_optional(&state.db)
-        .await
-        .map_err(ERPError::DBError)?;
+    // 2: 按订单的流程模板算出每个产品在各流程上的数量;
+    // 锁住这些产品, 同一个产品的标记排队处理, 避免并发时都通过了数量检查, 超出流程上的数量
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let order_item_id_to_count = sqlx::query!(
+        "select id, count from order_items where id = any($1) order by id for update",
+        &order_item_ids
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|r| (r.id, r.count))
+    .collect::<HashMap<i32, i32>>();
 
-        let step = match progress {
-            None => 1,
-            Some(real) => {
-                if real.done {
-                    real.step + 1
-                } else {
-                    real.step
+    let order = sqlx::query_as!(
+        OrderModel,
+        "select * from orders where id = (select order_id from order_items where id = $1)",
+        order_item_ids[0]
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    let workflow = Workflow::get_with_conn(&mut tx).await?;
+    let route = workflow.route_for_order(&order);
+
+    let progresses = sqlx::query_as!(
+        ProgressModel,
+        "select * from progress where order_item_id = any($1) order by id",
+        &order_item_ids
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    let order_item_quantities = order_item_ids
+        .iter()
+        .map(|oii| {
+            let records = progresses
+                .iter()
+                .filter(|progress| progress.order_item_id == *oii)
+                .map(ProgressRecord::from)
+                .collect::<Vec<ProgressRecord>>();
+            let count = order_item_id_to_count.get(oii).copied().unwrap_or(0);
+            let quantities = route.step_quantities(&workflow, count, &records);
+            (*oii, count, records, quantities)
+        })
+        .collect::<Vec<(i32, i32, Vec<ProgressRecord>, Vec<StepQuantity>)>>();
+
+    // 3: 确定要操作哪个流程: 自己部门的, 且有数量可以操作的
+    let mut steps = order_item_quantities
+        .iter()
+        .map(|(_, _, _, quantities)| {
+            let actionable_steps = actionable_steps_of(quantities);
+            match payload.step {
+                Some(step) => {
+                    if !actionable_steps.contains(&step) {
+                        return Err(ERPError::Failed("该流程当前没有可以操作的数量".to_string()));
+                    }
+                    Ok(step)
                 }
+                None => actionable_steps
+                    .into_iter()
+                    .find(|step| account.steps.contains(step))
+                    .ok_or(ERPError::NoPermission(
+                        "当前的状态并不是你可以修改的".to_string(),
+                    )),
             }
+        })
+        .collect::<ERPResult<Vec<i32>>>()?;
+    steps.sort();
+    steps.dedup();
+    if steps.len() > 1 {
+        return Err(ERPError::Failed(
+            "该产品的所有颜色，不在同一个流程下，请单独处理".to_string(),
+        ));
+    }
+    let step = steps[0];
+    if !account.steps.contains(&step) {
+        return Err(ERPError::NoPermission(
+            "当前的状态并不是你可以修改的".to_string(),
+        ));
+    }
+
+    let (index, is_done_option) = match payload.skip {
+        true => {
+            if !route.is_skippable(step) {
+                return Err(ERPError::Failed("该流程不能跳过".to_string()));
+            }
+            (workflow.done_option(step)?.index, true)
+        }
+        false => (
+            payload.index,
+            workflow.check_option(step, payload.index)?.is_done,
+        ),
+    };
+    // 出货要通过发货单完成(记录发了多少, 物流单号等)
+    if is_done_option && !payload.skip && workflow.shipping_step() == Some(step) {
+        return Err(ERPError::Failed(
+            "出货请新建发货单(/api/shipments), 不能直接标记完成".to_string(),
+        ));
+    }
+    // 成品不锈钢订货/外发: 每个产品都要先有采购单, 标记后关联上
+    let purchase_kind = match workflow.get_option(step, index) {
+        Some(option) if !payload.skip && !option.purchase_kind.is_empty() => {
+            Some(PurchaseKind::from_str(&option.purchase_kind)?)
+        }
+        _ => None,
+    };
+    if let Some(kind) = purchase_kind {
+        let order_item_ids = order_item_quantities
+            .iter()
+            .map(|(oii, _, _, _)| *oii)
+            .collect::<Vec<i32>>();
+        let without =
+            PurchaseOrderModel::get_order_items_without(&state.db, kind, &order_item_ids).await?;
+        if !without.is_empty() {
+            return Err(ERPError::Failed(format!(
+                "产品#{}还没有{}的采购单, 请先新建采购单(/api/purchase/orders)",
+                without.iter().join(","),
+                kind.name()
+            )));
+        }
+    }
+
+    let now = Utc::now();
+    let mut to_insert_progress_models = vec![];
+    for (oii, count, records, quantities) in order_item_quantities.iter() {
+        let step_quantity = quantities
+            .iter()
+            .find(|quantity| quantity.step == step)
+            .ok_or(ERPError::NotFound("流程不存在".to_string()))?;
+
+        // 完成(或跳过)时没传数量, 就是这个流程上所有的数量
+        let passed_quantity = match (is_done_option, quantity) {
+            (false, _) => quantity,
+            (true, 0) => step_quantity.in_progress - defective,
+            (true, _) => quantity,
         };
-        if !account.steps.contains(&step) {
-            return Err(ERPError::NoPermission(
-                "当前的状态并不是你可以修改的".to_string(),
+        if passed_quantity < 0 || passed_quantity + defective > step_quantity.in_progress {
+            return Err(ERPError::ParamError(format!(
+                "数量超出了该流程上的数量({})",
+                step_quantity.in_progress
+            )));
+        }
+        // 完成(或跳过)至少要经过1个或者记1个次品, 不然这条记录什么都没做
+        if is_done_option && passed_quantity + defective == 0 {
+            return Err(ERPError::ParamError(
+                "请填写完成的数量或次品数量".to_string(),
             ));
         }
 
-        let now = Utc::now();
-        sqlx::query!(
-            r#"
-            insert into progress (order_item_id, step, index, account_id, done, notes, dt)
-            values ($1, $2, $3, $4, $5, $6, $7)
-            "#,
-            order_item_id,
+        let mut progress = ProgressModel {
+            id: 0,
+            order_item_id: *oii,
             step,
-            payload.index,
-            account.id,
-            DONE_INDEX == payload.index,
-            payload.notes,
-            now
-        )
-        .execute(&state.db)
-        .await
-        .map_err(ERPError::DBError)?;
+            index,
+            account_id: account.id,
+            done: false,
+            notes: payload.notes.clone(),
+            dt: now,
+            skipped: payload.skip,
+            quantity: passed_quantity,
+            defective,
+        };
+        // 加上这条记录后这个流程完成了(流入的数量都完成或是次品), 才算完成
+        if is_done_option {
+            let mut records_after = records.clone();
+            records_after.push(ProgressRecord::from(&progress));
+            progress.done = route
+                .step_quantities(&workflow, *count, &records_after)
+                .iter()
+                .any(|quantity| quantity.step == step && quantity.done);
+        }
+        to_insert_progress_models.push(progress);
+    }
+    let progresses = ProgressModel::insert_multiple(&mut tx, &to_insert_progress_models).await?;
+    if let Some(kind) = purchase_kind {
+        PurchaseOrderModel::link_progress(&mut tx, kind, &progresses).await?;
     }
+    tx.commit().await.map_err(ERPError::DBError)?;
 
     Ok(APIEmptyResponse::new())
 }
@@ -640,6 +685,10 @@ mod tests {
             order_item_id: Some(1),
             notes: "notes..".to_string(),
             index: 1,
+            skip: false,
+            step: None,
+            quantity: None,
+            defective: None,
         };
         client
             .do_post("/api/mark/progress", serde_json::json!(param))
@@ -652,6 +701,10 @@ mod tests {
             order_item_id: Some(1),
             index: 2,
             notes: "".to_string(),
+            skip: false,
+            step: None,
+            quantity: None,
+            defective: None,
         };
         client
             .do_post("/api/mark/progress", serde_json::json!(param))
@@ -687,6 +740,10 @@ mod tests {
             order_item_id: None,
             index: 1,
             notes: "notes..".to_string(),
+            skip: false,
+            step: None,
+            quantity: None,
+            defective: None,
         };
         client
             .do_post("/api/mark/progress", serde_json::json!(param))
@@ -699,6 +756,10 @@ mod tests {
             order_item_id: None,
             index: 2,
             notes: "".to_string(),
+            skip: false,
+            step: None,
+            quantity: None,
+            defective: None,
         };
         client
             .do_post("/api/mark/progress", serde_json::json!(param))
@@ -711,6 +772,10 @@ mod tests {
             order_item_id: None,
             index: 2,
             notes: "".to_string(),
+            skip: false,
+            step: None,
+            quantity: None,
+            defective: None,
         };
         client
             .do_post("/api/mark/progress", serde_json::json!(param))
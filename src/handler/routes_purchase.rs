@@ -0,0 +1,266 @@
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{PurchaseRead, PurchaseWrite, Require};
+use crate::model::purchase::{
+    PurchaseKind, PurchaseOrder, PurchaseOrderModel, PurchaseReceiptModel,
+};
+use crate::model::supplier::SupplierModel;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(
+            "/api/purchase/orders",
+            get(get_purchase_orders).post(create_purchase_order),
+        )
+        .route("/api/purchase/order/update", post(update_purchase_order))
+        .route("/api/purchase/order/delete", post(delete_purchase_order))
+        .route(
+            "/api/purchase/receipts",
+            get(get_purchase_receipts).post(create_purchase_receipt),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPurchaseOrdersParam {
+    order_id: Option<i32>,
+    supplier_id: Option<i32>,
+    kind: Option<String>,
+    #[serde(default)]
+    overdue: bool, // 只看过了预计到货日期还没收齐的
+    page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    page_size: Option<i32>,
+}
+
+async fn get_purchase_orders(
+    _: Require<PurchaseRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListPurchaseOrdersParam>, ERPError>,
+) -> ERPResult<APIListResponse<PurchaseOrder>> {
+    let kind = param.kind.as_deref().unwrap_or("");
+    if !kind.is_empty() {
+        PurchaseKind::from_str(kind)?;
+    }
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (purchase_orders, count) = PurchaseOrderModel::get_list(
+        &state.db,
+        param.order_id.unwrap_or(0),
+        param.supplier_id.unwrap_or(0),
+        kind,
+        param.overdue,
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
+
+    Ok(APIListResponse::new(purchase_orders, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatePurchaseOrderParam {
+    order_item_id: i32,
+    supplier_id: i32,
+    kind: String, // stainless: 成品不锈钢订货, outsource: 外发
+    #[serde(default)]
+    item: String,
+    quantity: Option<i32>, // 不传: 订单sku的数量
+    #[serde(default)]
+    unit_price: Decimal, // 采购/加工单价, 算订单成本用
+    expected_date: NaiveDate,
+    #[serde(default)]
+    notes: String,
+}
+
+/// 新建采购单, 返回采购单id
+async fn create_purchase_order(
+    Require(account, _): Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreatePurchaseOrderParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    let kind = PurchaseKind::from_str(&payload.kind)?;
+    SupplierModel::get_by_id(&state.db, payload.supplier_id).await?;
+    let order_item = sqlx::query!(
+        "select order_id, count from order_items where id = $1",
+        payload.order_item_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .ok_or(ERPError::NotFound(format!(
+        "订单产品#{}",
+        payload.order_item_id
+    )))?;
+    let quantity = payload.quantity.unwrap_or(order_item.count);
+    if quantity <= 0 {
+        return Err(ERPError::ParamError("采购数量必须大于0".to_string()));
+    }
+    check_unit_price(payload.unit_price)?;
+
+    let id = sqlx::query!(
+        r#"
+        insert into purchase_orders (order_id, order_item_id, supplier_id, kind, item, quantity, expected_date, notes, account_id, unit_price)
+        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        returning id
+        "#,
+        order_item.order_id,
+        payload.order_item_id,
+        payload.supplier_id,
+        kind.as_str(),
+        payload.item.trim(),
+        quantity,
+        payload.expected_date,
+        payload.notes,
+        account.id,
+        payload.unit_price
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .id;
+
+    Ok(APIDataResponse::new(id))
+}
+
+fn check_unit_price(unit_price: Decimal) -> ERPResult<()> {
+    if unit_price < Decimal::ZERO {
+        return Err(ERPError::ParamError("单价不能小于0".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdatePurchaseOrderParam {
+    id: i32,
+    supplier_id: i32,
+    item: String,
+    quantity: i32,
+    unit_price: Option<Decimal>, // 不传: 不修改
+    expected_date: NaiveDate,
+    notes: String,
+}
+
+async fn update_purchase_order(
+    _: Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdatePurchaseOrderParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let purchase_order = PurchaseOrderModel::get_by_id(&state.db, payload.id).await?;
+    if payload.quantity <= 0 || payload.quantity < purchase_order.received {
+        return Err(ERPError::ParamError(format!(
+            "采购数量必须大于0, 且不能少于已收货的数量({})",
+            purchase_order.received
+        )));
+    }
+    SupplierModel::get_by_id(&state.db, payload.supplier_id).await?;
+    if let Some(unit_price) = payload.unit_price {
+        check_unit_price(unit_price)?;
+    }
+
+    sqlx::query!(
+        r#"
+        update purchase_orders
+        set supplier_id = $1, item = $2, quantity = $3, expected_date = $4, notes = $5,
+            unit_price = coalesce($6, unit_price)
+        where id = $7
+        "#,
+        payload.supplier_id,
+        payload.item.trim(),
+        payload.quantity,
+        payload.expected_date,
+        payload.notes,
+        payload.unit_price,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeletePurchaseOrderParam {
+    id: i32,
+}
+
+async fn delete_purchase_order(
+    _: Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeletePurchaseOrderParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let purchase_order = PurchaseOrderModel::get_by_id(&state.db, payload.id).await?;
+    if purchase_order.received > 0 {
+        return Err(ERPError::Failed(
+            "该采购单已经有收货记录, 不能删除".to_string(),
+        ));
+    }
+
+    sqlx::query!("delete from purchase_orders where id = $1", payload.id)
+        .execute(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPurchaseReceiptsParam {
+    purchase_order_id: i32,
+}
+
+async fn get_purchase_receipts(
+    _: Require<PurchaseRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListPurchaseReceiptsParam>, ERPError>,
+) -> ERPResult<APIListResponse<PurchaseReceiptModel>> {
+    let receipts =
+        PurchaseReceiptModel::get_by_purchase_order_id(&state.db, param.purchase_order_id).await?;
+
+    let count = receipts.len() as i32;
+    Ok(APIListResponse::new(receipts, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatePurchaseReceiptParam {
+    purchase_order_id: i32,
+    quantity: i32,
+    receipt_date: Option<NaiveDate>, // 不传: 今天
+    #[serde(default)]
+    notes: String,
+}
+
+/// 记一次收货(可以分多次收), 返回累计收货数量
+async fn create_purchase_receipt(
+    Require(account, _): Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreatePurchaseReceiptParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let received = PurchaseOrderModel::receive(
+        &mut tx,
+        payload.purchase_order_id,
+        payload.quantity,
+        payload.receipt_date.unwrap_or(Utc::now().date_naive()),
+        &payload.notes,
+        account.id,
+    )
+    .await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIDataResponse::new(received))
+}
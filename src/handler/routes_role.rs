@@ -0,0 +1,246 @@
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{AccountManage, Permission, Require};
+use crate::model::account::RoleModel;
+use crate::response::api_response::{APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::State;
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_extra::extract::WithRejection;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/permissions", get(get_permissions))
+        .route("/api/roles", get(get_roles).post(create_role))
+        .route("/api/role/update", post(update_role))
+        .route("/api/role/delete", post(delete_role))
+        .route("/api/account/roles", post(set_account_roles))
+        .route("/api/department/roles", post(set_department_roles))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+#[derive(Debug, Serialize)]
+struct PermissionDto {
+    key: &'static str,
+    name: &'static str,
+}
+
+async fn get_permissions(_: Require<AccountManage>) -> ERPResult<APIListResponse<PermissionDto>> {
+    let permissions = Permission::ALL
+        .iter()
+        .map(|permission| PermissionDto {
+            key: permission.key(),
+            name: permission.name(),
+        })
+        .collect::<Vec<PermissionDto>>();
+
+    let count = permissions.len() as i32;
+    Ok(APIListResponse::new(permissions, count))
+}
+
+async fn get_roles(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<RoleModel>> {
+    let roles = sqlx::query_as!(RoleModel, "select * from roles order by id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    let count = roles.len() as i32;
+    Ok(APIListResponse::new(roles, count))
+}
+
+fn check_permissions(permissions: &[String]) -> ERPResult<()> {
+    if let Some(invalid) = permissions.iter().find(|p| !Permission::is_valid_key(p)) {
+        return Err(ERPError::ParamError(format!("权限{}不存在", invalid)));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateRoleParam {
+    name: String,
+    permissions: Vec<String>,
+}
+
+async fn create_role(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateRoleParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    check_permissions(&payload.permissions)?;
+
+    if sqlx::query!("select id from roles where name = $1", name)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("角色{}", name)));
+    }
+
+    sqlx::query!(
+        "insert into roles (name, permissions) values ($1, $2)",
+        name,
+        &payload.permissions
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateRoleParam {
+    id: i32,
+    name: String,
+    permissions: Vec<String>,
+}
+
+async fn update_role(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateRoleParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    check_permissions(&payload.permissions)?;
+
+    if sqlx::query!(
+        "select id from roles where name = $1 and id != $2",
+        name,
+        payload.id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("角色{}", name)));
+    }
+
+    let rows_affected = sqlx::query!(
+        "update roles set name = $1, permissions = $2 where id = $3",
+        name,
+        &payload.permissions,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("角色不存在".to_string()));
+    }
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteRoleParam {
+    id: i32,
+}
+
+async fn delete_role(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteRoleParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+
+    let rows_affected = sqlx::query!("delete from roles where id = $1", payload.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?
+        .rows_affected();
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("角色不存在".to_string()));
+    }
+
+    // 已分配出去的也要一起去掉
+    sqlx::query!(
+        "update accounts set role_ids = array_remove(role_ids, $1) where $1 = any(role_ids)",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    sqlx::query!(
+        "update departments set role_ids = array_remove(role_ids, $1) where $1 = any(role_ids)",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRolesParam {
+    id: i32,
+    role_ids: Vec<i32>,
+}
+
+async fn set_account_roles(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<SetRolesParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    RoleModel::check_role_ids(&state.db, &payload.role_ids).await?;
+
+    let rows_affected = sqlx::query!(
+        "update accounts set role_ids = $1 where id = $2",
+        &payload.role_ids,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::AccountNotFound);
+    }
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn set_department_roles(
+    _: Require<AccountManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<SetRolesParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    RoleModel::check_role_ids(&state.db, &payload.role_ids).await?;
+
+    let rows_affected = sqlx::query!(
+        "update departments set role_ids = $1 where id = $2",
+        &payload.role_ids,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("部门不存在".to_string()));
+    }
+
+    Ok(APIEmptyResponse::new())
+}
@@ -0,0 +1,168 @@
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{OrderRead, ProgressMark, Require};
+use crate::model::order::OrderModel;
+use crate::model::shipment::{OrderItemShipping, Shipment, ShipmentModel};
+use crate::model::workflow::Workflow;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/shipments", get(get_shipments).post(create_shipment))
+        .route("/api/shipment/delete", post(delete_shipment))
+        .route("/api/order/shipping", get(get_order_shipping))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+async fn get_order(state: &AppState, order_id: i32) -> ERPResult<OrderModel> {
+    sqlx::query_as!(OrderModel, "select * from orders where id = $1", order_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("订单#{}", order_id)))
+}
+
+/// 发货单要由负责出货流程的部门操作
+async fn check_shipping_step(state: &AppState, steps: &[i32]) -> ERPResult<()> {
+    let workflow = Workflow::get(&state.db).await?;
+    if let Some(step) = workflow.shipping_step() {
+        if !steps.contains(&step) {
+            return Err(ERPError::NoPermission(format!(
+                "只有负责{}的部门可以操作发货单",
+                workflow.step_name(step)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderIdParam {
+    order_id: i32,
+}
+
+async fn get_shipments(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<OrderIdParam>, ERPError>,
+) -> ERPResult<APIListResponse<Shipment>> {
+    let shipments = Shipment::get_by_order_id(&state.db, param.order_id).await?;
+
+    let count = shipments.len() as i32;
+    Ok(APIListResponse::new(shipments, count))
+}
+
+/// 订单每个sku的订单数量/可发货/已发货/未发货
+async fn get_order_shipping(
+    _: Require<OrderRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<OrderIdParam>, ERPError>,
+) -> ERPResult<APIListResponse<OrderItemShipping>> {
+    let order = get_order(&state, param.order_id).await?;
+    let mut conn = state.db.acquire().await.map_err(ERPError::DBError)?;
+    let shippings = Shipment::get_order_shipping(&mut conn, &order).await?;
+
+    let count = shippings.len() as i32;
+    Ok(APIListResponse::new(shippings, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct ShipmentItemParam {
+    order_item_id: i32,
+    quantity: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateShipmentParam {
+    order_id: i32,
+    shipment_date: Option<NaiveDate>, // 不传: 今天
+    #[serde(default)]
+    carrier: String,
+    #[serde(default)]
+    tracking_no: String,
+    #[serde(default)]
+    boxes: i32,
+    #[serde(default)]
+    notes: String,
+    items: Vec<ShipmentItemParam>,
+}
+
+/// 新建发货单(可以只发一部分), 同时完成这些数量的出货流程, 返回发货单id
+async fn create_shipment(
+    Require(account, _): Require<ProgressMark>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateShipmentParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    if payload.items.is_empty() {
+        return Err(ERPError::ParamNeeded("items".to_string()));
+    }
+    if payload.boxes < 0 {
+        return Err(ERPError::ParamError("箱数不能小于0".to_string()));
+    }
+    let mut items: Vec<(i32, i32)> = vec![];
+    for item in payload.items.iter() {
+        if item.quantity <= 0 {
+            return Err(ERPError::ParamError("发货数量必须大于0".to_string()));
+        }
+        if items.iter().any(|(id, _)| *id == item.order_item_id) {
+            return Err(ERPError::ParamError(format!(
+                "产品#{}重复了",
+                item.order_item_id
+            )));
+        }
+        items.push((item.order_item_id, item.quantity));
+    }
+    check_shipping_step(&state, &account.steps).await?;
+    let order = get_order(&state, payload.order_id).await?;
+
+    let shipment = ShipmentModel {
+        id: 0,
+        order_id: order.id,
+        shipment_date: payload.shipment_date.unwrap_or(Utc::now().date_naive()),
+        carrier: payload.carrier.trim().to_string(),
+        tracking_no: payload.tracking_no.trim().to_string(),
+        boxes: payload.boxes,
+        notes: payload.notes.clone(),
+        account_id: account.id,
+        dt: Utc::now(),
+    };
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let shipment_id = Shipment::create(&mut tx, &order, &shipment, &items).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIDataResponse::new(shipment_id))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteShipmentParam {
+    id: i32,
+}
+
+/// 删除发货单, 出货流程上对应的记录一起撤销
+async fn delete_shipment(
+    Require(account, _): Require<ProgressMark>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteShipmentParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    check_shipping_step(&state, &account.steps).await?;
+    sqlx::query!("select id from shipments where id = $1", payload.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("发货单#{}", payload.id)))?;
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    Shipment::delete(&mut tx, payload.id).await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
@@ -1,13 +1,20 @@
 use crate::constants::DEFAULT_PAGE_SIZE;
 use crate::dto::dto_goods::{GoodsDto, SKUModelDto};
-use crate::dto::dto_stats::{ReturnOrderGoodsStat, ReturnOrderItemStat, ReturnOrderStat};
-use crate::response::api_response::APIListResponse;
+use crate::dto::dto_stats::{ReturnOrderGoodsStat, ReturnOrderItemStat};
+use crate::excel::export_margin::export_margin_excel;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{Require, StatsRead};
+use crate::model::cycle_time::{CycleTimeStats, ItemCycleTime, WaitingStats};
+use crate::model::margin::{CustomerMargin, MarginFilter, OrderMargin};
+use crate::model::stats::{OrderStats, ProductionStats, StatsFilter, StatsPeriod};
+use crate::response::api_response::{APIDataResponse, APIListResponse};
 use crate::service::goods_service::GoodsService;
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::get;
-use axum::Router;
+use axum::{middleware, Router};
 use axum_extra::extract::WithRejection;
+use chrono::NaiveDate;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -15,7 +22,10 @@ use std::sync::Arc;
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/api/stats/orders", get(order_stats))
-        .route("/api/stats/produce", get(order_stats))
+        .route("/api/stats/produce", get(produce_stats))
+        .route("/api/stats/cycle/steps", get(cycle_time_stats))
+        .route("/api/stats/cycle/items", get(item_cycle_times))
+        .route("/api/stats/cycle/waiting", get(waiting_stats))
         .route(
             "/api/stats/return/orders/by/goods",
             get(list_return_orders_by_goods),
@@ -24,22 +34,150 @@ pub fn routes(state: Arc<AppState>) -> Router {
             "/api/stats/return/orders/by/items",
             get(list_return_orders_by_items),
         )
+        .route("/api/stats/margin/orders", get(order_margins))
+        .route("/api/stats/margin/customers", get(customer_margins))
+        .route("/api/stats/margin/export", get(export_margins))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
 
-#[derive(Deserialize)]
-pub struct OrderStatParam {
+#[derive(Debug, Deserialize)]
+pub struct StatsParam {
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
     customer_no: Option<String>,
+    period: Option<String>, // day/week/month, 不传: day
+    limit: Option<i32>,     // 等待最久的产品数, 不传: DEFAULT_PAGE_SIZE
+}
 
-    page: Option<i32>,
-    #[serde(rename(deserialize = "pageSize"))]
-    page_size: Option<i32>,
+impl StatsParam {
+    fn to_filter(&self) -> StatsFilter {
+        StatsFilter {
+            start_date: self.start_date,
+            end_date: self.end_date,
+            customer_no: self.customer_no.as_deref().unwrap_or("").trim().to_string(),
+        }
+    }
 }
 
+/// 订单数/数量/金额(按周期和客户), 加急和返单占比, 按时交货情况
 async fn order_stats(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<StatsParam>, ERPError>,
+) -> ERPResult<APIDataResponse<OrderStats>> {
+    let period = StatsPeriod::from_str(param.period.as_deref().unwrap_or(""))?;
+    let stats = OrderStats::get(&state.db, &param.to_filter(), period).await?;
+
+    Ok(APIDataResponse::new(stats))
+}
+
+/// 每个流程每天流入/流出的数量, 现在的在制数量, 异常
+async fn produce_stats(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<StatsParam>, ERPError>,
+) -> ERPResult<APIDataResponse<ProductionStats>> {
+    let stats = ProductionStats::get(&state.db, &param.to_filter()).await?;
+
+    Ok(APIDataResponse::new(stats))
+}
+
+/// 每个流程花的时间(中位数, p90), 按流程/部门/客户, 以及按周期的趋势
+async fn cycle_time_stats(
+    _: Require<StatsRead>,
     State(state): State<Arc<AppState>>,
-) -> ERPResult<APIListResponse<ReturnOrderStat>> {
-    todo!()
+    WithRejection(Query(param), _): WithRejection<Query<StatsParam>, ERPError>,
+) -> ERPResult<APIDataResponse<CycleTimeStats>> {
+    let period = StatsPeriod::from_str(param.period.as_deref().unwrap_or(""))?;
+    let stats = CycleTimeStats::get(&state.db, &param.to_filter(), period).await?;
+
+    Ok(APIDataResponse::new(stats))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemCycleTimeParam {
+    order_id: i32,
+}
+
+/// 订单下每个产品在每个流程上花的时间
+async fn item_cycle_times(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ItemCycleTimeParam>, ERPError>,
+) -> ERPResult<APIListResponse<ItemCycleTime>> {
+    let items = ItemCycleTime::get_list(&state.db, param.order_id).await?;
+
+    let count = items.len() as i32;
+    Ok(APIListResponse::new(items, count))
+}
+
+/// 现在每个流程上等待的产品, 以及等待最久的产品
+async fn waiting_stats(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<StatsParam>, ERPError>,
+) -> ERPResult<APIDataResponse<WaitingStats>> {
+    let limit = param.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(0) as usize;
+    let stats = WaitingStats::get(&state.db, &param.to_filter(), limit).await?;
+
+    Ok(APIDataResponse::new(stats))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarginParam {
+    start_date: Option<NaiveDate>, // 下单日期
+    end_date: Option<NaiveDate>,
+    customer_no: Option<String>,
+    build_by: Option<i32>, // 制作方式, 0/不传: 不限
+}
+
+impl MarginParam {
+    fn to_filter(&self) -> MarginFilter {
+        MarginFilter {
+            start_date: self.start_date,
+            end_date: self.end_date,
+            customer_no: self.customer_no.as_deref().unwrap_or("").trim().to_string(),
+            build_by: self.build_by.unwrap_or(0),
+        }
+    }
+}
+
+/// 每个订单的销售额, 材料/采购/人工成本和毛利(基础货币)
+async fn order_margins(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<MarginParam>, ERPError>,
+) -> ERPResult<APIListResponse<OrderMargin>> {
+    let margins = OrderMargin::get_list(&state.db, &param.to_filter()).await?;
+
+    let count = margins.len() as i32;
+    Ok(APIListResponse::new(margins, count))
+}
+
+/// 按客户汇总的毛利
+async fn customer_margins(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<MarginParam>, ERPError>,
+) -> ERPResult<APIListResponse<CustomerMargin>> {
+    let orders = OrderMargin::get_list(&state.db, &param.to_filter()).await?;
+    let margins = CustomerMargin::from_orders(&orders);
+
+    let count = margins.len() as i32;
+    Ok(APIListResponse::new(margins, count))
+}
+
+/// 毛利报表xlsx(订单毛利和客户毛利两个sheet), 返回文件的地址
+async fn export_margins(
+    _: Require<StatsRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<MarginParam>, ERPError>,
+) -> ERPResult<APIDataResponse<String>> {
+    let orders = OrderMargin::get_list(&state.db, &param.to_filter()).await?;
+    let url = export_margin_excel(&orders)?;
+
+    Ok(APIDataResponse::new(url))
 }
 
 #[derive(Deserialize)]
@@ -54,6 +192,7 @@ pub struct ReturnOrderStatParam {
 }
 
 async fn list_return_orders_by_goods(
+    _: Require<StatsRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(params), _): WithRejection<Query<ReturnOrderStatParam>, ERPError>,
 ) -> ERPResult<APIListResponse<ReturnOrderGoodsStat>> {
@@ -203,6 +342,7 @@ async fn list_return_orders_by_goods(
 }
 
 async fn list_return_orders_by_items(
+    _: Require<StatsRead>,
     State(state): State<Arc<AppState>>,
     WithRejection(Query(params), _): WithRejection<Query<ReturnOrderStatParam>, ERPError>,
 ) -> ERPResult<APIListResponse<ReturnOrderItemStat>> {
@@ -0,0 +1,182 @@
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::dto::dto_material::{OrderItemMaterialDto, OrderMaterialShortageDto};
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{MaterialRead, MaterialWrite, Require};
+use crate::model::material::{MaterialModel, OrderItemMaterialModel};
+use crate::model::stock::{MaterialStock, StockMovement, StockMovementKind, WarehouseModel};
+use crate::model::supplier::SupplierModel;
+use crate::response::api_response::{APIDataResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/warehouses", get(get_warehouses))
+        .route("/api/stocks", get(get_stocks))
+        .route("/api/stock/movements", get(get_stock_movements))
+        .route("/api/stock/movement", post(create_stock_movement))
+        .route("/api/order/material/shortages", get(get_order_shortages))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+async fn get_warehouses(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<WarehouseModel>> {
+    let warehouses = WarehouseModel::get_all(&state.db).await?;
+
+    let count = warehouses.len() as i32;
+    Ok(APIListResponse::new(warehouses, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListStocksParam {
+    material_id: Option<i32>,
+    warehouse_id: Option<i32>,
+}
+
+/// 现有库存(按库存流水合计)
+async fn get_stocks(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListStocksParam>, ERPError>,
+) -> ERPResult<APIListResponse<MaterialStock>> {
+    let stocks = MaterialStock::get_list(
+        &state.db,
+        param.material_id.unwrap_or(0),
+        param.warehouse_id.unwrap_or(0),
+    )
+    .await?;
+
+    let count = stocks.len() as i32;
+    Ok(APIListResponse::new(stocks, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListStockMovementsParam {
+    material_id: Option<i32>,
+    warehouse_id: Option<i32>,
+    order_id: Option<i32>,
+    page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    page_size: Option<i32>,
+}
+
+async fn get_stock_movements(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListStockMovementsParam>, ERPError>,
+) -> ERPResult<APIListResponse<StockMovement>> {
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (movements, count) = StockMovement::get_list(
+        &state.db,
+        param.material_id.unwrap_or(0),
+        param.warehouse_id.unwrap_or(0),
+        param.order_id.unwrap_or(0),
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
+
+    Ok(APIListResponse::new(movements, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateStockMovementParam {
+    material_id: i32,
+    warehouse_id: i32,
+    kind: String,      // in: 入库, out: 出库, adjust: 盘点
+    quantity: Decimal, // 入库/出库的数量, 盘点时是盘点后的实际数量
+    #[serde(default)]
+    order_id: i32, // 出库到哪个订单
+    #[serde(default)]
+    supplier_id: i32, // 采购入库: 从哪个供应商买的
+    #[serde(default)]
+    notes: String,
+}
+
+/// 入库/出库/盘点, 返回变动后这个仓库的库存
+async fn create_stock_movement(
+    Require(account, _): Require<MaterialWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateStockMovementParam>, ERPError>,
+) -> ERPResult<APIDataResponse<Decimal>> {
+    let kind = StockMovementKind::from_str(&payload.kind)?;
+    MaterialModel::get_by_id(&state.db, payload.material_id).await?;
+    sqlx::query!(
+        "select id from warehouses where id = $1",
+        payload.warehouse_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .ok_or(ERPError::NotFound(format!("仓库#{}", payload.warehouse_id)))?;
+    if payload.order_id > 0 {
+        if kind != StockMovementKind::Out {
+            return Err(ERPError::ParamError("只有出库可以关联订单".to_string()));
+        }
+        sqlx::query!("select id from orders where id = $1", payload.order_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound(format!("订单#{}", payload.order_id)))?;
+    }
+    if payload.supplier_id > 0 {
+        if kind != StockMovementKind::In {
+            return Err(ERPError::ParamError("只有入库可以关联供应商".to_string()));
+        }
+        SupplierModel::get_by_id(&state.db, payload.supplier_id).await?;
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let on_hand = StockMovement::record(
+        &mut tx,
+        payload.material_id,
+        payload.warehouse_id,
+        kind,
+        payload.quantity,
+        payload.order_id,
+        payload.supplier_id,
+        &payload.notes,
+        account.id,
+    )
+    .await?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIDataResponse::new(on_hand))
+}
+
+#[derive(Debug, Deserialize)]
+struct OrderShortagesParam {
+    order_id: i32,
+}
+
+/// 订单按材料汇总的需求/已出库/预留/欠数
+async fn get_order_shortages(
+    _: Require<MaterialRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<OrderShortagesParam>, ERPError>,
+) -> ERPResult<APIListResponse<OrderMaterialShortageDto>> {
+    let materials = sqlx::query_as!(
+        OrderItemMaterialModel,
+        "select * from order_item_materials where order_id = $1 order by order_item_id, id",
+        param.order_id
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let materials = OrderItemMaterialDto::from_materials(&state.db, materials).await?;
+    let shortages = OrderMaterialShortageDto::from_materials(&materials);
+
+    let count = shortages.len() as i32;
+    Ok(APIListResponse::new(shortages, count))
+}
@@ -0,0 +1,190 @@
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{PurchaseRead, PurchaseWrite, Require};
+use crate::model::supplier::SupplierModel;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/suppliers", get(get_suppliers).post(create_supplier))
+        .route("/api/supplier/detail", get(detail_supplier))
+        .route("/api/supplier/update", post(update_supplier))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct ListSupplierParam {
+    keyword: Option<String>, // 编号/名称/提供的材料或加工
+
+    page: Option<i32>,
+    #[serde(rename(deserialize = "pageSize"))]
+    page_size: Option<i32>,
+}
+
+async fn get_suppliers(
+    _: Require<PurchaseRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<ListSupplierParam>, ERPError>,
+) -> ERPResult<APIListResponse<SupplierModel>> {
+    let page = param.page.unwrap_or(1);
+    let page_size = param.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = (page - 1) * page_size;
+    let (suppliers, count) = SupplierModel::get_list(
+        &state.db,
+        param.keyword.as_deref().unwrap_or("").trim(),
+        offset as i64,
+        page_size as i64,
+    )
+    .await?;
+
+    Ok(APIListResponse::new(suppliers, count as i32))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateSupplierParam {
+    pub code: String,
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub provides: Option<Vec<String>>,
+    pub notes: Option<String>,
+}
+
+/// 新建供应商, 返回供应商id
+async fn create_supplier(
+    _: Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateSupplierParam>, ERPError>,
+) -> ERPResult<APIDataResponse<i32>> {
+    let code = payload.code.trim().to_uppercase();
+    if code.is_empty() {
+        return Err(ERPError::ParamNeeded("code".to_string()));
+    }
+    if SupplierModel::get_by_code(&state.db, &code)
+        .await?
+        .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("供应商#{}", code)));
+    }
+
+    let id = sqlx::query!(
+        r#"
+        insert into suppliers (code, name, contact, phone, address, provides, notes)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        returning id
+        "#,
+        code,
+        payload.name.as_deref().unwrap_or("").trim(),
+        payload.contact.as_deref().unwrap_or("").trim(),
+        payload.phone.as_deref().unwrap_or("").trim(),
+        payload.address.as_deref().unwrap_or("").trim(),
+        &trim_provides(payload.provides.as_deref().unwrap_or(&[])),
+        payload.notes.as_deref().unwrap_or("")
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .id;
+
+    Ok(APIDataResponse::new(id))
+}
+
+/// 去掉空的和重复的
+fn trim_provides(provides: &[String]) -> Vec<String> {
+    let mut trimmed: Vec<String> = vec![];
+    for provide in provides.iter().map(|p| p.trim()) {
+        if !provide.is_empty() && !trimmed.iter().any(|p| p == provide) {
+            trimmed.push(provide.to_string());
+        }
+    }
+    trimmed
+}
+
+#[derive(Debug, Deserialize)]
+struct DetailParam {
+    id: Option<i32>,
+    code: Option<String>,
+}
+
+async fn detail_supplier(
+    _: Require<PurchaseRead>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Query(param), _): WithRejection<Query<DetailParam>, ERPError>,
+) -> ERPResult<APIDataResponse<SupplierModel>> {
+    let id = param.id.unwrap_or(0);
+    let code = param.code.as_deref().unwrap_or("").trim().to_uppercase();
+    let supplier = match (id, code.is_empty()) {
+        (0, true) => return Err(ERPError::ParamNeeded("id或code".to_string())),
+        (0, false) => SupplierModel::get_by_code(&state.db, &code)
+            .await?
+            .ok_or(ERPError::NotFound(format!("供应商#{}", code)))?,
+        _ => SupplierModel::get_by_id(&state.db, id).await?,
+    };
+
+    Ok(APIDataResponse::new(supplier))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateSupplierParam {
+    pub id: i32,
+    pub code: String,
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub provides: Option<Vec<String>>,
+    pub notes: Option<String>,
+}
+
+/// 没传的字段不修改
+async fn update_supplier(
+    _: Require<PurchaseWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateSupplierParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let supplier = SupplierModel::get_by_id(&state.db, payload.id).await?;
+    let code = payload.code.trim().to_uppercase();
+    if code.is_empty() {
+        return Err(ERPError::ParamNeeded("code".to_string()));
+    }
+    if supplier.code != code
+        && SupplierModel::get_by_code(&state.db, &code)
+            .await?
+            .is_some()
+    {
+        return Err(ERPError::Collision(format!("{} 已存在", code)));
+    }
+    let provides = payload.provides.as_deref().map(trim_provides);
+
+    sqlx::query!(
+        r#"
+        update suppliers
+        set code = $1, name = coalesce($2, name), contact = coalesce($3, contact),
+            phone = coalesce($4, phone), address = coalesce($5, address),
+            provides = coalesce($6, provides), notes = coalesce($7, notes)
+        where id = $8
+        "#,
+        code,
+        payload.name.as_deref().map(str::trim),
+        payload.contact.as_deref().map(str::trim),
+        payload.phone.as_deref().map(str::trim),
+        payload.address.as_deref().map(str::trim),
+        provides.as_deref(),
+        payload.notes,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
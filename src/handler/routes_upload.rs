@@ -1,9 +1,11 @@
 use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{Require, Upload};
 use crate::response::api_response::APIDataResponse;
 use crate::{AppState, ERPError, ERPResult};
 use axum::extract::{Multipart, State};
 use axum::routing::post;
-use axum::Router;
+use axum::{middleware, Router};
 use chrono::{Datelike, Timelike, Utc};
 use std::fs;
 use std::sync::Arc;
@@ -11,6 +13,7 @@ use std::sync::Arc;
 pub fn routes(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/api/upload/image", post(upload_image))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state)
 }
 
@@ -19,6 +22,7 @@ struct ImageUrlResponse {
     url: String,
 }
 async fn upload_image(
+    _: Require<Upload>,
     State(_state): State<Arc<AppState>>,
     mut multipart: Multipart,
 ) -> ERPResult<APIDataResponse<ImageUrlResponse>> {
@@ -0,0 +1,629 @@
+use crate::dto::dto_workflow::WorkflowStepDto;
+use crate::middleware::auth::auth;
+use crate::middleware::permission::{OrderWrite, Require, WorkflowManage};
+use crate::model::account::DepartmentModel;
+use crate::model::purchase::PurchaseKind;
+use crate::model::workflow::{Workflow, WorkflowTemplateModel};
+use crate::response::api_response::{APIEmptyResponse, APIListResponse};
+use crate::{AppState, ERPError, ERPResult};
+use axum::extract::State;
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_extra::extract::WithRejection;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/api/workflow", get(get_workflow))
+        .route("/api/workflow/steps", post(create_step))
+        .route("/api/workflow/step/update", post(update_step))
+        .route("/api/workflow/step/delete", post(delete_step))
+        .route("/api/workflow/options", post(create_option))
+        .route("/api/workflow/option/update", post(update_option))
+        .route("/api/workflow/option/delete", post(delete_option))
+        .route(
+            "/api/workflow/templates",
+            get(get_templates).post(create_template),
+        )
+        .route("/api/workflow/template/update", post(update_template))
+        .route("/api/workflow/template/delete", post(delete_template))
+        .route("/api/order/workflow/template", post(set_order_template))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+        .with_state(state)
+}
+
+async fn get_workflow(
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<WorkflowStepDto>> {
+    let workflow = Workflow::get(&state.db).await?;
+
+    let id_to_department = sqlx::query_as!(DepartmentModel, "select * from departments")
+        .fetch_all(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|department| (department.id, department.name))
+        .collect::<HashMap<i32, String>>();
+
+    let step_dtos = workflow
+        .steps
+        .into_iter()
+        .map(|step| {
+            let department = id_to_department
+                .get(&step.department_id)
+                .cloned()
+                .unwrap_or_default();
+            let options = workflow
+                .options
+                .iter()
+                .filter(|option| option.step == step.step)
+                .cloned()
+                .collect();
+            WorkflowStepDto::from(step, department, options)
+        })
+        .collect::<Vec<WorkflowStepDto>>();
+
+    let count = step_dtos.len() as i32;
+    Ok(APIListResponse::new(step_dtos, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateStepParam {
+    step: i32,
+    name: String,
+    department_id: i32,
+    #[serde(default)]
+    labor_rate: Decimal, // 计件工价(每件)
+}
+
+async fn create_step(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateStepParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.step <= 0 {
+        return Err(ERPError::ParamError("流程序号必须大于0".to_string()));
+    }
+    if payload.name.trim().is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    check_labor_rate(payload.labor_rate)?;
+
+    if sqlx::query!(
+        "select id from workflow_steps where step = $1",
+        payload.step
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .is_some()
+    {
+        return Err(ERPError::AlreadyExists(format!("流程{}", payload.step)));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    sqlx::query!(
+        "insert into workflow_steps (step, name, department_id, labor_rate) values ($1, $2, $3, $4)",
+        payload.step,
+        payload.name.trim(),
+        payload.department_id,
+        payload.labor_rate
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    // 新流程默认带上 异常(备注) 和 已完成
+    sqlx::query!(
+        r#"
+        insert into workflow_step_options (step, index, name, color, is_done, is_exception)
+        values ($1, 1, '异常(备注)', '#C9D2DC', false, true),
+               ($1, 2, '已完成', '#C9D2DC', true, false)
+        "#,
+        payload.step
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+fn check_labor_rate(labor_rate: Decimal) -> ERPResult<()> {
+    if labor_rate < Decimal::ZERO {
+        return Err(ERPError::ParamError("计件工价不能小于0".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateStepParam {
+    id: i32,
+    name: String,
+    department_id: i32,
+    is_shipping: Option<bool>, // 设为出货流程(只能有一个)
+    labor_rate: Option<Decimal>,
+}
+
+async fn update_step(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateStepParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.name.trim().is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    if let Some(labor_rate) = payload.labor_rate {
+        check_labor_rate(labor_rate)?;
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let rows_affected = sqlx::query!(
+        "update workflow_steps set name = $1, department_id = $2, is_shipping = coalesce($3, is_shipping), labor_rate = coalesce($4, labor_rate) where id = $5",
+        payload.name.trim(),
+        payload.department_id,
+        payload.is_shipping,
+        payload.labor_rate,
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("流程不存在".to_string()));
+    }
+    if payload.is_shipping == Some(true) {
+        sqlx::query!(
+            "update workflow_steps set is_shipping = false where id != $1",
+            payload.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+    }
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteParam {
+    id: i32,
+}
+
+async fn delete_step(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let step = sqlx::query!("select step from workflow_steps where id = $1", payload.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound("流程不存在".to_string()))?
+        .step;
+
+    // 已经有流程数据的不能删
+    if sqlx::query!("select id from progress where step = $1 limit 1", step)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some()
+    {
+        return Err(ERPError::Failed(
+            "该流程已经有流程数据，不能删除".to_string(),
+        ));
+    }
+
+    if sqlx::query!(
+        "select id from workflow_templates where $1 = any(steps) limit 1",
+        step
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .is_some()
+    {
+        return Err(ERPError::Failed(
+            "该流程还在流程模板里，不能删除".to_string(),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    sqlx::query!("delete from workflow_step_options where step = $1", step)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+    sqlx::query!("delete from workflow_steps where id = $1", payload.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateOptionParam {
+    step: i32,
+    index: i32,
+    name: String,
+    color: String,
+    #[serde(default)]
+    is_done: bool,
+    #[serde(default)]
+    is_exception: bool,
+    #[serde(default)]
+    purchase_kind: String, // stainless/outsource: 标记这个选项要有采购单
+}
+
+async fn create_option(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateOptionParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.index <= 0 {
+        return Err(ERPError::ParamError("index必须大于0".to_string()));
+    }
+    if payload.is_done && payload.is_exception {
+        return Err(ERPError::ParamError("完成和异常不能同时选".to_string()));
+    }
+    if !payload.purchase_kind.is_empty() {
+        PurchaseKind::from_str(&payload.purchase_kind)?;
+    }
+
+    let workflow = Workflow::get(&state.db).await?;
+    if !workflow.steps.iter().any(|step| step.step == payload.step) {
+        return Err(ERPError::NotFound("流程不存在".to_string()));
+    }
+    if workflow.get_option(payload.step, payload.index).is_some() {
+        return Err(ERPError::AlreadyExists(format!(
+            "流程{}的选项{}",
+            payload.step, payload.index
+        )));
+    }
+
+    sqlx::query!(
+        r#"
+        insert into workflow_step_options (step, index, name, color, is_done, is_exception, purchase_kind)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        payload.step,
+        payload.index,
+        payload.name.trim(),
+        payload.color.trim(),
+        payload.is_done,
+        payload.is_exception,
+        payload.purchase_kind
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateOptionParam {
+    id: i32,
+    name: String,
+    color: String,
+    is_done: bool,
+    is_exception: bool,
+    purchase_kind: Option<String>, // 不传: 不修改, 空字符串: 不需要采购单
+}
+
+async fn update_option(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateOptionParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.is_done && payload.is_exception {
+        return Err(ERPError::ParamError("完成和异常不能同时选".to_string()));
+    }
+    if let Some(purchase_kind) = payload.purchase_kind.as_deref() {
+        if !purchase_kind.is_empty() {
+            PurchaseKind::from_str(purchase_kind)?;
+        }
+    }
+
+    let workflow = Workflow::get(&state.db).await?;
+    let option = workflow
+        .options
+        .iter()
+        .find(|option| option.id == payload.id)
+        .ok_or(ERPError::NotFound("选项不存在".to_string()))?;
+    if option.is_done && !payload.is_done {
+        check_other_done_option(&workflow, option.step, option.id)?;
+    }
+
+    sqlx::query!(
+        r#"
+        update workflow_step_options
+        set name = $1, color = $2, is_done = $3, is_exception = $4,
+            purchase_kind = coalesce($5, purchase_kind)
+        where id = $6
+        "#,
+        payload.name.trim(),
+        payload.color.trim(),
+        payload.is_done,
+        payload.is_exception,
+        payload.purchase_kind,
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn delete_option(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let workflow = Workflow::get(&state.db).await?;
+    let option = workflow
+        .options
+        .iter()
+        .find(|option| option.id == payload.id)
+        .ok_or(ERPError::NotFound("选项不存在".to_string()))?;
+    if option.is_done {
+        check_other_done_option(&workflow, option.step, option.id)?;
+    }
+
+    if sqlx::query!(
+        "select id from progress where step = $1 and index = $2 limit 1",
+        option.step,
+        option.index
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .is_some()
+    {
+        return Err(ERPError::Failed(
+            "该选项已经有流程数据，不能删除".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        "delete from workflow_step_options where id = $1",
+        payload.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+/// 每个流程至少要留一个完成的选项, 不然永远走不到下一个流程
+fn check_other_done_option(workflow: &Workflow, step: i32, option_id: i32) -> ERPResult<()> {
+    if !workflow
+        .options
+        .iter()
+        .any(|option| option.step == step && option.id != option_id && option.is_done)
+    {
+        return Err(ERPError::Failed(
+            "每个流程至少需要一个完成的选项".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn get_templates(
+    State(state): State<Arc<AppState>>,
+) -> ERPResult<APIListResponse<WorkflowTemplateModel>> {
+    let templates = Workflow::get(&state.db).await?.templates;
+
+    let count = templates.len() as i32;
+    Ok(APIListResponse::new(templates, count))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTemplateParam {
+    name: String,
+    steps: Vec<i32>,
+    #[serde(default)]
+    optional_steps: Vec<i32>,
+    #[serde(default)]
+    skippable_steps: Vec<i32>,
+    #[serde(default)]
+    build_by: i32,
+    #[serde(default)]
+    customer_no: String,
+    #[serde(default)]
+    is_default: bool,
+}
+
+async fn create_template(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.name.trim().is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    let workflow = Workflow::get(&state.db).await?;
+    workflow.check_template_steps(
+        &payload.steps,
+        &payload.optional_steps,
+        &payload.skippable_steps,
+    )?;
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    // 默认模板只能有一个
+    if payload.is_default {
+        sqlx::query!("update workflow_templates set is_default = false")
+            .execute(&mut *tx)
+            .await
+            .map_err(ERPError::DBError)?;
+    }
+    sqlx::query!(
+        r#"
+        insert into workflow_templates (name, steps, optional_steps, skippable_steps, build_by, customer_no, is_default)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        payload.name.trim(),
+        &payload.steps,
+        &payload.optional_steps,
+        &payload.skippable_steps,
+        payload.build_by,
+        payload.customer_no.trim(),
+        payload.is_default
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateTemplateParam {
+    id: i32,
+    name: String,
+    steps: Vec<i32>,
+    #[serde(default)]
+    optional_steps: Vec<i32>,
+    #[serde(default)]
+    skippable_steps: Vec<i32>,
+    #[serde(default)]
+    build_by: i32,
+    #[serde(default)]
+    customer_no: String,
+    #[serde(default)]
+    is_default: bool,
+}
+
+async fn update_template(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.name.trim().is_empty() {
+        return Err(ERPError::ParamNeeded("name".to_string()));
+    }
+    let workflow = Workflow::get(&state.db).await?;
+    if !workflow.templates.iter().any(|t| t.id == payload.id) {
+        return Err(ERPError::NotFound("流程模板不存在".to_string()));
+    }
+    workflow.check_template_steps(
+        &payload.steps,
+        &payload.optional_steps,
+        &payload.skippable_steps,
+    )?;
+
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    if payload.is_default {
+        sqlx::query!(
+            "update workflow_templates set is_default = false where id != $1",
+            payload.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?;
+    }
+    sqlx::query!(
+        r#"
+        update workflow_templates
+        set name = $1, steps = $2, optional_steps = $3, skippable_steps = $4,
+            build_by = $5, customer_no = $6, is_default = $7
+        where id = $8
+        "#,
+        payload.name.trim(),
+        &payload.steps,
+        &payload.optional_steps,
+        &payload.skippable_steps,
+        payload.build_by,
+        payload.customer_no.trim(),
+        payload.is_default,
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn delete_template(
+    _: Require<WorkflowManage>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<DeleteParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let mut tx = state.db.begin().await.map_err(ERPError::DBError)?;
+    let rows_affected = sqlx::query!("delete from workflow_templates where id = $1", payload.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ERPError::DBError)?
+        .rows_affected();
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("流程模板不存在".to_string()));
+    }
+
+    // 指定了这个模板的订单改回自动匹配
+    sqlx::query!(
+        "update orders set workflow_template_id = 0 where workflow_template_id = $1",
+        payload.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ERPError::DBError)?;
+    tx.commit().await.map_err(ERPError::DBError)?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[derive(Debug, Deserialize)]
+struct SetOrderTemplateParam {
+    order_id: i32,
+    workflow_template_id: i32, // 0: 自动匹配
+}
+
+async fn set_order_template(
+    _: Require<OrderWrite>,
+    State(state): State<Arc<AppState>>,
+    WithRejection(Json(payload), _): WithRejection<Json<SetOrderTemplateParam>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    if payload.workflow_template_id != 0
+        && sqlx::query!(
+            "select id from workflow_templates where id = $1",
+            payload.workflow_template_id
+        )
+        .fetch_optional(&state.db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_none()
+    {
+        return Err(ERPError::NotFound("流程模板不存在".to_string()));
+    }
+
+    let rows_affected = sqlx::query!(
+        "update orders set workflow_template_id = $1 where id = $2",
+        payload.workflow_template_id,
+        payload.order_id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ERPError::DBError)?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(ERPError::NotFound("订单不存在".to_string()));
+    }
+
+    Ok(APIEmptyResponse::new())
+}
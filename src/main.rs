@@ -21,6 +21,7 @@ mod handler;
 mod macros;
 mod middleware;
 mod model;
+mod pdf;
 mod repository;
 mod response;
 mod service;
@@ -30,6 +31,7 @@ pub use self::error::{ERPError, ERPResult};
 #[derive(Debug, Clone)]
 pub struct AppState {
     db: Pool<Postgres>,
+    env: config::Config,
 }
 
 impl AppState {
@@ -64,7 +66,19 @@ async fn main() {
         Err(_err) => std::process::exit(-1),
     };
 
-    let app_state = Arc::new(AppState { db: pool.clone() });
+    let app_state = Arc::new(AppState {
+        db: pool.clone(),
+        env: config::Config::init(),
+    });
+    if let Err(err) = model::account::AccountModel::bootstrap_admin(
+        &app_state.db,
+        &app_state.env.admin_account,
+        app_state.env.admin_password.as_deref(),
+    )
+    .await
+    {
+        tracing::error!("创建管理员账号失败: {err}");
+    }
     let cors = CorsLayer::new()
         .allow_origin([
             "https://erp.ligulfzhou.com".parse().unwrap(),
@@ -90,12 +104,21 @@ async fn main() {
         .merge(handler::routes_account::routes(app_state.clone()))
         .merge(handler::routes_order::routes(app_state.clone()))
         .merge(handler::routes_material::routes(app_state.clone()))
+        .merge(handler::routes_currency::routes(app_state.clone()))
         .merge(handler::routes_customer::routes(app_state.clone()))
         .merge(handler::routes_goods::routes(app_state.clone()))
+        .merge(handler::routes_invoice::routes(app_state.clone()))
         .merge(handler::routes_excel::routes(app_state.clone()))
+        .merge(handler::routes_excel_template::routes(app_state.clone()))
         .merge(handler::routes_login::routes(app_state.clone()))
         .merge(handler::routes_progress::routes(app_state.clone()))
+        .merge(handler::routes_purchase::routes(app_state.clone()))
+        .merge(handler::routes_role::routes(app_state.clone()))
+        .merge(handler::routes_shipment::routes(app_state.clone()))
+        .merge(handler::routes_stock::routes(app_state.clone()))
+        .merge(handler::routes_supplier::routes(app_state.clone()))
         .merge(handler::routes_stats::routes(app_state.clone()))
+        .merge(handler::routes_workflow::routes(app_state.clone()))
         .fallback_service(handler::routes_static::routes())
         .layer(DefaultBodyLimit::max(usize::MAX))
         .layer(cors);
@@ -1,6 +1,8 @@
 use crate::dto::dto_account::AccountDto;
-use crate::model::account::{AccountModel, DepartmentModel};
+use crate::middleware::jwt_auth::{decode_token, extract_token, is_token_revoked};
+use crate::model::account::{AccountModel, DepartmentModel, RoleModel};
 use crate::{AppState, ERPError};
+use axum::http::HeaderMap;
 use axum::{extract::State, http::Request, middleware::Next, response::IntoResponse};
 use axum_extra::extract::cookie::CookieJar;
 use serde::Serialize;
@@ -14,16 +16,18 @@ pub struct ErrorResponse {
 
 pub async fn auth<B>(
     cookie_jar: CookieJar,
+    headers: HeaderMap,
     State(state): State<Arc<AppState>>,
     mut req: Request<B>,
     next: Next<B>,
 ) -> Result<impl IntoResponse, ERPError> {
-    let account_id = cookie_jar
-        .get("account_id")
-        .map(|cookie| cookie.value().to_string())
-        .ok_or(ERPError::NotAuthorized)?;
+    let token = extract_token(&cookie_jar, &headers).ok_or(ERPError::NotAuthorized)?;
+    let claims = decode_token(&token, &state.env)?;
+    if is_token_revoked(&state.db, &claims.jti).await? {
+        return Err(ERPError::NotAuthorized);
+    }
 
-    let account_id = account_id.parse::<i32>().unwrap_or(0);
+    let account_id = claims.account_id()?;
     tracing::info!("account_id: {}", account_id);
 
     let account = sqlx::query_as!(
@@ -36,6 +40,14 @@ pub async fn auth<B>(
     .map_err(ERPError::DBError)?
     .ok_or(ERPError::NotFound("账号不存在".to_string()))?;
 
+    if account.disabled {
+        return Err(ERPError::AccountDisabled);
+    }
+    // 重置密码/停用之前签发的token
+    if claims.ver != account.token_version {
+        return Err(ERPError::NotAuthorized);
+    }
+
     let department = sqlx::query_as::<_, DepartmentModel>(&format!(
         "select * from departments where id={}",
         account.department_id
@@ -45,8 +57,10 @@ pub async fn auth<B>(
     .map_err(ERPError::DBError)?
     .ok_or(ERPError::NotFound("账号不存在".to_string()))?;
 
-    let account_dto = AccountDto::from(account, department);
+    let roles = RoleModel::get_roles_of_account(&state.db, &account, &department).await?;
+    let account_dto = AccountDto::from(account, department, roles);
 
     req.extensions_mut().insert(account_dto);
+    req.extensions_mut().insert(claims);
     Ok(next.run(req).await)
 }
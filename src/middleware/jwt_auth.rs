@@ -1,99 +1,183 @@
-// use std::sync::Arc;
-//
-// use axum::{
-//     extract::State,
-//     http::{header, Request, StatusCode},
-//     middleware::Next,
-//     response::IntoResponse,
-//     Json,
-// };
-//
-// use axum_extra::extract::cookie::CookieJar;
-// use jsonwebtoken::{decode, DecodingKey, Validation};
-// use serde::Serialize;
-//
-// use crate::{
-//     model::{TokenClaims, User},
-//     AppState,
-// };
-//
-// #[derive(Debug, Serialize)]
-// pub struct ErrorResponse {
-//     pub status: &'static str,
-//     pub message: String,
-// }
-//
-// pub async fn auth<B>(
-//     cookie_jar: CookieJar,
-//     State(data): State<Arc<AppState>>,
-//     mut req: Request<B>,
-//     next: Next<B>,
-// ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-//     let token = cookie_jar
-//         .get("token")
-//         .map(|cookie| cookie.value().to_string())
-//         .or_else(|| {
-//             req.headers()
-//                 .get(header::AUTHORIZATION)
-//                 .and_then(|auth_header| auth_header.to_str().ok())
-//                 .and_then(|auth_value| {
-//                     if auth_value.starts_with("Bearer ") {
-//                         Some(auth_value[7..].to_owned())
-//                     } else {
-//                         None
-//                     }
-//                 })
-//         });
-//
-//     let token = token.ok_or_else(|| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "You are not logged in, please provide token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     let claims = decode::<TokenClaims>(
-//         &token,
-//         &DecodingKey::from_secret(data.env.jwt_secret.as_ref()),
-//         &Validation::default(),
-//     )
-//     .map_err(|_| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "Invalid token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?
-//     .claims;
-//
-//     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "Invalid token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", user_id)
-//         .fetch_optional(&data.db)
-//         .await
-//         .map_err(|e| {
-//             let json_error = ErrorResponse {
-//                 status: "fail",
-//                 message: format!("Error fetching user from database: {}", e),
-//             };
-//             (StatusCode::INTERNAL_SERVER_ERROR, Json(json_error))
-//         })?;
-//
-//     let user = user.ok_or_else(|| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "The user belonging to this token no longer exists".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     req.extensions_mut().insert(user);
-//     Ok(next.run(req).await)
-// }
+use crate::common::string::random_string;
+use crate::config::Config;
+use crate::constants::TOKEN_COOKIE_NAME;
+use crate::{ERPError, ERPResult};
+use axum::http::{header, HeaderMap};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use sqlx::{Pool, Postgres};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenClaims {
+    pub sub: String, // account id
+    pub jti: String, // token id, 登出/刷新时用来注销
+    #[serde(default)]
+    pub ver: i32, // 签发时账号的token_version, 重置密码/停用后对不上即失效
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl TokenClaims {
+    pub fn account_id(&self) -> ERPResult<i32> {
+        self.sub.parse::<i32>().map_err(|_| ERPError::NotAuthorized)
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.exp, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+    }
+}
+
+pub fn create_token(
+    account_id: i32,
+    token_version: i32,
+    config: &Config,
+) -> ERPResult<(String, TokenClaims)> {
+    let now = Utc::now();
+    let claims = TokenClaims {
+        sub: account_id.to_string(),
+        jti: random_string(32),
+        ver: token_version,
+        iat: now.timestamp(),
+        exp: (now + Duration::minutes(config.jwt_expires_in)).timestamp(),
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+    .map_err(|_| ERPError::Failed("生成token失败".to_string()))?;
+
+    Ok((token, claims))
+}
+
+pub fn decode_token(token: &str, config: &Config) -> ERPResult<TokenClaims> {
+    let claims = decode::<TokenClaims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+    .map_err(|_| ERPError::NotAuthorized)?
+    .claims;
+
+    Ok(claims)
+}
+
+/// 先从cookie里取, 没有的话再看 `Authorization: Bearer xxx`
+pub fn extract_token(cookie_jar: &CookieJar, headers: &HeaderMap) -> Option<String> {
+    cookie_jar
+        .get(TOKEN_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|auth_header| auth_header.to_str().ok())
+                .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+                .map(|token| token.trim().to_owned())
+        })
+}
+
+pub fn token_cookie(token: &str, max_age: time::Duration) -> Cookie<'static> {
+    Cookie::build(TOKEN_COOKIE_NAME, token.to_owned())
+        .path("/")
+        .max_age(max_age)
+        .same_site(SameSite::None)
+        .domain("")
+        .http_only(true)
+        .secure(true)
+        .finish()
+}
+
+pub async fn is_token_revoked(db: &Pool<Postgres>, jti: &str) -> ERPResult<bool> {
+    let revoked = sqlx::query!("select jti from revoked_tokens where jti = $1", jti)
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .is_some();
+
+    Ok(revoked)
+}
+
+pub async fn revoke_token(db: &Pool<Postgres>, claims: &TokenClaims) -> ERPResult<()> {
+    sqlx::query!(
+        r#"
+        insert into revoked_tokens (jti, account_id, expires_at)
+        values ($1, $2, $3)
+        on conflict (jti) do nothing
+        "#,
+        claims.jti,
+        claims.account_id().unwrap_or(0),
+        claims.expires_at()
+    )
+    .execute(db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    // 已经过期的token本身就无效了, 不用再留着
+    sqlx::query!("delete from revoked_tokens where expires_at < now()")
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::middleware::jwt_auth::{create_token, decode_token, extract_token};
+    use axum::http::{header, HeaderMap};
+    use axum_extra::extract::cookie::{Cookie, CookieJar};
+
+    fn config() -> Config {
+        Config {
+            jwt_secret: "secret".to_string(),
+            jwt_expires_in: 60,
+            pdf_font_path: "".to_string(),
+            admin_account: "ADMIN".to_string(),
+            admin_password: None,
+        }
+    }
+
+    #[test]
+    fn test_create_and_decode_token() -> anyhow::Result<()> {
+        let (token, claims) = create_token(7, 3, &config())?;
+        let decoded = decode_token(&token, &config())?;
+        assert_eq!(decoded.account_id()?, 7);
+        assert_eq!(decoded.jti, claims.jti);
+        assert_eq!(decoded.ver, 3);
+
+        let other = Config {
+            jwt_secret: "other".to_string(),
+            ..config()
+        };
+        assert!(decode_token(&token, &other).is_err());
+
+        let expired = Config {
+            jwt_expires_in: -60,
+            ..config()
+        };
+        let (expired_token, _) = create_token(7, 0, &expired)?;
+        assert!(decode_token(&expired_token, &config()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_token(&CookieJar::new(), &headers), None);
+
+        headers.insert(header::AUTHORIZATION, "Bearer abc".parse().unwrap());
+        assert_eq!(
+            extract_token(&CookieJar::new(), &headers),
+            Some("abc".to_string())
+        );
+
+        let jar = CookieJar::new().add(Cookie::new("token", "xyz"));
+        assert_eq!(extract_token(&jar, &headers), Some("xyz".to_string()));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod auth;
 pub mod jwt_auth;
+pub mod permission;
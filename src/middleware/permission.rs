@@ -0,0 +1,208 @@
+use crate::dto::dto_account::AccountDto;
+use crate::ERPError;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use std::marker::PhantomData;
+
+/// 角色里的权限, 数据库里存的是 key (roles.permissions), "*" 表示所有权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    OrderRead,
+    OrderWrite,
+    OrderDelete,
+    ExcelImport,
+    ExcelTemplateManage,
+    CustomerRead,
+    CustomerWrite,
+    GoodsRead,
+    GoodsWrite,
+    MaterialRead,
+    MaterialWrite,
+    PurchaseRead,
+    PurchaseWrite,
+    InvoiceRead,
+    InvoiceWrite,
+    ProgressMark,
+    StatsRead,
+    Upload,
+    AccountManage,
+    WorkflowManage,
+}
+
+pub const ALL_PERMISSION: &str = "*";
+
+impl Permission {
+    pub const ALL: [Permission; 20] = [
+        Permission::OrderRead,
+        Permission::OrderWrite,
+        Permission::OrderDelete,
+        Permission::ExcelImport,
+        Permission::ExcelTemplateManage,
+        Permission::CustomerRead,
+        Permission::CustomerWrite,
+        Permission::GoodsRead,
+        Permission::GoodsWrite,
+        Permission::MaterialRead,
+        Permission::MaterialWrite,
+        Permission::PurchaseRead,
+        Permission::PurchaseWrite,
+        Permission::InvoiceRead,
+        Permission::InvoiceWrite,
+        Permission::ProgressMark,
+        Permission::StatsRead,
+        Permission::Upload,
+        Permission::AccountManage,
+        Permission::WorkflowManage,
+    ];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Permission::OrderRead => "order:read",
+            Permission::OrderWrite => "order:write",
+            Permission::OrderDelete => "order:delete",
+            Permission::ExcelImport => "excel:import",
+            Permission::ExcelTemplateManage => "excel:template",
+            Permission::CustomerRead => "customer:read",
+            Permission::CustomerWrite => "customer:write",
+            Permission::GoodsRead => "goods:read",
+            Permission::GoodsWrite => "goods:write",
+            Permission::MaterialRead => "material:read",
+            Permission::MaterialWrite => "material:write",
+            Permission::PurchaseRead => "purchase:read",
+            Permission::PurchaseWrite => "purchase:write",
+            Permission::InvoiceRead => "invoice:read",
+            Permission::InvoiceWrite => "invoice:write",
+            Permission::ProgressMark => "progress:mark",
+            Permission::StatsRead => "stats:read",
+            Permission::Upload => "upload",
+            Permission::AccountManage => "account:manage",
+            Permission::WorkflowManage => "workflow:manage",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::OrderRead => "查看订单",
+            Permission::OrderWrite => "编辑订单",
+            Permission::OrderDelete => "删除订单",
+            Permission::ExcelImport => "导入excel",
+            Permission::ExcelTemplateManage => "配置excel模版",
+            Permission::CustomerRead => "查看客户",
+            Permission::CustomerWrite => "编辑客户",
+            Permission::GoodsRead => "查看商品",
+            Permission::GoodsWrite => "编辑商品",
+            Permission::MaterialRead => "查看物料",
+            Permission::MaterialWrite => "编辑物料",
+            Permission::PurchaseRead => "查看采购",
+            Permission::PurchaseWrite => "编辑采购",
+            Permission::InvoiceRead => "查看发票和收款",
+            Permission::InvoiceWrite => "开发票和记收款",
+            Permission::ProgressMark => "标记流程",
+            Permission::StatsRead => "查看统计",
+            Permission::Upload => "上传文件",
+            Permission::AccountManage => "管理账号和角色",
+            Permission::WorkflowManage => "编辑生产流程",
+        }
+    }
+
+    pub fn is_valid_key(key: &str) -> bool {
+        key == ALL_PERMISSION || Permission::ALL.iter().any(|p| p.key() == key)
+    }
+}
+
+pub trait PermissionMarker {
+    const PERMISSION: Permission;
+}
+
+macro_rules! permission_markers {
+    ($($marker:ident),* $(,)?) => {
+        $(
+            pub struct $marker;
+            impl PermissionMarker for $marker {
+                const PERMISSION: Permission = Permission::$marker;
+            }
+        )*
+    };
+}
+
+permission_markers!(
+    OrderRead,
+    OrderWrite,
+    OrderDelete,
+    ExcelImport,
+    ExcelTemplateManage,
+    CustomerRead,
+    CustomerWrite,
+    GoodsRead,
+    GoodsWrite,
+    MaterialRead,
+    MaterialWrite,
+    PurchaseRead,
+    PurchaseWrite,
+    InvoiceRead,
+    InvoiceWrite,
+    ProgressMark,
+    StatsRead,
+    Upload,
+    AccountManage,
+    WorkflowManage,
+);
+
+/// 权限检查, 需要放在 auth 中间件后面用, 如:
+/// `_: Require<OrderDelete>` 或 `Require(account, _): Require<OrderWrite>`
+pub struct Require<P: PermissionMarker>(pub AccountDto, pub PhantomData<P>);
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for Require<P>
+where
+    S: Send + Sync,
+    P: PermissionMarker,
+{
+    type Rejection = ERPError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let account = parts
+            .extensions
+            .get::<AccountDto>()
+            .cloned()
+            .ok_or(ERPError::NotAuthorized)?;
+        account.check_permission(P::PERMISSION)?;
+
+        Ok(Require(account, PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dto::dto_account::AccountDto;
+    use crate::middleware::permission::Permission;
+
+    fn account(permissions: &[&str]) -> AccountDto {
+        AccountDto {
+            id: 1,
+            name: "".to_string(),
+            account: "".to_string(),
+            department_id: 1,
+            department: "".to_string(),
+            steps: vec![],
+            disabled: false,
+            role_ids: vec![],
+            roles: vec![],
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_permission() {
+        let viewer = account(&["order:read"]);
+        assert!(viewer.has_permission(Permission::OrderRead));
+        assert!(viewer.check_permission(Permission::OrderDelete).is_err());
+
+        let admin = account(&["*"]);
+        assert!(Permission::ALL.iter().all(|p| admin.has_permission(*p)));
+
+        assert!(Permission::is_valid_key("excel:import"));
+        assert!(!Permission::is_valid_key("excel"));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::common::password::{check_password_strength, hash_password};
+use crate::{ERPError, ERPResult};
+use sqlx::{Pool, Postgres};
+
 #[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
 pub struct AccountModel {
     pub id: i32,
@@ -5,6 +9,59 @@ pub struct AccountModel {
     pub account: String,
     pub password: String,
     pub department_id: i32,
+    pub disabled: bool,
+    pub role_ids: Vec<i32>,
+    pub token_version: i32,
+}
+
+impl AccountModel {
+    /// 启动时调用: 管理员账号不存在, 且配置了初始密码, 就在管理部下创建一个
+    pub async fn bootstrap_admin(
+        db: &Pool<Postgres>,
+        account: &str,
+        password: Option<&str>,
+    ) -> ERPResult<()> {
+        if sqlx::query!("select id from accounts where account=$1", account)
+            .fetch_optional(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        let Some(password) = password else {
+            tracing::warn!(
+                "管理员账号{}不存在, 配置ADMIN_PASSWORD后重启即可创建",
+                account
+            );
+            return Ok(());
+        };
+        check_password_strength(password)?;
+
+        let hashed_password = hash_password(password)?;
+        let rows_affected = sqlx::query!(
+            r#"
+            insert into accounts (name, account, password, department_id)
+            select '管理员', $1, $2, id
+            from departments
+            where name = '管理部'
+            "#,
+            account,
+            hashed_password
+        )
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .rows_affected();
+
+        if rows_affected == 0 {
+            return Err(ERPError::NotFound("管理部不存在".to_string()));
+        }
+        tracing::info!("已创建管理员账号{}", account);
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
@@ -12,4 +69,60 @@ pub struct DepartmentModel {
     pub id: i32,
     pub name: String,
     pub steps: Vec<i32>,
+    pub role_ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct RoleModel {
+    pub id: i32,
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+impl RoleModel {
+    /// 账号自己的角色 + 所在部门的角色
+    pub async fn get_roles_of_account(
+        db: &Pool<Postgres>,
+        account: &AccountModel,
+        department: &DepartmentModel,
+    ) -> ERPResult<Vec<RoleModel>> {
+        let mut role_ids = account.role_ids.clone();
+        role_ids.extend(department.role_ids.iter());
+        if role_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let roles = sqlx::query_as!(
+            RoleModel,
+            "select * from roles where id = any($1) order by id",
+            &role_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(roles)
+    }
+
+    pub async fn check_role_ids(db: &Pool<Postgres>, role_ids: &[i32]) -> ERPResult<()> {
+        if role_ids.is_empty() {
+            return Ok(());
+        }
+
+        let count = sqlx::query!("select count(1) from roles where id = any($1)", role_ids)
+            .fetch_one(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .count
+            .unwrap_or(0);
+
+        let mut unique_ids = role_ids.to_vec();
+        unique_ids.sort();
+        unique_ids.dedup();
+        if count as usize != unique_ids.len() {
+            return Err(ERPError::NotFound("角色不存在".to_string()));
+        }
+
+        Ok(())
+    }
 }
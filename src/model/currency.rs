@@ -0,0 +1,201 @@
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+/// 币种, is_base: 基础货币(本币), 汇总金额都换算成它
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct CurrencyModel {
+    pub code: String,
+    pub name: String,
+    pub is_base: bool,
+}
+
+/// 汇率: 1单位外币 = rate 基础货币
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ExchangeRateModel {
+    pub id: i32,
+    pub currency: String,
+    pub rate: Decimal,
+    pub effective_date: NaiveDate,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+impl CurrencyModel {
+    pub async fn get_list(db: &Pool<Postgres>) -> ERPResult<Vec<CurrencyModel>> {
+        let currencies = sqlx::query_as!(
+            CurrencyModel,
+            "select * from currencies order by is_base desc, code"
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(currencies)
+    }
+
+    pub async fn get_by_code(db: &Pool<Postgres>, code: &str) -> ERPResult<CurrencyModel> {
+        sqlx::query_as!(
+            CurrencyModel,
+            "select * from currencies where code = $1",
+            code
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("币种#{}", code)))
+    }
+
+    pub async fn get_base(db: &Pool<Postgres>) -> ERPResult<CurrencyModel> {
+        sqlx::query_as!(CurrencyModel, "select * from currencies where is_base")
+            .fetch_optional(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound("基础货币".to_string()))
+    }
+
+    /// 币种要在currencies里, 没传时用基础货币
+    pub async fn checked(db: &Pool<Postgres>, currency: Option<&str>) -> ERPResult<String> {
+        let currency = match currency.map(|c| c.trim().to_uppercase()) {
+            Some(code) if !code.is_empty() => Self::get_by_code(db, &code).await?,
+            _ => Self::get_base(db).await?,
+        };
+
+        Ok(currency.code)
+    }
+
+    /// 订单的币种: 传了就用传的(要在currencies里), 没传用客户的币种, 客户不存在时用基础货币
+    pub async fn for_order(
+        db: &Pool<Postgres>,
+        customer_no: &str,
+        currency: Option<&str>,
+    ) -> ERPResult<String> {
+        if currency.is_some_and(|c| !c.trim().is_empty()) {
+            return Self::checked(db, currency).await;
+        }
+
+        let customer_currency = sqlx::query!(
+            "select currency from customers where customer_no = $1",
+            customer_no
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .map(|row| row.currency);
+
+        Self::checked(db, customer_currency.as_deref()).await
+    }
+}
+
+/// 某一天各币种的汇率, 用来把金额换算成基础货币
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeRates {
+    pub base: String,
+    pub date: NaiveDate,
+    pub rates: HashMap<String, Decimal>,
+}
+
+impl ExchangeRates {
+    /// 每个币种取生效日期<=date的最新汇率
+    pub async fn on(db: &Pool<Postgres>, date: NaiveDate) -> ERPResult<ExchangeRates> {
+        let base = CurrencyModel::get_base(db).await?;
+        let rates = sqlx::query!(
+            r#"
+            select distinct on (currency) currency, rate from exchange_rates
+            where effective_date <= $1
+            order by currency, effective_date desc
+            "#,
+            date
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| (row.currency, row.rate))
+        .collect::<HashMap<String, Decimal>>();
+
+        Ok(ExchangeRates {
+            base: base.code,
+            date,
+            rates,
+        })
+    }
+
+    /// 换算成基础货币, 保留2位小数; 没有汇率时报错
+    pub fn to_base(&self, amount: Decimal, currency: &str) -> ERPResult<Decimal> {
+        if currency == self.base {
+            return Ok(amount);
+        }
+        let rate = self.rates.get(currency).ok_or(ERPError::NotFound(format!(
+            "{}在{}之前的汇率",
+            currency, self.date
+        )))?;
+
+        Ok((amount * rate).round_dp(2))
+    }
+}
+
+impl ExchangeRateModel {
+    /// currency为空时是所有币种, 按生效日期倒序
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        currency: &str,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<ExchangeRateModel>, i64)> {
+        let rates = sqlx::query_as!(
+            ExchangeRateModel,
+            r#"
+            select * from exchange_rates where $1 = '' or currency = $1
+            order by effective_date desc, currency offset $2 limit $3
+            "#,
+            currency,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            "select count(1) from exchange_rates where $1 = '' or currency = $1",
+            currency
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((rates, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::currency::ExchangeRates;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_to_base() {
+        let rates = ExchangeRates {
+            base: "CNY".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            rates: HashMap::from([("USD".to_string(), Decimal::new(71234, 4))]),
+        };
+        assert_eq!(
+            rates.to_base(Decimal::from(100), "CNY").unwrap(),
+            Decimal::from(100)
+        );
+        // 35 * 7.1234 = 249.319
+        assert_eq!(
+            rates.to_base(Decimal::new(35, 0), "USD").unwrap(),
+            Decimal::new(24932, 2)
+        );
+        assert!(rates.to_base(Decimal::from(1), "EUR").is_err());
+    }
+}
@@ -9,6 +9,7 @@ pub struct CustomerModel {
     pub address: String,
     pub phone: String,
     pub notes: String,
+    pub currency: String, // 结算币种
 }
 
 impl CustomerModel {
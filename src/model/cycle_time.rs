@@ -0,0 +1,760 @@
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::model::stats::{StatsFilter, StatsPeriod};
+use crate::model::workflow::{ProgressRecord, StepQuantity, Workflow, WorkflowRoute};
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, HashMap};
+
+/// 产品在某个流程上花的时间:
+/// 从上一个流程第一次有数量完成(第一个流程从下单日期0点算)到这个流程全部完成, 没完成的算到现在;
+/// 全部完成和 WorkflowRoute::step_quantities 一样: 上一步完成后, 流进来的数量都完成了或者是次品
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StepTime {
+    pub step: i32,
+    pub entered_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>, // None: 还没全部完成
+    pub hours: Decimal,
+    pub skipped: bool, // 跳过的流程, 不算进统计
+}
+
+/// 两个时间之间的小时数, 保留2位小数
+fn hours_between(start: DateTime<Utc>, end: DateTime<Utc>) -> Decimal {
+    let seconds = (end - start).num_seconds().max(0);
+    (Decimal::from(seconds) / Decimal::from(3600)).round_dp(2)
+}
+
+/// 按流程路线算出产品在每个流程上的时间, 还没流到的流程不返回; records 按时间先后排好序
+pub fn step_times(
+    workflow: &Workflow,
+    route: &WorkflowRoute,
+    count: i32,
+    start: DateTime<Utc>,
+    records: &[(DateTime<Utc>, ProgressRecord)],
+    now: DateTime<Utc>,
+) -> Vec<StepTime> {
+    let mut times = vec![];
+    let mut entered_at = Some(start);
+    let mut available = count;
+    let mut upstream_left_at = Some(start);
+    for step in route.steps.iter() {
+        let step_records = records
+            .iter()
+            .filter(|(_, record)| record.step == *step)
+            .collect::<Vec<&(DateTime<Utc>, ProgressRecord)>>();
+        // 可选流程没人操作过, 直接流到下一步
+        if route.optional_steps.contains(step) && step_records.is_empty() {
+            continue;
+        }
+        let Some(entered) = entered_at else {
+            break;
+        };
+
+        let mut completed = 0;
+        let mut defective = 0;
+        let mut first_done_at = None;
+        let mut left_at = None;
+        let mut skipped = !step_records.is_empty();
+        for (dt, record) in step_records {
+            defective += record.defective;
+            if record.skipped || workflow.is_done(record.step, record.index) {
+                completed += record.quantity;
+                skipped &= record.skipped;
+                first_done_at.get_or_insert(*dt);
+            }
+            // 上一步的最后一个次品可能比这一步完成得晚, 取晚的那个
+            if let (None, Some(upstream)) = (left_at, upstream_left_at) {
+                if completed.min(count) + defective >= available {
+                    left_at = Some((*dt).max(upstream));
+                }
+            }
+        }
+        available = completed.min(count);
+        upstream_left_at = left_at;
+
+        times.push(StepTime {
+            step: *step,
+            entered_at: entered,
+            left_at,
+            hours: hours_between(entered, left_at.unwrap_or(now)),
+            skipped: skipped && first_done_at.is_some(),
+        });
+        entered_at = first_done_at;
+    }
+
+    times
+}
+
+/// 最近秩法的百分位数, hours 要从小到大排好序
+pub fn percentile(hours: &[Decimal], p: usize) -> Decimal {
+    if hours.is_empty() {
+        return Decimal::ZERO;
+    }
+    let rank = (p * hours.len()).div_ceil(100).max(1);
+    hours[rank - 1]
+}
+
+/// 一组流程时间的统计(小时)
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CycleTimeStat {
+    pub group: String, // 流程名/部门名/客户编号
+    pub step: i32,     // 按部门统计时是0
+    pub count: i32,
+    pub avg_hours: Decimal,
+    pub median_hours: Decimal,
+    pub p90_hours: Decimal,
+    pub max_hours: Decimal,
+}
+
+impl CycleTimeStat {
+    pub fn new(group: &str, step: i32, mut hours: Vec<Decimal>) -> CycleTimeStat {
+        hours.sort();
+        let count = hours.len() as i32;
+        let avg_hours = match count {
+            0 => Decimal::ZERO,
+            _ => (hours.iter().sum::<Decimal>() / Decimal::from(count)).round_dp(2),
+        };
+        CycleTimeStat {
+            group: group.to_string(),
+            step,
+            count,
+            avg_hours,
+            median_hours: percentile(&hours, 50),
+            p90_hours: percentile(&hours, 90),
+            max_hours: hours.last().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// 某个周期(按流程完成日期)某个流程的时间
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CycleTimeTrend {
+    pub period: NaiveDate,
+    pub step: i32,
+    pub name: String,
+    pub count: i32,
+    pub median_hours: Decimal,
+    pub p90_hours: Decimal,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CycleTimeStats {
+    pub by_step: Vec<CycleTimeStat>,
+    pub by_department: Vec<CycleTimeStat>,
+    pub by_customer: Vec<CycleTimeStat>, // 按客户编号, 流程排
+    pub trends: Vec<CycleTimeTrend>,     // 按周期, 流程排
+}
+
+/// 产品在每个流程上的时间
+#[derive(Debug, Serialize, Clone)]
+pub struct ItemCycleTime {
+    pub order_item_id: i32,
+    pub order_id: i32,
+    pub order_no: String,
+    pub customer_no: String,
+    pub steps: Vec<StepTime>,
+}
+
+/// 在某个流程上等待的产品
+#[derive(Debug, Serialize, Clone)]
+pub struct WaitingItem {
+    pub order_item_id: i32,
+    pub order_id: i32,
+    pub order_no: String,
+    pub customer_no: String,
+    pub step: i32,
+    pub name: String,
+    pub quantity: i32, // 在这个流程上还没完成的数量
+    pub entered_at: DateTime<Utc>,
+    pub waiting_hours: Decimal,
+    pub last_progress_at: Option<DateTime<Utc>>, // 最后一次操作的时间
+    pub idle_hours: Decimal,                     // 最后一次操作(没有操作过时从下单)到现在
+}
+
+/// 每个流程上等待的产品数, 等待时间
+#[derive(Debug, Serialize, Clone)]
+pub struct WaitingStep {
+    pub step: i32,
+    pub name: String,
+    pub items: i32,
+    pub quantity: i32,
+    pub median_hours: Decimal,
+    pub max_hours: Decimal,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WaitingStats {
+    pub steps: Vec<WaitingStep>, // 按最长等待时间排, 最长的就是瓶颈
+    pub items: Vec<WaitingItem>, // 等待最久的产品
+}
+
+/// 一个产品算好的流程时间和数量
+struct ItemCycle {
+    order_item_id: i32,
+    order: OrderModel,
+    start: DateTime<Utc>,
+    times: Vec<StepTime>,
+    quantities: Vec<StepQuantity>,
+}
+
+/// 日期当天0点(本地时间)
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| date.and_time(NaiveTime::MIN).and_utc())
+}
+
+/// 读出订单(按客户, 下单日期<=end_date, order_id不为0时只看这个订单)下所有产品的流程时间
+async fn load_item_cycles(
+    db: &Pool<Postgres>,
+    workflow: &Workflow,
+    filter: &StatsFilter,
+    order_id: i32,
+    now: DateTime<Utc>,
+) -> ERPResult<Vec<ItemCycle>> {
+    let orders = sqlx::query_as!(
+        OrderModel,
+        r#"
+        select * from orders
+        where ($1 = '' or customer_no = $1) and ($2::date is null or order_date <= $2)
+            and ($3 = 0 or id = $3)
+        "#,
+        filter.customer_no,
+        filter.end_date,
+        order_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let order_ids = orders.iter().map(|order| order.id).collect::<Vec<i32>>();
+    let order_id_to_order = orders
+        .into_iter()
+        .map(|order| (order.id, order))
+        .collect::<HashMap<i32, OrderModel>>();
+
+    let order_items = sqlx::query!(
+        "select id, order_id, count from order_items where order_id = any($1) order by id",
+        &order_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?;
+
+    let mut order_item_id_to_records: HashMap<i32, Vec<(DateTime<Utc>, ProgressRecord)>> =
+        HashMap::new();
+    sqlx::query_as!(
+        ProgressModel,
+        r#"
+        select p.*
+        from progress p, order_items oi
+        where p.order_item_id = oi.id and oi.order_id = any($1)
+        order by p.dt, p.id
+        "#,
+        &order_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .iter()
+    .for_each(|progress| {
+        order_item_id_to_records
+            .entry(progress.order_item_id)
+            .or_default()
+            .push((progress.dt, ProgressRecord::from(progress)))
+    });
+
+    let mut cycles = vec![];
+    for order_item in order_items {
+        let Some(order) = order_id_to_order.get(&order_item.order_id) else {
+            continue;
+        };
+        let route = workflow.route_for_order(order);
+        let records = order_item_id_to_records
+            .get(&order_item.id)
+            .map(|records| records.as_slice())
+            .unwrap_or(&[]);
+        let start = start_of_day(order.order_date);
+        let all_records = records
+            .iter()
+            .map(|(_, record)| *record)
+            .collect::<Vec<ProgressRecord>>();
+
+        cycles.push(ItemCycle {
+            order_item_id: order_item.id,
+            order: order.clone(),
+            start,
+            times: step_times(workflow, &route, order_item.count, start, records, now),
+            quantities: route.step_quantities(workflow, order_item.count, &all_records),
+        });
+    }
+
+    Ok(cycles)
+}
+
+impl CycleTimeStats {
+    /// 只统计完成日期在筛选范围内, 没有跳过的流程
+    pub fn from_items(
+        workflow: &Workflow,
+        department_names: &HashMap<i32, String>,
+        items: &[(String, Vec<StepTime>)],
+        filter: &StatsFilter,
+        period: StatsPeriod,
+    ) -> CycleTimeStats {
+        let mut step_hours: BTreeMap<i32, Vec<Decimal>> = BTreeMap::new();
+        let mut department_hours: BTreeMap<i32, Vec<Decimal>> = BTreeMap::new();
+        let mut customer_hours: BTreeMap<(String, i32), Vec<Decimal>> = BTreeMap::new();
+        let mut period_hours: BTreeMap<(NaiveDate, i32), Vec<Decimal>> = BTreeMap::new();
+        for (customer_no, times) in items.iter() {
+            for time in times.iter().filter(|time| !time.skipped) {
+                let Some(left_at) = time.left_at else {
+                    continue;
+                };
+                let left_date = left_at.with_timezone(&Local).date_naive();
+                if !filter.contains(left_date) {
+                    continue;
+                }
+                step_hours.entry(time.step).or_default().push(time.hours);
+                if let Some(step) = workflow.steps.iter().find(|step| step.step == time.step) {
+                    department_hours
+                        .entry(step.department_id)
+                        .or_default()
+                        .push(time.hours);
+                }
+                customer_hours
+                    .entry((customer_no.clone(), time.step))
+                    .or_default()
+                    .push(time.hours);
+                period_hours
+                    .entry((period.start_of(left_date), time.step))
+                    .or_default()
+                    .push(time.hours);
+            }
+        }
+
+        CycleTimeStats {
+            by_step: step_hours
+                .into_iter()
+                .map(|(step, hours)| CycleTimeStat::new(workflow.step_name(step), step, hours))
+                .collect(),
+            by_department: department_hours
+                .into_iter()
+                .map(|(department_id, hours)| {
+                    let name = department_names
+                        .get(&department_id)
+                        .map(|name| name.as_str())
+                        .unwrap_or("");
+                    CycleTimeStat::new(name, 0, hours)
+                })
+                .collect(),
+            by_customer: customer_hours
+                .into_iter()
+                .map(|((customer_no, step), hours)| CycleTimeStat::new(&customer_no, step, hours))
+                .collect(),
+            trends: period_hours
+                .into_iter()
+                .map(|((period, step), hours)| {
+                    let stat = CycleTimeStat::new(workflow.step_name(step), step, hours);
+                    CycleTimeTrend {
+                        period,
+                        step,
+                        name: stat.group,
+                        count: stat.count,
+                        median_hours: stat.median_hours,
+                        p90_hours: stat.p90_hours,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn get(
+        db: &Pool<Postgres>,
+        filter: &StatsFilter,
+        period: StatsPeriod,
+    ) -> ERPResult<CycleTimeStats> {
+        let workflow = Workflow::get(db).await?;
+        let department_names = sqlx::query!("select id, name from departments")
+            .fetch_all(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .into_iter()
+            .map(|row| (row.id, row.name))
+            .collect::<HashMap<i32, String>>();
+        let items = load_item_cycles(db, &workflow, filter, 0, Utc::now())
+            .await?
+            .into_iter()
+            .map(|cycle| (cycle.order.customer_no, cycle.times))
+            .collect::<Vec<(String, Vec<StepTime>)>>();
+
+        Ok(CycleTimeStats::from_items(
+            &workflow,
+            &department_names,
+            &items,
+            filter,
+            period,
+        ))
+    }
+}
+
+impl ItemCycleTime {
+    /// 订单下每个产品在每个流程上的时间
+    pub async fn get_list(db: &Pool<Postgres>, order_id: i32) -> ERPResult<Vec<ItemCycleTime>> {
+        let workflow = Workflow::get(db).await?;
+        let items = load_item_cycles(db, &workflow, &StatsFilter::default(), order_id, Utc::now())
+            .await?
+            .into_iter()
+            .map(|cycle| ItemCycleTime {
+                order_item_id: cycle.order_item_id,
+                order_id: cycle.order.id,
+                order_no: cycle.order.order_no,
+                customer_no: cycle.order.customer_no,
+                steps: cycle.times,
+            })
+            .collect();
+
+        Ok(items)
+    }
+}
+
+impl WaitingStats {
+    /// 按每个流程上等待的产品汇总; items 只保留等待最久的 limit 个
+    pub fn from_items(
+        workflow: &Workflow,
+        mut items: Vec<WaitingItem>,
+        limit: usize,
+    ) -> WaitingStats {
+        let mut step_to_items: BTreeMap<i32, Vec<&WaitingItem>> = BTreeMap::new();
+        for item in items.iter() {
+            step_to_items.entry(item.step).or_default().push(item);
+        }
+        let mut steps = step_to_items
+            .into_iter()
+            .map(|(step, step_items)| {
+                let stat = CycleTimeStat::new(
+                    workflow.step_name(step),
+                    step,
+                    step_items.iter().map(|item| item.waiting_hours).collect(),
+                );
+                WaitingStep {
+                    step,
+                    name: stat.group,
+                    items: stat.count,
+                    quantity: step_items.iter().map(|item| item.quantity).sum(),
+                    median_hours: stat.median_hours,
+                    max_hours: stat.max_hours,
+                }
+            })
+            .collect::<Vec<WaitingStep>>();
+        steps.sort_by(|a, b| b.max_hours.cmp(&a.max_hours).then(a.step.cmp(&b.step)));
+
+        items.sort_by(|a, b| {
+            b.waiting_hours
+                .cmp(&a.waiting_hours)
+                .then(a.order_item_id.cmp(&b.order_item_id))
+        });
+        items.truncate(limit);
+
+        WaitingStats { steps, items }
+    }
+
+    /// 现在还在流程上等待的产品(有数量到了这个流程还没完成), 只看筛选的客户和下单日期
+    pub async fn get(
+        db: &Pool<Postgres>,
+        filter: &StatsFilter,
+        limit: usize,
+    ) -> ERPResult<WaitingStats> {
+        let workflow = Workflow::get(db).await?;
+        let now = Utc::now();
+        let cycles = load_item_cycles(db, &workflow, filter, 0, now)
+            .await?
+            .into_iter()
+            .filter(|cycle| filter.contains(cycle.order.order_date))
+            .collect::<Vec<ItemCycle>>();
+        let order_item_ids = cycles
+            .iter()
+            .map(|cycle| cycle.order_item_id)
+            .collect::<Vec<i32>>();
+        let last_progresses = ProgressModel::get_last_progresses(db, &order_item_ids).await?;
+
+        let mut items = vec![];
+        for cycle in cycles.iter() {
+            let last_progress_at = last_progresses
+                .get(&cycle.order_item_id)
+                .map(|progress| progress.dt);
+            for time in cycle.times.iter().filter(|time| time.left_at.is_none()) {
+                let quantity = cycle
+                    .quantities
+                    .iter()
+                    .find(|quantity| quantity.step == time.step)
+                    .map(|quantity| quantity.in_progress)
+                    .unwrap_or(0);
+                if quantity == 0 {
+                    continue;
+                }
+                items.push(WaitingItem {
+                    order_item_id: cycle.order_item_id,
+                    order_id: cycle.order.id,
+                    order_no: cycle.order.order_no.clone(),
+                    customer_no: cycle.order.customer_no.clone(),
+                    step: time.step,
+                    name: workflow.step_name(time.step).to_string(),
+                    quantity,
+                    entered_at: time.entered_at,
+                    waiting_hours: time.hours,
+                    last_progress_at,
+                    idle_hours: hours_between(last_progress_at.unwrap_or(cycle.start), now),
+                });
+            }
+        }
+
+        Ok(WaitingStats::from_items(&workflow, items, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::cycle_time::{
+        percentile, step_times, CycleTimeStat, CycleTimeStats, StepTime, WaitingItem, WaitingStats,
+    };
+    use crate::model::stats::{StatsFilter, StatsPeriod};
+    use crate::model::workflow::{
+        ProgressRecord, Workflow, WorkflowRoute, WorkflowStepModel, WorkflowStepOptionModel,
+    };
+    use chrono::{DateTime, NaiveDate, Utc};
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn workflow() -> Workflow {
+        let step = |step: i32, name: &str, department_id: i32| WorkflowStepModel {
+            id: step,
+            step,
+            name: name.to_string(),
+            department_id,
+            is_shipping: false,
+            labor_rate: Decimal::ZERO,
+        };
+        let option = |step: i32, index: i32, is_done: bool| WorkflowStepOptionModel {
+            id: 0,
+            step,
+            index,
+            name: "".to_string(),
+            color: "".to_string(),
+            is_done,
+            is_exception: false,
+            purchase_kind: "".to_string(),
+        };
+        Workflow {
+            steps: vec![step(2, "备料", 2), step(3, "生产", 3), step(4, "品检", 4)],
+            options: vec![
+                option(2, 1, false),
+                option(2, 2, true),
+                option(3, 2, true),
+                option(4, 2, true),
+            ],
+            templates: vec![],
+        }
+    }
+
+    fn record(step: i32, index: i32, quantity: i32, skipped: bool) -> ProgressRecord {
+        ProgressRecord {
+            step,
+            index,
+            quantity,
+            defective: 0,
+            skipped,
+        }
+    }
+
+    #[test]
+    fn test_step_times() {
+        let workflow = workflow();
+        let route = WorkflowRoute {
+            template_id: 0,
+            steps: vec![2, 3, 4],
+            optional_steps: vec![],
+            skippable_steps: vec![],
+        };
+        let records = [
+            (at("2024-01-01T10:00:00Z"), record(2, 1, 0, false)),
+            (at("2024-01-01T12:00:00Z"), record(2, 2, 4, false)),
+            (at("2024-01-02T00:00:00Z"), record(2, 2, 6, false)),
+            (at("2024-01-02T06:00:00Z"), record(3, 2, 10, true)),
+        ];
+        let now = at("2024-01-03T06:00:00Z");
+        let times = step_times(
+            &workflow,
+            &route,
+            10,
+            at("2024-01-01T00:00:00Z"),
+            &records,
+            now,
+        );
+
+        assert_eq!(times.len(), 3);
+        // 备料: 下单到全部完成
+        assert_eq!(times[0].left_at, Some(at("2024-01-02T00:00:00Z")));
+        assert_eq!(times[0].hours, Decimal::from(24));
+        // 生产: 备料第一次完成4个就开始算, 跳过的
+        assert_eq!(times[1].entered_at, at("2024-01-01T12:00:00Z"));
+        assert_eq!(times[1].hours, Decimal::from(18));
+        assert!(times[1].skipped);
+        // 品检: 还没完成, 算到现在
+        assert_eq!(times[2].left_at, None);
+        assert_eq!(times[2].hours, Decimal::from(24));
+        assert!(!times[2].skipped);
+    }
+
+    #[test]
+    fn test_step_times_with_defective() {
+        let workflow = workflow();
+        let route = WorkflowRoute {
+            template_id: 0,
+            steps: vec![2, 3, 4],
+            optional_steps: vec![],
+            skippable_steps: vec![],
+        };
+        let defective = |step: i32, index: i32, quantity: i32, defective: i32| ProgressRecord {
+            defective,
+            ..record(step, index, quantity, false)
+        };
+        let records = [
+            (at("2024-01-01T12:00:00Z"), defective(2, 2, 8, 0)),
+            (at("2024-01-02T00:00:00Z"), defective(3, 2, 7, 1)),
+            // 备料剩下的2个是次品, 比生产完成得晚
+            (at("2024-01-02T06:00:00Z"), defective(2, 1, 0, 2)),
+            (at("2024-01-02T12:00:00Z"), defective(4, 2, 7, 0)),
+        ];
+        let now = at("2024-01-05T00:00:00Z");
+        let times = step_times(
+            &workflow,
+            &route,
+            10,
+            at("2024-01-01T00:00:00Z"),
+            &records,
+            now,
+        );
+
+        assert_eq!(times.len(), 3);
+        assert_eq!(times[0].left_at, Some(at("2024-01-02T06:00:00Z")));
+        assert_eq!(times[0].hours, Decimal::from(30));
+        // 生产: 流进来的8个, 7个完成1个次品, 等备料的次品记完才算完成
+        assert_eq!(times[1].left_at, Some(at("2024-01-02T06:00:00Z")));
+        assert_eq!(times[1].hours, Decimal::from(18));
+        assert_eq!(times[2].left_at, Some(at("2024-01-02T12:00:00Z")));
+        assert_eq!(times[2].hours, Decimal::from(12));
+    }
+
+    #[test]
+    fn test_cycle_time_stats() {
+        let hours = (1..=10).map(Decimal::from).collect::<Vec<Decimal>>();
+        assert_eq!(percentile(&hours, 50), Decimal::from(5));
+        assert_eq!(percentile(&hours, 90), Decimal::from(9));
+        assert_eq!(percentile(&[], 90), Decimal::ZERO);
+        let stat = CycleTimeStat::new("备料", 2, vec![Decimal::from(3), Decimal::from(1)]);
+        assert_eq!(stat.avg_hours, Decimal::from(2));
+        assert_eq!(stat.median_hours, Decimal::from(1));
+        assert_eq!(stat.max_hours, Decimal::from(3));
+
+        let time = |step: i32, left_at: Option<&str>, hours: i64, skipped: bool| StepTime {
+            step,
+            entered_at: at("2024-01-01T00:00:00Z"),
+            left_at: left_at.map(at),
+            hours: Decimal::from(hours),
+            skipped,
+        };
+        let items = vec![
+            (
+                "L1001".to_string(),
+                vec![
+                    time(2, Some("2024-01-02T10:00:00Z"), 10, false),
+                    time(3, Some("2024-01-09T10:00:00Z"), 30, false),
+                    time(4, None, 50, false),
+                ],
+            ),
+            (
+                "L1002".to_string(),
+                vec![
+                    time(2, Some("2024-01-03T10:00:00Z"), 20, false),
+                    time(3, Some("2024-01-04T10:00:00Z"), 1, true),
+                ],
+            ),
+        ];
+        let department_names = HashMap::from([(2, "仓库部".to_string())]);
+        let stats = CycleTimeStats::from_items(
+            &workflow(),
+            &department_names,
+            &items,
+            &StatsFilter::default(),
+            StatsPeriod::Week,
+        );
+        assert_eq!(stats.by_step.len(), 2);
+        assert_eq!(stats.by_step[0].group, "备料");
+        assert_eq!(stats.by_step[0].count, 2);
+        assert_eq!(stats.by_step[0].p90_hours, Decimal::from(20));
+        assert_eq!(stats.by_department[0].group, "仓库部");
+        assert_eq!(stats.by_customer.len(), 3);
+        assert_eq!(stats.trends.len(), 2);
+        assert_eq!(
+            stats.trends[1].period,
+            NaiveDate::from_ymd_opt(2024, 1, 8).unwrap()
+        );
+
+        let filter = StatsFilter {
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 5),
+            ..Default::default()
+        };
+        let stats = CycleTimeStats::from_items(
+            &workflow(),
+            &department_names,
+            &items,
+            &filter,
+            StatsPeriod::Day,
+        );
+        assert_eq!(stats.by_step.len(), 1);
+        assert_eq!(stats.by_step[0].step, 3);
+    }
+
+    #[test]
+    fn test_waiting_stats() {
+        let item = |order_item_id: i32, step: i32, hours: i64| WaitingItem {
+            order_item_id,
+            order_id: 1,
+            order_no: "20240101".to_string(),
+            customer_no: "L1001".to_string(),
+            step,
+            name: "".to_string(),
+            quantity: 5,
+            entered_at: at("2024-01-01T00:00:00Z"),
+            waiting_hours: Decimal::from(hours),
+            last_progress_at: None,
+            idle_hours: Decimal::from(hours),
+        };
+        let stats = WaitingStats::from_items(
+            &workflow(),
+            vec![item(1, 2, 10), item(2, 3, 40), item(3, 2, 20)],
+            2,
+        );
+        assert_eq!(stats.steps[0].step, 3);
+        assert_eq!(stats.steps[1].name, "备料");
+        assert_eq!(stats.steps[1].items, 2);
+        assert_eq!(stats.steps[1].quantity, 10);
+        assert_eq!(
+            stats
+                .items
+                .iter()
+                .map(|item| item.order_item_id)
+                .collect::<Vec<i32>>(),
+            vec![2, 3]
+        );
+    }
+}
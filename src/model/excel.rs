@@ -1,6 +1,175 @@
+use crate::{ERPError, ERPResult};
+use sqlx::{Pool, Postgres};
+
 #[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
 pub struct CustomerExcelTemplateModel {
     pub id: i32,             // SERIAL,
     pub customer_no: String, // 客户编号
     pub template_id: i32,    // 备注
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ExcelTemplateModel {
+    pub id: i32,
+    pub name: String,
+    pub header_row: i32,           // 表头所在的行
+    pub start_row: i32,            // 数据开始的行
+    pub package_image_column: i32, // 包装卡图片所在的列, 0: 没有
+    pub goods_image_column: i32,   // 商品图片所在的列, 0: 没有
+    pub notes_image_column: i32,   // 备注图片所在的列, 0: 没有
+    pub carry_down: Vec<String>,   // 为空时沿用上一行的字段
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ExcelTemplateColumnModel {
+    pub id: i32,
+    pub template_id: i32,
+    pub col: i32,                // 第几列(从1开始), 0: 按表头名称找
+    pub header: String,          // 表头名称
+    pub field: String,           // OrderItemExcel的字段
+    pub remove_whitespace: bool, // 去掉所有空白
+}
+
+/// 一个excel模版的完整定义
+#[derive(Debug, Serialize, Clone)]
+pub struct ExcelTemplate {
+    #[serde(flatten)]
+    pub template: ExcelTemplateModel,
+    pub columns: Vec<ExcelTemplateColumnModel>,
+}
+
+impl ExcelTemplate {
+    pub async fn get(db: &Pool<Postgres>, id: i32) -> ERPResult<ExcelTemplate> {
+        let template = sqlx::query_as!(
+            ExcelTemplateModel,
+            "select * from excel_templates where id = $1",
+            id
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("excel模版#{}", id)))?;
+
+        let columns = sqlx::query_as!(
+            ExcelTemplateColumnModel,
+            "select * from excel_template_columns where template_id = $1 order by col, id",
+            id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(ExcelTemplate { template, columns })
+    }
+
+    pub async fn get_all(db: &Pool<Postgres>) -> ERPResult<Vec<ExcelTemplate>> {
+        let templates = sqlx::query_as!(
+            ExcelTemplateModel,
+            "select * from excel_templates order by id"
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut columns = sqlx::query_as!(
+            ExcelTemplateColumnModel,
+            "select * from excel_template_columns order by col, id"
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let excel_templates = templates
+            .into_iter()
+            .map(|template| {
+                let (template_columns, rest) = columns
+                    .drain(..)
+                    .partition(|column| column.template_id == template.id);
+                columns = rest;
+                ExcelTemplate {
+                    template,
+                    columns: template_columns,
+                }
+            })
+            .collect();
+
+        Ok(excel_templates)
+    }
+
+    /// 模版里有没有某个字段
+    pub fn has_field(&self, field: &str) -> bool {
+        self.columns.iter().any(|column| column.field == field)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ExcelHeaderSynonymModel {
+    pub id: i32,
+    pub field: String,  // OrderItemExcel的字段
+    pub header: String, // 表头名称(去掉空白, 小写)
+}
+
+impl ExcelHeaderSynonymModel {
+    pub async fn get_all(db: &Pool<Postgres>) -> ERPResult<Vec<ExcelHeaderSynonymModel>> {
+        let synonyms = sqlx::query_as!(
+            ExcelHeaderSynonymModel,
+            "select * from excel_header_synonyms order by field, id"
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(synonyms)
+    }
+}
+
+/// 导入时实际用到的列对应关系
+#[derive(Debug, Serialize, Clone)]
+pub struct ExcelMapping {
+    pub detected: bool, // true: 按表头自动识别的, false: 用的客户配置的模版
+    pub template: ExcelTemplate,
+    pub warnings: Vec<String>, // 没有对应上字段的列
+}
+
+/// 每个sheet的导入结果
+#[derive(Debug, Serialize, Clone)]
+pub struct ExcelSheetResult {
+    pub sheet: String,
+    pub order_no: String,
+    pub continuation: bool, // true: 没有订单表头(或单号和上一个sheet相同), 接在上一个sheet的订单后面
+    pub item_count: usize,  // 这个sheet读到的产品数
+    pub exists: bool,       // 订单是否已经存在
+    pub skipped: bool,      // true: 没有订单表头, 也没有识别出表头和产品(比如备注/汇总), 没有导入
+    pub mapping: ExcelMapping,
+}
+
+/// excel里的某一处问题
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ExcelIssue {
+    pub sheet: String,
+    pub row: u32, // 0: 不是某一行的问题
+    pub col: u32, // 0: 不是某一列的问题
+    pub field: String,
+    pub value: String,
+    pub message: String,
+}
+
+impl ExcelIssue {
+    pub fn new(sheet: &str, row: u32, col: u32, field: &str, value: &str, message: &str) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            row,
+            col,
+            field: field.to_string(),
+            value: value.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// excel校验不通过时返回的所有问题
+#[derive(Debug, Serialize, Clone)]
+pub struct ExcelIssueReport {
+    pub issues: Vec<ExcelIssue>,
+    pub annotated_file: String, // 标红了问题单元格的excel文件地址, 生成失败时为空
+}
@@ -0,0 +1,691 @@
+use crate::model::currency::ExchangeRates;
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Pool, Postgres};
+use std::collections::HashMap;
+
+/// 发票(对账单)
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct InvoiceModel {
+    pub id: i32,
+    pub invoice_no: String,
+    pub customer_no: String,
+    pub invoice_date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub subtotal: Decimal,
+    pub discount: Decimal,
+    pub tax_rate: Decimal, // 税率(%)
+    pub tax: Decimal,
+    pub total: Decimal,
+    pub paid: Decimal,
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+    pub currency: String,
+}
+
+/// 发票明细
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct InvoiceItemModel {
+    pub id: i32,
+    pub invoice_id: i32,
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub shipment_id: i32, // 0: 按订单开的
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: Decimal,
+    pub discount: Decimal,
+    pub amount: Decimal,
+}
+
+/// 收款记录
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct PaymentModel {
+    pub id: i32,
+    pub invoice_id: i32,
+    pub amount: Decimal,
+    pub payment_date: NaiveDate,
+    pub method: String,
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Invoice {
+    #[serde(flatten)]
+    pub invoice: InvoiceModel,
+    pub balance: Decimal, // 未收
+    pub items: Vec<InvoiceItemModel>,
+    pub payments: Vec<PaymentModel>,
+}
+
+/// 要开票的一行(还没保存)
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceLine {
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub shipment_id: i32,
+    pub customer_no: String,
+    pub currency: String,
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: Decimal,
+    pub discount: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceTotals {
+    pub subtotal: Decimal,
+    pub tax: Decimal,
+    pub total: Decimal,
+}
+
+/// 一行的金额 = 数量 * 单价 - 折扣
+pub fn line_amount(quantity: i32, unit_price: Decimal, discount: Decimal) -> Decimal {
+    (Decimal::from(quantity) * unit_price - discount).round_dp(2)
+}
+
+/// 应收 = 明细合计 - 整单折扣 + 税额, 税额按折扣后的金额算
+pub fn invoice_totals(
+    amounts: &[Decimal],
+    discount: Decimal,
+    tax_rate: Decimal,
+) -> ERPResult<InvoiceTotals> {
+    if tax_rate < Decimal::ZERO || tax_rate > Decimal::ONE_HUNDRED {
+        return Err(ERPError::ParamError("税率要在0到100之间".to_string()));
+    }
+    if amounts.iter().any(|amount| *amount < Decimal::ZERO) {
+        return Err(ERPError::ParamError("明细的折扣不能超过金额".to_string()));
+    }
+    let subtotal = amounts.iter().sum::<Decimal>();
+    if discount < Decimal::ZERO || discount > subtotal {
+        return Err(ERPError::ParamError(format!(
+            "折扣要在0到明细合计({})之间",
+            subtotal
+        )));
+    }
+    let tax = ((subtotal - discount) * tax_rate / Decimal::ONE_HUNDRED).round_dp(2);
+
+    Ok(InvoiceTotals {
+        subtotal,
+        tax,
+        total: subtotal - discount + tax,
+    })
+}
+
+/// 收款不能超过未收的金额
+pub fn check_payment_amount(total: Decimal, paid: Decimal, amount: Decimal) -> ERPResult<()> {
+    if amount <= Decimal::ZERO {
+        return Err(ERPError::ParamError("收款金额必须大于0".to_string()));
+    }
+    if paid + amount > total {
+        return Err(ERPError::Failed(format!(
+            "收款超出了未收的金额({})",
+            total - paid
+        )));
+    }
+
+    Ok(())
+}
+
+impl InvoiceLine {
+    /// 按订单开票: 每个sku开还没开过的数量; 要在事务里调用, 锁住这些sku直到发票保存
+    pub async fn from_orders(
+        db: &mut PgConnection,
+        order_ids: &[i32],
+    ) -> ERPResult<Vec<InvoiceLine>> {
+        // 锁住订单sku再算已开票的数量, 避免并发时同一个数量开两次
+        sqlx::query!(
+            "select id from order_items where order_id = any($1) order by id for update",
+            order_ids
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let rows = sqlx::query!(
+            r#"
+            select oi.id, oi.order_id, o.customer_no, o.currency, oi.count, oi.unit_price,
+                coalesce(g.name, '') as "goods_name!", coalesce(s.sku_no, '') as "sku_no!",
+                coalesce((select sum(ii.quantity) from invoice_items ii where ii.order_item_id = oi.id), 0) as "invoiced!"
+            from order_items oi
+                join orders o on o.id = oi.order_id
+                left join skus s on s.id = oi.sku_id
+                left join goods g on g.id = s.goods_id
+            where oi.order_id = any($1)
+            order by oi.order_id, oi.id
+            "#,
+            order_ids
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut lines = vec![];
+        let mut without_price = vec![];
+        for row in rows {
+            let quantity = row.count - row.invoiced as i32;
+            if quantity <= 0 {
+                continue;
+            }
+            match valid_unit_price(row.unit_price) {
+                Some(unit_price) => lines.push(InvoiceLine {
+                    order_id: row.order_id,
+                    order_item_id: row.id,
+                    shipment_id: 0,
+                    customer_no: row.customer_no,
+                    currency: row.currency,
+                    description: format!("{} {}", row.goods_name, row.sku_no)
+                        .trim()
+                        .to_string(),
+                    quantity,
+                    unit_price,
+                    discount: Decimal::ZERO,
+                }),
+                None => without_price.push(row.id),
+            }
+        }
+        check_unit_prices(&without_price)?;
+
+        Ok(lines)
+    }
+
+    /// 按发货单开票: 每个sku开这次发货的数量, 一张发货单只能开一次; 要在事务里调用, 锁住这些sku直到发票保存
+    pub async fn from_shipments(
+        db: &mut PgConnection,
+        shipment_ids: &[i32],
+    ) -> ERPResult<Vec<InvoiceLine>> {
+        // 锁住发货单里的订单sku再检查, 避免并发时同一张发货单或同一个数量开两次
+        sqlx::query!(
+            r#"
+            select id from order_items
+            where id in (select order_item_id from shipment_items where shipment_id = any($1))
+            order by id for update
+            "#,
+            shipment_ids
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        if let Some(row) = sqlx::query!(
+            "select shipment_id, invoice_id from invoice_items where shipment_id = any($1) limit 1",
+            shipment_ids
+        )
+        .fetch_optional(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        {
+            return Err(ERPError::AlreadyExists(format!(
+                "发货单#{}已经开在发票#{}里",
+                row.shipment_id, row.invoice_id
+            )));
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            select si.shipment_id, si.order_item_id, si.quantity, oi.order_id, o.customer_no, o.currency, oi.count, oi.unit_price,
+                coalesce(g.name, '') as "goods_name!", coalesce(s.sku_no, '') as "sku_no!",
+                coalesce((select sum(ii.quantity) from invoice_items ii where ii.order_item_id = oi.id), 0) as "invoiced!"
+            from shipment_items si
+                join order_items oi on oi.id = si.order_item_id
+                join orders o on o.id = oi.order_id
+                left join skus s on s.id = oi.sku_id
+                left join goods g on g.id = s.goods_id
+            where si.shipment_id = any($1)
+            order by si.shipment_id, si.order_item_id
+            "#,
+            shipment_ids
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut lines: Vec<InvoiceLine> = vec![];
+        let mut without_price = vec![];
+        for row in rows {
+            // 同一个sku已经按订单开过票了, 不能再按发货单开
+            let in_lines = lines
+                .iter()
+                .filter(|line| line.order_item_id == row.order_item_id)
+                .map(|line| line.quantity)
+                .sum::<i32>();
+            if row.invoiced as i32 + in_lines + row.quantity > row.count {
+                return Err(ERPError::Failed(format!(
+                    "产品#{}开票的数量超出了订单数量({})",
+                    row.order_item_id, row.count
+                )));
+            }
+            match valid_unit_price(row.unit_price) {
+                Some(unit_price) => lines.push(InvoiceLine {
+                    order_id: row.order_id,
+                    order_item_id: row.order_item_id,
+                    shipment_id: row.shipment_id,
+                    customer_no: row.customer_no,
+                    currency: row.currency,
+                    description: format!("{} {}", row.goods_name, row.sku_no)
+                        .trim()
+                        .to_string(),
+                    quantity: row.quantity,
+                    unit_price,
+                    discount: Decimal::ZERO,
+                }),
+                None => without_price.push(row.order_item_id),
+            }
+        }
+        check_unit_prices(&without_price)?;
+
+        Ok(lines)
+    }
+}
+
+/// 老数据导入时没有单价存的是0, 和没有单价一样处理
+fn valid_unit_price(unit_price: Option<Decimal>) -> Option<Decimal> {
+    unit_price.filter(|unit_price| *unit_price > Decimal::ZERO)
+}
+
+fn check_unit_prices(without_price: &[i32]) -> ERPResult<()> {
+    if without_price.is_empty() {
+        return Ok(());
+    }
+
+    Err(ERPError::Failed(format!(
+        "产品#{}没有单价, 请先在订单里填写单价",
+        without_price
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    )))
+}
+
+impl InvoiceModel {
+    /// 保存发票和明细, 返回发票id; 明细都要是同一个客户, 同一个币种的
+    pub async fn create(
+        db: &mut PgConnection,
+        invoice: &InvoiceModel,
+        lines: &[InvoiceLine],
+    ) -> ERPResult<i32> {
+        if lines.is_empty() {
+            return Err(ERPError::Failed("没有可以开票的产品".to_string()));
+        }
+        if let Some(line) = lines
+            .iter()
+            .find(|line| line.customer_no != invoice.customer_no)
+        {
+            return Err(ERPError::ParamError(format!(
+                "订单#{}是客户{}的, 一张发票只能开同一个客户的订单",
+                line.order_id, line.customer_no
+            )));
+        }
+        if let Some(line) = lines.iter().find(|line| line.currency != invoice.currency) {
+            return Err(ERPError::ParamError(format!(
+                "订单#{}的币种是{}, 一张发票只能开同一个币种的订单",
+                line.order_id, line.currency
+            )));
+        }
+        let amounts = lines
+            .iter()
+            .map(|line| line_amount(line.quantity, line.unit_price, line.discount))
+            .collect::<Vec<Decimal>>();
+        let totals = invoice_totals(&amounts, invoice.discount, invoice.tax_rate)?;
+
+        let invoice_id = sqlx::query!(
+            r#"
+            insert into invoices (invoice_no, customer_no, invoice_date, due_date, subtotal, discount, tax_rate, tax, total, notes, account_id, currency)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            returning id
+            "#,
+            invoice.invoice_no,
+            invoice.customer_no,
+            invoice.invoice_date,
+            invoice.due_date,
+            totals.subtotal,
+            invoice.discount,
+            invoice.tax_rate,
+            totals.tax,
+            totals.total,
+            invoice.notes,
+            invoice.account_id,
+            invoice.currency
+        )
+        .fetch_one(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .id;
+
+        for (line, amount) in lines.iter().zip(amounts.iter()) {
+            sqlx::query!(
+                r#"
+                insert into invoice_items (invoice_id, order_id, order_item_id, shipment_id, description, quantity, unit_price, discount, amount)
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#,
+                invoice_id,
+                line.order_id,
+                line.order_item_id,
+                line.shipment_id,
+                line.description,
+                line.quantity,
+                line.unit_price,
+                line.discount,
+                amount
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+        }
+
+        Ok(invoice_id)
+    }
+
+    /// 参数为空时不过滤; unpaid: 只看还没收完的
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        customer_no: &str,
+        unpaid: bool,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<InvoiceModel>, i64)> {
+        let invoices = sqlx::query_as!(
+            InvoiceModel,
+            r#"
+            select * from invoices
+            where ($1 = '' or customer_no = $1) and (not $2 or paid < total)
+            order by invoice_date desc, id desc offset $3 limit $4
+            "#,
+            customer_no,
+            unpaid,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            "select count(1) from invoices where ($1 = '' or customer_no = $1) and (not $2 or paid < total)",
+            customer_no,
+            unpaid
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((invoices, count))
+    }
+
+    /// 记一笔收款, 返回累计已收; 锁住发票, 避免并发时收款超出
+    pub async fn record_payment(
+        db: &mut PgConnection,
+        payment: &PaymentModel,
+    ) -> ERPResult<Decimal> {
+        let invoice = sqlx::query!(
+            "select total, paid from invoices where id = $1 for update",
+            payment.invoice_id
+        )
+        .fetch_optional(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("发票#{}", payment.invoice_id)))?;
+        check_payment_amount(invoice.total, invoice.paid, payment.amount)?;
+
+        sqlx::query!(
+            r#"
+            insert into payments (invoice_id, amount, payment_date, method, notes, account_id)
+            values ($1, $2, $3, $4, $5, $6)
+            "#,
+            payment.invoice_id,
+            payment.amount,
+            payment.payment_date,
+            payment.method,
+            payment.notes,
+            payment.account_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+        sqlx::query!(
+            "update invoices set paid = paid + $1 where id = $2",
+            payment.amount,
+            payment.invoice_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(invoice.paid + payment.amount)
+    }
+
+    /// 删除收款记录(记错了的时候)
+    pub async fn delete_payment(db: &mut PgConnection, payment_id: i32) -> ERPResult<()> {
+        let payment = sqlx::query!(
+            "delete from payments where id = $1 returning invoice_id, amount",
+            payment_id
+        )
+        .fetch_optional(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("收款记录#{}", payment_id)))?;
+        sqlx::query!(
+            "update invoices set paid = paid - $1 where id = $2",
+            payment.amount,
+            payment.invoice_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+}
+
+impl Invoice {
+    pub async fn get(db: &Pool<Postgres>, id: i32) -> ERPResult<Invoice> {
+        let invoice = sqlx::query_as!(InvoiceModel, "select * from invoices where id = $1", id)
+            .fetch_optional(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound(format!("发票#{}", id)))?;
+        let items = sqlx::query_as!(
+            InvoiceItemModel,
+            "select * from invoice_items where invoice_id = $1 order by id",
+            id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let payments = sqlx::query_as!(
+            PaymentModel,
+            "select * from payments where invoice_id = $1 order by payment_date, id",
+            id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(Invoice {
+            balance: invoice.total - invoice.paid,
+            invoice,
+            items,
+            payments,
+        })
+    }
+}
+
+/// 客户的应收账款和账龄(按到期日算逾期了多少天)
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct CustomerReceivable {
+    pub customer_no: String,
+    pub invoiced: Decimal,     // 开票合计
+    pub paid: Decimal,         // 已收
+    pub balance: Decimal,      // 未收
+    pub current: Decimal,      // 未到期
+    pub days_1_30: Decimal,    // 逾期1-30天
+    pub days_31_60: Decimal,   // 逾期31-60天
+    pub days_61_90: Decimal,   // 逾期61-90天
+    pub days_over_90: Decimal, // 逾期90天以上
+}
+
+impl CustomerReceivable {
+    fn add(&mut self, due_date: NaiveDate, total: Decimal, paid: Decimal, as_of: NaiveDate) {
+        let balance = total - paid;
+        self.invoiced += total;
+        self.paid += paid;
+        self.balance += balance;
+        let bucket = match (as_of - due_date).num_days() {
+            days if days <= 0 => &mut self.current,
+            1..=30 => &mut self.days_1_30,
+            31..=60 => &mut self.days_31_60,
+            61..=90 => &mut self.days_61_90,
+            _ => &mut self.days_over_90,
+        };
+        *bucket += balance;
+    }
+
+    /// invoices: (customer_no, due_date, total, paid), 按客户编号排
+    pub fn from_invoices(
+        invoices: &[(String, NaiveDate, Decimal, Decimal)],
+        as_of: NaiveDate,
+    ) -> Vec<CustomerReceivable> {
+        let mut customer_no_to_receivable: HashMap<&str, CustomerReceivable> = HashMap::new();
+        for (customer_no, due_date, total, paid) in invoices.iter() {
+            customer_no_to_receivable
+                .entry(customer_no)
+                .or_insert_with(|| CustomerReceivable {
+                    customer_no: customer_no.clone(),
+                    ..Default::default()
+                })
+                .add(*due_date, *total, *paid, as_of);
+        }
+
+        let mut receivables = customer_no_to_receivable
+            .into_values()
+            .collect::<Vec<CustomerReceivable>>();
+        receivables.sort_by(|a, b| a.customer_no.cmp(&b.customer_no));
+        receivables
+    }
+
+    /// customer_no为空时是所有客户; 已收只算as_of当天及之前的收款; 外币发票按as_of当天的汇率换算成基础货币
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        customer_no: &str,
+        as_of: NaiveDate,
+    ) -> ERPResult<Vec<CustomerReceivable>> {
+        let rates = ExchangeRates::on(db, as_of).await?;
+        let invoices = sqlx::query!(
+            r#"
+            select i.customer_no, i.due_date, i.total, i.currency,
+                coalesce((select sum(p.amount) from payments p where p.invoice_id = i.id and p.payment_date <= $2), 0) as "paid!"
+            from invoices i
+            where ($1 = '' or i.customer_no = $1) and i.invoice_date <= $2
+            "#,
+            customer_no,
+            as_of
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| {
+            Ok((
+                row.customer_no,
+                row.due_date,
+                rates.to_base(row.total, &row.currency)?,
+                rates.to_base(row.paid, &row.currency)?,
+            ))
+        })
+        .collect::<ERPResult<Vec<(String, NaiveDate, Decimal, Decimal)>>>()?;
+
+        Ok(Self::from_invoices(&invoices, as_of))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::invoice::{
+        check_payment_amount, invoice_totals, line_amount, valid_unit_price, CustomerReceivable,
+    };
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_invoice_totals() {
+        let amounts = [
+            line_amount(10, Decimal::new(1250, 2), Decimal::ZERO),
+            line_amount(3, Decimal::from(20), Decimal::from(5)),
+        ];
+        assert_eq!(amounts, [Decimal::from(125), Decimal::from(55)]);
+
+        // (180 - 30) * 13% = 19.5
+        let totals = invoice_totals(&amounts, Decimal::from(30), Decimal::from(13)).unwrap();
+        assert_eq!(totals.subtotal, Decimal::from(180));
+        assert_eq!(totals.tax, Decimal::new(1950, 2));
+        assert_eq!(totals.total, Decimal::new(16950, 2));
+
+        assert!(invoice_totals(&amounts, Decimal::from(181), Decimal::ZERO).is_err());
+        assert!(invoice_totals(&amounts, Decimal::ZERO, Decimal::from(101)).is_err());
+
+        assert!(
+            check_payment_amount(Decimal::from(100), Decimal::from(60), Decimal::from(40)).is_ok()
+        );
+        assert!(
+            check_payment_amount(Decimal::from(100), Decimal::from(60), Decimal::from(41)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_valid_unit_price() {
+        assert_eq!(
+            valid_unit_price(Some(Decimal::new(125, 2))),
+            Some(Decimal::new(125, 2))
+        );
+        assert_eq!(valid_unit_price(Some(Decimal::ZERO)), None);
+        assert_eq!(valid_unit_price(None), None);
+    }
+
+    #[test]
+    fn test_receivable_aging() {
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        let invoices = [
+            (
+                "L1002".to_string(),
+                date("2024-03-31"),
+                Decimal::from(100),
+                Decimal::ZERO,
+            ),
+            (
+                "L1001".to_string(),
+                date("2024-03-01"),
+                Decimal::from(200),
+                Decimal::from(50),
+            ),
+            (
+                "L1001".to_string(),
+                date("2023-12-01"),
+                Decimal::from(80),
+                Decimal::ZERO,
+            ),
+            (
+                "L1001".to_string(),
+                date("2024-01-01"),
+                Decimal::from(60),
+                Decimal::from(60),
+            ),
+        ];
+        let receivables = CustomerReceivable::from_invoices(&invoices, date("2024-03-20"));
+
+        assert_eq!(receivables.len(), 2);
+        let l1001 = &receivables[0];
+        assert_eq!(l1001.customer_no, "L1001");
+        assert_eq!(l1001.invoiced, Decimal::from(340));
+        assert_eq!(l1001.balance, Decimal::from(230));
+        assert_eq!(l1001.days_1_30, Decimal::from(150));
+        assert_eq!(l1001.days_over_90, Decimal::from(80));
+        assert_eq!(receivables[1].current, Decimal::from(100));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::model::progress::ProgressModel;
+use crate::model::workflow::{ProgressRecord, Workflow};
+use crate::{ERPError, ERPResult};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+
+/// 毛利报表的筛选条件, 日期按下单日期
+#[derive(Debug, Clone, Default)]
+pub struct MarginFilter {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub customer_no: String, // 空: 所有客户
+    pub build_by: i32,       // 0: 不限
+}
+
+/// 订单的毛利, 金额都换算成基础货币
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct OrderMargin {
+    pub order_id: i32,
+    pub order_no: String,
+    pub customer_no: String,
+    pub order_date: NaiveDate,
+    pub build_by: i32,
+    pub currency: String,
+    pub sales: Decimal,         // 销售金额(订单币种)
+    pub sales_base: Decimal,    // 销售金额(基础货币, 按下单日的汇率)
+    pub material_cost: Decimal, // 出库到订单的材料 * 材料单价
+    pub purchase_cost: Decimal, // 不锈钢订货/外发加工的采购单
+    pub labor_cost: Decimal,    // 完成的流程数量 * 计件工价
+    pub total_cost: Decimal,
+    pub margin: Decimal,
+    pub margin_rate: Decimal, // 毛利率(%)
+}
+
+/// 客户的毛利(客户名下订单的合计)
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CustomerMargin {
+    pub customer_no: String,
+    pub order_count: i32,
+    pub sales_base: Decimal,
+    pub material_cost: Decimal,
+    pub purchase_cost: Decimal,
+    pub labor_cost: Decimal,
+    pub total_cost: Decimal,
+    pub margin: Decimal,
+    pub margin_rate: Decimal,
+}
+
+/// 毛利率(%) = 毛利 / 销售额, 销售额为0时是0
+pub fn margin_rate(sales: Decimal, margin: Decimal) -> Decimal {
+    if sales.is_zero() {
+        return Decimal::ZERO;
+    }
+    (margin * Decimal::ONE_HUNDRED / sales).round_dp(2)
+}
+
+/// 一个产品的人工成本: 每个流程完成的数量(不算跳过的, 最多按产品数量算) * 计件工价
+pub fn labor_cost(workflow: &Workflow, count: i32, records: &[ProgressRecord]) -> Decimal {
+    workflow
+        .steps
+        .iter()
+        .filter(|step| step.labor_rate > Decimal::ZERO)
+        .map(|step| {
+            let completed = records
+                .iter()
+                .filter(|record| {
+                    record.step == step.step
+                        && !record.skipped
+                        && workflow.is_done(record.step, record.index)
+                })
+                .map(|record| record.quantity)
+                .sum::<i32>()
+                .min(count);
+            Decimal::from(completed) * step.labor_rate
+        })
+        .sum()
+}
+
+impl OrderMargin {
+    /// 算出合计成本, 毛利和毛利率
+    fn with_costs(
+        mut self,
+        material_cost: Decimal,
+        purchase_cost: Decimal,
+        labor_cost: Decimal,
+    ) -> OrderMargin {
+        self.material_cost = material_cost.round_dp(2);
+        self.purchase_cost = purchase_cost.round_dp(2);
+        self.labor_cost = labor_cost.round_dp(2);
+        self.total_cost = self.material_cost + self.purchase_cost + self.labor_cost;
+        self.margin = self.sales_base - self.total_cost;
+        self.margin_rate = margin_rate(self.sales_base, self.margin);
+        self
+    }
+
+    /// 按下单日期倒序; 外币订单在下单日之前没有汇率时报错
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        filter: &MarginFilter,
+    ) -> ERPResult<Vec<OrderMargin>> {
+        let orders = sqlx::query!(
+            r#"
+            select o.id, o.order_no, o.customer_no, o.order_date, o.build_by, o.currency,
+                coalesce((
+                    select sum(coalesce(oi.total_price, oi.unit_price * oi.count, 0))
+                    from order_items oi where oi.order_id = o.id
+                ), 0) as "sales!",
+                case when exists (select 1 from currencies c where c.code = o.currency and c.is_base) then 1
+                    else (
+                        select er.rate from exchange_rates er
+                        where er.currency = o.currency and er.effective_date <= o.order_date
+                        order by er.effective_date desc limit 1
+                    )
+                end as rate
+            from orders o
+            where ($1::date is null or o.order_date >= $1) and ($2::date is null or o.order_date <= $2)
+                and ($3 = '' or o.customer_no = $3) and ($4 = 0 or o.build_by = $4)
+            order by o.order_date desc, o.id desc
+            "#,
+            filter.start_date,
+            filter.end_date,
+            filter.customer_no,
+            filter.build_by
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        if let Some(order) = orders.iter().find(|order| order.rate.is_none()) {
+            return Err(ERPError::NotFound(format!(
+                "{}在{}之前的汇率(订单#{})",
+                order.currency, order.order_date, order.order_no
+            )));
+        }
+        let order_ids = orders.iter().map(|order| order.id).collect::<Vec<i32>>();
+
+        // 材料: 出库记为负数, 退回入库(带订单号的)记为正数
+        let material_costs = sqlx::query!(
+            r#"
+            select sm.order_id, sum(-sm.quantity * m.unit_cost) as "cost!"
+            from stock_movements sm, materials m
+            where sm.material_id = m.id and sm.order_id = any($1)
+            group by sm.order_id
+            "#,
+            &order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| (row.order_id, row.cost))
+        .collect::<HashMap<i32, Decimal>>();
+
+        let purchase_costs = sqlx::query!(
+            r#"
+            select order_id, sum(quantity * unit_price) as "cost!"
+            from purchase_orders where order_id = any($1)
+            group by order_id
+            "#,
+            &order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| (row.order_id, row.cost))
+        .collect::<HashMap<i32, Decimal>>();
+
+        // 人工: 按每条完成记录的数量算, 分批完成的都要算上
+        let workflow = Workflow::get(db).await?;
+        let mut order_item_id_to_records: HashMap<i32, Vec<ProgressRecord>> = HashMap::new();
+        sqlx::query_as!(
+            ProgressModel,
+            r#"
+            select p.*
+            from progress p, order_items oi
+            where p.order_item_id = oi.id and oi.order_id = any($1)
+            order by p.id
+            "#,
+            &order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .iter()
+        .for_each(|progress| {
+            order_item_id_to_records
+                .entry(progress.order_item_id)
+                .or_default()
+                .push(ProgressRecord::from(progress))
+        });
+        let mut labor_costs: HashMap<i32, Decimal> = HashMap::new();
+        for order_item in sqlx::query!(
+            "select id, order_id, count from order_items where order_id = any($1)",
+            &order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        {
+            let records = order_item_id_to_records
+                .get(&order_item.id)
+                .map(|records| records.as_slice())
+                .unwrap_or(&[]);
+            *labor_costs.entry(order_item.order_id).or_default() +=
+                labor_cost(&workflow, order_item.count, records);
+        }
+
+        let margins = orders
+            .into_iter()
+            .map(|order| {
+                let cost = |costs: &HashMap<i32, Decimal>| {
+                    costs.get(&order.id).cloned().unwrap_or_default()
+                };
+                OrderMargin {
+                    order_id: order.id,
+                    order_no: order.order_no.clone(),
+                    customer_no: order.customer_no.clone(),
+                    order_date: order.order_date,
+                    build_by: order.build_by,
+                    currency: order.currency.clone(),
+                    sales: order.sales,
+                    sales_base: (order.sales * order.rate.unwrap_or_default()).round_dp(2),
+                    ..Default::default()
+                }
+                .with_costs(
+                    cost(&material_costs),
+                    cost(&purchase_costs),
+                    cost(&labor_costs),
+                )
+            })
+            .collect();
+
+        Ok(margins)
+    }
+}
+
+impl CustomerMargin {
+    /// 按客户汇总, 按毛利从高到低排
+    pub fn from_orders(orders: &[OrderMargin]) -> Vec<CustomerMargin> {
+        let mut customer_no_to_margin: HashMap<&str, CustomerMargin> = HashMap::new();
+        for order in orders.iter() {
+            let margin = customer_no_to_margin
+                .entry(&order.customer_no)
+                .or_insert_with(|| CustomerMargin {
+                    customer_no: order.customer_no.clone(),
+                    ..Default::default()
+                });
+            margin.order_count += 1;
+            margin.sales_base += order.sales_base;
+            margin.material_cost += order.material_cost;
+            margin.purchase_cost += order.purchase_cost;
+            margin.labor_cost += order.labor_cost;
+            margin.total_cost += order.total_cost;
+            margin.margin += order.margin;
+        }
+
+        let mut margins = customer_no_to_margin
+            .into_values()
+            .map(|mut margin| {
+                margin.margin_rate = margin_rate(margin.sales_base, margin.margin);
+                margin
+            })
+            .collect::<Vec<CustomerMargin>>();
+        margins.sort_by(|a, b| {
+            b.margin
+                .cmp(&a.margin)
+                .then_with(|| a.customer_no.cmp(&b.customer_no))
+        });
+        margins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::margin::{labor_cost, margin_rate, CustomerMargin, OrderMargin};
+    use crate::model::workflow::{
+        ProgressRecord, Workflow, WorkflowStepModel, WorkflowStepOptionModel,
+    };
+    use rust_decimal::Decimal;
+
+    fn order(order_id: i32, customer_no: &str, sales_base: i64) -> OrderMargin {
+        OrderMargin {
+            order_id,
+            customer_no: customer_no.to_string(),
+            sales_base: Decimal::from(sales_base),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_margin() {
+        assert_eq!(
+            margin_rate(Decimal::ZERO, Decimal::from(-10)),
+            Decimal::ZERO
+        );
+        assert_eq!(
+            margin_rate(Decimal::from(3), Decimal::from(1)),
+            Decimal::new(3333, 2)
+        );
+
+        let orders = [
+            order(1, "L1001", 1000).with_costs(
+                Decimal::new(2005, 1),
+                Decimal::from(300),
+                Decimal::from(100),
+            ),
+            order(2, "L1001", 500).with_costs(Decimal::from(100), Decimal::ZERO, Decimal::ZERO),
+            order(3, "L1002", 200).with_costs(Decimal::from(250), Decimal::ZERO, Decimal::ZERO),
+        ];
+        assert_eq!(orders[0].total_cost, Decimal::new(6005, 1));
+        assert_eq!(orders[0].margin, Decimal::new(3995, 1));
+        assert_eq!(orders[0].margin_rate, Decimal::new(3995, 2));
+        assert_eq!(orders[2].margin, Decimal::from(-50));
+
+        let customers = CustomerMargin::from_orders(&orders);
+        assert_eq!(customers.len(), 2);
+        assert_eq!(customers[0].customer_no, "L1001");
+        assert_eq!(customers[0].order_count, 2);
+        assert_eq!(customers[0].sales_base, Decimal::from(1500));
+        assert_eq!(customers[0].margin, Decimal::new(7995, 1));
+        assert_eq!(customers[0].margin_rate, Decimal::new(5330, 2));
+        assert_eq!(customers[1].margin_rate, Decimal::from(-25));
+    }
+
+    #[test]
+    fn test_labor_cost() {
+        let step = |step: i32, labor_rate: Decimal| WorkflowStepModel {
+            id: step,
+            step,
+            name: "".to_string(),
+            department_id: 1,
+            is_shipping: false,
+            labor_rate,
+        };
+        let option = |step: i32, index: i32, is_done: bool| WorkflowStepOptionModel {
+            id: step * 10 + index,
+            step,
+            index,
+            name: "".to_string(),
+            color: "".to_string(),
+            is_done,
+            is_exception: false,
+            purchase_kind: "".to_string(),
+        };
+        let workflow = Workflow {
+            steps: vec![
+                step(2, Decimal::new(15, 1)),
+                step(3, Decimal::ZERO),
+                step(4, Decimal::from(2)),
+            ],
+            options: vec![option(2, 1, false), option(2, 2, true), option(4, 2, true)],
+            templates: vec![],
+        };
+        let record = |step: i32, index: i32, quantity: i32, skipped: bool| ProgressRecord {
+            step,
+            index,
+            quantity,
+            defective: 0,
+            skipped,
+        };
+
+        // 分两次完成6个和4个, 两次都算; 没完成的选项和跳过的不算
+        let records = [
+            record(2, 1, 3, false),
+            record(2, 2, 6, false),
+            record(2, 2, 4, false),
+            record(4, 2, 10, true),
+        ];
+        assert_eq!(labor_cost(&workflow, 10, &records), Decimal::from(15));
+        // 最多按产品数量算
+        assert_eq!(labor_cost(&workflow, 8, &records), Decimal::from(12));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{ERPError, ERPResult};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Pool, Postgres, QueryBuilder};
+
+/// 材料
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct MaterialModel {
+    pub id: i32,
+    pub name: String,       // 材料名称
+    pub color: String,      // 材料颜色
+    pub unit: String,       // 单位, 如: 米, 个, 克
+    pub notes: String,      // 备注
+    pub unit_cost: Decimal, // 单价(基础货币), 算订单的材料成本
+}
+
+impl MaterialModel {
+    pub async fn get_by_id(db: &Pool<Postgres>, id: i32) -> ERPResult<MaterialModel> {
+        sqlx::query_as!(MaterialModel, "select * from materials where id = $1", id)
+            .fetch_optional(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound(format!("材料#{}", id)))
+    }
+
+    /// name为空时不过滤, 否则按名称模糊查询
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        name: &str,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<MaterialModel>, i64)> {
+        let materials = sqlx::query_as!(
+            MaterialModel,
+            r#"
+            select * from materials
+            where $1 = '' or name like '%' || $1 || '%'
+            order by name, color offset $2 limit $3
+            "#,
+            name,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            "select count(1) from materials where $1 = '' or name like '%' || $1 || '%'",
+            name
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((materials, count))
+    }
+}
+
+/// sku的材料清单(BOM)里的一行: 做一个sku要用多少材料
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct SkuMaterial {
+    pub id: i32,
+    pub sku_id: i32,
+    pub material_id: i32,
+    pub name: String,
+    pub color: String,
+    pub unit: String,
+    pub single: Decimal, // 单件用量
+    pub notes: String,
+}
+
+/// 设置sku材料清单时的一行
+#[derive(Debug, Deserialize, Clone)]
+pub struct SkuMaterialLine {
+    pub material_id: i32,
+    pub single: Decimal,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl SkuMaterial {
+    pub async fn get_by_sku_id(db: &Pool<Postgres>, sku_id: i32) -> ERPResult<Vec<SkuMaterial>> {
+        let materials = sqlx::query_as!(
+            SkuMaterial,
+            r#"
+            select sm.id, sm.sku_id, sm.material_id, m.name, m.color, m.unit, sm.single, sm.notes
+            from sku_materials sm, materials m
+            where sm.material_id = m.id and sm.sku_id = $1
+            order by sm.id
+            "#,
+            sku_id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(materials)
+    }
+
+    /// 整个替换sku的材料清单(只影响之后导入的订单)
+    pub async fn set_for_sku(
+        db: &mut PgConnection,
+        sku_id: i32,
+        lines: &[SkuMaterialLine],
+    ) -> ERPResult<()> {
+        sqlx::query!("delete from sku_materials where sku_id = $1", sku_id)
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder: QueryBuilder<Postgres> =
+            QueryBuilder::new("insert into sku_materials (sku_id, material_id, single, notes) ");
+        query_builder.push_values(lines, |mut b, line| {
+            b.push_bind(sku_id)
+                .push_bind(line.material_id)
+                .push_bind(line.single)
+                .push_bind(&line.notes);
+        });
+        query_builder
+            .build()
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+}
+
+/// 订单sku的材料单
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct OrderItemMaterialModel {
+    pub id: i32,
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub material_id: i32, // 0: 手工添加的(不在材料表里)
+    pub name: String,
+    pub color: String,
+    pub unit: String,
+    pub single: Decimal, // 单件用量
+    pub count: i32,      // 数量(订单sku的数量)
+    pub total: Decimal,  // 总用量
+    pub notes: String,
+}
+
+/// 总用量 = 单件用量 * 数量
+pub fn material_total(single: Decimal, count: i32) -> Decimal {
+    single * Decimal::from(count)
+}
+
+impl OrderItemMaterialModel {
+    pub async fn get_by_id(db: &Pool<Postgres>, id: i32) -> ERPResult<OrderItemMaterialModel> {
+        sqlx::query_as!(
+            OrderItemMaterialModel,
+            "select * from order_item_materials where id = $1",
+            id
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("材料单#{}", id)))
+    }
+
+    pub async fn insert_many(
+        db: &Pool<Postgres>,
+        materials: &[OrderItemMaterialModel],
+    ) -> ERPResult<()> {
+        if materials.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "insert into order_item_materials (order_id, order_item_id, material_id, name, color, unit, single, count, total, notes) ",
+        );
+        query_builder.push_values(materials, |mut b, material| {
+            b.push_bind(material.order_id)
+                .push_bind(material.order_item_id)
+                .push_bind(material.material_id)
+                .push_bind(&material.name)
+                .push_bind(&material.color)
+                .push_bind(&material.unit)
+                .push_bind(material.single)
+                .push_bind(material.count)
+                .push_bind(material.total)
+                .push_bind(&material.notes);
+        });
+        query_builder
+            .build()
+            .execute(db)
+            .await
+            .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+
+    pub async fn update(&self, db: &Pool<Postgres>) -> ERPResult<()> {
+        sqlx::query!(
+            r#"
+            update order_item_materials
+            set name = $1, color = $2, unit = $3, single = $4, count = $5, total = $6, notes = $7
+            where id = $8
+            "#,
+            self.name,
+            self.color,
+            self.unit,
+            self.single,
+            self.count,
+            self.total,
+            self.notes,
+            self.id
+        )
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+
+    /// 按sku的材料清单生成订单的材料单(导入订单, 修改/新增订单sku时调用):
+    /// 没有的材料新增, 已有的(包括手工添加的)按新的数量重算总用量(保留手工改过的单件用量),
+    /// 已经删掉的订单sku的材料单一起删掉
+    pub async fn generate_for_order(db: &mut PgConnection, order_id: i32) -> ERPResult<()> {
+        sqlx::query!(
+            r#"
+            insert into order_item_materials (order_id, order_item_id, material_id, name, color, unit, single, count, total)
+            select oi.order_id, oi.id, m.id, m.name, m.color, m.unit, sm.single, oi.count, sm.single * oi.count
+            from order_items oi, sku_materials sm, materials m
+            where oi.order_id = $1 and sm.sku_id = oi.sku_id and m.id = sm.material_id
+            on conflict (order_item_id, name, color) do update
+            set count = excluded.count,
+                total = order_item_materials.single * excluded.count
+            "#,
+            order_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        sqlx::query!(
+            r#"
+            update order_item_materials oim
+            set count = oi.count, total = oim.single * oi.count
+            from order_items oi
+            where oi.id = oim.order_item_id and oim.order_id = $1 and oim.count != oi.count
+            "#,
+            order_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        sqlx::query!(
+            r#"
+            delete from order_item_materials oim
+            where oim.order_id = $1
+                and not exists (select 1 from order_items oi where oi.id = oim.order_item_id)
+            "#,
+            order_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::material::material_total;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_material_total() {
+        let single = Decimal::from_str("0.125").unwrap();
+        assert_eq!(material_total(single, 24), Decimal::from(3));
+        assert_eq!(material_total(single, 0), Decimal::ZERO);
+    }
+}
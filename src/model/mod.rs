@@ -1,6 +1,17 @@
 pub mod account;
+pub mod currency;
 pub mod customer;
+pub mod cycle_time;
 pub mod excel;
 pub mod goods;
+pub mod invoice;
+pub mod margin;
+pub mod material;
 pub mod order;
 pub mod progress;
+pub mod purchase;
+pub mod shipment;
+pub mod stats;
+pub mod stock;
+pub mod supplier;
+pub mod workflow;
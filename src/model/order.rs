@@ -1,9 +1,11 @@
 use crate::common::hashmap::key_of_max_value;
 use crate::common::string::common_prefix;
+use crate::model::excel::ExcelSheetResult;
 use crate::model::goods::SKUModel;
 use crate::{ERPError, ERPResult};
-use chrono::NaiveDate;
-use sqlx::{FromRow, Pool, Postgres, QueryBuilder};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::{FromRow, PgConnection, Pool, Postgres, QueryBuilder};
 use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Clone, FromRow)]
@@ -18,11 +20,13 @@ pub struct OrderModel {
     pub is_special: bool,                 // 特别客人
     pub special_customer: String,         // 特别客人
     pub build_by: i32,                    // 制作方式，0: 不明，1: 手工，2: 不锈钢
+    pub workflow_template_id: i32,        // 流程模板，0: 自动匹配
+    pub currency: String,                 // 币种
 }
 
 impl OrderModel {
     pub async fn get_order_with_order_no(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         order_no: &str,
     ) -> ERPResult<Option<OrderModel>> {
         let order = sqlx::query_as!(
@@ -38,6 +42,56 @@ impl OrderModel {
     }
 }
 
+/// 订单生成过的单据
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct OrderDocumentModel {
+    pub id: i32,
+    pub order_id: i32,
+    pub kind: String, // confirmation: 订单确认书, packing: 装箱单, delivery: 送货单
+    pub file: String, // 文件地址
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+impl OrderDocumentModel {
+    pub async fn insert(
+        db: &Pool<Postgres>,
+        order_id: i32,
+        kind: &str,
+        file: &str,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            "insert into order_documents (order_id, kind, file, account_id) values ($1, $2, $3, $4)",
+            order_id,
+            kind,
+            file,
+            account_id
+        )
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+
+    pub async fn get_by_order_id(
+        db: &Pool<Postgres>,
+        order_id: i32,
+    ) -> ERPResult<Vec<OrderDocumentModel>> {
+        let documents = sqlx::query_as!(
+            OrderDocumentModel,
+            "select * from order_documents where order_id = $1 order by id desc",
+            order_id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(documents)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct OrderGoodsModel {
     pub id: i32,
@@ -61,7 +115,7 @@ pub struct GoodsImagesAndPackageModel {
 
 impl OrderGoodsModel {
     pub async fn add_rows(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         rows: &[OrderGoodsModel],
     ) -> ERPResult<Vec<OrderGoodsModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
@@ -96,15 +150,15 @@ pub struct OrderItemModel {
     pub sku_id: i32,
     pub count: i32,
     pub unit: Option<String>,
-    pub unit_price: Option<i32>,
-    pub total_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub total_price: Option<Decimal>,
     pub notes_images: Vec<String>,
     pub notes: String,
 }
 
 impl OrderItemModel {
     pub async fn save_to_order_item_table(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         items: &[OrderItemModel],
     ) -> ERPResult<Vec<OrderItemModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
@@ -116,8 +170,8 @@ impl OrderItemModel {
                 .push_bind(item.sku_id)
                 .push_bind(item.count)
                 .push_bind(item.unit.as_deref().unwrap_or(""))
-                .push_bind(item.unit_price.unwrap_or(0))
-                .push_bind(item.total_price.unwrap_or(0))
+                .push_bind(item.unit_price)
+                .push_bind(item.total_price)
                 .push_bind(item.notes_images.clone())
                 .push_bind(item.notes.clone());
         });
@@ -171,7 +225,7 @@ impl OrderItemModel {
     // }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ExcelOrderGoods {
     pub index: i32,
     pub goods_no: String,
@@ -185,7 +239,7 @@ pub struct ExcelOrderGoods {
 
 impl ExcelOrderGoods {
     pub async fn insert_into_goods_table(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         items: &[ExcelOrderGoods],
         customer_no: &str,
     ) -> ERPResult<HashMap<String, i32>> {
@@ -216,7 +270,7 @@ impl ExcelOrderGoods {
     }
 
     pub async fn insert_into_skus_table(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         items: &[SKUModel],
     ) -> ERPResult<Vec<SKUModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
@@ -254,28 +308,96 @@ pub struct ExcelOrderItems {
     /// 数量
     pub count: i32,
     /// 进货价
-    pub purchase_price: Option<i32>,
+    pub purchase_price: Option<Decimal>,
     /// 单位
     pub unit: Option<String>,
     /// 单价
-    pub unit_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
     /// 金额
-    pub total_price: Option<i32>,
+    pub total_price: Option<Decimal>,
     /// 备注
     pub notes: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ExcelOrderGoodsWithItems {
     pub goods: ExcelOrderGoods,
     pub items: Vec<OrderItemExcel>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ExcelOrderV2 {
     pub info: OrderInfo,
     pub items: Vec<ExcelOrderGoodsWithItems>,
     pub exists: bool,
+    pub sheets: Vec<ExcelSheetResult>, // 订单来自哪些sheet, 以及每个sheet用的列对应关系
+}
+
+impl ExcelOrderV2 {
+    pub fn set_exists(&mut self, exists: bool) {
+        self.exists = exists;
+        self.sheets
+            .iter_mut()
+            .for_each(|sheet| sheet.exists = exists);
+    }
+}
+
+/// excel导入前的预览: 解析出来的订单, 以及和已存在订单的差异
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcelOrderPreview {
+    #[serde(flatten)]
+    pub order: ExcelOrderV2,
+    pub diff: ExcelOrderDiff,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExcelOrderDiff {
+    pub new_goods: Vec<String>,     // 新增的商品(goods_no)
+    pub removed_goods: Vec<String>, // 会被删掉的商品(goods_no)
+    pub new_items: Vec<ExcelOrderItemDiff>,
+    pub changed_items: Vec<ExcelOrderItemDiff>,
+    pub removed_items: Vec<ExcelOrderItemDiff>,
+}
+
+impl ExcelOrderDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_goods.is_empty()
+            && self.removed_goods.is_empty()
+            && self.new_items.is_empty()
+            && self.changed_items.is_empty()
+            && self.removed_items.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcelOrderItemDiff {
+    pub order_item_id: i32, // 新增的为0
+    pub goods_no: String,
+    pub plating: String,
+    pub color: String,
+    pub old: Option<ExcelOrderItemValues>, // 订单里原来的, 新增的为None
+    pub new: Option<ExcelOrderItemValues>, // excel里的, 删除的为None
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExcelOrderItemValues {
+    pub count: i32,
+    pub unit: String,
+    pub unit_price: Decimal,
+    pub total_price: Decimal,
+    pub notes: String,
+}
+
+impl From<&OrderItemExcel> for ExcelOrderItemValues {
+    fn from(item: &OrderItemExcel) -> Self {
+        Self {
+            count: item.count,
+            unit: item.unit.as_deref().unwrap_or("").to_string(),
+            unit_price: item.unit_price.unwrap_or_default(),
+            total_price: item.total_price.unwrap_or_default(),
+            notes: item.notes.as_deref().unwrap_or("").to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -285,7 +407,7 @@ pub struct ExcelOrder {
     pub exists: bool,
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize)]
 pub struct OrderInfo {
     pub customer_no: String,
     pub order_no: String,
@@ -299,14 +421,14 @@ pub struct OrderInfo {
 
 impl OrderInfo {
     pub async fn insert_to_orders(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         order_info: &OrderInfo,
         build_by: i32,
     ) -> ERPResult<i32> {
         let order_id = sqlx::query!(
             r#"
-            insert into orders (customer_no, order_no, order_date, delivery_date, is_urgent, is_return_order, build_by)
-            values ($1, $2, $3, $4, $5, $6, $7) returning id;
+            insert into orders (customer_no, order_no, order_date, delivery_date, is_urgent, is_return_order, build_by, currency)
+            values ($1, $2, $3, $4, $5, $6, $7, (select coalesce(max(currency), base_currency()) from customers where customer_no = $1)) returning id;
             "#,
             order_info.customer_no,
             order_info.order_no,
@@ -323,7 +445,7 @@ impl OrderInfo {
     }
 
     pub async fn update_to_orders(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         order_info: &OrderInfo,
         build_by: i32,
         order_id: i32,
@@ -347,8 +469,10 @@ impl OrderInfo {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct OrderItemExcel {
+    /// excel里的第几行
+    pub row: u32,
     pub index: i32,
     pub package_card: Option<String>,
     pub package_card_des: Option<String>,
@@ -377,13 +501,13 @@ pub struct OrderItemExcel {
     /// 数量
     pub count: i32,
     /// 进货价
-    pub purchase_price: Option<i32>,
+    pub purchase_price: Option<Decimal>,
     /// 单位
     pub unit: Option<String>,
     /// 单价
-    pub unit_price: Option<i32>,
+    pub unit_price: Option<Decimal>,
     /// 金额
-    pub total_price: Option<i32>,
+    pub total_price: Option<Decimal>,
     /// 备注里的图片列表
     pub notes_images: Vec<String>,
     /// 备注
@@ -525,19 +649,3 @@ impl OrderItemExcel {
     //     Ok(id)
     // }
 }
-
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
-pub struct OrderItemMaterialModel {
-    pub id: i32,
-    pub order_id: i32,
-    pub order_item_id: i32,
-    pub name: String,
-    pub color: String,
-    // material_id   integer, -- 材料ID  (暂时先不用)
-    pub single: Option<i32>,   //  integer, -- 单数      ？小数
-    pub count: i32,            //  integer, -- 数量      ？小数
-    pub total: Option<i32>,    //  integer, -- 总数(米)  ? 小数
-    pub stock: Option<i32>,    //  integer, -- 库存 ?
-    pub debt: Option<i32>,     //  integer, -- 欠数
-    pub notes: Option<String>, //  text,     -- 备注
-}
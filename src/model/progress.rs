@@ -1,6 +1,9 @@
+use crate::dto::dto_progress::OneProgress;
+use crate::model::order::OrderModel;
+use crate::model::workflow::{current_step_of, ProgressRecord, Workflow, WorkflowRoute};
 use crate::{ERPError, ERPResult};
 use chrono::{DateTime, Utc};
-use sqlx::{Pool, Postgres, QueryBuilder};
+use sqlx::{PgConnection, Pool, Postgres, QueryBuilder};
 use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
@@ -13,6 +16,18 @@ pub struct ProgressModel {
     pub done: bool,         // 完成
     pub notes: String,      // 备注
     pub dt: DateTime<Utc>,  // 操作日期
+    pub skipped: bool,      // 跳过
+    pub quantity: i32,      // 本次经过的数量
+    pub defective: i32,     // 本次的次品数量
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OrderQuantityStats {
+    pub done_count: i32,         // 走完流程路线的产品数
+    pub total_quantity: i32,     // 总数量
+    pub done_quantity: i32,      // 走完流程路线的数量
+    pub defective_quantity: i32, // 次品数量
+    pub shipped_quantity: i32,   // 已发货数量
 }
 
 // order_id, (step, index), count
@@ -26,11 +41,11 @@ struct IdId {
 
 impl ProgressModel {
     pub async fn insert_multiple(
-        db: &Pool<Postgres>,
+        db: &mut PgConnection,
         rows: &[ProgressModel],
     ) -> ERPResult<Vec<ProgressModel>> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-            "insert into progress (order_item_id, step, index, account_id, done, notes, dt) ",
+            "insert into progress (order_item_id, step, index, account_id, done, notes, dt, skipped, quantity, defective) ",
         );
 
         query_builder.push_values(rows, |mut b, item| {
@@ -40,7 +55,10 @@ impl ProgressModel {
                 .push_bind(item.account_id)
                 .push_bind(item.done)
                 .push_bind(item.notes.clone())
-                .push_bind(item.dt);
+                .push_bind(item.dt)
+                .push_bind(item.skipped)
+                .push_bind(item.quantity)
+                .push_bind(item.defective);
         });
         query_builder.push(" returning *;");
 
@@ -77,17 +95,81 @@ impl ProgressModel {
         Ok(order_id_to_exception_count)
     }
 
-    pub async fn get_order_done_count(
+    /// 按数量统计订单的进度: 走完流程路线的产品数, 总数量, 完成数量, 次品数量
+    pub async fn get_order_quantity_stats(
         db: &Pool<Postgres>,
         order_ids: &[i32],
-    ) -> ERPResult<HashMap<i32, i32>> {
-        let order_id_to_exception_count = sqlx::query!(
+    ) -> ERPResult<HashMap<i32, OrderQuantityStats>> {
+        let orders = sqlx::query_as!(
+            OrderModel,
+            "select * from orders where id = any($1)",
+            order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let workflow = Workflow::get(db).await?;
+        let order_id_to_route = orders
+            .iter()
+            .map(|order| (order.id, workflow.route_for_order(order)))
+            .collect::<HashMap<i32, WorkflowRoute>>();
+
+        let order_items = sqlx::query!(
+            "select id, order_id, count from order_items where order_id = any($1)",
+            order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut order_item_id_to_records: HashMap<i32, Vec<ProgressRecord>> = HashMap::new();
+        sqlx::query_as!(
+            ProgressModel,
             r#"
-            select o.id, count(1)
-            from orders o, order_items oi, progress p
-            where o.id = oi.order_id and p.order_item_id=oi.id
-                and o.id = any($1) and p.step=8 and p.index=2
-            group by o.id;
+            select p.*
+            from progress p, order_items oi
+            where p.order_item_id = oi.id and oi.order_id = any($1)
+            order by p.id;
+            "#,
+            order_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .iter()
+        .for_each(|progress| {
+            order_item_id_to_records
+                .entry(progress.order_item_id)
+                .or_default()
+                .push(ProgressRecord::from(progress))
+        });
+
+        let mut order_id_to_stats: HashMap<i32, OrderQuantityStats> = HashMap::new();
+        for order_item in order_items {
+            let Some(route) = order_id_to_route.get(&order_item.order_id) else {
+                continue;
+            };
+            let records = order_item_id_to_records
+                .get(&order_item.id)
+                .map(|records| records.as_slice())
+                .unwrap_or(&[]);
+            let quantities = route.step_quantities(&workflow, order_item.count, records);
+
+            let stats = order_id_to_stats.entry(order_item.order_id).or_default();
+            if current_step_of(&quantities).is_none() {
+                stats.done_count += 1;
+            }
+            stats.total_quantity += order_item.count;
+            stats.done_quantity += quantities.last().map(|q| q.completed).unwrap_or(0);
+            stats.defective_quantity += quantities.iter().map(|q| q.defective).sum::<i32>();
+        }
+
+        sqlx::query!(
+            r#"
+            select s.order_id, sum(si.quantity) as "quantity!"
+            from shipments s, shipment_items si
+            where s.id = si.shipment_id and s.order_id = any($1)
+            group by s.order_id
             "#,
             order_ids
         )
@@ -95,10 +177,46 @@ impl ProgressModel {
         .await
         .map_err(ERPError::DBError)?
         .into_iter()
-        .map(|r| (r.id, r.count.unwrap_or(0) as i32))
-        .collect::<HashMap<i32, i32>>();
+        .for_each(|row| {
+            order_id_to_stats
+                .entry(row.order_id)
+                .or_default()
+                .shipped_quantity = row.quantity as i32
+        });
 
-        Ok(order_id_to_exception_count)
+        Ok(order_id_to_stats)
+    }
+
+    /// 产品的所有流程记录(带操作人和部门), 按产品分组, 按时间先后排序
+    pub async fn get_order_items_progresses(
+        db: &Pool<Postgres>,
+        order_item_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, Vec<OneProgress>>> {
+        let progresses = sqlx::query_as!(
+            OneProgress,
+            r#"
+            select
+                p.*, a.name as account_name, d.name as department
+            from progress p, accounts a, departments d
+            where p.account_id = a.id and a.department_id = d.id
+                and p.order_item_id = any($1)
+            order by p.id;
+            "#,
+            order_item_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut order_item_id_to_progress_vec: HashMap<i32, Vec<OneProgress>> = HashMap::new();
+        progresses.into_iter().for_each(|one_progress| {
+            order_item_id_to_progress_vec
+                .entry(one_progress.order_item_id)
+                .or_default()
+                .push(one_progress);
+        });
+
+        Ok(order_item_id_to_progress_vec)
     }
 
     pub async fn get_order_exception_count(
@@ -110,13 +228,17 @@ impl ProgressModel {
             select o.id, count(1)
             from (
                 select distinct on (order_item_id)
-                    id, order_item_id, step, account_id, done, notes, dt, index
+                    id, order_item_id, step, account_id, done, notes, dt, index, skipped, quantity, defective
                 from progress
                 where order_item_id = any($1)
-                order by order_item_id, step desc, id desc
+                order by order_item_id, id desc
             ) pp, orders o, order_items oi
             where o.id = oi.order_id and pp.order_item_id=oi.id
-                 and o.id = any($1) and pp.index=1
+                 and o.id = any($1)
+                 and exists (
+                    select 1 from workflow_step_options wso
+                    where wso.step = pp.step and wso.index = pp.index and wso.is_exception
+                 )
             group by o.id;
             "#,
             order_ids
@@ -131,6 +253,32 @@ impl ProgressModel {
         Ok(order_id_to_exception_count)
     }
 
+    /// 每个产品最后一次的流程记录
+    pub async fn get_last_progresses(
+        db: &Pool<Postgres>,
+        order_item_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, ProgressModel>> {
+        let order_item_id_to_progress = sqlx::query_as!(
+            ProgressModel,
+            r#"
+            select distinct on (order_item_id)
+            id, order_item_id, step, account_id, done, notes, dt, index, skipped, quantity, defective
+            from progress
+            where order_item_id = any($1)
+            order by order_item_id, id desc;
+            "#,
+            order_item_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|progress| (progress.order_item_id, progress))
+        .collect::<HashMap<i32, ProgressModel>>();
+
+        Ok(order_item_id_to_progress)
+    }
+
     pub async fn get_progress_status(
         db: &Pool<Postgres>,
         order_ids: &[i32],
@@ -160,10 +308,10 @@ impl ProgressModel {
             ProgressModel,
             r#"
             select distinct on (order_item_id)
-            id, order_item_id, step, account_id, done, notes, dt, index
+            id, order_item_id, step, account_id, done, notes, dt, index, skipped, quantity, defective
             from progress
             where order_item_id = any($1)
-            order by order_item_id, step desc, id desc;
+            order by order_item_id, id desc;
             "#,
             &order_item_ids,
         )
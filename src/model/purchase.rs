@@ -0,0 +1,321 @@
+use crate::model::progress::ProgressModel;
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Pool, Postgres};
+
+/// 采购单类型, 对应流程上需要采购单的选项(workflow_step_options.purchase_kind)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PurchaseKind {
+    Stainless, // 成品不锈钢订货
+    Outsource, // 外发加工
+}
+
+impl PurchaseKind {
+    pub fn from_str(kind: &str) -> ERPResult<PurchaseKind> {
+        match kind {
+            "stainless" => Ok(PurchaseKind::Stainless),
+            "outsource" => Ok(PurchaseKind::Outsource),
+            _ => Err(ERPError::ParamError(format!(
+                "采购单类型不对: {}, 只支持stainless/outsource",
+                kind
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PurchaseKind::Stainless => "stainless",
+            PurchaseKind::Outsource => "outsource",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PurchaseKind::Stainless => "成品不锈钢订货",
+            PurchaseKind::Outsource => "外发",
+        }
+    }
+}
+
+/// 采购单: 向供应商订不锈钢成品, 或者外发加工, 对应订单里的一个sku
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct PurchaseOrderModel {
+    pub id: i32,
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub supplier_id: i32,
+    pub kind: String,
+    pub item: String, // 采购/外发的内容
+    pub quantity: i32,
+    pub received: i32, // 已收货数量
+    pub expected_date: NaiveDate,
+    pub progress_id: i32, // 标记流程时关联的流程记录, 0: 还没标记
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+    pub unit_price: Decimal, // 采购/加工单价(基础货币)
+}
+
+/// 采购单列表(带供应商/订单/sku的信息)
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct PurchaseOrder {
+    pub id: i32,
+    pub order_id: i32,
+    pub order_no: String,
+    pub order_item_id: i32,
+    pub sku_no: String,
+    pub supplier_id: i32,
+    pub supplier_code: String,
+    pub supplier_name: String,
+    pub kind: String,
+    pub item: String,
+    pub quantity: i32,
+    pub received: i32,
+    pub unit_price: Decimal,
+    pub expected_date: NaiveDate,
+    pub overdue: bool, // 过了预计到货日期还没收齐
+    pub progress_id: i32,
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+/// 收货记录
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct PurchaseReceiptModel {
+    pub id: i32,
+    pub purchase_order_id: i32,
+    pub quantity: i32,
+    pub receipt_date: NaiveDate,
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+/// 收货数量要大于0, 且累计不能超过采购数量
+pub fn check_receipt_quantity(ordered: i32, received: i32, quantity: i32) -> ERPResult<()> {
+    if quantity <= 0 {
+        return Err(ERPError::ParamError("收货数量必须大于0".to_string()));
+    }
+    if received + quantity > ordered {
+        return Err(ERPError::Failed(format!(
+            "收货数量超出了采购数量: 采购{}, 已收{}, 本次{}",
+            ordered, received, quantity
+        )));
+    }
+
+    Ok(())
+}
+
+impl PurchaseOrderModel {
+    pub async fn get_by_id(db: &Pool<Postgres>, id: i32) -> ERPResult<PurchaseOrderModel> {
+        sqlx::query_as!(
+            PurchaseOrderModel,
+            "select * from purchase_orders where id = $1",
+            id
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("采购单#{}", id)))
+    }
+
+    /// 参数为0/空时不过滤; overdue: 只看逾期未收齐的, 按预计到货日期排
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        order_id: i32,
+        supplier_id: i32,
+        kind: &str,
+        overdue: bool,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<PurchaseOrder>, i64)> {
+        let purchase_orders = sqlx::query_as!(
+            PurchaseOrder,
+            r#"
+            select po.id, po.order_id, coalesce(o.order_no, '') as "order_no!", po.order_item_id,
+                coalesce(sku.sku_no, '') as "sku_no!", po.supplier_id,
+                coalesce(s.code, '') as "supplier_code!", coalesce(s.name, '') as "supplier_name!",
+                po.kind, po.item, po.quantity, po.received, po.unit_price, po.expected_date,
+                (po.received < po.quantity and po.expected_date < current_date) as "overdue!",
+                po.progress_id, po.notes, po.account_id, po.dt
+            from purchase_orders po
+                left join suppliers s on s.id = po.supplier_id
+                left join orders o on o.id = po.order_id
+                left join order_items oi on oi.id = po.order_item_id
+                left join skus sku on sku.id = oi.sku_id
+            where ($1 = 0 or po.order_id = $1) and ($2 = 0 or po.supplier_id = $2)
+                and ($3 = '' or po.kind = $3)
+                and (not $4 or (po.received < po.quantity and po.expected_date < current_date))
+            order by case when $4 then po.expected_date end, po.id desc
+            offset $5 limit $6
+            "#,
+            order_id,
+            supplier_id,
+            kind,
+            overdue,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            r#"
+            select count(1) from purchase_orders
+            where ($1 = 0 or order_id = $1) and ($2 = 0 or supplier_id = $2)
+                and ($3 = '' or kind = $3)
+                and (not $4 or (received < quantity and expected_date < current_date))
+            "#,
+            order_id,
+            supplier_id,
+            kind,
+            overdue
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((purchase_orders, count))
+    }
+
+    /// 这些订单sku里, 还没有这种采购单的
+    pub async fn get_order_items_without(
+        db: &Pool<Postgres>,
+        kind: PurchaseKind,
+        order_item_ids: &[i32],
+    ) -> ERPResult<Vec<i32>> {
+        let order_item_ids = sqlx::query!(
+            r#"
+            select id from order_items
+            where id = any($1)
+                and not exists (select 1 from purchase_orders po where po.order_item_id = order_items.id and po.kind = $2)
+            order by id
+            "#,
+            order_item_ids,
+            kind.as_str()
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+
+        Ok(order_item_ids)
+    }
+
+    /// 标记流程后, 把还没关联流程记录的采购单关联上
+    pub async fn link_progress(
+        db: &mut PgConnection,
+        kind: PurchaseKind,
+        progresses: &[ProgressModel],
+    ) -> ERPResult<()> {
+        let progress_ids = progresses.iter().map(|p| p.id).collect::<Vec<i32>>();
+        sqlx::query!(
+            r#"
+            update purchase_orders po
+            set progress_id = p.id
+            from progress p
+            where p.id = any($1) and po.order_item_id = p.order_item_id
+                and po.kind = $2 and po.progress_id = 0
+            "#,
+            &progress_ids,
+            kind.as_str()
+        )
+        .execute(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+
+    /// 记一次收货, 返回累计收货数量; 锁住采购单, 避免并发时收货超出
+    pub async fn receive(
+        db: &mut PgConnection,
+        id: i32,
+        quantity: i32,
+        receipt_date: NaiveDate,
+        notes: &str,
+        account_id: i32,
+    ) -> ERPResult<i32> {
+        let purchase_order = sqlx::query!(
+            "select quantity, received from purchase_orders where id = $1 for update",
+            id
+        )
+        .fetch_optional(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("采购单#{}", id)))?;
+        check_receipt_quantity(purchase_order.quantity, purchase_order.received, quantity)?;
+
+        sqlx::query!(
+            r#"
+            insert into purchase_receipts (purchase_order_id, quantity, receipt_date, notes, account_id)
+            values ($1, $2, $3, $4, $5)
+            "#,
+            id,
+            quantity,
+            receipt_date,
+            notes,
+            account_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+        sqlx::query!(
+            "update purchase_orders set received = received + $1 where id = $2",
+            quantity,
+            id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(purchase_order.received + quantity)
+    }
+}
+
+impl PurchaseReceiptModel {
+    pub async fn get_by_purchase_order_id(
+        db: &Pool<Postgres>,
+        purchase_order_id: i32,
+    ) -> ERPResult<Vec<PurchaseReceiptModel>> {
+        let receipts = sqlx::query_as!(
+            PurchaseReceiptModel,
+            "select * from purchase_receipts where purchase_order_id = $1 order by receipt_date, id",
+            purchase_order_id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(receipts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::purchase::{check_receipt_quantity, PurchaseKind};
+
+    #[test]
+    fn test_check_receipt_quantity() {
+        assert!(check_receipt_quantity(100, 0, 60).is_ok());
+        assert!(check_receipt_quantity(100, 60, 40).is_ok());
+        assert!(check_receipt_quantity(100, 60, 41).is_err());
+        assert!(check_receipt_quantity(100, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_purchase_kind() {
+        for kind in [PurchaseKind::Stainless, PurchaseKind::Outsource] {
+            assert_eq!(PurchaseKind::from_str(kind.as_str()).unwrap(), kind);
+        }
+        assert!(PurchaseKind::from_str("stock").is_err());
+    }
+}
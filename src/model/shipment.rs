@@ -0,0 +1,396 @@
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::model::workflow::{ProgressRecord, StepQuantity, Workflow, WorkflowRoute};
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgConnection, Pool, Postgres};
+use std::collections::HashMap;
+
+/// 发货单
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ShipmentModel {
+    pub id: i32,
+    pub order_id: i32,
+    pub shipment_date: NaiveDate, // 发货日期
+    pub carrier: String,          // 物流公司
+    pub tracking_no: String,      // 物流单号
+    pub boxes: i32,               // 箱数
+    pub notes: String,
+    pub account_id: i32,
+    pub dt: DateTime<Utc>,
+}
+
+/// 发货单里的一个订单sku
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ShipmentItemModel {
+    pub id: i32,
+    pub shipment_id: i32,
+    pub order_item_id: i32,
+    pub quantity: i32,
+    pub progress_id: i32, // 对应的出货流程记录, 0: 订单的流程里没有出货
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Shipment {
+    #[serde(flatten)]
+    pub shipment: ShipmentModel,
+    pub items: Vec<ShipmentItemModel>,
+}
+
+/// 订单sku的发货情况
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct OrderItemShipping {
+    pub order_item_id: i32,
+    pub count: i32,       // 订单数量
+    pub ready: i32,       // 成品库存: 已经到了出货流程, 可以发货的数量
+    pub shipped: i32,     // 已发货
+    pub outstanding: i32, // 未发货: 订单数量减去次品和已发货的
+    pub defective: i32,   // 各流程上的次品, 不会发货
+}
+
+/// 可以发货的数量: 订单的流程里有出货时, 是出货流程上的数量;
+/// 没有出货时, 是走完最后一个流程的数量减去已经发的
+pub fn shippable_quantity(
+    route: &WorkflowRoute,
+    shipping_step: Option<i32>,
+    count: i32,
+    quantities: &[StepQuantity],
+    shipped: i32,
+) -> i32 {
+    let at_shipping_step = shipping_step
+        .filter(|step| route.steps.contains(step))
+        .and_then(|step| quantities.iter().find(|quantity| quantity.step == step));
+    match at_shipping_step {
+        Some(quantity) => quantity.in_progress,
+        None => {
+            let finished = quantities.last().map(|q| q.completed).unwrap_or(count);
+            (finished - shipped).max(0)
+        }
+    }
+}
+
+impl Shipment {
+    pub async fn get_by_order_id(db: &Pool<Postgres>, order_id: i32) -> ERPResult<Vec<Shipment>> {
+        let shipments = sqlx::query_as!(
+            ShipmentModel,
+            "select * from shipments where order_id = $1 order by shipment_date, id",
+            order_id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let shipment_ids = shipments.iter().map(|s| s.id).collect::<Vec<i32>>();
+
+        let mut shipment_id_to_items: HashMap<i32, Vec<ShipmentItemModel>> = HashMap::new();
+        sqlx::query_as!(
+            ShipmentItemModel,
+            "select * from shipment_items where shipment_id = any($1) order by id",
+            &shipment_ids
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .for_each(|item| {
+            shipment_id_to_items
+                .entry(item.shipment_id)
+                .or_default()
+                .push(item)
+        });
+
+        Ok(shipments
+            .into_iter()
+            .map(|shipment| Shipment {
+                items: shipment_id_to_items
+                    .remove(&shipment.id)
+                    .unwrap_or_default(),
+                shipment,
+            })
+            .collect())
+    }
+
+    /// 订单里每个sku的发货情况, 按order_items的id排序
+    pub async fn get_order_shipping(
+        db: &mut PgConnection,
+        order: &OrderModel,
+    ) -> ERPResult<Vec<OrderItemShipping>> {
+        let workflow = Workflow::get_with_conn(&mut *db).await?;
+        let route = workflow.route_for_order(order);
+
+        let order_items = sqlx::query!(
+            "select id, count from order_items where order_id = $1 order by id",
+            order.id
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let mut order_item_id_to_records: HashMap<i32, Vec<ProgressRecord>> = HashMap::new();
+        sqlx::query_as!(
+            ProgressModel,
+            r#"
+            select p.*
+            from progress p, order_items oi
+            where p.order_item_id = oi.id and oi.order_id = $1
+            order by p.id
+            "#,
+            order.id
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .iter()
+        .for_each(|progress| {
+            order_item_id_to_records
+                .entry(progress.order_item_id)
+                .or_default()
+                .push(ProgressRecord::from(progress))
+        });
+
+        let order_item_id_to_shipped = sqlx::query!(
+            r#"
+            select si.order_item_id, sum(si.quantity) as "quantity!"
+            from shipment_items si, shipments s
+            where si.shipment_id = s.id and s.order_id = $1
+            group by si.order_item_id
+            "#,
+            order.id
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .into_iter()
+        .map(|row| (row.order_item_id, row.quantity as i32))
+        .collect::<HashMap<i32, i32>>();
+
+        let shipping_step = workflow.shipping_step();
+        Ok(order_items
+            .into_iter()
+            .map(|order_item| {
+                let records = order_item_id_to_records
+                    .get(&order_item.id)
+                    .map(|records| records.as_slice())
+                    .unwrap_or(&[]);
+                let quantities = route.step_quantities(&workflow, order_item.count, records);
+                let shipped = order_item_id_to_shipped
+                    .get(&order_item.id)
+                    .copied()
+                    .unwrap_or(0);
+                let defective = quantities
+                    .iter()
+                    .map(|quantity| quantity.defective)
+                    .sum::<i32>();
+                OrderItemShipping {
+                    order_item_id: order_item.id,
+                    count: order_item.count,
+                    ready: shippable_quantity(
+                        &route,
+                        shipping_step,
+                        order_item.count,
+                        &quantities,
+                        shipped,
+                    ),
+                    shipped,
+                    outstanding: (order_item.count - defective - shipped).max(0),
+                    defective,
+                }
+            })
+            .collect())
+    }
+
+    /// 新建发货单: 检查每个sku可以发货的数量, 同时在出货流程上记一条完成的记录;
+    /// items: (order_item_id, quantity)
+    pub async fn create(
+        db: &mut PgConnection,
+        order: &OrderModel,
+        shipment: &ShipmentModel,
+        items: &[(i32, i32)],
+    ) -> ERPResult<i32> {
+        // 同一个订单的发货排队处理, 避免并发时超发
+        sqlx::query!("select id from orders where id = $1 for update", order.id)
+            .fetch_one(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+
+        let shippings = Self::get_order_shipping(&mut *db, order)
+            .await?
+            .into_iter()
+            .map(|shipping| (shipping.order_item_id, shipping))
+            .collect::<HashMap<i32, OrderItemShipping>>();
+        for (order_item_id, quantity) in items.iter() {
+            let shipping = shippings
+                .get(order_item_id)
+                .ok_or(ERPError::NotFound(format!(
+                    "订单#{}里没有产品#{}",
+                    order.order_no, order_item_id
+                )))?;
+            if *quantity > shipping.ready {
+                return Err(ERPError::Failed(format!(
+                    "产品#{}可以发货的数量只有{}",
+                    order_item_id, shipping.ready
+                )));
+            }
+        }
+
+        let shipment_id = sqlx::query!(
+            r#"
+            insert into shipments (order_id, shipment_date, carrier, tracking_no, boxes, notes, account_id)
+            values ($1, $2, $3, $4, $5, $6, $7)
+            returning id
+            "#,
+            order.id,
+            shipment.shipment_date,
+            shipment.carrier,
+            shipment.tracking_no,
+            shipment.boxes,
+            shipment.notes,
+            shipment.account_id
+        )
+        .fetch_one(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .id;
+
+        let workflow = Workflow::get_with_conn(&mut *db).await?;
+        let route = workflow.route_for_order(order);
+        let shipping_step = workflow
+            .shipping_step()
+            .filter(|step| route.steps.contains(step));
+        let now = Utc::now();
+        for (order_item_id, quantity) in items.iter() {
+            let progress_id = match shipping_step {
+                Some(step) => {
+                    // 次品不会发货, 发完订单数量减去次品就算出货完成
+                    let shipping = &shippings[order_item_id];
+                    let shippable = shipping.count - shipping.defective;
+                    sqlx::query!(
+                        r#"
+                        insert into progress (order_item_id, step, index, account_id, done, notes, dt, skipped, quantity, defective)
+                        values ($1, $2, $3, $4, $5, $6, $7, false, $8, 0)
+                        returning id
+                        "#,
+                        order_item_id,
+                        step,
+                        workflow.done_option(step)?.index,
+                        shipment.account_id,
+                        shipping.shipped + quantity >= shippable,
+                        format!("发货单#{}", shipment_id),
+                        now,
+                        quantity
+                    )
+                    .fetch_one(&mut *db)
+                    .await
+                    .map_err(ERPError::DBError)?
+                    .id
+                }
+                None => 0,
+            };
+
+            sqlx::query!(
+                "insert into shipment_items (shipment_id, order_item_id, quantity, progress_id) values ($1, $2, $3, $4)",
+                shipment_id,
+                order_item_id,
+                quantity,
+                progress_id
+            )
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+        }
+
+        Ok(shipment_id)
+    }
+
+    /// 删除发货单(和它在出货流程上的记录); 后面的流程已经开始了就不能删
+    pub async fn delete(db: &mut PgConnection, shipment_id: i32) -> ERPResult<()> {
+        let items = sqlx::query_as!(
+            ShipmentItemModel,
+            "select * from shipment_items where shipment_id = $1",
+            shipment_id
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let progress_ids = items
+            .iter()
+            .map(|item| item.progress_id)
+            .filter(|id| *id > 0)
+            .collect::<Vec<i32>>();
+
+        let later = sqlx::query!(
+            r#"
+            select count(1)
+            from progress p, progress shipped
+            where shipped.id = any($1) and p.order_item_id = shipped.order_item_id
+                and p.step != shipped.step and p.id > shipped.id
+            "#,
+            &progress_ids
+        )
+        .fetch_one(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+        if later > 0 {
+            return Err(ERPError::Failed(
+                "发货后的流程已经有记录了, 不能删除发货单".to_string(),
+            ));
+        }
+
+        sqlx::query!("delete from progress where id = any($1)", &progress_ids)
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+        sqlx::query!(
+            "delete from shipment_items where shipment_id = $1",
+            shipment_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+        sqlx::query!("delete from shipments where id = $1", shipment_id)
+            .execute(&mut *db)
+            .await
+            .map_err(ERPError::DBError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::shipment::shippable_quantity;
+    use crate::model::workflow::{StepQuantity, WorkflowRoute};
+
+    fn quantity(step: i32, completed: i32, in_progress: i32) -> StepQuantity {
+        StepQuantity {
+            step,
+            completed,
+            in_progress,
+            defective: 0,
+            done: false,
+        }
+    }
+
+    #[test]
+    fn test_shippable_quantity() {
+        let route = WorkflowRoute {
+            template_id: 0,
+            steps: vec![6, 7, 8],
+            optional_steps: vec![],
+            skippable_steps: vec![],
+        };
+        // 包装完成了8个, 其中3个已经发货
+        let quantities = [quantity(6, 8, 2), quantity(7, 3, 5), quantity(8, 0, 3)];
+        assert_eq!(shippable_quantity(&route, Some(7), 10, &quantities, 3), 5);
+
+        // 流程里没有出货: 最后一个流程完成的数量减去已经发的
+        let route = WorkflowRoute {
+            steps: vec![6],
+            ..route
+        };
+        let quantities = [quantity(6, 8, 2)];
+        assert_eq!(shippable_quantity(&route, Some(7), 10, &quantities, 3), 5);
+        assert_eq!(shippable_quantity(&route, None, 10, &[], 4), 6);
+    }
+}
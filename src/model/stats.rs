@@ -0,0 +1,689 @@
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::model::workflow::{ProgressRecord, Workflow, WorkflowRoute};
+use crate::{ERPError, ERPResult};
+use chrono::{Datelike, Duration, Local, NaiveDate};
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_STATS_DAYS: i64 = 30; // 生产统计没传开始日期时, 统计最近多少天
+
+/// 统计的时间粒度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsPeriod {
+    pub fn from_str(period: &str) -> ERPResult<StatsPeriod> {
+        match period {
+            "" | "day" => Ok(StatsPeriod::Day),
+            "week" => Ok(StatsPeriod::Week),
+            "month" => Ok(StatsPeriod::Month),
+            _ => Err(ERPError::ParamError(format!(
+                "period只能是day/week/month: {}",
+                period
+            ))),
+        }
+    }
+
+    /// 日期所在周期的第一天, 周从周一开始
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            StatsPeriod::Day => date,
+            StatsPeriod::Week => {
+                date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            StatsPeriod::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+/// 统计的筛选条件: 订单按下单日期, 流程按操作日期
+#[derive(Debug, Clone, Default)]
+pub struct StatsFilter {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub customer_no: String, // 空: 所有客户
+}
+
+impl StatsFilter {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start_date.map(|start| date >= start).unwrap_or(true)
+            && self.end_date.map(|end| date <= end).unwrap_or(true)
+    }
+
+    /// 没有开始日期时, 取截止日期(默认今天)往前days天
+    pub fn with_default_start(&self, days: i64, today: NaiveDate) -> StatsFilter {
+        let start_date = self
+            .start_date
+            .unwrap_or(self.end_date.unwrap_or(today) - Duration::days(days - 1));
+        StatsFilter {
+            start_date: Some(start_date),
+            ..self.clone()
+        }
+    }
+}
+
+/// 占比(%), 总数为0时是0
+pub fn ratio(part: i32, total: i32) -> Decimal {
+    if total == 0 {
+        return Decimal::ZERO;
+    }
+    (Decimal::from(part) * Decimal::ONE_HUNDRED / Decimal::from(total)).round_dp(2)
+}
+
+/// 订单的交货情况
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    OnTime,         // 交货日期前发完货
+    Late,           // 发完货了, 但晚于交货日期
+    Overdue,        // 过了交货日期还没发完
+    Pending,        // 还没到交货日期, 没发完
+    NoDeliveryDate, // 没有交货日期
+}
+
+/// 用来统计的订单数据, 金额已换算成基础货币
+#[derive(Debug, Clone)]
+pub struct OrderStatRow {
+    pub order_no: String,
+    pub customer_no: String,
+    pub currency: String,
+    pub order_date: NaiveDate,
+    pub delivery_date: Option<NaiveDate>,
+    pub is_urgent: bool,
+    pub is_return_order: bool,
+    pub quantity: i32,
+    pub amount_base: Option<Decimal>, // None: 外币在下单日之前没有汇率
+    pub shipped: i32,
+    pub defective: i32, // 次品不会发货, 发完订单数量减去次品就算交货了
+    pub last_shipment_date: Option<NaiveDate>,
+}
+
+impl OrderStatRow {
+    pub fn delivery_status(&self, today: NaiveDate) -> DeliveryStatus {
+        let Some(delivery_date) = self.delivery_date else {
+            return DeliveryStatus::NoDeliveryDate;
+        };
+        match (
+            self.quantity > 0 && self.shipped + self.defective >= self.quantity,
+            self.last_shipment_date,
+        ) {
+            (true, Some(shipment_date)) if shipment_date <= delivery_date => DeliveryStatus::OnTime,
+            (true, _) => DeliveryStatus::Late,
+            (false, _) if today > delivery_date => DeliveryStatus::Overdue,
+            (false, _) => DeliveryStatus::Pending,
+        }
+    }
+}
+
+/// 某个周期某个客户的订单
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct OrderPeriodStat {
+    pub period: NaiveDate, // 周期的第一天
+    pub customer_no: String,
+    pub order_count: i32,
+    pub quantity: i32,
+    pub amount_base: Decimal,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct OrderSummary {
+    pub order_count: i32,
+    pub quantity: i32,
+    pub amount_base: Decimal, // 金额(基础货币, 按下单日的汇率)
+    pub urgent_count: i32,
+    pub urgent_ratio: Decimal, // 加急占比(%)
+    pub return_count: i32,
+    pub return_ratio: Decimal, // 返单占比(%)
+    pub on_time: i32,
+    pub late: i32,
+    pub overdue: i32,
+    pub pending: i32,
+    pub on_time_ratio: Decimal, // 按时交货率(%) = 按时 / (按时 + 延期交货)
+}
+
+/// 没有汇率的外币订单, 金额没有算进amount_base
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MissingExchangeRate {
+    pub order_no: String,
+    pub currency: String,
+    pub order_date: NaiveDate, // 要录入这天之前的汇率
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderStats {
+    pub summary: OrderSummary,
+    pub periods: Vec<OrderPeriodStat>, // 按周期, 客户排
+    pub missing_rates: Vec<MissingExchangeRate>,
+}
+
+impl OrderStats {
+    pub fn from_rows(rows: &[OrderStatRow], period: StatsPeriod, today: NaiveDate) -> OrderStats {
+        let mut summary = OrderSummary::default();
+        let mut periods: BTreeMap<(NaiveDate, &str), OrderPeriodStat> = BTreeMap::new();
+        let mut missing_rates = vec![];
+        for row in rows.iter() {
+            let amount_base = match row.amount_base {
+                Some(amount_base) => amount_base,
+                None => {
+                    missing_rates.push(MissingExchangeRate {
+                        order_no: row.order_no.clone(),
+                        currency: row.currency.clone(),
+                        order_date: row.order_date,
+                    });
+                    Decimal::ZERO
+                }
+            };
+            summary.order_count += 1;
+            summary.quantity += row.quantity;
+            summary.amount_base += amount_base;
+            summary.urgent_count += row.is_urgent as i32;
+            summary.return_count += row.is_return_order as i32;
+            match row.delivery_status(today) {
+                DeliveryStatus::OnTime => summary.on_time += 1,
+                DeliveryStatus::Late => summary.late += 1,
+                DeliveryStatus::Overdue => summary.overdue += 1,
+                DeliveryStatus::Pending => summary.pending += 1,
+                DeliveryStatus::NoDeliveryDate => {}
+            }
+
+            let start = period.start_of(row.order_date);
+            let stat =
+                periods
+                    .entry((start, &row.customer_no))
+                    .or_insert_with(|| OrderPeriodStat {
+                        period: start,
+                        customer_no: row.customer_no.clone(),
+                        order_count: 0,
+                        quantity: 0,
+                        amount_base: Decimal::ZERO,
+                    });
+            stat.order_count += 1;
+            stat.quantity += row.quantity;
+            stat.amount_base += amount_base;
+        }
+        summary.urgent_ratio = ratio(summary.urgent_count, summary.order_count);
+        summary.return_ratio = ratio(summary.return_count, summary.order_count);
+        summary.on_time_ratio = ratio(summary.on_time, summary.on_time + summary.late);
+
+        OrderStats {
+            summary,
+            periods: periods.into_values().collect(),
+            missing_rates,
+        }
+    }
+
+    /// 外币订单在下单日之前没有汇率时, 金额不算进去, 放在missing_rates里
+    pub async fn get(
+        db: &Pool<Postgres>,
+        filter: &StatsFilter,
+        period: StatsPeriod,
+    ) -> ERPResult<OrderStats> {
+        let orders = sqlx::query!(
+            r#"
+            select o.order_no, o.customer_no, o.order_date, o.delivery_date, o.is_urgent, o.is_return_order, o.currency,
+                coalesce((select sum(oi.count) from order_items oi where oi.order_id = o.id), 0) as "quantity!",
+                coalesce((
+                    select sum(coalesce(oi.total_price, oi.unit_price * oi.count, 0))
+                    from order_items oi where oi.order_id = o.id
+                ), 0) as "amount!",
+                case when exists (select 1 from currencies c where c.code = o.currency and c.is_base) then 1
+                    else (
+                        select er.rate from exchange_rates er
+                        where er.currency = o.currency and er.effective_date <= o.order_date
+                        order by er.effective_date desc limit 1
+                    )
+                end as rate,
+                coalesce((
+                    select sum(si.quantity) from shipments s, shipment_items si
+                    where s.id = si.shipment_id and s.order_id = o.id
+                ), 0) as "shipped!",
+                coalesce((
+                    select sum(p.defective) from progress p, order_items oi
+                    where p.order_item_id = oi.id and oi.order_id = o.id
+                ), 0) as "defective!",
+                (select max(s.shipment_date) from shipments s where s.order_id = o.id) as last_shipment_date
+            from orders o
+            where ($1::date is null or o.order_date >= $1) and ($2::date is null or o.order_date <= $2)
+                and ($3 = '' or o.customer_no = $3)
+            order by o.order_date, o.id
+            "#,
+            filter.start_date,
+            filter.end_date,
+            filter.customer_no
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let rows = orders
+            .into_iter()
+            .map(|order| OrderStatRow {
+                order_no: order.order_no,
+                customer_no: order.customer_no,
+                currency: order.currency,
+                order_date: order.order_date,
+                delivery_date: order.delivery_date,
+                is_urgent: order.is_urgent,
+                is_return_order: order.is_return_order,
+                quantity: order.quantity as i32,
+                amount_base: order.rate.map(|rate| (order.amount * rate).round_dp(2)),
+                shipped: order.shipped as i32,
+                defective: order.defective as i32,
+                last_shipment_date: order.last_shipment_date,
+            })
+            .collect::<Vec<OrderStatRow>>();
+
+        Ok(Self::from_rows(&rows, period, Local::now().date_naive()))
+    }
+}
+
+/// 某一天某个流程的流转
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StepFlow {
+    pub date: NaiveDate,
+    pub step: i32,
+    pub entered: i32, // 流到这个流程的数量(上一个流程完成的, 第一个流程按下单日期算)
+    pub left: i32,    // 完成(或跳过)这个流程的数量
+    pub exceptions: i32, // 异常记录数
+    pub defective: i32, // 次品数量
+}
+
+/// 流程在筛选的日期内的合计, wip是现在的在制数量
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StepStat {
+    pub step: i32,
+    pub name: String,
+    pub wip: i32, // 已到这个流程, 还没完成的数量
+    pub entered: i32,
+    pub left: i32,
+    pub exceptions: i32,
+    pub defective: i32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ProductionStats {
+    pub start_date: NaiveDate, // 统计的开始日期, 没传时是最近DEFAULT_STATS_DAYS天
+    pub steps: Vec<StepStat>,
+    pub days: Vec<StepFlow>, // 按日期, 流程排
+}
+
+fn step_flow(
+    flows: &mut BTreeMap<(NaiveDate, i32), StepFlow>,
+    date: NaiveDate,
+    step: i32,
+) -> &mut StepFlow {
+    flows.entry((date, step)).or_insert_with(|| StepFlow {
+        date,
+        step,
+        entered: 0,
+        left: 0,
+        exceptions: 0,
+        defective: 0,
+    })
+}
+
+/// 把一个产品的流程记录按天累加到flows里; records: (操作日期, 记录)
+pub fn add_step_flows(
+    flows: &mut BTreeMap<(NaiveDate, i32), StepFlow>,
+    workflow: &Workflow,
+    route: &WorkflowRoute,
+    records: &[(NaiveDate, ProgressRecord)],
+) {
+    for (date, record) in records.iter() {
+        if record.skipped || workflow.is_done(record.step, record.index) {
+            let quantity = record.quantity;
+            step_flow(flows, *date, record.step).left += quantity;
+            let next_step = route
+                .steps
+                .iter()
+                .skip_while(|step| **step != record.step)
+                .nth(1);
+            if let Some(next_step) = next_step {
+                step_flow(flows, *date, *next_step).entered += quantity;
+            }
+        }
+        let is_exception = workflow
+            .get_option(record.step, record.index)
+            .map(|option| option.is_exception)
+            .unwrap_or(false);
+        let flow = step_flow(flows, *date, record.step);
+        flow.exceptions += is_exception as i32;
+        flow.defective += record.defective;
+    }
+}
+
+impl ProductionStats {
+    /// 流转/异常按操作日期筛选, 没传开始日期时是最近DEFAULT_STATS_DAYS天;
+    /// 在制数量是现在的, 只看筛选的客户, 不管下单日期
+    pub async fn get(db: &Pool<Postgres>, filter: &StatsFilter) -> ERPResult<ProductionStats> {
+        let filter = filter.with_default_start(DEFAULT_STATS_DAYS, Local::now().date_naive());
+        let start_date = filter.start_date.unwrap_or_default();
+        let workflow = Workflow::get(db).await?;
+        let orders = sqlx::query_as!(
+            OrderModel,
+            "select * from orders where ($1 = '' or customer_no = $1)",
+            filter.customer_no
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let order_id_to_route = orders
+            .iter()
+            .map(|order| (order.id, workflow.route_for_order(order)))
+            .collect::<HashMap<i32, WorkflowRoute>>();
+        let (order_ids, last_steps): (Vec<i32>, Vec<i32>) = order_id_to_route
+            .iter()
+            .map(|(order_id, route)| (*order_id, route.steps.last().copied().unwrap_or(0)))
+            .unzip();
+
+        // 要用到的产品: 还没走完流程路线的(最后一个流程还没有完成的记录, 算在制数量),
+        // 下单日期在范围内的(第一个流程的流入), 范围内有流程记录的(算流转);
+        // 流程记录的日期是本地日期, 这里多取一天, 后面再按本地日期精确筛选
+        let order_items = sqlx::query!(
+            r#"
+            select t.id as "id!", t.order_id as "order_id!", t.count as "count!",
+                t.order_date as "order_date!", t.unfinished as "unfinished!"
+            from (
+                select oi.id, oi.order_id, oi.count, o.order_date,
+                    not exists (
+                        select 1 from progress p
+                        where p.order_item_id = oi.id and p.done and p.step = r.last_step
+                    ) as unfinished
+                from order_items oi, orders o, unnest($1::int[], $2::int[]) as r(order_id, last_step)
+                where oi.order_id = r.order_id and o.id = oi.order_id
+            ) t
+            where t.unfinished
+                or (t.order_date >= $3 and ($4::date is null or t.order_date <= $4))
+                or exists (
+                    select 1 from progress p
+                    where p.order_item_id = t.id and p.dt >= $3::date - 1
+                        and ($4::date is null or p.dt < $4::date + 2)
+                )
+            "#,
+            &order_ids,
+            &last_steps,
+            start_date,
+            filter.end_date
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+        let order_item_ids = order_items
+            .iter()
+            .map(|order_item| order_item.id)
+            .collect::<Vec<i32>>();
+        let unfinished_ids = order_items
+            .iter()
+            .filter(|order_item| order_item.unfinished)
+            .map(|order_item| order_item.id)
+            .collect::<Vec<i32>>();
+
+        let mut order_item_id_to_records: HashMap<i32, Vec<(NaiveDate, ProgressRecord)>> =
+            HashMap::new();
+        // 在制数量要还没走完的产品的全部记录, 流转只要范围内的记录
+        sqlx::query_as!(
+            ProgressModel,
+            r#"
+            select p.*
+            from progress p
+            where p.order_item_id = any($1)
+                and (
+                    p.order_item_id = any($2)
+                    or (p.dt >= $3::date - 1 and ($4::date is null or p.dt < $4::date + 2))
+                )
+            order by p.id
+            "#,
+            &order_item_ids,
+            &unfinished_ids,
+            start_date,
+            filter.end_date
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .iter()
+        .for_each(|progress| {
+            order_item_id_to_records
+                .entry(progress.order_item_id)
+                .or_default()
+                .push((
+                    progress.dt.with_timezone(&Local).date_naive(),
+                    ProgressRecord::from(progress),
+                ))
+        });
+
+        let mut wip: HashMap<i32, i32> = HashMap::new();
+        let mut flows: BTreeMap<(NaiveDate, i32), StepFlow> = BTreeMap::new();
+        for order_item in order_items.iter() {
+            let Some(route) = order_id_to_route.get(&order_item.order_id) else {
+                continue;
+            };
+            let records = order_item_id_to_records
+                .get(&order_item.id)
+                .map(|records| records.as_slice())
+                .unwrap_or(&[]);
+
+            if order_item.unfinished {
+                let all_records = records
+                    .iter()
+                    .map(|(_, record)| *record)
+                    .collect::<Vec<ProgressRecord>>();
+                for quantity in route.step_quantities(&workflow, order_item.count, &all_records) {
+                    *wip.entry(quantity.step).or_default() += quantity.in_progress;
+                }
+            }
+            // 第一个流程: 下单就流进来了
+            if filter.contains(order_item.order_date) {
+                if let Some(first_step) = route.steps.first() {
+                    step_flow(&mut flows, order_item.order_date, *first_step).entered +=
+                        order_item.count;
+                }
+            }
+
+            let records_in_range = records
+                .iter()
+                .filter(|(date, _)| filter.contains(*date))
+                .cloned()
+                .collect::<Vec<(NaiveDate, ProgressRecord)>>();
+            add_step_flows(&mut flows, &workflow, route, &records_in_range);
+        }
+
+        let steps = workflow
+            .steps
+            .iter()
+            .map(|step| {
+                let step_flows = flows.values().filter(|flow| flow.step == step.step);
+                StepStat {
+                    step: step.step,
+                    name: step.name.clone(),
+                    wip: wip.get(&step.step).cloned().unwrap_or(0),
+                    entered: step_flows.clone().map(|flow| flow.entered).sum(),
+                    left: step_flows.clone().map(|flow| flow.left).sum(),
+                    exceptions: step_flows.clone().map(|flow| flow.exceptions).sum(),
+                    defective: step_flows.map(|flow| flow.defective).sum(),
+                }
+            })
+            .collect();
+
+        Ok(ProductionStats {
+            start_date,
+            steps,
+            days: flows.into_values().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::stats::{
+        add_step_flows, DeliveryStatus, OrderStatRow, OrderStats, StatsFilter, StatsPeriod,
+    };
+    use crate::model::workflow::{
+        ProgressRecord, Workflow, WorkflowRoute, WorkflowStepOptionModel,
+    };
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use std::collections::BTreeMap;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn row(customer_no: &str, order_date: &str, delivery_date: Option<&str>) -> OrderStatRow {
+        OrderStatRow {
+            order_no: format!("{}-{}", customer_no, order_date),
+            customer_no: customer_no.to_string(),
+            currency: "CNY".to_string(),
+            order_date: date(order_date),
+            delivery_date: delivery_date.map(date),
+            is_urgent: false,
+            is_return_order: false,
+            quantity: 10,
+            amount_base: Some(Decimal::from(100)),
+            shipped: 0,
+            defective: 0,
+            last_shipment_date: None,
+        }
+    }
+
+    #[test]
+    fn test_order_stats() {
+        assert_eq!(
+            StatsPeriod::Week.start_of(date("2024-01-10")),
+            date("2024-01-08")
+        );
+        assert_eq!(
+            StatsPeriod::Month.start_of(date("2024-01-10")),
+            date("2024-01-01")
+        );
+        assert!(StatsPeriod::from_str("year").is_err());
+
+        let filter = StatsFilter::default().with_default_start(30, date("2024-01-31"));
+        assert_eq!(filter.start_date, Some(date("2024-01-02")));
+        let filter = StatsFilter {
+            end_date: Some(date("2024-01-10")),
+            ..Default::default()
+        }
+        .with_default_start(10, date("2024-01-31"));
+        assert_eq!(filter.start_date, Some(date("2024-01-01")));
+
+        let today = date("2024-02-01");
+        let mut on_time = row("L1001", "2024-01-02", Some("2024-01-20"));
+        on_time.shipped = 10;
+        on_time.last_shipment_date = Some(date("2024-01-20"));
+        on_time.is_urgent = true;
+        let mut late = row("L1001", "2024-01-03", Some("2024-01-20"));
+        late.shipped = 10;
+        late.last_shipment_date = Some(date("2024-01-25"));
+        let mut overdue = row("L1002", "2024-01-10", Some("2024-01-30"));
+        overdue.shipped = 5;
+        overdue.is_return_order = true;
+        let pending = row("L1002", "2024-01-29", Some("2024-02-10"));
+        assert_eq!(on_time.delivery_status(today), DeliveryStatus::OnTime);
+        assert_eq!(late.delivery_status(today), DeliveryStatus::Late);
+        assert_eq!(overdue.delivery_status(today), DeliveryStatus::Overdue);
+        assert_eq!(pending.delivery_status(today), DeliveryStatus::Pending);
+        // 2个次品不会发货, 发完剩下的8个就算交货了
+        let mut with_defective = overdue.clone();
+        with_defective.shipped = 8;
+        with_defective.defective = 2;
+        with_defective.last_shipment_date = Some(date("2024-01-28"));
+        assert_eq!(
+            with_defective.delivery_status(today),
+            DeliveryStatus::OnTime
+        );
+
+        let stats = OrderStats::from_rows(
+            &[on_time, late, overdue, pending.clone()],
+            StatsPeriod::Week,
+            today,
+        );
+        assert_eq!(stats.summary.order_count, 4);
+        assert_eq!(stats.summary.amount_base, Decimal::from(400));
+        assert!(stats.missing_rates.is_empty());
+        assert_eq!(stats.summary.urgent_ratio, Decimal::from(25));
+        assert_eq!(stats.summary.on_time_ratio, Decimal::from(50));
+        // 2024-01-01这周L1001两单, 2024-01-08这周L1002一单, 2024-01-29这周L1002一单
+        assert_eq!(stats.periods.len(), 3);
+        assert_eq!(stats.periods[0].period, date("2024-01-01"));
+        assert_eq!(stats.periods[0].order_count, 2);
+        assert_eq!(stats.periods[0].quantity, 20);
+        assert_eq!(stats.periods[2].period, date("2024-01-29"));
+
+        // 没有汇率的外币订单: 金额不算, 列出来要补录的汇率
+        let mut usd = row("L1003", "2024-01-29", None);
+        usd.currency = "USD".to_string();
+        usd.amount_base = None;
+        let stats = OrderStats::from_rows(&[pending, usd], StatsPeriod::Week, today);
+        assert_eq!(stats.summary.order_count, 2);
+        assert_eq!(stats.summary.quantity, 20);
+        assert_eq!(stats.summary.amount_base, Decimal::from(100));
+        assert_eq!(stats.missing_rates.len(), 1);
+        assert_eq!(stats.missing_rates[0].currency, "USD");
+        assert_eq!(stats.missing_rates[0].order_date, date("2024-01-29"));
+    }
+
+    fn option(step: i32, index: i32, is_done: bool, is_exception: bool) -> WorkflowStepOptionModel {
+        WorkflowStepOptionModel {
+            id: 0,
+            step,
+            index,
+            name: "".to_string(),
+            color: "".to_string(),
+            is_done,
+            is_exception,
+            purchase_kind: "".to_string(),
+        }
+    }
+
+    fn record(step: i32, index: i32, quantity: i32) -> ProgressRecord {
+        ProgressRecord {
+            step,
+            index,
+            quantity,
+            defective: 0,
+            skipped: false,
+        }
+    }
+
+    #[test]
+    fn test_add_step_flows() {
+        let workflow = Workflow {
+            steps: vec![],
+            options: vec![
+                option(2, 1, false, true),
+                option(2, 2, true, false),
+                option(3, 2, true, false),
+            ],
+            templates: vec![],
+        };
+        let route = WorkflowRoute {
+            template_id: 0,
+            steps: vec![2, 3],
+            optional_steps: vec![],
+            skippable_steps: vec![],
+        };
+        let records = [
+            (date("2024-01-02"), record(2, 2, 4)),
+            (date("2024-01-02"), record(2, 1, 0)),
+            (date("2024-01-03"), record(2, 2, 6)),
+            (date("2024-01-03"), record(3, 2, 10)),
+        ];
+        let mut flows = BTreeMap::new();
+        add_step_flows(&mut flows, &workflow, &route, &records);
+
+        let flow = &flows[&(date("2024-01-02"), 2)];
+        assert_eq!((flow.left, flow.exceptions), (4, 1));
+        assert_eq!(flows[&(date("2024-01-02"), 3)].entered, 4);
+        assert_eq!(flows[&(date("2024-01-03"), 3)].entered, 6);
+        // 最后一个流程完成了就不再流到别的流程
+        assert_eq!(flows[&(date("2024-01-03"), 3)].left, 10);
+        assert_eq!(flows[&(date("2024-01-03"), 2)].left, 6);
+        assert_eq!(flows.len(), 4);
+    }
+}
@@ -0,0 +1,570 @@
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::model::workflow::{current_step_of, ProgressRecord, Workflow};
+use crate::{ERPError, ERPResult};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+
+/// 仓库
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct WarehouseModel {
+    pub id: i32,
+    pub name: String,
+}
+
+impl WarehouseModel {
+    pub async fn get_all(db: &Pool<Postgres>) -> ERPResult<Vec<WarehouseModel>> {
+        let warehouses = sqlx::query_as!(WarehouseModel, "select * from warehouses order by id")
+            .fetch_all(db)
+            .await
+            .map_err(ERPError::DBError)?;
+
+        Ok(warehouses)
+    }
+}
+
+/// 库存变动类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StockMovementKind {
+    In,     // 入库
+    Out,    // 出库
+    Adjust, // 盘点调整
+}
+
+impl StockMovementKind {
+    pub fn from_str(kind: &str) -> ERPResult<StockMovementKind> {
+        match kind {
+            "in" => Ok(StockMovementKind::In),
+            "out" => Ok(StockMovementKind::Out),
+            "adjust" => Ok(StockMovementKind::Adjust),
+            _ => Err(ERPError::ParamError(format!(
+                "库存变动类型不对: {}, 只支持in/out/adjust",
+                kind
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StockMovementKind::In => "in",
+            StockMovementKind::Out => "out",
+            StockMovementKind::Adjust => "adjust",
+        }
+    }
+}
+
+/// 把提交的数量换成流水里的变动数量:
+/// 入库/出库填的是正数, 出库不能超过现有库存; 盘点填的是盘点后的实际数量
+pub fn movement_quantity(
+    kind: StockMovementKind,
+    quantity: Decimal,
+    on_hand: Decimal,
+) -> ERPResult<Decimal> {
+    match kind {
+        StockMovementKind::In | StockMovementKind::Out if quantity <= Decimal::ZERO => {
+            Err(ERPError::ParamError("入库/出库数量必须大于0".to_string()))
+        }
+        StockMovementKind::In => Ok(quantity),
+        StockMovementKind::Out if quantity > on_hand => Err(ERPError::Failed(format!(
+            "库存不足: 现有{}, 要出库{}",
+            on_hand, quantity
+        ))),
+        StockMovementKind::Out => Ok(-quantity),
+        StockMovementKind::Adjust if quantity < Decimal::ZERO => {
+            Err(ERPError::ParamError("盘点数量不能小于0".to_string()))
+        }
+        StockMovementKind::Adjust if quantity == on_hand => Err(ERPError::ParamError(
+            "盘点数量和现有库存一样, 不用调整".to_string(),
+        )),
+        StockMovementKind::Adjust => Ok(quantity - on_hand),
+    }
+}
+
+/// 库存流水(带材料/仓库/操作人的名称)
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct StockMovement {
+    pub id: i32,
+    pub material_id: i32,
+    pub name: String,
+    pub color: String,
+    pub unit: String,
+    pub warehouse_id: i32,
+    pub warehouse: String,
+    pub kind: String,
+    pub quantity: Decimal,
+    pub order_id: i32,
+    pub supplier_id: i32, // 采购入库的供应商
+    pub supplier_name: String,
+    pub notes: String,
+    pub account_id: i32,
+    pub account_name: String,
+    pub dt: DateTime<Utc>,
+}
+
+impl StockMovement {
+    /// 参数为0时不过滤
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        material_id: i32,
+        warehouse_id: i32,
+        order_id: i32,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<StockMovement>, i64)> {
+        let movements = sqlx::query_as!(
+            StockMovement,
+            r#"
+            select sm.id, sm.material_id, m.name, m.color, m.unit, sm.warehouse_id, w.name as warehouse,
+                sm.kind, sm.quantity, sm.order_id, sm.supplier_id, coalesce(s.name, '') as "supplier_name!",
+                sm.notes, sm.account_id, coalesce(a.name, '') as "account_name!", sm.dt
+            from stock_movements sm
+                join materials m on m.id = sm.material_id
+                join warehouses w on w.id = sm.warehouse_id
+                left join suppliers s on s.id = sm.supplier_id
+                left join accounts a on a.id = sm.account_id
+            where ($1 = 0 or sm.material_id = $1) and ($2 = 0 or sm.warehouse_id = $2)
+                and ($3 = 0 or sm.order_id = $3)
+            order by sm.id desc offset $4 limit $5
+            "#,
+            material_id,
+            warehouse_id,
+            order_id,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            r#"
+            select count(1) from stock_movements
+            where ($1 = 0 or material_id = $1) and ($2 = 0 or warehouse_id = $2)
+                and ($3 = 0 or order_id = $3)
+            "#,
+            material_id,
+            warehouse_id,
+            order_id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((movements, count))
+    }
+
+    /// 记一笔库存变动, 返回变动后的库存;
+    /// 同一个材料的变动排队处理(advisory lock), 避免并发出库时把库存扣成负数
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record(
+        db: &mut PgConnection,
+        material_id: i32,
+        warehouse_id: i32,
+        kind: StockMovementKind,
+        quantity: Decimal,
+        order_id: i32,
+        supplier_id: i32,
+        notes: &str,
+        account_id: i32,
+    ) -> ERPResult<Decimal> {
+        sqlx::query!(
+            "select pg_advisory_xact_lock(hashtext('stock'), $1)",
+            material_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let on_hand = MaterialStock::on_hand(&mut *db, material_id, warehouse_id).await?;
+        let quantity = movement_quantity(kind, quantity, on_hand)?;
+        sqlx::query!(
+            r#"
+            insert into stock_movements (material_id, warehouse_id, kind, quantity, order_id, supplier_id, notes, account_id)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            material_id,
+            warehouse_id,
+            kind.as_str(),
+            quantity,
+            order_id,
+            supplier_id,
+            notes,
+            account_id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(on_hand + quantity)
+    }
+}
+
+/// 某个材料在某个仓库的库存
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct MaterialStock {
+    pub material_id: i32,
+    pub name: String,
+    pub color: String,
+    pub unit: String,
+    pub warehouse_id: i32,
+    pub warehouse: String,
+    pub on_hand: Decimal, // 现有库存
+}
+
+impl MaterialStock {
+    pub async fn on_hand(
+        db: &mut PgConnection,
+        material_id: i32,
+        warehouse_id: i32,
+    ) -> ERPResult<Decimal> {
+        let on_hand = sqlx::query!(
+            "select sum(quantity) from stock_movements where material_id = $1 and warehouse_id = $2",
+            material_id,
+            warehouse_id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .sum
+        .unwrap_or(Decimal::ZERO);
+
+        Ok(on_hand)
+    }
+
+    /// 参数为0时不过滤, 没有流水的材料不列出来
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        material_id: i32,
+        warehouse_id: i32,
+    ) -> ERPResult<Vec<MaterialStock>> {
+        let stocks = sqlx::query_as!(
+            MaterialStock,
+            r#"
+            select m.id as material_id, m.name, m.color, m.unit, w.id as warehouse_id, w.name as warehouse,
+                sum(sm.quantity) as "on_hand!"
+            from stock_movements sm
+                join materials m on m.id = sm.material_id
+                join warehouses w on w.id = sm.warehouse_id
+            where ($1 = 0 or sm.material_id = $1) and ($2 = 0 or sm.warehouse_id = $2)
+            group by m.id, w.id
+            order by m.name, m.color, w.id
+            "#,
+            material_id,
+            warehouse_id
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(stocks)
+    }
+}
+
+/// 订单材料单的一行需要多少材料(按先后排好)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDemand {
+    pub id: i32,
+    pub order_id: i32,
+    pub material_id: i32, // 0: 手工添加的, 不算库存
+    pub total: Decimal,
+    pub finished: bool, // 产品已经走完流程或者发完货, 不再占用库存
+}
+
+/// 材料单一行的备料情况
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MaterialAllocation {
+    pub issued: Decimal,   // 已经出库给这个订单的
+    pub reserved: Decimal, // 库存里预留给这个订单的
+    pub debt: Decimal,     // 欠数
+}
+
+/// 按先后把库存分给订单的材料单:
+/// 先扣掉已经出库给这个订单的, 剩下的从所有仓库的库存里预留, 不够的就是欠数;
+/// 已经完成的产品只算出库了多少, 不再预留也没有欠数
+pub fn allocate_stock(
+    demands: &[MaterialDemand],
+    issued: &HashMap<(i32, i32), Decimal>,
+    on_hand: &HashMap<i32, Decimal>,
+) -> HashMap<i32, MaterialAllocation> {
+    let mut issued = issued.clone();
+    let mut on_hand = on_hand.clone();
+
+    let mut allocations = HashMap::new();
+    for demand in demands.iter() {
+        if demand.material_id == 0 {
+            let allocation = MaterialAllocation {
+                debt: demand.total,
+                ..Default::default()
+            };
+            allocations.insert(demand.id, allocation);
+            continue;
+        }
+
+        let issued_left = issued
+            .entry((demand.order_id, demand.material_id))
+            .or_insert(Decimal::ZERO);
+        let from_issued = demand.total.min(*issued_left).max(Decimal::ZERO);
+        *issued_left -= from_issued;
+        if demand.finished {
+            let allocation = MaterialAllocation {
+                issued: from_issued,
+                ..Default::default()
+            };
+            allocations.insert(demand.id, allocation);
+            continue;
+        }
+
+        let need = demand.total - from_issued;
+        let available = on_hand.entry(demand.material_id).or_insert(Decimal::ZERO);
+        let reserved = need.min(*available).max(Decimal::ZERO);
+        *available -= reserved;
+
+        allocations.insert(
+            demand.id,
+            MaterialAllocation {
+                issued: from_issued,
+                reserved,
+                debt: need - reserved,
+            },
+        );
+    }
+
+    allocations
+}
+
+/// 已经完成的产品: 走完了流程路线, 或者发货数量加上次品已经够订单数量
+async fn get_finished_order_items(
+    db: &Pool<Postgres>,
+    order_item_ids: &[i32],
+) -> ERPResult<HashSet<i32>> {
+    let order_items = sqlx::query!(
+        r#"
+        select oi.id, oi.order_id, oi.count,
+            coalesce((select sum(si.quantity) from shipment_items si where si.order_item_id = oi.id), 0) as "shipped!"
+        from order_items oi
+        where oi.id = any($1)
+        "#,
+        order_item_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let order_ids = order_items
+        .iter()
+        .map(|order_item| order_item.order_id)
+        .collect::<Vec<i32>>();
+    let workflow = Workflow::get(db).await?;
+    let order_id_to_order = sqlx::query_as!(
+        OrderModel,
+        "select * from orders where id = any($1)",
+        &order_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|order| (order.id, order))
+    .collect::<HashMap<i32, OrderModel>>();
+
+    let mut order_item_id_to_records: HashMap<i32, Vec<ProgressRecord>> = HashMap::new();
+    sqlx::query_as!(
+        ProgressModel,
+        "select * from progress where order_item_id = any($1) order by id",
+        order_item_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .iter()
+    .for_each(|progress| {
+        order_item_id_to_records
+            .entry(progress.order_item_id)
+            .or_default()
+            .push(ProgressRecord::from(progress))
+    });
+
+    let finished = order_items
+        .into_iter()
+        .filter(|order_item| {
+            let Some(order) = order_id_to_order.get(&order_item.order_id) else {
+                return false;
+            };
+            let records = order_item_id_to_records
+                .get(&order_item.id)
+                .map(|records| records.as_slice())
+                .unwrap_or(&[]);
+            let defective = records.iter().map(|record| record.defective).sum::<i32>();
+            let quantities = workflow.route_for_order(order).step_quantities(
+                &workflow,
+                order_item.count,
+                records,
+            );
+            current_step_of(&quantities).is_none()
+                || order_item.shipped as i32 + defective >= order_item.count
+        })
+        .map(|order_item| order_item.id)
+        .collect();
+
+    Ok(finished)
+}
+
+/// 算出材料单的备料情况: 涉及到的材料的所有订单一起排(加急的在前, 然后按订货日期),
+/// 返回 材料单id -> 备料情况
+pub async fn get_material_allocations(
+    db: &Pool<Postgres>,
+    material_ids: &[i32],
+) -> ERPResult<HashMap<i32, MaterialAllocation>> {
+    if material_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        select oim.id, oim.order_id, oim.order_item_id, oim.material_id, oim.total
+        from order_item_materials oim, orders o
+        where o.id = oim.order_id and oim.material_id = any($1)
+        order by o.is_urgent desc, o.order_date, o.id, oim.order_item_id, oim.id
+        "#,
+        material_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let order_item_ids = rows
+        .iter()
+        .map(|row| row.order_item_id)
+        .collect::<Vec<i32>>();
+    let finished = get_finished_order_items(db, &order_item_ids).await?;
+    let demands = rows
+        .into_iter()
+        .map(|row| MaterialDemand {
+            id: row.id,
+            order_id: row.order_id,
+            material_id: row.material_id,
+            total: row.total,
+            finished: finished.contains(&row.order_item_id),
+        })
+        .collect::<Vec<MaterialDemand>>();
+
+    let issued = sqlx::query!(
+        r#"
+        select order_id, material_id, -sum(quantity) as "quantity!"
+        from stock_movements
+        where kind = 'out' and order_id > 0 and material_id = any($1)
+        group by order_id, material_id
+        "#,
+        material_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|row| ((row.order_id, row.material_id), row.quantity))
+    .collect::<HashMap<(i32, i32), Decimal>>();
+
+    let on_hand = sqlx::query!(
+        r#"
+        select material_id, sum(quantity) as "quantity!"
+        from stock_movements
+        where material_id = any($1)
+        group by material_id
+        "#,
+        material_ids
+    )
+    .fetch_all(db)
+    .await
+    .map_err(ERPError::DBError)?
+    .into_iter()
+    .map(|row| (row.material_id, row.quantity))
+    .collect::<HashMap<i32, Decimal>>();
+
+    Ok(allocate_stock(&demands, &issued, &on_hand))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::stock::{
+        allocate_stock, movement_quantity, MaterialAllocation, MaterialDemand, StockMovementKind,
+    };
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+
+    fn demand(id: i32, order_id: i32, material_id: i32, total: i64) -> MaterialDemand {
+        MaterialDemand {
+            id,
+            order_id,
+            material_id,
+            total: Decimal::from(total),
+            finished: false,
+        }
+    }
+
+    fn allocation(issued: i64, reserved: i64, debt: i64) -> MaterialAllocation {
+        MaterialAllocation {
+            issued: Decimal::from(issued),
+            reserved: Decimal::from(reserved),
+            debt: Decimal::from(debt),
+        }
+    }
+
+    #[test]
+    fn test_allocate_stock() {
+        // 订单1要10, 已经出库4; 订单2要8; 材料2没有库存; 手工添加的材料不算库存
+        let demands = [
+            demand(1, 1, 1, 10),
+            demand(2, 2, 1, 8),
+            demand(3, 2, 2, 5),
+            demand(4, 2, 0, 3),
+        ];
+        let issued = HashMap::from([((1, 1), Decimal::from(4))]);
+        let on_hand = HashMap::from([(1, Decimal::from(9))]);
+
+        let allocations = allocate_stock(&demands, &issued, &on_hand);
+        assert_eq!(allocations[&1], allocation(4, 6, 0));
+        assert_eq!(allocations[&2], allocation(0, 3, 5));
+        assert_eq!(allocations[&3], allocation(0, 0, 5));
+        assert_eq!(allocations[&4], allocation(0, 0, 3));
+    }
+
+    #[test]
+    fn test_allocate_stock_finished() {
+        // 订单1排在前面, 但已经发完货了: 只算出库的4, 不再占用库存, 库存都留给订单2
+        let demands = [
+            MaterialDemand {
+                finished: true,
+                ..demand(1, 1, 1, 10)
+            },
+            demand(2, 2, 1, 8),
+        ];
+        let issued = HashMap::from([((1, 1), Decimal::from(4))]);
+        let on_hand = HashMap::from([(1, Decimal::from(9))]);
+
+        let allocations = allocate_stock(&demands, &issued, &on_hand);
+        assert_eq!(allocations[&1], allocation(4, 0, 0));
+        assert_eq!(allocations[&2], allocation(0, 8, 0));
+    }
+
+    #[test]
+    fn test_movement_quantity() {
+        let on_hand = Decimal::from(5);
+        assert_eq!(
+            movement_quantity(StockMovementKind::In, Decimal::from(3), on_hand).unwrap(),
+            Decimal::from(3)
+        );
+        assert_eq!(
+            movement_quantity(StockMovementKind::Out, Decimal::from(3), on_hand).unwrap(),
+            Decimal::from(-3)
+        );
+        assert!(movement_quantity(StockMovementKind::Out, Decimal::from(6), on_hand).is_err());
+        assert!(movement_quantity(StockMovementKind::In, Decimal::ZERO, on_hand).is_err());
+        assert_eq!(
+            movement_quantity(StockMovementKind::Adjust, Decimal::from(2), on_hand).unwrap(),
+            Decimal::from(-3)
+        );
+        assert!(movement_quantity(StockMovementKind::Adjust, on_hand, on_hand).is_err());
+    }
+}
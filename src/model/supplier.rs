@@ -0,0 +1,78 @@
+use crate::{ERPError, ERPResult};
+use sqlx::{Pool, Postgres};
+
+/// 供应商(不锈钢成品订货, 外发加工, 材料采购等)
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct SupplierModel {
+    pub id: i32,
+    pub code: String,
+    pub name: String,
+    pub notes: String,
+    pub contact: String, // 联系人
+    pub phone: String,
+    pub address: String,
+    pub provides: Vec<String>, // 提供的材料/加工
+}
+
+impl SupplierModel {
+    pub async fn get_by_id(db: &Pool<Postgres>, id: i32) -> ERPResult<SupplierModel> {
+        sqlx::query_as!(SupplierModel, "select * from suppliers where id = $1", id)
+            .fetch_optional(db)
+            .await
+            .map_err(ERPError::DBError)?
+            .ok_or(ERPError::NotFound(format!("供应商#{}", id)))
+    }
+
+    pub async fn get_by_code(db: &Pool<Postgres>, code: &str) -> ERPResult<Option<SupplierModel>> {
+        let supplier = sqlx::query_as!(
+            SupplierModel,
+            "select * from suppliers where code = $1",
+            code
+        )
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(supplier)
+    }
+
+    /// keyword为空时不过滤, 否则按编号/名称/提供的材料或加工模糊查询
+    pub async fn get_list(
+        db: &Pool<Postgres>,
+        keyword: &str,
+        offset: i64,
+        limit: i64,
+    ) -> ERPResult<(Vec<SupplierModel>, i64)> {
+        let suppliers = sqlx::query_as!(
+            SupplierModel,
+            r#"
+            select * from suppliers
+            where $1 = '' or code like '%' || $1 || '%' or name like '%' || $1 || '%'
+                or array_to_string(provides, ',') like '%' || $1 || '%'
+            order by code offset $2 limit $3
+            "#,
+            keyword,
+            offset,
+            limit
+        )
+        .fetch_all(db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let count = sqlx::query!(
+            r#"
+            select count(1) from suppliers
+            where $1 = '' or code like '%' || $1 || '%' or name like '%' || $1 || '%'
+                or array_to_string(provides, ',') like '%' || $1 || '%'
+            "#,
+            keyword
+        )
+        .fetch_one(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .count
+        .unwrap_or(0);
+
+        Ok((suppliers, count))
+    }
+}
@@ -0,0 +1,470 @@
+use crate::dto::dto_progress::OneProgress;
+use crate::model::order::OrderModel;
+use crate::model::progress::ProgressModel;
+use crate::{ERPError, ERPResult};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Pool, Postgres};
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct WorkflowStepModel {
+    pub id: i32,
+    pub step: i32,
+    pub name: String,
+    pub department_id: i32,
+    pub is_shipping: bool,   // 出货流程: 只能通过发货单完成
+    pub labor_rate: Decimal, // 计件工价(每件), 0: 不算人工成本
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct WorkflowStepOptionModel {
+    pub id: i32,
+    pub step: i32,
+    pub index: i32,
+    pub name: String,
+    pub color: String,
+    pub is_done: bool,
+    pub is_exception: bool,
+    pub purchase_kind: String, // 需要采购单的选项: stainless/outsource, 空: 不需要
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct WorkflowTemplateModel {
+    pub id: i32,
+    pub name: String,
+    pub steps: Vec<i32>,
+    pub optional_steps: Vec<i32>,
+    pub skippable_steps: Vec<i32>,
+    pub build_by: i32,
+    pub customer_no: String,
+    pub is_default: bool,
+}
+
+/// 整个生产流程的定义, steps 按 step 从小到大排好序
+#[derive(Debug, Clone)]
+pub struct Workflow {
+    pub steps: Vec<WorkflowStepModel>,
+    pub options: Vec<WorkflowStepOptionModel>,
+    pub templates: Vec<WorkflowTemplateModel>,
+}
+
+/// 某个订单实际要走的流程路线
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkflowRoute {
+    pub template_id: i32, // 0: 没有模板, 走所有流程
+    pub steps: Vec<i32>,
+    pub optional_steps: Vec<i32>,
+    pub skippable_steps: Vec<i32>,
+}
+
+/// 算数量用到的流程记录字段
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressRecord {
+    pub step: i32,
+    pub index: i32,
+    pub quantity: i32,
+    pub defective: i32,
+    pub skipped: bool,
+}
+
+impl From<&ProgressModel> for ProgressRecord {
+    fn from(progress: &ProgressModel) -> Self {
+        Self {
+            step: progress.step,
+            index: progress.index,
+            quantity: progress.quantity,
+            defective: progress.defective,
+            skipped: progress.skipped,
+        }
+    }
+}
+
+impl From<&OneProgress> for ProgressRecord {
+    fn from(progress: &OneProgress) -> Self {
+        Self {
+            step: progress.step,
+            index: progress.index,
+            quantity: progress.quantity,
+            defective: progress.defective,
+            skipped: progress.skipped,
+        }
+    }
+}
+
+/// 某个产品在某个流程上的数量
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StepQuantity {
+    pub step: i32,
+    pub completed: i32,   // 已经过这个流程的数量
+    pub in_progress: i32, // 已到这个流程, 还没完成的数量
+    pub defective: i32,   // 次品数量
+    pub done: bool,       // 流入的数量都已经过这个流程(完成或次品), 且上一步也已完成
+}
+
+impl Workflow {
+    pub async fn get(db: &Pool<Postgres>) -> ERPResult<Workflow> {
+        let mut conn = db.acquire().await.map_err(ERPError::DBError)?;
+        Self::get_with_conn(&mut conn).await
+    }
+
+    /// 在事务里读取流程定义
+    pub async fn get_with_conn(db: &mut PgConnection) -> ERPResult<Workflow> {
+        let steps = sqlx::query_as!(
+            WorkflowStepModel,
+            "select * from workflow_steps order by step"
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let options = sqlx::query_as!(
+            WorkflowStepOptionModel,
+            "select * from workflow_step_options order by step, index"
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        let templates = sqlx::query_as!(
+            WorkflowTemplateModel,
+            "select * from workflow_templates order by id"
+        )
+        .fetch_all(&mut *db)
+        .await
+        .map_err(ERPError::DBError)?;
+
+        Ok(Workflow {
+            steps,
+            options,
+            templates,
+        })
+    }
+
+    /// 模板的优先级: 订单指定的 > 按客户 > 按制作方式 > 默认模板 > 所有流程
+    pub fn route_for_order(&self, order: &OrderModel) -> WorkflowRoute {
+        let template = self
+            .templates
+            .iter()
+            .find(|t| order.workflow_template_id != 0 && t.id == order.workflow_template_id)
+            .or_else(|| {
+                self.templates
+                    .iter()
+                    .find(|t| !t.customer_no.is_empty() && t.customer_no == order.customer_no)
+            })
+            .or_else(|| {
+                self.templates
+                    .iter()
+                    .find(|t| t.build_by != 0 && t.build_by == order.build_by)
+            })
+            .or_else(|| self.templates.iter().find(|t| t.is_default));
+
+        match template {
+            Some(template) => WorkflowRoute {
+                template_id: template.id,
+                steps: template.steps.clone(),
+                optional_steps: template.optional_steps.clone(),
+                skippable_steps: template.skippable_steps.clone(),
+            },
+            None => WorkflowRoute {
+                template_id: 0,
+                steps: self.steps.iter().map(|step| step.step).collect(),
+                optional_steps: vec![],
+                skippable_steps: vec![],
+            },
+        }
+    }
+
+    pub fn step_name(&self, step: i32) -> &str {
+        self.steps
+            .iter()
+            .find(|s| s.step == step)
+            .map(|s| s.name.as_str())
+            .unwrap_or("")
+    }
+
+    /// 出货流程, 没有设置时为None
+    pub fn shipping_step(&self) -> Option<i32> {
+        self.steps.iter().find(|s| s.is_shipping).map(|s| s.step)
+    }
+
+    pub fn get_option(&self, step: i32, index: i32) -> Option<&WorkflowStepOptionModel> {
+        self.options
+            .iter()
+            .find(|option| option.step == step && option.index == index)
+    }
+
+    pub fn is_done(&self, step: i32, index: i32) -> bool {
+        self.get_option(step, index)
+            .map(|option| option.is_done)
+            .unwrap_or(false)
+    }
+
+    /// mark_progress 时检查 index 是不是这个流程的选项
+    pub fn check_option(&self, step: i32, index: i32) -> ERPResult<&WorkflowStepOptionModel> {
+        self.get_option(step, index)
+            .ok_or(ERPError::ParamError("请选择正确的流程".to_string()))
+    }
+
+    /// 跳过流程时记为这个流程的完成选项
+    pub fn done_option(&self, step: i32) -> ERPResult<&WorkflowStepOptionModel> {
+        self.options
+            .iter()
+            .find(|option| option.step == step && option.is_done)
+            .ok_or(ERPError::NotFound("该流程没有完成的选项".to_string()))
+    }
+
+    /// 检查模板里的流程都存在, 且不重复
+    pub fn check_template_steps(
+        &self,
+        steps: &[i32],
+        optional_steps: &[i32],
+        skippable_steps: &[i32],
+    ) -> ERPResult<()> {
+        if steps.is_empty() {
+            return Err(ERPError::ParamNeeded("steps".to_string()));
+        }
+        if let Some(step) = steps
+            .iter()
+            .find(|step| !self.steps.iter().any(|s| s.step == **step))
+        {
+            return Err(ERPError::NotFound(format!("流程{}", step)));
+        }
+        let mut unique_steps = steps.to_vec();
+        unique_steps.sort();
+        unique_steps.dedup();
+        if unique_steps.len() != steps.len() {
+            return Err(ERPError::ParamError("流程不能重复".to_string()));
+        }
+        if optional_steps
+            .iter()
+            .chain(skippable_steps.iter())
+            .any(|step| !steps.contains(step))
+        {
+            return Err(ERPError::ParamError(
+                "可选/可跳过的流程必须在流程路线里".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl WorkflowRoute {
+    pub fn first_step(&self) -> i32 {
+        self.steps.first().copied().unwrap_or(1)
+    }
+
+    pub fn is_skippable(&self, step: i32) -> bool {
+        self.skippable_steps.contains(&step)
+    }
+
+    /// 按流程路线算出每一步的数量: 上一步完成的数量才能流到这一步, 次品不再往下流
+    pub fn step_quantities(
+        &self,
+        workflow: &Workflow,
+        count: i32,
+        records: &[ProgressRecord],
+    ) -> Vec<StepQuantity> {
+        let mut available = count;
+        let mut upstream_done = true;
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(position, step)| {
+                let step_records = records
+                    .iter()
+                    .filter(|record| record.step == *step)
+                    .collect::<Vec<&ProgressRecord>>();
+
+                // 可选流程没人操作过, 数量直接流到下一步; 后面的流程已经开始了就当它完成了
+                if self.optional_steps.contains(step) && step_records.is_empty() {
+                    let passed = records
+                        .iter()
+                        .any(|record| self.steps[position + 1..].contains(&record.step));
+                    return StepQuantity {
+                        step: *step,
+                        completed: 0,
+                        in_progress: if passed { 0 } else { available },
+                        defective: 0,
+                        done: passed,
+                    };
+                }
+
+                let completed = step_records
+                    .iter()
+                    .filter(|record| record.skipped || workflow.is_done(record.step, record.index))
+                    .map(|record| record.quantity)
+                    .sum::<i32>()
+                    .min(count);
+                let defective = step_records
+                    .iter()
+                    .map(|record| record.defective)
+                    .sum::<i32>();
+                let in_progress = (available - completed - defective).max(0);
+                // 上一步全部完成后, 流进来的数量都完成了或者是次品, 这一步就完成了
+                let done = upstream_done && completed + defective >= available;
+                upstream_done = done;
+                available = completed;
+
+                StepQuantity {
+                    step: *step,
+                    completed,
+                    in_progress,
+                    defective,
+                    done,
+                }
+            })
+            .collect()
+    }
+}
+
+/// 第一个还没全部完成的流程, 所有流程都已完成时返回None
+pub fn current_step_of(quantities: &[StepQuantity]) -> Option<i32> {
+    quantities
+        .iter()
+        .find(|quantity| !quantity.done)
+        .map(|quantity| quantity.step)
+}
+
+/// 有数量可以操作的流程
+pub fn actionable_steps_of(quantities: &[StepQuantity]) -> Vec<i32> {
+    quantities
+        .iter()
+        .filter(|quantity| quantity.in_progress > 0)
+        .map(|quantity| quantity.step)
+        .collect()
+}
+
+/// 还没全部完成的流程
+pub fn remaining_steps_of(quantities: &[StepQuantity]) -> Vec<i32> {
+    quantities
+        .iter()
+        .filter(|quantity| !quantity.done)
+        .map(|quantity| quantity.step)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::workflow::{
+        actionable_steps_of, current_step_of, remaining_steps_of, ProgressRecord, Workflow,
+        WorkflowRoute, WorkflowStepOptionModel,
+    };
+
+    fn route() -> WorkflowRoute {
+        WorkflowRoute {
+            template_id: 1,
+            steps: vec![1, 2, 4, 3],
+            optional_steps: vec![2],
+            skippable_steps: vec![3],
+        }
+    }
+
+    fn workflow() -> Workflow {
+        let options = [1, 2, 3, 4]
+            .iter()
+            .map(|step| WorkflowStepOptionModel {
+                id: *step,
+                step: *step,
+                index: 2,
+                name: "已完成".to_string(),
+                color: "".to_string(),
+                is_done: true,
+                is_exception: false,
+                purchase_kind: "".to_string(),
+            })
+            .collect();
+        Workflow {
+            steps: vec![],
+            options,
+            templates: vec![],
+        }
+    }
+
+    fn record(step: i32, index: i32, quantity: i32, defective: i32) -> ProgressRecord {
+        ProgressRecord {
+            step,
+            index,
+            quantity,
+            defective,
+            skipped: false,
+        }
+    }
+
+    #[test]
+    fn test_step_quantities() {
+        let route = route();
+        let workflow = workflow();
+        assert_eq!(route.first_step(), 1);
+        assert!(route.is_skippable(3));
+
+        let quantities = route.step_quantities(&workflow, 10, &[]);
+        assert_eq!(current_step_of(&quantities), Some(1));
+        assert_eq!(actionable_steps_of(&quantities), vec![1]);
+
+        // 第一步完成了6个, 可选的第二步没人操作, 数量直接流到第4步
+        let records = vec![record(1, 2, 6, 0), record(4, 1, 0, 0)];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert_eq!(quantities[0].in_progress, 4);
+        assert_eq!(quantities[1].in_progress, 0);
+        assert!(quantities[1].done);
+        assert_eq!(quantities[2].in_progress, 6);
+        assert_eq!(current_step_of(&quantities), Some(1));
+        assert_eq!(actionable_steps_of(&quantities), vec![1, 4]);
+        assert_eq!(remaining_steps_of(&quantities), vec![1, 4, 3]);
+
+        // 剩下的4个有1个次品, 第4步全部完成
+        let records = vec![record(1, 2, 6, 0), record(1, 2, 3, 1), record(4, 2, 9, 0)];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert_eq!(quantities[0].completed, 9);
+        assert_eq!(quantities[0].defective, 1);
+        assert_eq!(quantities[0].in_progress, 0);
+        assert!(quantities[0].done);
+        assert_eq!(quantities[2].completed, 9);
+        assert!(quantities[2].done);
+        assert_eq!(quantities[3].in_progress, 9);
+        assert_eq!(current_step_of(&quantities), Some(3));
+        assert_eq!(actionable_steps_of(&quantities), vec![3]);
+
+        let records = vec![
+            record(1, 2, 10, 0),
+            record(4, 2, 10, 0),
+            record(3, 2, 10, 0),
+        ];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert_eq!(current_step_of(&quantities), None);
+        assert!(remaining_steps_of(&quantities).is_empty());
+    }
+
+    #[test]
+    fn test_step_quantities_with_defective() {
+        let route = route();
+        let workflow = workflow();
+
+        // 第一步还有数量没完成, 后面的流程把流进来的都做完了也不算完成
+        let records = vec![record(1, 2, 6, 0), record(4, 2, 6, 0)];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert!(!quantities[0].done);
+        assert!(!quantities[2].done);
+        assert_eq!(current_step_of(&quantities), Some(1));
+
+        // 第一步2个次品, 第4步又1个次品, 剩下的7个出完最后一步, 整个产品完成
+        let records = vec![record(1, 2, 8, 2), record(4, 2, 7, 1), record(3, 2, 7, 0)];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert_eq!(quantities[0].defective, 2);
+        assert!(quantities[0].done);
+        assert_eq!(quantities[2].completed, 7);
+        assert_eq!(quantities[2].defective, 1);
+        assert!(quantities[2].done);
+        assert_eq!(quantities[3].completed, 7);
+        assert!(quantities[3].done);
+        assert_eq!(current_step_of(&quantities), None);
+        assert!(actionable_steps_of(&quantities).is_empty());
+
+        // 剩下的都是次品: 完成0个, 不能当成整个产品流到下一步
+        let records = vec![record(1, 2, 8, 0), record(1, 2, 0, 2)];
+        let quantities = route.step_quantities(&workflow, 10, &records);
+        assert_eq!(quantities[0].completed, 8);
+        assert!(quantities[0].done);
+        assert_eq!(quantities[2].in_progress, 8);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod order_pdf;
+pub mod pdf_table;
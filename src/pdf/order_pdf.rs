@@ -0,0 +1,422 @@
+use crate::common::datetime::format_date;
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::excel::export_order_excel::{
+    load_export_order_goods, local_image_path, ExportOrderGoods,
+};
+use crate::model::customer::CustomerModel;
+use crate::model::order::{OrderDocumentModel, OrderModel};
+use crate::pdf::pdf_table::{render_pdf, PdfDoc, PdfTable, PdfTableRow};
+use crate::{ERPError, ERPResult};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+
+/// 单据类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderPdfKind {
+    Confirmation, // 订单确认书
+    PackingList,  // 装箱单
+    DeliveryNote, // 送货单
+}
+
+impl OrderPdfKind {
+    pub fn from_str(kind: &str) -> ERPResult<OrderPdfKind> {
+        match kind {
+            "confirmation" => Ok(OrderPdfKind::Confirmation),
+            "packing" => Ok(OrderPdfKind::PackingList),
+            "delivery" => Ok(OrderPdfKind::DeliveryNote),
+            _ => Err(ERPError::ParamError(format!(
+                "单据类型不对: {}, 只支持confirmation/packing/delivery",
+                kind
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderPdfKind::Confirmation => "confirmation",
+            OrderPdfKind::PackingList => "packing",
+            OrderPdfKind::DeliveryNote => "delivery",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            OrderPdfKind::Confirmation => "订单确认书",
+            OrderPdfKind::PackingList => "装箱单",
+            OrderPdfKind::DeliveryNote => "送货单",
+        }
+    }
+}
+
+fn order_info_lines(
+    kind: OrderPdfKind,
+    order: &OrderModel,
+    customer: Option<&CustomerModel>,
+) -> Vec<String> {
+    let customer_name = customer.map(|c| c.name.as_str()).unwrap_or("");
+    let mut lines = vec![format!("客户: {} {}", order.customer_no, customer_name)];
+    if kind == OrderPdfKind::DeliveryNote {
+        if let Some(customer) = customer {
+            lines.push(format!(
+                "地址: {}  电话: {}",
+                customer.address, customer.phone
+            ));
+        }
+    }
+    let mut line = format!(
+        "单号: {}  订货日期: {}",
+        order.order_no,
+        format_date(order.order_date)
+    );
+    if let Some(delivery_date) = order.delivery_date {
+        line.push_str(&format!("  交货日期: {}", format_date(delivery_date)));
+    }
+    lines.push(line);
+    if kind == OrderPdfKind::DeliveryNote {
+        lines.push(format!(
+            "送货日期: {}",
+            format_date(Utc::now().date_naive())
+        ));
+    }
+    lines
+}
+
+/// 数据库里是numeric(12,4), 去掉多余的0再显示
+fn optional_price(price: Option<Decimal>) -> String {
+    price.map(|p| p.normalize().to_string()).unwrap_or_default()
+}
+
+/// 按单据类型排出表格; image_path 把图片地址转成本地文件
+pub fn build_order_pdf(
+    kind: OrderPdfKind,
+    order: &OrderModel,
+    customer: Option<&CustomerModel>,
+    goods: &[ExportOrderGoods],
+    image_path: &dyn Fn(&str) -> Option<String>,
+) -> PdfDoc {
+    let (headers, widths, image_column): (Vec<&str>, Vec<f32>, usize) = match kind {
+        OrderPdfKind::Confirmation => (
+            vec![
+                "序号",
+                "图片",
+                "商品编号",
+                "名称",
+                "电镀",
+                "颜色",
+                "数量",
+                "单位",
+                "单价",
+                "金额",
+                "备注",
+            ],
+            vec![
+                10.0, 24.0, 22.0, 26.0, 14.0, 16.0, 14.0, 10.0, 14.0, 16.0, 24.0,
+            ],
+            2,
+        ),
+        OrderPdfKind::PackingList => (
+            vec![
+                "序号",
+                "图片",
+                "商品编号",
+                "名称",
+                "电镀",
+                "颜色",
+                "数量",
+                "单位",
+                "包装",
+                "备注",
+            ],
+            vec![10.0, 24.0, 22.0, 28.0, 14.0, 18.0, 14.0, 10.0, 22.0, 28.0],
+            2,
+        ),
+        OrderPdfKind::DeliveryNote => (
+            vec![
+                "序号",
+                "商品编号",
+                "名称",
+                "电镀",
+                "颜色",
+                "数量",
+                "单位",
+                "备注",
+            ],
+            vec![12.0, 28.0, 36.0, 18.0, 22.0, 16.0, 12.0, 46.0],
+            0,
+        ),
+    };
+
+    let mut rows = vec![];
+    let mut total_count = 0;
+    let mut total_price = Decimal::ZERO;
+    for (index, order_goods) in goods.iter().enumerate() {
+        for (i, item) in order_goods.items.iter().enumerate() {
+            total_count += item.item.count;
+            total_price += item.item.total_price.unwrap_or_default();
+
+            // 商品的序号/图片/编号/名称只写在第一行
+            let first = i == 0;
+            let goods_cell = |value: &str| match first {
+                true => value.to_string(),
+                false => "".to_string(),
+            };
+            let index_cell = goods_cell(&(index + 1).to_string());
+            let goods_no = goods_cell(&order_goods.goods.goods_no);
+            let name = goods_cell(&order_goods.goods.name);
+            let count = item.item.count.to_string();
+            let unit = item.item.unit.clone().unwrap_or_default();
+            let cells = match kind {
+                OrderPdfKind::Confirmation => vec![
+                    index_cell,
+                    "".to_string(),
+                    goods_no,
+                    name,
+                    item.plating.clone(),
+                    item.item.color.clone(),
+                    count,
+                    unit,
+                    optional_price(item.item.unit_price),
+                    optional_price(item.item.total_price),
+                    item.item.notes.clone(),
+                ],
+                OrderPdfKind::PackingList => vec![
+                    index_cell,
+                    "".to_string(),
+                    goods_no,
+                    name,
+                    item.plating.clone(),
+                    item.item.color.clone(),
+                    count,
+                    unit,
+                    goods_cell(&order_goods.goods.package_card_des),
+                    item.item.notes.clone(),
+                ],
+                OrderPdfKind::DeliveryNote => vec![
+                    index_cell,
+                    goods_no,
+                    name,
+                    item.plating.clone(),
+                    item.item.color.clone(),
+                    count,
+                    unit,
+                    item.item.notes.clone(),
+                ],
+            };
+            let image = match (first, image_column) {
+                (true, column) if column > 0 => order_goods
+                    .goods
+                    .images
+                    .first()
+                    .and_then(|url| image_path(url)),
+                _ => None,
+            };
+            rows.push(PdfTableRow { cells, image });
+        }
+    }
+
+    let mut footer_lines = vec![format!(
+        "共{}款, 合计数量: {}",
+        goods.iter().filter(|g| !g.items.is_empty()).count(),
+        total_count
+    )];
+    match kind {
+        OrderPdfKind::Confirmation => {
+            footer_lines.push(format!("合计金额: {}", total_price.normalize()));
+            footer_lines.push("客户确认(签字/盖章):                    日期:".to_string());
+        }
+        OrderPdfKind::PackingList => {}
+        OrderPdfKind::DeliveryNote => {
+            footer_lines.push(
+                "送货人:                    收货人(签字):                    日期:".to_string(),
+            );
+        }
+    }
+
+    PdfDoc {
+        title: kind.title().to_string(),
+        info_lines: order_info_lines(kind, order, customer),
+        table: PdfTable {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            widths,
+            image_column,
+            rows,
+        },
+        footer_lines,
+    }
+}
+
+/// 生成订单的单据PDF; archive为true时同时存到订单的目录下, 并记录到 order_documents
+pub async fn generate_order_pdf(
+    db: &Pool<Postgres>,
+    font_path: &str,
+    order_id: i32,
+    kind: OrderPdfKind,
+    archive: bool,
+    account_id: i32,
+) -> ERPResult<(OrderModel, Vec<u8>)> {
+    let order = sqlx::query_as!(OrderModel, "select * from orders where id = $1", order_id)
+        .fetch_optional(db)
+        .await
+        .map_err(ERPError::DBError)?
+        .ok_or(ERPError::NotFound(format!("订单#{}", order_id)))?;
+    let customer = sqlx::query_as!(
+        CustomerModel,
+        "select * from customers where customer_no = $1",
+        &order.customer_no
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(ERPError::DBError)?;
+    let goods = load_export_order_goods(db, order_id).await?;
+
+    let font = std::fs::read(font_path)
+        .map_err(|_| ERPError::Failed(format!("PDF字体文件不存在: {}", font_path)))?;
+    let pdf = build_order_pdf(kind, &order, customer.as_ref(), &goods, &local_image_path);
+    // 画PDF(读图片, 压缩)比较慢, 放到阻塞线程里, 不占住异步的工作线程
+    let bytes = tokio::task::spawn_blocking(move || render_pdf(&pdf, &font))
+        .await
+        .map_err(|_| ERPError::Failed("生成PDF失败".to_string()))??;
+
+    if archive {
+        let dir_path = format!("{}order/{}", STORAGE_FILE_PATH, order.order_no);
+        std::fs::create_dir_all(&dir_path)
+            .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", dir_path)))?;
+        let file_path = format!(
+            "{}/{}-{}.pdf",
+            dir_path,
+            kind.as_str(),
+            Utc::now().format("%Y%m%d%H%M%S")
+        );
+        std::fs::write(&file_path, &bytes)
+            .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", file_path)))?;
+        let url = file_path.replacen(STORAGE_FILE_PATH, STORAGE_URL_PREFIX, 1);
+        OrderDocumentModel::insert(db, order_id, kind.as_str(), &url, account_id).await?;
+    }
+
+    Ok((order, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::DEFAULT_PDF_FONT_PATH;
+    use crate::dto::dto_orders::{OrderGoodsDto, OrderGoodsItemDto};
+    use crate::excel::export_order_excel::{ExportOrderGoods, ExportOrderItem};
+    use crate::model::order::OrderModel;
+    use crate::pdf::order_pdf::{build_order_pdf, OrderPdfKind};
+    use crate::pdf::pdf_table::render_pdf;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    fn goods(id: i32, goods_no: &str, counts: &[i32]) -> ExportOrderGoods {
+        ExportOrderGoods {
+            goods: OrderGoodsDto {
+                id,
+                order_id: 1,
+                goods_id: id,
+                goods_no: goods_no.to_string(),
+                name: "耳环".to_string(),
+                images: vec![format!("https://x/{}.png", goods_no)],
+                image_des: "".to_string(),
+                package_card: "".to_string(),
+                package_card_des: "卡".to_string(),
+            },
+            items: counts
+                .iter()
+                .map(|count| ExportOrderItem {
+                    item: OrderGoodsItemDto {
+                        id: 1,
+                        order_id: 1,
+                        order_goods_id: id,
+                        goods_id: id,
+                        sku_id: 1,
+                        sku_no: None,
+                        color: "红".to_string(),
+                        count: *count,
+                        unit: Some("个".to_string()),
+                        unit_price: Some(Decimal::from(2)),
+                        total_price: Some(Decimal::from(count * 2)),
+                        notes_images: vec![],
+                        notes: "".to_string(),
+                    },
+                    plating: "".to_string(),
+                    color_2: "".to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn order() -> OrderModel {
+        OrderModel {
+            id: 1,
+            customer_no: "L1001".to_string(),
+            order_no: "X001".to_string(),
+            order_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            delivery_date: None,
+            is_urgent: false,
+            is_return_order: false,
+            is_special: false,
+            special_customer: "".to_string(),
+            build_by: 0,
+            workflow_template_id: 0,
+            currency: "CNY".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_order_pdf() {
+        let order = order();
+        let goods = [goods(1, "A01", &[10, 5]), goods(2, "B01", &[3])];
+        let image_path = |url: &str| Some(url.replace("https://x/", "/tmp/"));
+
+        let pdf = build_order_pdf(
+            OrderPdfKind::Confirmation,
+            &order,
+            None,
+            &goods,
+            &image_path,
+        );
+        assert_eq!(pdf.title, "订单确认书");
+        assert_eq!(pdf.table.rows.len(), 3);
+        assert_eq!(pdf.table.rows[0].cells[2], "A01");
+        assert_eq!(pdf.table.rows[0].image.as_deref(), Some("/tmp/A01.png"));
+        // 同一个商品的第二行不重复写商品编号和图片
+        assert_eq!(pdf.table.rows[1].cells[2], "");
+        assert_eq!(pdf.table.rows[1].image, None);
+        assert_eq!(pdf.footer_lines[0], "共2款, 合计数量: 18");
+        assert_eq!(pdf.footer_lines[1], "合计金额: 36");
+
+        let pdf = build_order_pdf(
+            OrderPdfKind::DeliveryNote,
+            &order,
+            None,
+            &goods,
+            &image_path,
+        );
+        assert_eq!(pdf.title, "送货单");
+        assert!(pdf.table.rows.iter().all(|row| row.image.is_none()));
+        assert_eq!(pdf.table.headers.len(), pdf.table.widths.len());
+        assert_eq!(pdf.table.rows[2].cells.len(), pdf.table.headers.len());
+    }
+
+    #[test]
+    #[ignore = "需要仓库里的中文子集字体, 见 assets/fonts/README.md"]
+    fn test_render_order_pdf() {
+        let font = std::fs::read(format!(
+            "{}/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            DEFAULT_PDF_FONT_PATH
+        ))
+        .unwrap();
+        let goods = (1..=30)
+            .map(|id| goods(id, &format!("A{:02}", id), &[10, 5]))
+            .collect::<Vec<ExportOrderGoods>>();
+        let image_path = |_: &str| None;
+
+        for kind in [OrderPdfKind::Confirmation, OrderPdfKind::DeliveryNote] {
+            let pdf = build_order_pdf(kind, &order(), None, &goods, &image_path);
+            let bytes = render_pdf(&pdf, &font).unwrap();
+            assert!(bytes.starts_with(b"%PDF"));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{ERPError, ERPResult};
+use printpdf::image_crate::DynamicImage;
+use printpdf::{
+    Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point,
+};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 10.0;
+const TITLE_SIZE: f32 = 16.0;
+const TEXT_SIZE: f32 = 9.0;
+const LINE_HEIGHT: f32 = 6.0;
+const ROW_HEIGHT: f32 = 7.0;
+const IMAGE_ROW_HEIGHT: f32 = 22.0;
+const PT_TO_MM: f32 = 0.3528;
+
+/// 表格的一行, image 是本地图片文件(放在图片列里)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfTableRow {
+    pub cells: Vec<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfTable {
+    pub headers: Vec<String>,
+    pub widths: Vec<f32>,    // 各列宽度(mm), 合计不超过 PAGE_WIDTH - 2 * MARGIN
+    pub image_column: usize, // 0: 没有图片列, 否则是第几列(从1开始)
+    pub rows: Vec<PdfTableRow>,
+}
+
+/// 一份单据: 标题, 表头上面的信息, 表格, 表格下面的合计/签字
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfDoc {
+    pub title: String,
+    pub info_lines: Vec<String>,
+    pub table: PdfTable,
+    pub footer_lines: Vec<String>,
+}
+
+/// 估算文字宽度(mm): 中文按一个字号宽, 其他按半个
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars()
+        .map(|c| if c.is_ascii() { 0.55 } else { 1.0 })
+        .sum::<f32>()
+        * size
+        * PT_TO_MM
+}
+
+/// 超出宽度的文字截断, 末尾加上"…"
+pub fn fit_text(text: &str, width: f32, size: f32) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+    let mut fitted = String::new();
+    for c in text.chars() {
+        if text_width(&format!("{}{}…", fitted, c), size) > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    format!("{}…", fitted)
+}
+
+/// 图片缩小后转成RGB(printpdf不支持透明通道)
+fn load_image(path: &str) -> Option<DynamicImage> {
+    let image = printpdf::image_crate::open(path).ok()?;
+    Some(DynamicImage::ImageRgb8(image.thumbnail(400, 400).to_rgb8()))
+}
+
+struct PdfWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    y: f32,
+}
+
+impl PdfWriter {
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn text(&self, text: &str, size: f32, x: f32, y: f32) {
+        self.layer.use_text(text, size, Mm(x), Mm(y), &self.font);
+    }
+
+    fn line(&self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(x1), Mm(y1)), false),
+                (Point::new(Mm(x2), Mm(y2)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn text_line(&mut self, text: &str) {
+        if self.y - LINE_HEIGHT < MARGIN {
+            self.new_page();
+        }
+        self.y -= LINE_HEIGHT;
+        self.text(text, TEXT_SIZE, MARGIN, self.y + 1.5);
+    }
+
+    /// 画一行表格(带边框), 放不下时换页
+    fn table_row(&mut self, table: &PdfTable, cells: &[String], image: Option<&str>) -> bool {
+        let height = match image {
+            Some(_) => IMAGE_ROW_HEIGHT,
+            None => ROW_HEIGHT,
+        };
+        if self.y - height < MARGIN {
+            return false;
+        }
+
+        let top = self.y;
+        let bottom = self.y - height;
+        let right = MARGIN + table.widths.iter().sum::<f32>();
+        self.line(MARGIN, top, right, top);
+        self.line(MARGIN, bottom, right, bottom);
+
+        let mut x = MARGIN;
+        for (j, width) in table.widths.iter().enumerate() {
+            self.line(x, top, x, bottom);
+            if let Some(cell) = cells.get(j).filter(|cell| !cell.is_empty()) {
+                let text = fit_text(cell, width - 2.0, TEXT_SIZE);
+                self.text(&text, TEXT_SIZE, x + 1.0, bottom + height / 2.0 - 1.2);
+            }
+            if j + 1 == table.image_column {
+                if let Some(image) = image.and_then(load_image) {
+                    self.image(image, x + 1.0, bottom + 1.0, width - 2.0, height - 2.0);
+                }
+            }
+            x += width;
+        }
+        self.line(right, top, right, bottom);
+
+        self.y = bottom;
+        true
+    }
+
+    /// 图片等比缩放到 width x height 里面
+    fn image(&self, image: DynamicImage, x: f32, y: f32, width: f32, height: f32) {
+        let (px_width, px_height) = (image.width() as f32, image.height() as f32);
+        let dpi = (px_width / (width / 25.4)).max(px_height / (height / 25.4));
+        Image::from_dynamic_image(&image).add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(x)),
+                translate_y: Some(Mm(y)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn table(&mut self, table: &PdfTable) {
+        if !self.table_row(table, &table.headers, None) {
+            self.new_page();
+            self.table_row(table, &table.headers, None);
+        }
+        for row in table.rows.iter() {
+            let image = row.image.as_deref();
+            if !self.table_row(table, &row.cells, image) {
+                // 换页后重复表头
+                self.new_page();
+                self.table_row(table, &table.headers, None);
+                self.table_row(table, &row.cells, image);
+            }
+        }
+    }
+}
+
+/// 用中文字体(整个字体文件的内容)画出单据, 字体只嵌入用到的字
+pub fn render_pdf(pdf: &PdfDoc, font: &[u8]) -> ERPResult<Vec<u8>> {
+    let (doc, page, layer) =
+        PdfDocument::new(&pdf.title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    let font = doc
+        .add_external_font(font)
+        .map_err(|_| ERPError::Failed("PDF字体文件加载失败".to_string()))?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let mut writer = PdfWriter {
+        doc,
+        layer,
+        font,
+        y: PAGE_HEIGHT - MARGIN,
+    };
+
+    writer.y -= 10.0;
+    let title_x = (PAGE_WIDTH - text_width(&pdf.title, TITLE_SIZE)) / 2.0;
+    writer.text(&pdf.title, TITLE_SIZE, title_x, writer.y);
+    writer.y -= 4.0;
+    for line in pdf.info_lines.iter() {
+        writer.text_line(line);
+    }
+    writer.y -= 2.0;
+    writer.table(&pdf.table);
+    writer.y -= 2.0;
+    for line in pdf.footer_lines.iter() {
+        writer.text_line(line);
+    }
+
+    writer
+        .doc
+        .save_to_bytes()
+        .map_err(|_| ERPError::Failed("生成PDF失败".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pdf::pdf_table::fit_text;
+
+    #[test]
+    fn test_fit_text() {
+        assert_eq!(fit_text("A01", 20.0, 9.0), "A01");
+        let fitted = fit_text("很长很长很长很长的商品名称", 20.0, 9.0);
+        assert!(fitted.ends_with('…'));
+        assert!(fitted.chars().count() < 13);
+    }
+}